@@ -2,6 +2,12 @@
 pub mod monerod;
 pub mod wallet;
 
+use anyhow::{Context, Result};
+use digest_auth::AuthContext;
+use reqwest::{
+    header::{AUTHORIZATION, CONTENT_TYPE, WWW_AUTHENTICATE},
+    StatusCode, Url,
+};
 use serde::{Deserialize, Serialize};
 
 #[derive(Serialize, Debug, Clone)]
@@ -36,15 +42,139 @@ struct Response<T> {
     pub result: T,
 }
 
+/// Credentials of an RPC server started with `--rpc-login`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Login {
+    pub username: String,
+    pub password: String,
+}
+
+/// POSTs `body` as JSON to `url` and returns the response body.
+///
+/// If the server challenges the request and `login` is given, the request is
+/// repeated with HTTP digest authentication.
+async fn post_json<T>(
+    client: &reqwest::Client,
+    url: Url,
+    login: Option<&Login>,
+    body: &T,
+) -> Result<String>
+where
+    T: Serialize,
+{
+    let body = serde_json::to_vec(body)?;
+    let request = client
+        .post(url.clone())
+        .header(CONTENT_TYPE, "application/json")
+        .body(body);
+
+    let response = request
+        .try_clone()
+        .expect("request body is not a stream")
+        .send()
+        .await?;
+
+    let response = match login {
+        Some(login) if response.status() == StatusCode::UNAUTHORIZED => {
+            let www_authenticate = response
+                .headers()
+                .get(WWW_AUTHENTICATE)
+                .context("server did not send a digest challenge")?
+                .to_str()?;
+
+            let context = AuthContext::new_post(
+                login.username.as_str(),
+                login.password.as_str(),
+                url.path(),
+                Option::<&[u8]>::None,
+            );
+            let authorization = digest_auth::parse(www_authenticate)?
+                .respond(&context)?
+                .to_header_string();
+
+            request.header(AUTHORIZATION, authorization).send().await?
+        }
+        _ => response,
+    };
+
+    let response = response.error_for_status()?.text().await?;
+
+    Ok(response)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use digest_auth::AuthorizationHeader;
+    use hyper::{
+        service::{make_service_fn, service_fn},
+        Body, Server,
+    };
+    use std::convert::Infallible;
+
+    const USERNAME: &str = "alice";
+    const PASSWORD: &str = "secret";
+    const CHALLENGE: &str = r#"Digest qop="auth",algorithm=MD5,realm="monero-rpc",nonce="dcd98b7102dd2f0e8b11d0f600bfb0c093""#;
+    const RESPONSE: &str = r#"{"id":"1","jsonrpc":"2.0","result":{}}"#;
 
     #[derive(Serialize, Debug, Clone)]
     struct Params {
         val: u32,
     }
 
+    /// Answers requests with `RESPONSE` if they are signed with `USERNAME`
+    /// and `PASSWORD`, challenges them otherwise.
+    fn serve_with_digest_auth() -> Url {
+        let make_service = make_service_fn(|_| async {
+            Ok::<_, Infallible>(service_fn(|request: hyper::Request<Body>| async move {
+                let response = if is_authorized(&request) {
+                    hyper::Response::new(Body::from(RESPONSE))
+                } else {
+                    hyper::Response::builder()
+                        .status(StatusCode::UNAUTHORIZED)
+                        .header(WWW_AUTHENTICATE, CHALLENGE)
+                        .body(Body::empty())
+                        .unwrap()
+                };
+
+                Ok::<_, Infallible>(response)
+            }))
+        });
+        let server = Server::bind(&([127, 0, 0, 1], 0).into()).serve(make_service);
+        let url = format!("http://{}/json_rpc", server.local_addr())
+            .parse()
+            .unwrap();
+
+        tokio::spawn(server);
+
+        url
+    }
+
+    fn is_authorized(request: &hyper::Request<Body>) -> bool {
+        let header = match request.headers().get(AUTHORIZATION) {
+            Some(header) => header.to_str().unwrap(),
+            None => return false,
+        };
+        let mut authorization = AuthorizationHeader::parse(header).unwrap();
+        let response = authorization.response.clone();
+
+        authorization.digest(&AuthContext::new_post(
+            USERNAME,
+            PASSWORD,
+            request.uri().path(),
+            Option::<&[u8]>::None,
+        ));
+
+        authorization.response == response
+    }
+
+    fn login(password: &str) -> Login {
+        Login {
+            username: USERNAME.to_string(),
+            password: password.to_string(),
+        }
+    }
+
     #[test]
     fn can_serialize_request_with_params() {
         // Dummy method and parameters.
@@ -60,4 +190,47 @@ mod tests {
 
         assert_eq!(got, want);
     }
+
+    #[tokio::test]
+    async fn answers_digest_challenge_with_login() {
+        let url = serve_with_digest_auth();
+        let request = Request::new("get_version", Params { val: 0 });
+
+        let response = post_json(
+            &reqwest::Client::new(),
+            url,
+            Some(&login(PASSWORD)),
+            &request,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(response, RESPONSE);
+    }
+
+    #[tokio::test]
+    async fn fails_on_digest_challenge_with_wrong_login() {
+        let url = serve_with_digest_auth();
+        let request = Request::new("get_version", Params { val: 0 });
+
+        let result = post_json(
+            &reqwest::Client::new(),
+            url,
+            Some(&login("wrong")),
+            &request,
+        )
+        .await;
+
+        assert!(result.is_err());
+    }
+
+    #[tokio::test]
+    async fn fails_on_digest_challenge_without_login() {
+        let url = serve_with_digest_auth();
+        let request = Request::new("get_version", Params { val: 0 });
+
+        let result = post_json(&reqwest::Client::new(), url, None, &request).await;
+
+        assert!(result.is_err());
+    }
 }
@@ -1,4 +1,4 @@
-use crate::rpc::{Request, Response};
+use crate::rpc::{post_json, Login, Request, Response};
 use anyhow::Result;
 use reqwest::Url;
 use serde::{Deserialize, Serialize};
@@ -9,6 +9,7 @@ use tracing::debug;
 pub struct Client {
     pub inner: reqwest::Client,
     pub url: Url,
+    pub login: Option<Login>,
 }
 
 impl Client {
@@ -17,10 +18,19 @@ impl Client {
         let url = format!("http://127.0.0.1:{}/json_rpc", port);
         let url = Url::parse(&url).expect("url is well formed");
 
-        Self {
-            inner: reqwest::Client::new(),
-            url,
-        }
+        Client::new(url)
+    }
+
+    /// Constructs a monerod client with `url` endpoint.
+    pub fn new(url: Url) -> Self {
+        Client::new_with_http_client(reqwest::Client::new(), url, None)
+    }
+
+    /// Constructs a monerod client with `url` endpoint that sends its requests
+    /// through `inner` and authenticates with `login` if the daemon asks for
+    /// it.
+    pub fn new_with_http_client(inner: reqwest::Client, url: Url, login: Option<Login>) -> Self {
+        Self { inner, url, login }
     }
 
     pub async fn generate_blocks(
@@ -32,28 +42,10 @@ impl Client {
             amount_of_blocks,
             wallet_address: wallet_address.to_owned(),
         };
-        let url = self.url.clone();
-        // // Step 1:  Get the auth header
-        // let res = self.inner.get(url.clone()).send().await?;
-        // let headers = res.headers();
-        // let wwwauth = headers["www-authenticate"].to_str()?;
-        //
-        // // Step 2:  Given the auth header, sign the digest for the real req.
-        // let tmp_url = url.clone();
-        // let context = AuthContext::new("username", "password", tmp_url.path());
-        // let mut prompt = digest_auth::parse(wwwauth)?;
-        // let answer = prompt.respond(&context)?.to_header_string();
-
         let request = Request::new("generateblocks", params);
 
-        let response = self
-            .inner
-            .post(url)
-            .json(&request)
-            .send()
-            .await?
-            .text()
-            .await?;
+        let response =
+            post_json(&self.inner, self.url.clone(), self.login.as_ref(), &request).await?;
 
         debug!("generate blocks response: {}", response);
 
@@ -67,14 +59,8 @@ impl Client {
         let params = GetBlockHeaderByHeightParams { height };
         let request = Request::new("get_block_header_by_height", params);
 
-        let response = self
-            .inner
-            .post(self.url.clone())
-            .json(&request)
-            .send()
-            .await?
-            .text()
-            .await?;
+        let response =
+            post_json(&self.inner, self.url.clone(), self.login.as_ref(), &request).await?;
 
         debug!("get block header by height response: {}", response);
 
@@ -86,14 +72,8 @@ impl Client {
     pub async fn get_block_count(&self) -> Result<u32> {
         let request = Request::new("get_block_count", "");
 
-        let response = self
-            .inner
-            .post(self.url.clone())
-            .json(&request)
-            .send()
-            .await?
-            .text()
-            .await?;
+        let response =
+            post_json(&self.inner, self.url.clone(), self.login.as_ref(), &request).await?;
 
         debug!("get block count response: {}", response);
 
@@ -101,6 +81,39 @@ impl Client {
 
         Ok(res.result.count)
     }
+
+    pub async fn get_info(&self) -> Result<GetInfo> {
+        let request = Request::new("get_info", "");
+
+        let response =
+            post_json(&self.inner, self.url.clone(), self.login.as_ref(), &request).await?;
+
+        debug!("get info response: {}", response);
+
+        let res: Response<GetInfo> = serde_json::from_str(&response)?;
+
+        Ok(res.result)
+    }
+
+    /// Fetches transactions by hash, decoded as JSON by monerod.
+    ///
+    /// This is not a JSON RPC method, monerod serves it on `/get_transactions`
+    /// next to the `/json_rpc` endpoint.
+    pub async fn get_transactions(&self, tx_hashes: &[String]) -> Result<Vec<Transaction>> {
+        let params = GetTransactionsParams {
+            txs_hashes: tx_hashes.to_vec(),
+            decode_as_json: true,
+        };
+        let url = self.url.join("get_transactions")?;
+
+        let response = post_json(&self.inner, url, self.login.as_ref(), &params).await?;
+
+        debug!("get transactions response: {}", response);
+
+        let res: GetTransactions = serde_json::from_str(&response)?;
+
+        Ok(res.txs)
+    }
 }
 
 #[derive(Clone, Debug, Serialize)]
@@ -140,7 +153,7 @@ struct BlockCount {
 pub struct BlockHeader {
     pub block_size: u32,
     pub depth: u32,
-    pub difficulty: u32,
+    pub difficulty: u64,
     pub hash: String,
     pub height: u32,
     pub major_version: u32,
@@ -152,3 +165,81 @@ pub struct BlockHeader {
     pub reward: u64,
     pub timestamp: u32,
 }
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct GetInfo {
+    pub height: u32,
+    /// One of `mainnet`, `stagenet`, `testnet` or `fakechain`.
+    pub nettype: String,
+    pub status: String,
+    pub synchronized: bool,
+}
+
+#[derive(Clone, Debug, Serialize)]
+struct GetTransactionsParams {
+    txs_hashes: Vec<String>,
+    decode_as_json: bool,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+struct GetTransactions {
+    #[serde(default)]
+    txs: Vec<Transaction>,
+    status: String,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct Transaction {
+    pub tx_hash: String,
+    pub as_json: String,
+    /// Height of the block the transaction was included in, zero while the
+    /// transaction is still in the pool.
+    #[serde(default)]
+    pub block_height: u32,
+    pub in_pool: bool,
+}
+
+impl Transaction {
+    /// Decodes the JSON representation of the transaction returned by monerod.
+    pub fn decode(&self) -> Result<TransactionJson> {
+        let tx = serde_json::from_str(&self.as_json)?;
+
+        Ok(tx)
+    }
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct TransactionJson {
+    pub version: u32,
+    pub vout: Vec<TxOut>,
+    pub rct_signatures: RctSignatures,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct TxOut {
+    pub amount: u64,
+    pub target: TxOutTarget,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct TxOutTarget {
+    pub key: String,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct RctSignatures {
+    #[serde(rename = "type")]
+    pub rct_type: u8,
+    #[serde(rename = "ecdhInfo", default)]
+    pub ecdh_info: Vec<EcdhInfo>,
+    #[serde(rename = "outPk", default)]
+    pub out_pk: Vec<String>,
+}
+
+/// Encrypted amount of an output. Since bulletproofs v2 the mask is no longer
+/// transmitted and the amount is encrypted in 8 bytes.
+#[derive(Clone, Debug, Deserialize)]
+pub struct EcdhInfo {
+    pub mask: Option<String>,
+    pub amount: String,
+}
@@ -1,4 +1,4 @@
-use crate::rpc::{Request, Response};
+use crate::rpc::{post_json, Login, Request, Response};
 use anyhow::{bail, Result};
 use reqwest::Url;
 use serde::{Deserialize, Serialize};
@@ -9,6 +9,7 @@ use tracing::debug;
 pub struct Client {
     pub inner: reqwest::Client,
     pub url: Url,
+    pub login: Option<Login>,
 }
 
 impl Client {
@@ -25,6 +26,16 @@ impl Client {
         Self {
             inner: reqwest::Client::new(),
             url,
+            login: None,
+        }
+    }
+
+    /// Constructs a monero-wallet-rpc client with `url` endpoint that
+    /// authenticates with `login`, for instances started with `--rpc-login`.
+    pub fn new_with_login(url: Url, login: Login) -> Self {
+        Self {
+            login: Some(login),
+            ..Client::new(url)
         }
     }
 
@@ -33,14 +44,8 @@ impl Client {
         let params = GetAddressParams { account_index };
         let request = Request::new("get_address", params);
 
-        let response = self
-            .inner
-            .post(self.url.clone())
-            .json(&request)
-            .send()
-            .await?
-            .text()
-            .await?;
+        let response =
+            post_json(&self.inner, self.url.clone(), self.login.as_ref(), &request).await?;
 
         debug!("get address RPC response: {}", response);
 
@@ -50,19 +55,23 @@ impl Client {
 
     /// Gets the balance of account by index.
     pub async fn get_balance(&self, index: u32) -> Result<u64> {
+        Ok(self.balance(index).await?.balance)
+    }
+
+    /// Gets the part of the balance of account by index that can be spent
+    /// already.
+    pub async fn get_unlocked_balance(&self, index: u32) -> Result<u64> {
+        Ok(self.balance(index).await?.unlocked_balance)
+    }
+
+    async fn balance(&self, index: u32) -> Result<GetBalance> {
         let params = GetBalanceParams {
             account_index: index,
         };
         let request = Request::new("get_balance", params);
 
-        let response = self
-            .inner
-            .post(self.url.clone())
-            .json(&request)
-            .send()
-            .await?
-            .text()
-            .await?;
+        let response =
+            post_json(&self.inner, self.url.clone(), self.login.as_ref(), &request).await?;
 
         debug!(
             "get balance of account index {} RPC response: {}",
@@ -71,9 +80,7 @@ impl Client {
 
         let res: Response<GetBalance> = serde_json::from_str(&response)?;
 
-        let balance = res.result.balance;
-
-        Ok(balance)
+        Ok(res.result)
     }
 
     pub async fn create_account(&self, label: &str) -> Result<CreateAccount> {
@@ -82,14 +89,8 @@ impl Client {
         };
         let request = Request::new("create_account", params);
 
-        let response = self
-            .inner
-            .post(self.url.clone())
-            .json(&request)
-            .send()
-            .await?
-            .text()
-            .await?;
+        let response =
+            post_json(&self.inner, self.url.clone(), self.login.as_ref(), &request).await?;
 
         debug!("create account RPC response: {}", response);
 
@@ -104,14 +105,8 @@ impl Client {
         };
         let request = Request::new("get_accounts", params);
 
-        let response = self
-            .inner
-            .post(self.url.clone())
-            .json(&request)
-            .send()
-            .await?
-            .text()
-            .await?;
+        let response =
+            post_json(&self.inner, self.url.clone(), self.login.as_ref(), &request).await?;
 
         debug!("get accounts RPC response: {}", response);
 
@@ -127,14 +122,8 @@ impl Client {
         };
         let request = Request::new("open_wallet", params);
 
-        let response = self
-            .inner
-            .post(self.url.clone())
-            .json(&request)
-            .send()
-            .await?
-            .text()
-            .await?;
+        let response =
+            post_json(&self.inner, self.url.clone(), self.login.as_ref(), &request).await?;
 
         debug!("open wallet RPC response: {}", response);
 
@@ -155,14 +144,8 @@ impl Client {
         };
         let request = Request::new("create_wallet", params);
 
-        let response = self
-            .inner
-            .post(self.url.clone())
-            .json(&request)
-            .send()
-            .await?
-            .text()
-            .await?;
+        let response =
+            post_json(&self.inner, self.url.clone(), self.login.as_ref(), &request).await?;
 
         debug!("create wallet RPC response: {}", response);
 
@@ -200,14 +183,8 @@ impl Client {
         };
         let request = Request::new("transfer", params);
 
-        let response = self
-            .inner
-            .post(self.url.clone())
-            .json(&request)
-            .send()
-            .await?
-            .text()
-            .await?;
+        let response =
+            post_json(&self.inner, self.url.clone(), self.login.as_ref(), &request).await?;
 
         debug!("transfer RPC response: {}", response);
 
@@ -219,14 +196,8 @@ impl Client {
     pub async fn block_height(&self) -> Result<BlockHeight> {
         let request = Request::new("get_height", "");
 
-        let response = self
-            .inner
-            .post(self.url.clone())
-            .json(&request)
-            .send()
-            .await?
-            .text()
-            .await?;
+        let response =
+            post_json(&self.inner, self.url.clone(), self.login.as_ref(), &request).await?;
 
         debug!("wallet height RPC response: {}", response);
 
@@ -248,14 +219,8 @@ impl Client {
         };
         let request = Request::new("check_tx_key", params);
 
-        let response = self
-            .inner
-            .post(self.url.clone())
-            .json(&request)
-            .send()
-            .await?
-            .text()
-            .await?;
+        let response =
+            post_json(&self.inner, self.url.clone(), self.login.as_ref(), &request).await?;
 
         debug!("transfer RPC response: {}", response);
 
@@ -281,14 +246,8 @@ impl Client {
         };
         let request = Request::new("generate_from_keys", params);
 
-        let response = self
-            .inner
-            .post(self.url.clone())
-            .json(&request)
-            .send()
-            .await?
-            .text()
-            .await?;
+        let response =
+            post_json(&self.inner, self.url.clone(), self.login.as_ref(), &request).await?;
 
         debug!("generate_from_keys RPC response: {}", response);
 
@@ -296,17 +255,28 @@ impl Client {
         Ok(r.result)
     }
 
+    /// Sends all unlocked funds of the primary account to `address`.
+    pub async fn sweep_all(&self, address: &str) -> Result<SweepAll> {
+        let params = SweepAllParams {
+            account_index: 0,
+            address: address.to_owned(),
+        };
+        let request = Request::new("sweep_all", params);
+
+        let response =
+            post_json(&self.inner, self.url.clone(), self.login.as_ref(), &request).await?;
+
+        debug!("sweep_all RPC response: {}", response);
+
+        let r: Response<SweepAll> = serde_json::from_str(&response)?;
+        Ok(r.result)
+    }
+
     pub async fn refresh(&self) -> Result<Refreshed> {
         let request = Request::new("refresh", "");
 
-        let response = self
-            .inner
-            .post(self.url.clone())
-            .json(&request)
-            .send()
-            .await?
-            .text()
-            .await?;
+        let response =
+            post_json(&self.inner, self.url.clone(), self.login.as_ref(), &request).await?;
 
         debug!("refresh RPC response: {}", response);
 
@@ -453,3 +423,16 @@ pub struct Refreshed {
     pub blocks_fetched: u32,
     pub received_money: bool,
 }
+
+#[derive(Serialize, Debug, Clone)]
+struct SweepAllParams {
+    account_index: u32,
+    address: String,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct SweepAll {
+    pub amount_list: Vec<u64>,
+    pub fee_list: Vec<u64>,
+    pub tx_hash_list: Vec<String>,
+}
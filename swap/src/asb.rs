@@ -1,7 +1,10 @@
+pub mod access_control;
 pub mod command;
 pub mod config;
 pub mod fixed_rate;
 pub mod kraken;
+pub mod metrics;
+pub mod rpc;
 
 mod amounts;
 
@@ -0,0 +1,142 @@
+use libp2p::PeerId;
+use std::{
+    collections::{HashMap, HashSet},
+    time::{Duration, Instant},
+};
+
+/// Requests of a peer are counted towards the rate limit within windows of
+/// this length.
+const RATE_LIMIT_WINDOW: Duration = Duration::from_secs(60);
+
+/// Decides whose requests the ASB answers.
+///
+/// Answering a quote request queries the Monero wallet and derives a new
+/// Bitcoin address, so peers are limited to `max_requests_per_minute`.
+#[derive(Debug)]
+pub struct AccessControl {
+    allow_list: HashSet<PeerId>,
+    deny_list: HashSet<PeerId>,
+    max_requests_per_minute: u32,
+    requests: HashMap<PeerId, Window>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Window {
+    start: Instant,
+    count: u32,
+}
+
+#[derive(Debug, Clone, Copy, thiserror::Error, PartialEq)]
+pub enum Refused {
+    #[error("peer {0} is on the deny list")]
+    Denied(PeerId),
+    #[error("peer {0} is not on the allow list")]
+    NotAllowed(PeerId),
+    #[error("peer {peer} exceeded the limit of {max} requests per minute")]
+    RateLimited { peer: PeerId, max: u32 },
+}
+
+impl AccessControl {
+    /// Everyone who is not on the `deny_list` is allowed if the `allow_list`
+    /// is empty.
+    pub fn new(
+        allow_list: Vec<PeerId>,
+        deny_list: Vec<PeerId>,
+        max_requests_per_minute: u32,
+    ) -> Self {
+        Self {
+            allow_list: allow_list.into_iter().collect(),
+            deny_list: deny_list.into_iter().collect(),
+            max_requests_per_minute,
+            requests: HashMap::new(),
+        }
+    }
+
+    pub fn deny_list(&self) -> impl Iterator<Item = &PeerId> {
+        self.deny_list.iter()
+    }
+
+    /// Checks whether a request of `peer` should be answered and counts it
+    /// towards the rate limit of `peer`.
+    pub fn check_request(&mut self, peer: PeerId) -> Result<(), Refused> {
+        self.check_request_at(peer, Instant::now())
+    }
+
+    fn check_request_at(&mut self, peer: PeerId, now: Instant) -> Result<(), Refused> {
+        if self.deny_list.contains(&peer) {
+            return Err(Refused::Denied(peer));
+        }
+
+        if !self.allow_list.is_empty() && !self.allow_list.contains(&peer) {
+            return Err(Refused::NotAllowed(peer));
+        }
+
+        // Forget expired windows so that the map does not grow with every
+        // peer that ever sent a request.
+        self.requests
+            .retain(|_, window| now.duration_since(window.start) < RATE_LIMIT_WINDOW);
+
+        let window = self.requests.entry(peer).or_insert(Window {
+            start: now,
+            count: 0,
+        });
+
+        if window.count >= self.max_requests_per_minute {
+            return Err(Refused::RateLimited {
+                peer,
+                max: self.max_requests_per_minute,
+            });
+        }
+        window.count += 1;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn denied_peers_are_refused_even_if_allowed() {
+        let peer = PeerId::random();
+        let mut access_control = AccessControl::new(vec![peer], vec![peer], 10);
+
+        assert_eq!(
+            access_control.check_request(peer),
+            Err(Refused::Denied(peer))
+        );
+    }
+
+    #[test]
+    fn only_allowed_peers_are_accepted_if_allow_list_is_set() {
+        let allowed = PeerId::random();
+        let other = PeerId::random();
+        let mut access_control = AccessControl::new(vec![allowed], vec![], 10);
+
+        assert_eq!(access_control.check_request(allowed), Ok(()));
+        assert_eq!(
+            access_control.check_request(other),
+            Err(Refused::NotAllowed(other))
+        );
+    }
+
+    #[test]
+    fn requests_are_limited_per_peer_and_window() {
+        let peer = PeerId::random();
+        let other = PeerId::random();
+        let mut access_control = AccessControl::new(vec![], vec![], 2);
+        let start = Instant::now();
+
+        assert_eq!(access_control.check_request_at(peer, start), Ok(()));
+        assert_eq!(access_control.check_request_at(peer, start), Ok(()));
+        assert_eq!(
+            access_control.check_request_at(peer, start),
+            Err(Refused::RateLimited { peer, max: 2 })
+        );
+        assert_eq!(access_control.check_request_at(other, start), Ok(()));
+
+        let next_window = start + RATE_LIMIT_WINDOW;
+        assert_eq!(access_control.check_request_at(peer, next_window), Ok(()));
+    }
+}
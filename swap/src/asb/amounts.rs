@@ -16,6 +16,18 @@ impl Rate {
         ask: bitcoin::Amount::ZERO,
     };
 
+    /// Raises the ask by `spread`, e.g. by 2% for a `spread` of `0.02`.
+    pub fn with_spread(self, spread: Decimal) -> Result<Rate> {
+        let ask = (Decimal::from(self.ask.as_sat()) * (Decimal::from(1) + spread))
+            .round()
+            .to_u64()
+            .ok_or_else(|| anyhow!("ask with spread cannot be represented as u64"))?;
+
+        Ok(Rate {
+            ask: bitcoin::Amount::from_sat(ask),
+        })
+    }
+
     // This function takes the quote amount as it is what Bob sends to Alice in the
     // swap request
     pub fn sell_quote(&self, quote: bitcoin::Amount) -> Result<monero::Amount> {
@@ -70,4 +82,15 @@ mod tests {
 
         assert_eq!(xmr_amount, monero::Amount::from_monero(1000.0).unwrap())
     }
+
+    #[test]
+    fn spread_raises_ask() {
+        let rate = Rate {
+            ask: bitcoin::Amount::from_sat(1_000_000),
+        };
+
+        let with_spread = rate.with_spread(Decimal::new(2, 2)).unwrap();
+
+        assert_eq!(with_spread.ask, bitcoin::Amount::from_sat(1_020_000));
+    }
 }
@@ -1,6 +1,6 @@
 use crate::monero::Amount;
 use anyhow::Result;
-use std::path::PathBuf;
+use std::{net::SocketAddr, path::PathBuf};
 
 #[derive(structopt::StructOpt, Debug)]
 pub struct Arguments {
@@ -11,6 +11,15 @@ pub struct Arguments {
     )]
     pub config: Option<PathBuf>,
 
+    #[structopt(long, help = "Log structured JSON events instead of text.")]
+    pub json: bool,
+
+    #[structopt(
+        long = "log-to-file",
+        help = "Also write the logs to a daily rotating file in the logs directory of the data directory."
+    )]
+    pub log_to_file: bool,
+
     #[structopt(subcommand)]
     pub cmd: Command,
 }
@@ -21,6 +30,18 @@ pub enum Command {
     Start {
         #[structopt(long = "max-sell-xmr", help = "The maximum amount of XMR the ASB is willing to sell.", default_value="0.5", parse(try_from_str = parse_xmr))]
         max_sell: Amount,
+
+        #[structopt(
+            long = "metrics",
+            help = "Serve Prometheus metrics under /metrics on this address, e.g. 127.0.0.1:9100."
+        )]
+        metrics_address: Option<SocketAddr>,
+
+        #[structopt(
+            long = "rpc",
+            help = "Serve the JSON-RPC control API on this address, e.g. 127.0.0.1:9944. Requests must carry the token from the rpc_token file in the data directory."
+        )]
+        rpc_address: Option<SocketAddr>,
     },
     History,
 }
@@ -2,11 +2,15 @@ use crate::fs::{default_data_dir, ensure_directory_exists};
 use anyhow::{Context, Result};
 use config::ConfigError;
 use dialoguer::{theme::ColorfulTheme, Input};
-use libp2p::core::Multiaddr;
-use serde::{Deserialize, Serialize};
+use libp2p::{
+    core::{multiaddr::Protocol, Multiaddr},
+    PeerId,
+};
+use serde::{de::Error as _, Deserialize, Deserializer, Serialize};
 use std::{
     ffi::OsStr,
     fs,
+    net::SocketAddr,
     path::{Path, PathBuf},
 };
 use tracing::info;
@@ -16,6 +20,8 @@ const DEFAULT_LISTEN_ADDRESS: &str = "/ip4/0.0.0.0/tcp/9939";
 const DEFAULT_ELECTRUM_HTTP_URL: &str = "https://blockstream.info/testnet/api/";
 const DEFAULT_ELECTRUM_RPC_URL: &str = "ssl://electrum.blockstream.info:60002";
 const DEFAULT_MONERO_WALLET_RPC_TESTNET_URL: &str = "http://127.0.0.1:38083/json_rpc";
+const DEFAULT_MAX_REQUESTS_PER_MINUTE: u32 = 20;
+const DEFAULT_MAX_CONCURRENT_EXECUTION_SETUPS: usize = 5;
 
 #[derive(Clone, Debug, serde::Serialize, serde::Deserialize, PartialEq)]
 pub struct Config {
@@ -23,6 +29,9 @@ pub struct Config {
     pub network: Network,
     pub bitcoin: Bitcoin,
     pub monero: Monero,
+    pub tor: Option<Tor>,
+    #[serde(default)]
+    pub limits: Limits,
 }
 
 impl Config {
@@ -48,6 +57,19 @@ pub struct Data {
 #[serde(deny_unknown_fields)]
 pub struct Network {
     pub listen: Multiaddr,
+    /// Websocket addresses to listen on in addition to `listen`, e.g.
+    /// `/ip4/0.0.0.0/tcp/9940/ws`, so that takers running in a browser can
+    /// connect. Every address has to end with `/ws`.
+    #[serde(default, deserialize_with = "deserialize_ws_addresses")]
+    pub listen_ws: Vec<Multiaddr>,
+    /// Rendezvous point to announce the ASB at, given as
+    /// `<multiaddr>/p2p/<peer id>`.
+    pub rendezvous_point: Option<Multiaddr>,
+    /// Addresses under which the ASB is reachable from the outside. The
+    /// listen addresses are announced if this is empty, which does not work
+    /// for unspecified ones like `/ip4/0.0.0.0`.
+    #[serde(default)]
+    pub external_addresses: Vec<Multiaddr>,
 }
 
 #[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
@@ -61,6 +83,73 @@ pub struct Bitcoin {
 #[serde(deny_unknown_fields)]
 pub struct Monero {
     pub wallet_rpc_url: Url,
+    /// Hand out our key share of swaps we punished if Bob asks for it, so he
+    /// can still redeem the Monero. We cannot redeem it without his key share.
+    #[serde(default)]
+    pub cooperative_redeem_after_punish: bool,
+}
+
+/// Publish the ASB as a Tor onion service in addition to the listen address.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Tor {
+    /// The control port of the Tor daemon, usually `127.0.0.1:9051`.
+    pub control_port: SocketAddr,
+    /// Password to authenticate with if the daemon is configured with
+    /// `HashedControlPassword`. The auth cookie is used otherwise.
+    pub control_password: Option<String>,
+    /// The port of the onion service, forwarded to the TCP port of the listen
+    /// address.
+    pub onion_port: u16,
+}
+
+/// Protects the ASB against peers that flood it with requests.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Limits {
+    /// Only these peers are answered if the list is not empty.
+    #[serde(with = "crate::serde_peer_id::vec")]
+    pub allow_list: Vec<PeerId>,
+    /// These peers are banned.
+    #[serde(with = "crate::serde_peer_id::vec")]
+    pub deny_list: Vec<PeerId>,
+    /// Quote and bid quote requests answered per peer and minute.
+    pub max_requests_per_minute: u32,
+    /// Execution setups that may be in progress at the same time. Each peer
+    /// can only have one in progress.
+    pub max_concurrent_execution_setups: usize,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            allow_list: vec![],
+            deny_list: vec![],
+            max_requests_per_minute: DEFAULT_MAX_REQUESTS_PER_MINUTE,
+            max_concurrent_execution_setups: DEFAULT_MAX_CONCURRENT_EXECUTION_SETUPS,
+        }
+    }
+}
+
+/// Otherwise a plain TCP address in `listen_ws` silently becomes another TCP
+/// listener.
+fn deserialize_ws_addresses<'de, D>(deserializer: D) -> Result<Vec<Multiaddr>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let addresses = Vec::<Multiaddr>::deserialize(deserializer)?;
+
+    if let Some(address) = addresses
+        .iter()
+        .find(|address| !matches!(address.iter().last(), Some(Protocol::Ws(_))))
+    {
+        return Err(D::Error::custom(format!(
+            "websocket listen address {} does not end with /ws",
+            address
+        )));
+    }
+
+    Ok(addresses)
 }
 
 #[derive(thiserror::Error, Debug, Clone, Copy)]
@@ -144,6 +233,9 @@ pub fn query_user_for_initial_testnet_config() -> Result<Config> {
         data: Data { dir: data_dir },
         network: Network {
             listen: listen_address,
+            listen_ws: vec![],
+            rendezvous_point: None,
+            external_addresses: vec![],
         },
         bitcoin: Bitcoin {
             electrum_http_url,
@@ -151,7 +243,10 @@ pub fn query_user_for_initial_testnet_config() -> Result<Config> {
         },
         monero: Monero {
             wallet_rpc_url: monero_wallet_rpc_url,
+            cooperative_redeem_after_punish: false,
         },
+        tor: None,
+        limits: Limits::default(),
     })
 }
 
@@ -176,11 +271,17 @@ mod tests {
             },
             network: Network {
                 listen: DEFAULT_LISTEN_ADDRESS.parse().unwrap(),
+                listen_ws: vec![],
+                rendezvous_point: None,
+                external_addresses: vec![],
             },
 
             monero: Monero {
                 wallet_rpc_url: Url::from_str(DEFAULT_MONERO_WALLET_RPC_TESTNET_URL).unwrap(),
+                cooperative_redeem_after_punish: false,
             },
+            tor: None,
+            limits: Limits::default(),
         };
 
         initial_setup(config_path.clone(), || Ok(expected.clone())).unwrap();
@@ -188,4 +289,118 @@ mod tests {
 
         assert_eq!(expected, actual);
     }
+
+    #[test]
+    fn rendezvous_point_roundtrip() {
+        let temp_dir = tempdir().unwrap().path().to_path_buf();
+        let config_path = Path::join(&temp_dir, "config.toml");
+
+        let expected = Config {
+            data: Data {
+                dir: Default::default(),
+            },
+            bitcoin: Bitcoin {
+                electrum_http_url: Url::from_str(DEFAULT_ELECTRUM_HTTP_URL).unwrap(),
+                electrum_rpc_url: Url::from_str(DEFAULT_ELECTRUM_RPC_URL).unwrap(),
+            },
+            network: Network {
+                listen: DEFAULT_LISTEN_ADDRESS.parse().unwrap(),
+                listen_ws: vec!["/ip4/0.0.0.0/tcp/9940/ws".parse().unwrap()],
+                rendezvous_point: Some(
+                    "/dns4/rendezvous.example.com/tcp/8888/p2p/12D3KooWCdMKjesXMJz1SiZ7HgotrxuqhQJbP5sgBm2BwP1cqThi"
+                        .parse()
+                        .unwrap(),
+                ),
+                external_addresses: vec!["/dns4/asb.example.com/tcp/9939".parse().unwrap()],
+            },
+
+            monero: Monero {
+                wallet_rpc_url: Url::from_str(DEFAULT_MONERO_WALLET_RPC_TESTNET_URL).unwrap(),
+                cooperative_redeem_after_punish: true,
+            },
+            tor: Some(Tor {
+                control_port: "127.0.0.1:9051".parse().unwrap(),
+                control_password: None,
+                onion_port: 9939,
+            }),
+            limits: Limits::default(),
+        };
+
+        initial_setup(config_path.clone(), || Ok(expected.clone())).unwrap();
+        let actual = read_config(config_path).unwrap().unwrap();
+
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn limits_roundtrip() {
+        let temp_dir = tempdir().unwrap().path().to_path_buf();
+        let config_path = Path::join(&temp_dir, "config.toml");
+
+        let expected = Config {
+            data: Data {
+                dir: Default::default(),
+            },
+            bitcoin: Bitcoin {
+                electrum_http_url: Url::from_str(DEFAULT_ELECTRUM_HTTP_URL).unwrap(),
+                electrum_rpc_url: Url::from_str(DEFAULT_ELECTRUM_RPC_URL).unwrap(),
+            },
+            network: Network {
+                listen: DEFAULT_LISTEN_ADDRESS.parse().unwrap(),
+                listen_ws: vec![],
+                rendezvous_point: None,
+                external_addresses: vec![],
+            },
+
+            monero: Monero {
+                wallet_rpc_url: Url::from_str(DEFAULT_MONERO_WALLET_RPC_TESTNET_URL).unwrap(),
+                cooperative_redeem_after_punish: false,
+            },
+            tor: None,
+            limits: Limits {
+                allow_list: vec![PeerId::random(), PeerId::random()],
+                deny_list: vec![PeerId::random()],
+                max_requests_per_minute: 5,
+                max_concurrent_execution_setups: 1,
+            },
+        };
+
+        initial_setup(config_path.clone(), || Ok(expected.clone())).unwrap();
+        let actual = read_config(config_path).unwrap().unwrap();
+
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn websocket_listen_address_without_ws_is_rejected() {
+        let temp_dir = tempdir().unwrap().path().to_path_buf();
+        let config_path = Path::join(&temp_dir, "config.toml");
+
+        let config = Config {
+            data: Data {
+                dir: Default::default(),
+            },
+            bitcoin: Bitcoin {
+                electrum_http_url: Url::from_str(DEFAULT_ELECTRUM_HTTP_URL).unwrap(),
+                electrum_rpc_url: Url::from_str(DEFAULT_ELECTRUM_RPC_URL).unwrap(),
+            },
+            network: Network {
+                listen: DEFAULT_LISTEN_ADDRESS.parse().unwrap(),
+                listen_ws: vec!["/ip4/0.0.0.0/tcp/9940".parse().unwrap()],
+                rendezvous_point: None,
+                external_addresses: vec![],
+            },
+
+            monero: Monero {
+                wallet_rpc_url: Url::from_str(DEFAULT_MONERO_WALLET_RPC_TESTNET_URL).unwrap(),
+                cooperative_redeem_after_punish: false,
+            },
+            tor: None,
+            limits: Limits::default(),
+        };
+
+        initial_setup(config_path.clone(), || Ok(config.clone())).unwrap();
+
+        assert!(read_config(config_path).is_err());
+    }
 }
@@ -0,0 +1,236 @@
+use crate::{asb::Rate, bitcoin, monero, protocol::alice::AliceState};
+use anyhow::{Context, Result};
+use conquer_once::Lazy;
+use hyper::{
+    header::CONTENT_TYPE,
+    service::{make_service_fn, service_fn},
+    Body, Request, Response, Server, StatusCode,
+};
+use prometheus::{
+    exponential_buckets, Encoder, Gauge, HistogramOpts, HistogramVec, IntCounterVec, IntGauge,
+    IntGaugeVec, Opts, Registry, TextEncoder,
+};
+use rust_decimal::{prelude::ToPrimitive, Decimal};
+use std::{
+    collections::HashMap,
+    convert::Infallible,
+    net::SocketAddr,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+use tracing::info;
+use uuid::Uuid;
+
+/// Metrics are collected whether or not they are served so that the
+/// instrumented code does not have to care.
+static METRICS: Lazy<Metrics> = Lazy::new(|| Metrics::new().expect("metrics to be valid"));
+
+struct Metrics {
+    registry: Registry,
+    quotes_served: IntCounterVec,
+    quotes_rejected: IntCounterVec,
+    swaps: IntGaugeVec,
+    swap_duration: HistogramVec,
+    bitcoin_balance: Gauge,
+    monero_balance: Gauge,
+    rate: Gauge,
+    rate_age: Gauge,
+    connected_peers: IntGauge,
+    /// The state each swap is currently counted in.
+    swap_states: Mutex<HashMap<Uuid, String>>,
+    last_rate_update: Mutex<Option<Instant>>,
+}
+
+impl Metrics {
+    fn new() -> Result<Self> {
+        let registry = Registry::new_custom(Some("asb".to_string()), None)?;
+
+        let quotes_served = IntCounterVec::new(
+            Opts::new("quotes_served_total", "Quotes sent to takers"),
+            &["kind"],
+        )?;
+        let quotes_rejected = IntCounterVec::new(
+            Opts::new("quotes_rejected_total", "Quote requests that were refused"),
+            &["kind", "reason"],
+        )?;
+        let swaps = IntGaugeVec::new(Opts::new("swaps", "Swaps by their current state"), &[
+            "state",
+        ])?;
+        let swap_duration = HistogramVec::new(
+            HistogramOpts::new(
+                "swap_duration_seconds",
+                "Time from the execution setup to the final state of a swap",
+            )
+            .buckets(exponential_buckets(60.0, 2.0, 10)?),
+            &["state"],
+        )?;
+        let bitcoin_balance = Gauge::new("bitcoin_balance_btc", "Balance of the Bitcoin wallet")?;
+        let monero_balance = Gauge::new("monero_balance_xmr", "Balance of the Monero wallet")?;
+        let rate = Gauge::new("rate_btc_per_xmr", "Current ask price of 1 XMR in BTC")?;
+        let rate_age = Gauge::new(
+            "rate_age_seconds",
+            "Time since the rate service last provided a rate",
+        )?;
+        let connected_peers = IntGauge::new("connected_peers", "Peers we are connected to")?;
+
+        registry.register(Box::new(quotes_served.clone()))?;
+        registry.register(Box::new(quotes_rejected.clone()))?;
+        registry.register(Box::new(swaps.clone()))?;
+        registry.register(Box::new(swap_duration.clone()))?;
+        registry.register(Box::new(bitcoin_balance.clone()))?;
+        registry.register(Box::new(monero_balance.clone()))?;
+        registry.register(Box::new(rate.clone()))?;
+        registry.register(Box::new(rate_age.clone()))?;
+        registry.register(Box::new(connected_peers.clone()))?;
+
+        Ok(Self {
+            registry,
+            quotes_served,
+            quotes_rejected,
+            swaps,
+            swap_duration,
+            bitcoin_balance,
+            monero_balance,
+            rate,
+            rate_age,
+            connected_peers,
+            swap_states: Mutex::new(HashMap::new()),
+            last_rate_update: Mutex::new(None),
+        })
+    }
+}
+
+/// Kinds of quotes, used as `kind` label.
+pub const QUOTE: &str = "quote";
+pub const BID_QUOTE: &str = "bid_quote";
+
+pub fn quote_served(kind: &str) {
+    METRICS.quotes_served.with_label_values(&[kind]).inc();
+}
+
+pub fn quote_rejected(kind: &str, reason: &str) {
+    METRICS
+        .quotes_rejected
+        .with_label_values(&[kind, reason])
+        .inc();
+}
+
+/// Moves the swap with `swap_id` from the state it was counted in so far to
+/// `state`.
+pub fn swap_state(swap_id: Uuid, state: &AliceState) {
+    let state = state.to_string();
+    let mut swap_states = METRICS
+        .swap_states
+        .lock()
+        .expect("no other thread to panic while holding the lock");
+
+    if let Some(previous) = swap_states.insert(swap_id, state.clone()) {
+        METRICS.swaps.with_label_values(&[&previous]).dec();
+    }
+    METRICS.swaps.with_label_values(&[&state]).inc();
+}
+
+pub fn swap_finished(state: &AliceState, duration: Duration) {
+    METRICS
+        .swap_duration
+        .with_label_values(&[&state.to_string()])
+        .observe(duration.as_secs_f64());
+}
+
+pub fn balances(bitcoin: bitcoin::Amount, monero: monero::Amount) {
+    METRICS.bitcoin_balance.set(bitcoin.as_btc());
+    METRICS.monero_balance.set(as_xmr(monero));
+}
+
+/// Records the result of asking the rate service for the latest rate.
+pub fn rate(rate: Option<Rate>) {
+    let mut last_rate_update = METRICS
+        .last_rate_update
+        .lock()
+        .expect("no other thread to panic while holding the lock");
+
+    if let Some(rate) = rate {
+        METRICS.rate.set(rate.ask.as_btc());
+        *last_rate_update = Some(Instant::now());
+    }
+
+    if let Some(last_rate_update) = *last_rate_update {
+        METRICS
+            .rate_age
+            .set(last_rate_update.elapsed().as_secs_f64());
+    }
+}
+
+pub fn peer_connected() {
+    METRICS.connected_peers.inc();
+}
+
+pub fn peer_disconnected() {
+    METRICS.connected_peers.dec();
+}
+
+/// Serves the metrics in the Prometheus text format under `/metrics`.
+pub async fn serve(address: SocketAddr) -> Result<()> {
+    let server = Server::try_bind(&address)
+        .with_context(|| format!("Failed to bind metrics endpoint to {}", address))?
+        .serve(make_service_fn(|_| async {
+            Ok::<_, Infallible>(service_fn(handle))
+        }));
+
+    info!("Serving metrics on http://{}/metrics", address);
+    server.await.context("Metrics endpoint failed")?;
+
+    Ok(())
+}
+
+async fn handle(request: Request<Body>) -> Result<Response<Body>, Infallible> {
+    if request.uri().path() != "/metrics" {
+        return Ok(status(StatusCode::NOT_FOUND));
+    }
+
+    let encoder = TextEncoder::new();
+    let mut buffer = Vec::new();
+    if encoder
+        .encode(&METRICS.registry.gather(), &mut buffer)
+        .is_err()
+    {
+        return Ok(status(StatusCode::INTERNAL_SERVER_ERROR));
+    }
+
+    let mut response = Response::new(Body::from(buffer));
+    if let Ok(content_type) = encoder.format_type().parse() {
+        response.headers_mut().insert(CONTENT_TYPE, content_type);
+    }
+
+    Ok(response)
+}
+
+fn status(status: StatusCode) -> Response<Body> {
+    let mut response = Response::new(Body::empty());
+    *response.status_mut() = status;
+    response
+}
+
+fn as_xmr(amount: monero::Amount) -> f64 {
+    (Decimal::from(amount.as_piconero()) / Decimal::from(monero::Amount::ONE_XMR.as_piconero()))
+        .to_f64()
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn swaps_are_counted_in_their_latest_state() {
+        let swap_id = Uuid::new_v4();
+
+        swap_state(swap_id, &AliceState::SafelyAborted);
+        swap_state(swap_id, &AliceState::XmrRefunded);
+
+        let count =
+            |state: &AliceState| METRICS.swaps.with_label_values(&[&state.to_string()]).get();
+        assert_eq!(count(&AliceState::SafelyAborted), 0);
+        assert_eq!(count(&AliceState::XmrRefunded), 1);
+    }
+}
@@ -0,0 +1,138 @@
+use crate::{bitcoin, monero, protocol::alice::event_loop::ControlHandle, rpc};
+use anyhow::Result;
+use rust_decimal::Decimal;
+use serde::{de::Error as _, Deserialize, Deserializer, Serialize};
+use std::net::SocketAddr;
+use uuid::Uuid;
+
+/// The methods of the control API, sent as JSON-RPC 2.0 over HTTP POST:
+///
+/// `{"jsonrpc": "2.0", "id": 1, "method": "set_max_sell", "params": {"xmr":
+/// "0.5"}}`
+#[derive(Debug, Deserialize, PartialEq)]
+#[serde(tag = "method", content = "params", rename_all = "snake_case")]
+pub enum Request {
+    ListSwaps,
+    GetBalances,
+    SetMaxSell {
+        #[serde(deserialize_with = "deserialize_xmr")]
+        xmr: monero::Amount,
+    },
+    /// Raise the ask of the rate service by `spread`, e.g. `0.02` for 2%.
+    SetSpread {
+        #[serde(deserialize_with = "deserialize_spread")]
+        spread: Decimal,
+    },
+    PauseQuoting,
+    ResumeQuoting,
+    Cancel {
+        swap_id: Uuid,
+    },
+    Refund {
+        swap_id: Uuid,
+    },
+    Punish {
+        swap_id: Uuid,
+    },
+}
+
+impl Request {
+    /// The swap a manual intervention is about.
+    pub fn swap_id(&self) -> Option<Uuid> {
+        match self {
+            Request::Cancel { swap_id }
+            | Request::Refund { swap_id }
+            | Request::Punish { swap_id } => Some(*swap_id),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, PartialEq)]
+#[serde(untagged)]
+pub enum Response {
+    Swaps(Vec<SwapSummary>),
+    Balances { bitcoin: String, monero: String },
+    Transaction { txid: bitcoin::Txid, state: String },
+    State { state: String },
+    Done,
+}
+
+#[derive(Debug, Serialize, PartialEq)]
+pub struct SwapSummary {
+    pub swap_id: Uuid,
+    pub state: String,
+}
+
+fn deserialize_xmr<'de, D>(deserializer: D) -> Result<monero::Amount, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let string = String::deserialize(deserializer)?;
+    monero::Amount::parse_monero(&string).map_err(D::Error::custom)
+}
+
+/// A negative spread would sell below the rate, most likely by accident.
+fn deserialize_spread<'de, D>(deserializer: D) -> Result<Decimal, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let spread = Decimal::deserialize(deserializer)?;
+    if spread.is_sign_negative() {
+        return Err(D::Error::custom(format!(
+            "spread must not be negative, got {}",
+            spread
+        )));
+    }
+
+    Ok(spread)
+}
+
+/// Serves the control API. Every request has to carry the `token`.
+pub async fn serve(address: SocketAddr, token: String, control: ControlHandle) -> Result<()> {
+    rpc::serve(address, token, move |request| {
+        let control = control.clone();
+        async move { control.send(request).await }
+    })
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rpc::parse_request;
+    use serde_json::{json, Value};
+
+    #[test]
+    fn parses_methods_with_and_without_params() {
+        assert_eq!(
+            parse_request::<Request>("list_swaps".to_string(), Value::Null).unwrap(),
+            Request::ListSwaps
+        );
+        assert_eq!(
+            parse_request("set_max_sell".to_string(), json!({ "xmr": "0.5" })).unwrap(),
+            Request::SetMaxSell {
+                xmr: monero::Amount::parse_monero("0.5").unwrap()
+            }
+        );
+        assert_eq!(
+            parse_request("set_spread".to_string(), json!({ "spread": "0.02" })).unwrap(),
+            Request::SetSpread {
+                spread: Decimal::new(2, 2)
+            }
+        );
+    }
+
+    #[test]
+    fn rejects_negative_spread() {
+        assert!(
+            parse_request::<Request>("set_spread".to_string(), json!({ "spread": "-0.9" }))
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn rejects_unknown_methods() {
+        assert!(parse_request::<Request>("shutdown".to_string(), Value::Null).is_err());
+    }
+}
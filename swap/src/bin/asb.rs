@@ -14,6 +14,8 @@
 
 use anyhow::{Context, Result};
 use bdk::{descriptor::Segwitv0, keys::DerivableKey};
+use futures::{future, FutureExt};
+use libp2p::core::multiaddr::Protocol;
 use prettytable::{row, Table};
 use std::{path::Path, sync::Arc};
 use structopt::StructOpt;
@@ -24,7 +26,7 @@ use swap::{
             initial_setup, query_user_for_initial_testnet_config, read_config, Config,
             ConfigNotInitialized,
         },
-        kraken,
+        kraken, metrics, rpc,
     },
     bitcoin,
     database::Database,
@@ -33,9 +35,10 @@ use swap::{
     fs::default_config_path,
     monero,
     monero::{Amount, CreateWallet, OpenWallet},
+    network::{handshake::Capabilities, rendezvous::parse_rendezvous_point, tor::TorControl},
     protocol::alice::EventLoop,
     seed::Seed,
-    trace::init_tracing,
+    trace::{init_tracing, Format, LogFile},
 };
 use tracing::{info, warn};
 use tracing_subscriber::filter::LevelFilter;
@@ -49,8 +52,6 @@ const MONERO_NETWORK: monero::Network = monero::Network::Stagenet;
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    init_tracing(LevelFilter::DEBUG).expect("initialize tracing");
-
     let opt = Arguments::from_args();
 
     let config_path = if let Some(config_path) = opt.config {
@@ -67,6 +68,17 @@ async fn main() -> Result<()> {
         }
     };
 
+    let log_file = if opt.log_to_file {
+        Some(LogFile::Daily {
+            dir: config.data.dir.join("logs"),
+            prefix: "asb.log".to_string(),
+        })
+    } else {
+        None
+    };
+    let _log_guard = init_tracing(LevelFilter::DEBUG, Format::new(opt.json), log_file)
+        .expect("initialize tracing");
+
     info!(
         "Database and Seed will be stored in directory: {}",
         config.data.dir.display()
@@ -80,7 +92,11 @@ async fn main() -> Result<()> {
     let wallet_data_dir = config.data.dir.join("wallet");
 
     match opt.cmd {
-        Command::Start { max_sell } => {
+        Command::Start {
+            max_sell,
+            metrics_address,
+            rpc_address,
+        } => {
             let seed = Seed::from_file_or_generate(&config.data.dir)
                 .expect("Could not retrieve/initialize seed");
 
@@ -100,7 +116,42 @@ async fn main() -> Result<()> {
 
             let rate_service = kraken::RateService::new().await?;
 
-            let (event_loop, _) = EventLoop::new(
+            let mut external_addresses = config.network.external_addresses.clone();
+
+            // Tor removes the onion service once the control connection is
+            // closed, keep it open for as long as we run.
+            let _tor_control = match &config.tor {
+                Some(tor) => {
+                    let target_port = config
+                        .network
+                        .listen
+                        .iter()
+                        .find_map(|protocol| match protocol {
+                            Protocol::Tcp(port) => Some(port),
+                            _ => None,
+                        })
+                        .context("An onion service requires a TCP listen address")?;
+
+                    let mut control =
+                        TorControl::connect(tor.control_port, tor.control_password.as_deref())
+                            .await?;
+                    let onion_address = control
+                        .add_onion(
+                            &config.data.dir.join("tor").join("onion_service_key"),
+                            tor.onion_port,
+                            target_port,
+                        )
+                        .await?;
+
+                    info!("Published onion service at {}", onion_address);
+                    external_addresses.push(onion_address);
+
+                    Some(control)
+                }
+                None => None,
+            };
+
+            let (mut event_loop, _) = EventLoop::new(
                 config.network.listen,
                 seed,
                 execution_params,
@@ -109,12 +160,53 @@ async fn main() -> Result<()> {
                 Arc::new(db),
                 rate_service,
                 max_sell,
+                Capabilities::new(BITCOIN_NETWORK, MONERO_NETWORK),
+                config.limits,
             )
             .unwrap();
 
             info!("Our peer id is {}", event_loop.peer_id());
 
-            event_loop.run().await;
+            for address in config.network.listen_ws {
+                event_loop.listen_on(address.clone())?;
+                info!("Listening for websocket connections on {}", address);
+            }
+
+            if let Some(rendezvous_point) = &config.network.rendezvous_point {
+                let (peer_id, address) = parse_rendezvous_point(rendezvous_point)?;
+                event_loop.register_at(peer_id, address, external_addresses)?;
+
+                info!(
+                    "Announcing ourselves at rendezvous point {}",
+                    rendezvous_point
+                );
+            }
+
+            if config.monero.cooperative_redeem_after_punish {
+                event_loop.enable_cooperative_redeem();
+                info!("Handing out our key share of punished swaps on request");
+            }
+
+            let metrics_server = async {
+                match metrics_address {
+                    Some(address) => metrics::serve(address).await,
+                    None => future::pending().await,
+                }
+            };
+
+            let rpc_server = match rpc_address {
+                Some(address) => {
+                    let token = swap::rpc::load_or_generate_token(&config.data.dir)?;
+                    rpc::serve(address, token, event_loop.control_handle()).boxed()
+                }
+                None => future::pending().boxed(),
+            };
+
+            tokio::select! {
+                _ = event_loop.run() => {},
+                result = metrics_server => result?,
+                result = rpc_server => result?,
+            }
         }
         Command::History => {
             let mut table = Table::new();
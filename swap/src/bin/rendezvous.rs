@@ -0,0 +1,73 @@
+#![warn(
+    unused_extern_crates,
+    missing_copy_implementations,
+    rust_2018_idioms,
+    clippy::cast_possible_truncation,
+    clippy::cast_sign_loss,
+    clippy::fallible_impl_from,
+    clippy::cast_precision_loss,
+    clippy::cast_possible_wrap,
+    clippy::dbg_macro
+)]
+#![forbid(unsafe_code)]
+
+use anyhow::{Context, Result};
+use libp2p::core::Multiaddr;
+use std::path::PathBuf;
+use structopt::StructOpt;
+use swap::{
+    fs::default_data_dir,
+    network::rendezvous::Server,
+    seed::Seed,
+    trace::{init_tracing, Format},
+};
+use tracing::info;
+use tracing_subscriber::filter::LevelFilter;
+
+const DEFAULT_LISTEN_ADDRESS: &str = "/ip4/0.0.0.0/tcp/8888";
+
+#[derive(structopt::StructOpt, Debug)]
+#[structopt(
+    name = "rendezvous",
+    about = "Rendezvous point for XMR/BTC swap sellers"
+)]
+pub struct Arguments {
+    #[structopt(long = "listen", default_value = DEFAULT_LISTEN_ADDRESS)]
+    pub listen: Multiaddr,
+
+    #[structopt(
+        long = "data-dir",
+        help = "Directory of the seed the peer id is derived from.",
+        parse(from_os_str)
+    )]
+    pub data_dir: Option<PathBuf>,
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let _log_guard =
+        init_tracing(LevelFilter::INFO, Format::Text, None).expect("initialize tracing");
+
+    let args = Arguments::from_args();
+
+    let data_dir = match args.data_dir {
+        Some(data_dir) => data_dir,
+        None => default_data_dir()
+            .context("No default data dir value for this system")?
+            .join("rendezvous"),
+    };
+    let seed =
+        Seed::from_file_or_generate(&data_dir).context("Could not retrieve/initialize seed")?;
+
+    let server = Server::new(&seed.derive_libp2p_identity(), args.listen.clone())?;
+
+    info!(
+        "Rendezvous point listening on {}/p2p/{}",
+        args.listen,
+        server.peer_id()
+    );
+
+    server.run().await;
+
+    Ok(())
+}
@@ -12,10 +12,15 @@
 #![forbid(unsafe_code)]
 #![allow(non_snake_case)]
 
-use anyhow::{Context, Result};
+use anyhow::{bail, Context, Result};
+use libp2p::{Multiaddr, PeerId};
+use monero_rpc::wallet;
 use prettytable::{row, Table};
-use reqwest::Url;
-use std::{path::Path, sync::Arc, time::Duration};
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
 use structopt::StructOpt;
 use swap::{
     bitcoin,
@@ -23,20 +28,30 @@ use swap::{
     cli::{
         command::{Arguments, Command},
         config::{read_config, Config},
+        daemon::Daemon,
+        list_sellers::{list_sellers, Status},
+        watch,
     },
     database::Database,
     execution_params,
     execution_params::GetExecutionParams,
     monero,
     monero::{CreateWallet, OpenWallet},
+    network::{handshake::Capabilities, rendezvous},
     protocol::{
         bob,
-        bob::{cancel::CancelError, Builder, EventLoop},
+        bob::{abort::AbortError, cancel::CancelError, AbortHandle, BobState, Builder, EventLoop},
+        progress::ProgressEvent,
     },
+    rpc,
     seed::Seed,
+    trace::{init_cli_tracing, Format, LogFile},
+};
+use tokio::{
+    signal,
+    sync::{broadcast, broadcast::error::RecvError},
 };
-use tracing::{debug, error, info, warn, Level};
-use tracing_subscriber::FmtSubscriber;
+use tracing::{debug, error, info, warn};
 use uuid::Uuid;
 
 #[macro_use]
@@ -47,38 +62,22 @@ const MONERO_BLOCKCHAIN_MONITORING_WALLET_NAME: &str = "swap-tool-blockchain-mon
 #[tokio::main]
 async fn main() -> Result<()> {
     let args = Arguments::from_args();
-
-    let is_terminal = atty::is(atty::Stream::Stderr);
-    let base_subscriber = |level| {
-        FmtSubscriber::builder()
-            .with_writer(std::io::stderr)
-            .with_ansi(is_terminal)
-            .with_target(false)
-            .with_env_filter(format!("swap={}", level))
-    };
-
-    if args.debug {
-        let subscriber = base_subscriber(Level::DEBUG)
-            .with_timer(tracing_subscriber::fmt::time::ChronoLocal::with_format(
-                "%F %T".to_owned(),
-            ))
-            .finish();
-
-        tracing::subscriber::set_global_default(subscriber)?;
-    } else {
-        let subscriber = base_subscriber(Level::INFO)
-            .without_time()
-            .with_level(false)
-            .finish();
-
-        tracing::subscriber::set_global_default(subscriber)?;
-    }
+    let cmd = args.cmd.unwrap_or_default();
 
     let config = match args.config {
         Some(config_path) => read_config(config_path)??,
         None => Config::testnet(),
     };
 
+    // Generated up front so that the log file of a new swap is named after it.
+    let new_swap_id = Uuid::new_v4();
+    let log_file = if args.log_to_file {
+        log_file(&cmd, new_swap_id, config.data.dir.join("logs"))
+    } else {
+        None
+    };
+    let _log_guard = init_cli_tracing(args.debug, Format::new(args.json), log_file)?;
+
     debug!(
         "Database and seed will be stored in {}",
         config.data.dir.display()
@@ -96,24 +95,67 @@ async fn main() -> Result<()> {
     let monero_network = monero::Network::Stagenet;
     let execution_params = execution_params::Testnet::get_execution_params();
 
-    let monero_wallet_rpc = monero::WalletRpc::new(config.data.dir.join("monero")).await?;
+    let monero_daemon = Arc::new(init_monero_daemon(&config.monero.daemon, monero_network).await?);
+
+    // The process has to outlive the swap, monero-wallet-rpc is killed on drop.
+    let (monero_wallet, _monero_wallet_rpc_process) = match &config.monero.wallet_rpc_url {
+        Some(url) => {
+            let client = match config.monero.wallet_rpc_login.clone() {
+                Some(login) => wallet::Client::new_with_login(url.clone(), login.into()),
+                None => wallet::Client::new(url.clone()),
+            };
+            let monero_wallet = monero::Wallet::new_with_client(
+                client,
+                monero_network,
+                MONERO_BLOCKCHAIN_MONITORING_WALLET_NAME.to_string(),
+            );
+
+            (monero_wallet, None)
+        }
+        None => {
+            let monero_wallet_rpc_process = init_monero_wallet_rpc(&config)
+                .await?
+                .run(monero_network, &config.monero.daemon)
+                .await?;
+            let monero_wallet = monero::Wallet::new(
+                monero_wallet_rpc_process.endpoint(),
+                monero_network,
+                MONERO_BLOCKCHAIN_MONITORING_WALLET_NAME.to_string(),
+            )
+            .with_rpc_status(monero_wallet_rpc_process.status());
+
+            (monero_wallet, Some(monero_wallet_rpc_process))
+        }
+    };
 
-    let monero_wallet_rpc_process = monero_wallet_rpc
-        .run(monero_network, "stagenet.community.xmr.to")
-        .await?;
+    let socks5_proxy = config.tor.map(|tor| tor.socks5_proxy);
 
-    match args.cmd.unwrap_or_default() {
+    match cmd {
         Command::BuyXmr {
             alice_peer_id,
             alice_addr,
+            receive_address,
         } => {
+            if let Some(receive_address) = receive_address {
+                if receive_address.network != monero_network {
+                    bail!(
+                        "The receive address {} is not a {:?} address",
+                        receive_address,
+                        monero_network
+                    );
+                }
+            }
+
             let bitcoin_wallet =
                 init_bitcoin_wallet(config, bitcoin_network, &wallet_data_dir, seed).await?;
-            let monero_wallet =
-                init_monero_wallet(monero_network, monero_wallet_rpc_process.endpoint()).await?;
+            let monero_wallet = init_monero_wallet(monero_wallet).await?;
             let bitcoin_wallet = Arc::new(bitcoin_wallet);
 
-            let swap_id = Uuid::new_v4();
+            let swap_id = new_swap_id;
+            // Saved up front so that resuming the swap sends the Monero there too.
+            if let Some(receive_address) = receive_address {
+                db.insert_monero_address(swap_id, receive_address).await?;
+            }
 
             // TODO: Also wait for more funds if balance < dust
             if bitcoin_wallet.balance().await? == Amount::ZERO {
@@ -141,21 +183,28 @@ async fn main() -> Result<()> {
             let (event_loop, event_loop_handle) = EventLoop::new(
                 &seed.derive_libp2p_identity(),
                 alice_peer_id,
-                alice_addr,
+                alice_addr.clone(),
                 bitcoin_wallet.clone(),
+                db.clone(),
+                socks5_proxy,
+                Capabilities::new(bitcoin_network, monero_network),
+                execution_params,
             )?;
+            let abort_handle = event_loop.abort_handle();
             let handle = tokio::spawn(event_loop.run());
 
-            let swap = Builder::new(
-                db,
+            let builder = Builder::new(
+                db.clone(),
                 swap_id,
                 bitcoin_wallet.clone(),
                 Arc::new(monero_wallet),
+                monero_daemon.clone(),
                 execution_params,
                 event_loop_handle,
             )
-            .with_init_params(send_bitcoin)
-            .build()?;
+            .with_init_params(send_bitcoin);
+            tokio::spawn(print_progress(builder.subscribe()));
+            let swap = builder.build()?;
 
             let swap = bob::run(swap);
             tokio::select! {
@@ -165,6 +214,11 @@ async fn main() -> Result<()> {
                 swap_result = swap => {
                     swap_result?;
                 }
+                interrupted = signal::ctrl_c() => {
+                    interrupted?;
+                    stop_swap(swap_id, db, bitcoin_wallet, abort_handle, alice_peer_id, alice_addr)
+                        .await?;
+                }
             }
         }
         Command::History => {
@@ -186,27 +240,59 @@ async fn main() -> Result<()> {
         } => {
             let bitcoin_wallet =
                 init_bitcoin_wallet(config, bitcoin_network, &wallet_data_dir, seed).await?;
-            let monero_wallet =
-                init_monero_wallet(monero_network, monero_wallet_rpc_process.endpoint()).await?;
             let bitcoin_wallet = Arc::new(bitcoin_wallet);
 
+            // Alice is not needed anymore to get the Bitcoin back.
+            let state = db.get_state(swap_id)?.try_into_bob()?.into();
+            if watch::is_cancel_timelock_expired(&state, &bitcoin_wallet).await? {
+                // Cancelling fails for good once Alice redeemed the Bitcoin.
+                if let Some(state5) = watch::btc_redeemed(&state, &bitcoin_wallet).await? {
+                    let monero_wallet = init_monero_wallet(monero_wallet).await?;
+                    let state =
+                        watch::redeem_xmr(swap_id, state5, Arc::new(monero_wallet), db).await?;
+                    info!("Swap {} is in state {}", swap_id, state);
+
+                    return Ok(());
+                }
+
+                info!(
+                    "The cancel timelock of swap {} expired, refunding the Bitcoin",
+                    swap_id
+                );
+                let state =
+                    watch::cancel_and_refund(swap_id, state, execution_params, bitcoin_wallet, db)
+                        .await?;
+                info!("Swap {} is in state {}", swap_id, state);
+
+                return Ok(());
+            }
+
+            let monero_wallet = init_monero_wallet(monero_wallet).await?;
+
             let (event_loop, event_loop_handle) = EventLoop::new(
                 &seed.derive_libp2p_identity(),
                 alice_peer_id,
-                alice_addr,
+                alice_addr.clone(),
                 bitcoin_wallet.clone(),
+                db.clone(),
+                socks5_proxy,
+                Capabilities::new(bitcoin_network, monero_network),
+                execution_params,
             )?;
+            let abort_handle = event_loop.abort_handle();
             let handle = tokio::spawn(event_loop.run());
 
-            let swap = Builder::new(
-                db,
+            let builder = Builder::new(
+                db.clone(),
                 swap_id,
                 bitcoin_wallet.clone(),
                 Arc::new(monero_wallet),
+                monero_daemon.clone(),
                 execution_params,
                 event_loop_handle,
-            )
-            .build()?;
+            );
+            tokio::spawn(print_progress(builder.subscribe()));
+            let swap = builder.build()?;
 
             let swap = bob::run(swap);
             tokio::select! {
@@ -216,19 +302,30 @@ async fn main() -> Result<()> {
                 swap_result = swap => {
                     swap_result?;
                 }
+                interrupted = signal::ctrl_c() => {
+                    interrupted?;
+                    stop_swap(swap_id, db, bitcoin_wallet, abort_handle, alice_peer_id, alice_addr)
+                        .await?;
+                }
             }
         }
         Command::Cancel { swap_id, force } => {
             let bitcoin_wallet =
                 init_bitcoin_wallet(config, bitcoin_network, &wallet_data_dir, seed).await?;
+            let bitcoin_wallet = Arc::new(bitcoin_wallet);
 
             let resume_state = db.get_state(swap_id)?.try_into_bob()?.into();
             let cancel =
-                bob::cancel(swap_id, resume_state, Arc::new(bitcoin_wallet), db, force).await?;
+                bob::cancel(swap_id, resume_state, bitcoin_wallet.clone(), db, force).await?;
 
             match cancel {
-                Ok((txid, _)) => {
-                    debug!("Cancel transaction successfully published with id {}", txid)
+                Ok((txid, state)) => {
+                    debug!("Cancel transaction successfully published with id {}", txid);
+
+                    if let BobState::BtcCancelled(state4) = state {
+                        bob::refund::warn_about_punish_timelock(swap_id, &state4, &bitcoin_wallet)
+                            .await?;
+                    }
                 }
                 Err(CancelError::CancelTimelockNotExpiredYet) => error!(
                     "The Cancel Transaction cannot be published yet, \
@@ -245,7 +342,7 @@ async fn main() -> Result<()> {
 
             let resume_state = db.get_state(swap_id)?.try_into_bob()?.into();
 
-            bob::refund(
+            let state = bob::refund(
                 swap_id,
                 resume_state,
                 execution_params,
@@ -254,11 +351,212 @@ async fn main() -> Result<()> {
                 force,
             )
             .await??;
+
+            if let BobState::BtcPunished { .. } = state {
+                bail!(
+                    "The Bitcoin of swap {} was taken by Alice, the Monero can only be redeemed with `cooperative-redeem` if she agrees",
+                    swap_id
+                );
+            }
+        }
+        Command::CooperativeRedeem {
+            swap_id,
+            alice_peer_id,
+            alice_addr,
+        } => {
+            let bitcoin_wallet =
+                init_bitcoin_wallet(config, bitcoin_network, &wallet_data_dir, seed).await?;
+            let monero_wallet = init_monero_wallet(monero_wallet).await?;
+
+            let state = db.get_state(swap_id)?.try_into_bob()?.into();
+
+            let (event_loop, mut event_loop_handle) = EventLoop::new(
+                &seed.derive_libp2p_identity(),
+                alice_peer_id,
+                alice_addr,
+                Arc::new(bitcoin_wallet),
+                db.clone(),
+                socks5_proxy,
+                Capabilities::new(bitcoin_network, monero_network),
+                execution_params,
+            )?;
+            let handle = tokio::spawn(event_loop.run());
+
+            let redeem = bob::cooperative_redeem(
+                swap_id,
+                state,
+                &mut event_loop_handle,
+                Arc::new(monero_wallet),
+                db,
+            );
+            tokio::select! {
+                event_loop_result = handle => {
+                    event_loop_result??;
+                },
+                redeem_result = redeem => {
+                    match redeem_result? {
+                        Ok(state) => info!("Swap {} is in state {}", swap_id, state),
+                        Err(reason) => bail!("Alice refused to hand out her key share of swap {}: {}", swap_id, reason),
+                    }
+                }
+            }
+        }
+        Command::Watch => {
+            let bitcoin_wallet =
+                init_bitcoin_wallet(config, bitcoin_network, &wallet_data_dir, seed).await?;
+            let monero_wallet = init_monero_wallet(monero_wallet).await?;
+
+            watch::watch(
+                db,
+                Arc::new(bitcoin_wallet),
+                Arc::new(monero_wallet),
+                execution_params,
+            )
+            .await;
+        }
+        Command::ListSellers { rendezvous_point } => {
+            let sellers = list_sellers(
+                &rendezvous_point,
+                rendezvous::XMR_BTC_NAMESPACE,
+                socks5_proxy,
+            )
+            .await?;
+
+            let mut table = Table::new();
+
+            table.add_row(row!["PEER ID", "ADDRESS", "PRICE", "MIN", "MAX"]);
+
+            for seller in sellers {
+                let addresses = seller
+                    .addresses
+                    .iter()
+                    .map(|address| address.to_string())
+                    .collect::<Vec<_>>()
+                    .join("\n");
+
+                match seller.status {
+                    Status::Online(quote) => table.add_row(row![
+                        seller.peer_id,
+                        addresses,
+                        format!("{}/XMR", quote.price),
+                        quote.min_quantity,
+                        quote.max_quantity
+                    ]),
+                    Status::Unreachable => {
+                        table.add_row(row![seller.peer_id, addresses, "unreachable", "", ""])
+                    }
+                };
+            }
+
+            // Print the table to stdout
+            table.printstd();
+        }
+        Command::Daemon {
+            rpc_address,
+            websocket_address,
+        } => {
+            let token = rpc::load_or_generate_token(&config.data.dir)?;
+            let bitcoin_wallet =
+                init_bitcoin_wallet(config, bitcoin_network, &wallet_data_dir, seed).await?;
+            let monero_wallet = init_monero_wallet(monero_wallet).await?;
+
+            let daemon = Daemon::new(
+                db,
+                seed,
+                Arc::new(bitcoin_wallet),
+                Arc::new(monero_wallet),
+                monero_daemon,
+                execution_params,
+                Capabilities::new(bitcoin_network, monero_network),
+                socks5_proxy,
+            );
+
+            daemon.run(rpc_address, websocket_address, token).await?;
         }
     };
     Ok(())
 }
 
+/// Commands that act on a swap log to a file of that swap, long running
+/// commands log to a daily rotating file.
+fn log_file(cmd: &Command, new_swap_id: Uuid, dir: PathBuf) -> Option<LogFile> {
+    match cmd {
+        Command::BuyXmr { .. } => Some(LogFile::Swap {
+            dir,
+            swap_id: new_swap_id,
+        }),
+        Command::Resume { swap_id, .. }
+        | Command::Cancel { swap_id, .. }
+        | Command::Refund { swap_id, .. }
+        | Command::CooperativeRedeem { swap_id, .. } => Some(LogFile::Swap {
+            dir,
+            swap_id: *swap_id,
+        }),
+        Command::Daemon { .. } => Some(LogFile::Daily {
+            dir,
+            prefix: "daemon.log".to_string(),
+        }),
+        Command::Watch => Some(LogFile::Daily {
+            dir,
+            prefix: "watch.log".to_string(),
+        }),
+        Command::History | Command::ListSellers { .. } => None,
+    }
+}
+
+/// Called when the user interrupts a running swap. A swap that did not lock
+/// any Bitcoin yet is aborted, otherwise it stays in its last saved state and
+/// has to be resumed.
+async fn stop_swap(
+    swap_id: Uuid,
+    db: Database,
+    bitcoin_wallet: Arc<bitcoin::Wallet>,
+    abort_handle: AbortHandle,
+    alice_peer_id: PeerId,
+    alice_addr: Multiaddr,
+) -> Result<()> {
+    let state = match db.get_state(swap_id) {
+        Ok(state) => state.try_into_bob()?.into(),
+        Err(_) => {
+            info!("Swap {} was stopped before it started", swap_id);
+            return Ok(());
+        }
+    };
+
+    match bob::abort(swap_id, state, bitcoin_wallet, db).await? {
+        Ok(tx_lock_id) => {
+            // Otherwise Alice only gives up once the time to lock the Bitcoin is over.
+            if let Some(tx_lock_id) = tx_lock_id {
+                if let Err(error) = abort_handle.abort(tx_lock_id).await {
+                    warn!("Failed to tell Alice that the swap is aborted: {:#}", error);
+                }
+            }
+
+            info!("Swap {} is aborted, no Bitcoin was locked", swap_id);
+        }
+        Err(AbortError::BtcAlreadyLocked) => {
+            info!(
+                "The Bitcoin of swap {} may already be locked. Resume the swap with:\n\n    \
+                 swap_cli resume --swap-id {} --counterpart-peer-id {} --counterpart-addr {}\n",
+                swap_id, swap_id, alice_peer_id, alice_addr
+            );
+        }
+    }
+
+    Ok(())
+}
+
+/// Logs the progress of a swap until it is finished.
+async fn print_progress(mut progress: broadcast::Receiver<ProgressEvent>) {
+    loop {
+        match progress.recv().await {
+            Ok(event) => info!("{}", event.progress),
+            Err(RecvError::Lagged(missed)) => debug!("Missed {} progress events", missed),
+            Err(RecvError::Closed) => break,
+        }
+    }
+}
+
 async fn init_bitcoin_wallet(
     config: Config,
     bitcoin_network: bitcoin::Network,
@@ -282,16 +580,43 @@ async fn init_bitcoin_wallet(
     Ok(bitcoin_wallet)
 }
 
-async fn init_monero_wallet(
+async fn init_monero_wallet_rpc(config: &Config) -> Result<monero::WalletRpc> {
+    let working_dir = config.data.dir.join("monero");
+
+    let monero_wallet_rpc = match (
+        &config.monero.wallet_rpc_path,
+        &config.monero.wallet_rpc_download_url,
+    ) {
+        (Some(exec_path), _) => monero::WalletRpc::from_executable(working_dir, exec_path).await?,
+        (None, Some(url)) => {
+            monero::WalletRpc::download(working_dir, monero::Download::mirror(url.clone())?).await?
+        }
+        (None, None) => monero::WalletRpc::new(working_dir).await?,
+    };
+
+    Ok(monero_wallet_rpc)
+}
+
+async fn init_monero_daemon(
+    connection: &monero::DaemonConnection,
     monero_network: monero::Network,
-    monero_wallet_rpc_url: Url,
-) -> Result<monero::Wallet> {
-    let monero_wallet = monero::Wallet::new(
-        monero_wallet_rpc_url.clone(),
-        monero_network,
-        MONERO_BLOCKCHAIN_MONITORING_WALLET_NAME.to_string(),
-    );
+) -> Result<monero::Daemon> {
+    let monero_daemon = monero::Daemon::new(connection)?;
+
+    monero_daemon
+        .ensure_network(monero_network)
+        .await
+        .with_context(|| {
+            format!(
+                "Monero daemon at {}:{} cannot be used",
+                connection.host, connection.port
+            )
+        })?;
+
+    Ok(monero_daemon)
+}
 
+async fn init_monero_wallet(monero_wallet: monero::Wallet) -> Result<monero::Wallet> {
     // Setup the temporary Monero wallet necessary for monitoring the blockchain
     let open_monitoring_wallet_response = monero_wallet
         .open_wallet(MONERO_BLOCKCHAIN_MONITORING_WALLET_NAME)
@@ -303,7 +628,7 @@ async fn init_monero_wallet(
             .context(format!(
                 "Unable to create Monero wallet for blockchain monitoring.\
              Please ensure that the monero-wallet-rpc is available at {}",
-                monero_wallet_rpc_url
+                monero_wallet.rpc_url().await
             ))?;
 
         debug!(
@@ -45,6 +45,14 @@ use std::str::FromStr;
 // runs.
 pub const TX_FEE: u64 = 15_000;
 
+/// Outputs worth less than this are not relayed by Bitcoin nodes.
+pub const DUST_AMOUNT: u64 = 546;
+
+/// The smallest amount of Bitcoin that can be swapped. After the cancel
+/// transaction the refund or punish transaction has to pay its fee too and
+/// still leave more than dust.
+pub const MIN_LOCK_AMOUNT: u64 = 2 * TX_FEE + DUST_AMOUNT;
+
 #[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
 pub struct SecretKey {
     inner: Scalar,
@@ -2,7 +2,7 @@ use crate::{
     bitcoin::{timelocks::BlockHeight, Address, Amount, Transaction},
     execution_params::ExecutionParams,
 };
-use ::bitcoin::{util::psbt::PartiallySignedTransaction, Txid};
+use ::bitcoin::{util::psbt::PartiallySignedTransaction, OutPoint, Txid};
 use anyhow::{anyhow, bail, Context, Result};
 use backoff::{backoff::Constant as ConstantBackoff, future::retry};
 use bdk::{
@@ -13,7 +13,7 @@ use bdk::{
     FeeRate, KeychainKind,
 };
 use bitcoin::Script;
-use reqwest::{Method, Url};
+use reqwest::{Method, StatusCode, Url};
 use serde::{Deserialize, Serialize};
 use std::{path::Path, sync::Arc, time::Duration};
 use tokio::{sync::Mutex, time::interval};
@@ -206,6 +206,18 @@ impl Wallet {
         Ok(tx)
     }
 
+    /// Whether the Electrum server knows the transaction, either from its
+    /// mempool or from a block.
+    pub async fn is_published(&self, txid: Txid) -> Result<bool> {
+        let client = Client::new(self.rpc_url.as_ref()).map_err(Error::ElectrumClient)?;
+
+        match client.transaction_get(&txid) {
+            Ok(_) => Ok(true),
+            Err(electrum_client::Error::Protocol(_)) => Ok(false),
+            Err(err) => Err(Error::ElectrumClient(err).into()),
+        }
+    }
+
     pub async fn get_block_height(&self) -> Result<BlockHeight> {
         let url = blocks_tip_height_url(&self.http_url)?;
         let height = retry(ConstantBackoff::new(Duration::from_secs(1)), || async {
@@ -258,15 +270,67 @@ impl Wallet {
         Ok(BlockHeight::new(height))
     }
 
+    /// The id of the transaction that spends the given output, `None` if it
+    /// is unspent or does not exist.
+    pub async fn spending_transaction(&self, outpoint: OutPoint) -> Result<Option<Txid>> {
+        let url = tx_outspend_url(outpoint, &self.http_url)?;
+        #[derive(Serialize, Deserialize, Debug, Clone)]
+        struct OutputStatus {
+            spent: bool,
+            txid: Option<Txid>,
+        }
+        let status = retry(ConstantBackoff::new(Duration::from_secs(1)), || async {
+            let resp = reqwest::Client::new()
+                .request(Method::GET, url.clone())
+                .send()
+                .await
+                .map_err(|err| backoff::Error::Transient(Error::Io(err)))?;
+
+            if resp.status() == StatusCode::NOT_FOUND {
+                return Ok(None);
+            }
+
+            let status: OutputStatus = resp
+                .json()
+                .await
+                .map_err(|err| backoff::Error::Permanent(Error::JsonDeserialization(err)))?;
+
+            Result::<_, backoff::Error<Error>>::Ok(Some(status))
+        })
+        .await
+        .context("transient errors to be retried")?;
+
+        match status {
+            Some(OutputStatus {
+                spent: true,
+                txid: Some(txid),
+            }) => Ok(Some(txid)),
+            _ => Ok(None),
+        }
+    }
+
     pub async fn wait_for_transaction_finality(
         &self,
         txid: Txid,
         execution_params: ExecutionParams,
+    ) -> Result<()> {
+        self.wait_for_transaction_finality_with_progress(txid, execution_params, |_| {})
+            .await
+    }
+
+    /// Like [`Wallet::wait_for_transaction_finality`] but calls `progress`
+    /// with the number of confirmations whenever it changes.
+    pub async fn wait_for_transaction_finality_with_progress(
+        &self,
+        txid: Txid,
+        execution_params: ExecutionParams,
+        mut progress: impl FnMut(u32) + Send,
     ) -> Result<()> {
         tracing::debug!("waiting for tx finality: {}", txid);
         // Divide by 4 to not check too often yet still be aware of the new block early
         // on.
         let mut interval = interval(execution_params.bitcoin_avg_block_time / 4);
+        let mut last_confirmations = None;
 
         loop {
             let tx_block_height = self.transaction_block_height(txid).await?;
@@ -279,7 +343,12 @@ impl Wallet {
                     .expect("transaction must be included in block with height >= 1"),
             ) {
                 tracing::debug!("confirmations: {:?}", confirmations);
-                if u32::from(confirmations) >= execution_params.bitcoin_finality_confirmations {
+                let confirmations = u32::from(confirmations);
+                if last_confirmations != Some(confirmations) {
+                    progress(confirmations);
+                    last_confirmations = Some(confirmations);
+                }
+                if confirmations >= execution_params.bitcoin_finality_confirmations {
                     break;
                 }
             }
@@ -302,6 +371,11 @@ fn tx_status_url(txid: Txid, base_url: &Url) -> Result<Url> {
     Ok(url)
 }
 
+fn tx_outspend_url(outpoint: OutPoint, base_url: &Url) -> Result<Url> {
+    let url = base_url.join(&format!("tx/{}/outspend/{}", outpoint.txid, outpoint.vout))?;
+    Ok(url)
+}
+
 fn blocks_tip_height_url(base_url: &Url) -> Result<Url> {
     let url = base_url.join("blocks/tip/height")?;
     Ok(url)
@@ -311,11 +385,12 @@ fn blocks_tip_height_url(base_url: &Url) -> Result<Url> {
 mod tests {
     use crate::{
         bitcoin::{
-            wallet::{blocks_tip_height_url, tx_status_url},
+            wallet::{blocks_tip_height_url, tx_outspend_url, tx_status_url},
             Txid,
         },
         cli::config::DEFAULT_ELECTRUM_HTTP_URL,
     };
+    use ::bitcoin::OutPoint;
     use reqwest::Url;
 
     #[test]
@@ -327,6 +402,18 @@ mod tests {
         assert_eq!(url.as_str(), expected);
     }
 
+    #[test]
+    fn create_tx_outspend_url_from_default_base_url_success() {
+        let outpoint = OutPoint::new(Txid::default(), 0);
+        let base_url = Url::parse(DEFAULT_ELECTRUM_HTTP_URL).expect("Could not parse url");
+        let url = tx_outspend_url(outpoint, &base_url).expect("Could not create url");
+        let expected = format!(
+            "https://blockstream.info/testnet/api/tx/{}/outspend/0",
+            outpoint.txid
+        );
+        assert_eq!(url.as_str(), expected);
+    }
+
     #[test]
     fn create_block_tip_height_url_from_default_base_url_success() {
         let base_url = Url::parse(DEFAULT_ELECTRUM_HTTP_URL).expect("Could not parse url");
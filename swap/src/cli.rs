@@ -1,2 +1,5 @@
 pub mod command;
 pub mod config;
+pub mod daemon;
+pub mod list_sellers;
+pub mod watch;
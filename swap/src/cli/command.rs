@@ -1,9 +1,12 @@
+use crate::monero;
 use libp2p::{core::Multiaddr, PeerId};
-use std::path::PathBuf;
+use std::{net::SocketAddr, path::PathBuf};
 use uuid::Uuid;
 
 pub const DEFAULT_ALICE_MULTIADDR: &str = "/dns4/xmr-btc-asb.coblox.tech/tcp/9876";
 pub const DEFAULT_ALICE_PEER_ID: &str = "12D3KooWCdMKjesXMJz1SiZ7HgotrxuqhQJbP5sgBm2BwP1cqThi";
+pub const DEFAULT_DAEMON_RPC_ADDRESS: &str = "127.0.0.1:9955";
+pub const DEFAULT_DAEMON_WEBSOCKET_ADDRESS: &str = "127.0.0.1:9956";
 
 #[derive(structopt::StructOpt, Debug)]
 pub struct Arguments {
@@ -17,6 +20,15 @@ pub struct Arguments {
     #[structopt(long, help = "Activate debug logging.")]
     pub debug: bool,
 
+    #[structopt(long, help = "Log structured JSON events instead of text.")]
+    pub json: bool,
+
+    #[structopt(
+        long = "log-to-file",
+        help = "Also write the logs to a file in the logs directory of the data directory, one per swap."
+    )]
+    pub log_to_file: bool,
+
     #[structopt(subcommand)]
     pub cmd: Option<Command>,
 }
@@ -33,6 +45,12 @@ pub enum Command {
         default_value = DEFAULT_ALICE_MULTIADDR
         )]
         alice_addr: Multiaddr,
+
+        #[structopt(
+            long = "receive-address",
+            help = "The Monero address to send the redeemed Monero to. It is kept in a wallet of its own otherwise."
+        )]
+        receive_address: Option<monero::Address>,
     },
     History,
     Resume {
@@ -64,6 +82,45 @@ pub enum Command {
         #[structopt(short, long)]
         force: bool,
     },
+    /// Ask Alice for her key share of a swap she punished and redeem the
+    /// Monero with it. Only works if Alice agrees to it.
+    CooperativeRedeem {
+        #[structopt(long = "swap-id")]
+        swap_id: Uuid,
+
+        #[structopt(long = "counterpart-peer-id", default_value = DEFAULT_ALICE_PEER_ID)]
+        alice_peer_id: PeerId,
+
+        #[structopt(
+        long = "counterpart-addr",
+        default_value = DEFAULT_ALICE_MULTIADDR
+        )]
+        alice_addr: Multiaddr,
+    },
+    /// Keep running in the background and watch all unfinished swaps. Their
+    /// Bitcoin is refunded as soon as the cancel timelock expires.
+    Watch,
+    /// Discover sellers at a rendezvous point and show their offers.
+    ListSellers {
+        #[structopt(
+            long = "rendezvous-point",
+            help = "Address of the rendezvous point including its peer id, e.g. /dns4/example.com/tcp/8888/p2p/<peer id>"
+        )]
+        rendezvous_point: Multiaddr,
+    },
+    /// Run in the background and execute swaps on behalf of JSON-RPC clients
+    /// such as a desktop UI.
+    Daemon {
+        #[structopt(long = "rpc", default_value = DEFAULT_DAEMON_RPC_ADDRESS)]
+        rpc_address: SocketAddr,
+
+        #[structopt(
+            long = "websocket",
+            help = "Address to stream the state transitions of swaps on",
+            default_value = DEFAULT_DAEMON_WEBSOCKET_ADDRESS
+        )]
+        websocket_address: SocketAddr,
+    },
 }
 
 impl Default for Command {
@@ -75,6 +132,7 @@ impl Default for Command {
             alice_addr: DEFAULT_ALICE_MULTIADDR
                 .parse()
                 .expect("default alice multiaddr str is a valid PeerId"),
+            receive_address: None,
         }
     }
 }
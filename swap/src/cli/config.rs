@@ -1,9 +1,10 @@
-use crate::fs::default_data_dir;
+use crate::{fs::default_data_dir, monero};
 use anyhow::{Context, Result};
 use config::ConfigError;
 use serde::{Deserialize, Serialize};
 use std::{
     ffi::OsStr,
+    net::SocketAddr,
     path::{Path, PathBuf},
 };
 use tracing::debug;
@@ -11,11 +12,16 @@ use url::Url;
 
 pub const DEFAULT_ELECTRUM_HTTP_URL: &str = "https://blockstream.info/testnet/api/";
 const DEFAULT_ELECTRUM_RPC_URL: &str = "ssl://electrum.blockstream.info:60002";
+const DEFAULT_MONERO_DAEMON_HOST: &str = "stagenet.community.xmr.to";
+const DEFAULT_MONERO_DAEMON_PORT: u16 = 38081;
 
 #[derive(Clone, Debug, serde::Serialize, serde::Deserialize, PartialEq)]
 pub struct Config {
     pub data: Data,
     pub bitcoin: Bitcoin,
+    #[serde(default)]
+    pub monero: Monero,
+    pub tor: Option<Tor>,
 }
 
 impl Config {
@@ -43,6 +49,8 @@ impl Config {
                     .parse()
                     .expect("default electrum rpc str is a valid url"),
             },
+            monero: Monero::default(),
+            tor: None,
         }
     }
 }
@@ -60,6 +68,51 @@ pub struct Bitcoin {
     pub electrum_rpc_url: Url,
 }
 
+// TOML cannot serialize plain values after tables, keep the tables last.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Monero {
+    /// Use the monero-wallet-rpc listening at this URL instead of running our
+    /// own. `wallet_rpc_path` and `wallet_rpc_download_url` are ignored if set.
+    pub wallet_rpc_url: Option<Url>,
+    /// Run this monero-wallet-rpc binary instead of downloading one.
+    pub wallet_rpc_path: Option<PathBuf>,
+    /// Download monero-wallet-rpc from this mirror instead of getmonero.org.
+    pub wallet_rpc_download_url: Option<Url>,
+    pub daemon: monero::DaemonConnection,
+    /// Credentials if the monero-wallet-rpc at `wallet_rpc_url` was started
+    /// with `--rpc-login`.
+    pub wallet_rpc_login: Option<monero::RpcLogin>,
+}
+
+/// Config files written before the `[monero]` section existed connect to the
+/// default stagenet daemon.
+impl Default for Monero {
+    fn default() -> Self {
+        Self {
+            daemon: monero::DaemonConnection {
+                host: DEFAULT_MONERO_DAEMON_HOST.to_string(),
+                port: DEFAULT_MONERO_DAEMON_PORT,
+                login: None,
+                ssl: Default::default(),
+            },
+            wallet_rpc_url: None,
+            wallet_rpc_login: None,
+            wallet_rpc_path: None,
+            wallet_rpc_download_url: None,
+        }
+    }
+}
+
+/// Connect to the seller through Tor. Required to reach sellers that are only
+/// reachable under an `/onion3` address.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Tor {
+    /// The SOCKS5 proxy of the Tor daemon, usually `127.0.0.1:9050`.
+    pub socks5_proxy: SocketAddr,
+}
+
 #[derive(thiserror::Error, Debug, Clone, Copy)]
 #[error("config not initialized")]
 pub struct ConfigNotInitialized {}
@@ -109,6 +162,30 @@ mod tests {
                 electrum_http_url: Url::from_str(DEFAULT_ELECTRUM_HTTP_URL).unwrap(),
                 electrum_rpc_url: Url::from_str(DEFAULT_ELECTRUM_RPC_URL).unwrap(),
             },
+            monero: Monero {
+                daemon: monero::DaemonConnection {
+                    host: DEFAULT_MONERO_DAEMON_HOST.to_string(),
+                    port: DEFAULT_MONERO_DAEMON_PORT,
+                    login: Some(monero::RpcLogin {
+                        username: "alice".to_string(),
+                        password: "secret".to_string(),
+                    }),
+                    ssl: monero::DaemonSsl {
+                        mode: monero::SslMode::Enabled,
+                        allow_any_cert: true,
+                        ca_certificates: Some(PathBuf::from("/etc/ssl/monerod.pem")),
+                    },
+                },
+                wallet_rpc_url: None,
+                wallet_rpc_login: None,
+                wallet_rpc_path: None,
+                wallet_rpc_download_url: Some(
+                    Url::from_str("https://mirror.example/monero.tar.bz2").unwrap(),
+                ),
+            },
+            tor: Some(Tor {
+                socks5_proxy: "127.0.0.1:9050".parse().unwrap(),
+            }),
         };
 
         initial_setup(config_path.clone(), expected.clone()).unwrap();
@@ -116,4 +193,83 @@ mod tests {
 
         assert_eq!(expected, actual);
     }
+
+    #[test]
+    fn monero_daemon_login_and_ssl_are_optional() {
+        let temp_dir = tempdir().unwrap().path().to_path_buf();
+        let config_path = Path::join(&temp_dir, "config.toml");
+        ensure_directory_exists(config_path.as_path()).unwrap();
+        fs::write(
+            &config_path,
+            r#"
+[data]
+dir = "/tmp/swap"
+
+[bitcoin]
+electrum_http_url = "https://blockstream.info/testnet/api/"
+electrum_rpc_url = "ssl://electrum.blockstream.info:60002"
+
+[monero.daemon]
+host = "localhost"
+port = 38081
+"#,
+        )
+        .unwrap();
+
+        let config = read_config(config_path).unwrap().unwrap();
+
+        assert_eq!(config.monero.daemon, monero::DaemonConnection {
+            host: "localhost".to_string(),
+            port: 38081,
+            login: None,
+            ssl: monero::DaemonSsl {
+                mode: monero::SslMode::Autodetect,
+                allow_any_cert: false,
+                ca_certificates: None,
+            },
+        });
+    }
+
+    #[test]
+    fn config_without_monero_section_uses_default_daemon() {
+        let temp_dir = tempdir().unwrap().path().to_path_buf();
+        let config_path = Path::join(&temp_dir, "config.toml");
+        ensure_directory_exists(config_path.as_path()).unwrap();
+        fs::write(
+            &config_path,
+            r#"
+[data]
+dir = "/tmp/swap"
+
+[bitcoin]
+electrum_http_url = "https://blockstream.info/testnet/api/"
+electrum_rpc_url = "ssl://electrum.blockstream.info:60002"
+"#,
+        )
+        .unwrap();
+
+        let config = read_config(config_path).unwrap().unwrap();
+
+        assert_eq!(config.monero, Config::testnet().monero);
+        assert_eq!(config.tor, None);
+    }
+
+    #[test]
+    fn external_monero_wallet_rpc_roundtrip() {
+        let temp_dir = tempdir().unwrap().path().to_path_buf();
+        let config_path = Path::join(&temp_dir, "config.toml");
+
+        let mut expected = Config::testnet();
+        expected.monero.wallet_rpc_url =
+            Some(Url::from_str("https://wallet.example:38083/json_rpc").unwrap());
+        expected.monero.wallet_rpc_login = Some(monero::RpcLogin {
+            username: "bob".to_string(),
+            password: "secret".to_string(),
+        });
+
+        initial_setup(config_path.clone(), expected.clone()).unwrap();
+        let actual = read_config(config_path).unwrap().unwrap();
+
+        assert_eq!(expected, actual);
+    }
 }
@@ -0,0 +1,536 @@
+//! The swap execution daemon, a long running `swap_cli` that user interfaces
+//! drive through JSON-RPC instead of running one command per action.
+//!
+//! Swaps run in the background, the states they go through are streamed to
+//! websocket clients as `{"swap_id": "...", "state": "..."}`.
+
+use crate::{
+    bitcoin,
+    bitcoin::{Amount, TxLock},
+    cli::list_sellers::{get_bid_quote, list_sellers, Status},
+    database::{Database, Swap},
+    execution_params::ExecutionParams,
+    monero,
+    network::{handshake::Capabilities, rendezvous},
+    protocol::{
+        alice::BidQuote,
+        bob,
+        bob::{BobState, Builder, EventLoop},
+    },
+    rpc,
+    seed::Seed,
+};
+use anyhow::{bail, Context, Result};
+use futures::{SinkExt, StreamExt};
+use libp2p::{Multiaddr, PeerId};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::{
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+};
+use tokio::net::{TcpListener, TcpStream};
+use tokio_tungstenite::tungstenite::{
+    handshake::server::{
+        ErrorResponse, Request as HandshakeRequest, Response as HandshakeResponse,
+    },
+    http::{header::AUTHORIZATION, StatusCode},
+    Message,
+};
+use tracing::{debug, error, info, warn};
+use uuid::Uuid;
+
+/// The methods of the daemon, sent as JSON-RPC 2.0 over HTTP POST:
+///
+/// `{"jsonrpc": "2.0", "id": 1, "method": "get_swap", "params": {"swap_id":
+/// "..."}}`
+#[derive(Debug, Deserialize, PartialEq)]
+#[serde(tag = "method", content = "params", rename_all = "snake_case")]
+pub enum Request {
+    ListSellers {
+        rendezvous_point: Multiaddr,
+    },
+    GetQuote {
+        #[serde(with = "crate::serde_peer_id")]
+        peer_id: PeerId,
+        address: Multiaddr,
+    },
+    /// Swaps the whole balance of the Bitcoin wallet.
+    BuyXmr {
+        #[serde(with = "crate::serde_peer_id")]
+        peer_id: PeerId,
+        address: Multiaddr,
+    },
+    Resume {
+        swap_id: Uuid,
+        #[serde(with = "crate::serde_peer_id")]
+        peer_id: PeerId,
+        address: Multiaddr,
+    },
+    Cancel {
+        swap_id: Uuid,
+        #[serde(default)]
+        force: bool,
+    },
+    Refund {
+        swap_id: Uuid,
+        #[serde(default)]
+        force: bool,
+    },
+    History,
+    GetSwap {
+        swap_id: Uuid,
+    },
+}
+
+#[derive(Debug, Serialize, PartialEq)]
+#[serde(untagged)]
+pub enum Response {
+    Sellers(Vec<SellerSummary>),
+    Quote(Quote),
+    Swap(SwapSummary),
+    Swaps(Vec<SwapSummary>),
+    Transaction { txid: bitcoin::Txid, state: String },
+    State { state: String },
+}
+
+#[derive(Debug, Serialize, PartialEq)]
+pub struct SellerSummary {
+    pub peer_id: String,
+    pub addresses: Vec<Multiaddr>,
+    /// `None` if the seller did not answer.
+    pub quote: Option<Quote>,
+}
+
+/// A [`BidQuote`] with the amounts as strings, e.g. `"0.005 BTC"`.
+#[derive(Debug, Serialize, PartialEq)]
+pub struct Quote {
+    pub price: String,
+    pub min_quantity: String,
+    pub max_quantity: String,
+}
+
+#[derive(Debug, Serialize, PartialEq)]
+pub struct SwapSummary {
+    pub swap_id: Uuid,
+    pub state: String,
+    /// Whether the swap is executed by this daemon at the moment.
+    pub running: bool,
+    pub tx_lock_id: Option<bitcoin::Txid>,
+}
+
+/// Everything needed to execute swaps on behalf of RPC clients.
+#[derive(Clone)]
+pub struct Daemon {
+    db: Database,
+    seed: Seed,
+    bitcoin_wallet: Arc<bitcoin::Wallet>,
+    monero_wallet: Arc<monero::Wallet>,
+    monero_daemon: Arc<monero::Daemon>,
+    execution_params: ExecutionParams,
+    capabilities: Capabilities,
+    socks5_proxy: Option<SocketAddr>,
+    /// Only one swap is executed at a time because every swap spends the
+    /// whole balance of the Bitcoin wallet.
+    running: Arc<Mutex<Option<Uuid>>>,
+}
+
+impl Daemon {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        db: Database,
+        seed: Seed,
+        bitcoin_wallet: Arc<bitcoin::Wallet>,
+        monero_wallet: Arc<monero::Wallet>,
+        monero_daemon: Arc<monero::Daemon>,
+        execution_params: ExecutionParams,
+        capabilities: Capabilities,
+        socks5_proxy: Option<SocketAddr>,
+    ) -> Self {
+        Self {
+            db,
+            seed,
+            bitcoin_wallet,
+            monero_wallet,
+            monero_daemon,
+            execution_params,
+            capabilities,
+            socks5_proxy,
+            running: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    /// Serves the JSON-RPC API on `rpc_address` and the stream of state
+    /// transitions on `websocket_address` until either of them fails.
+    pub async fn run(
+        self,
+        rpc_address: SocketAddr,
+        websocket_address: SocketAddr,
+        token: String,
+    ) -> Result<()> {
+        let db = self.db.clone();
+        let rpc_server = rpc::serve(rpc_address, token.clone(), move |request| {
+            let daemon = self.clone();
+            async move { daemon.handle(request).await }
+        });
+        let websocket_server = serve_state_updates(websocket_address, token, db);
+
+        tokio::select! {
+            result = rpc_server => result,
+            result = websocket_server => result,
+        }
+    }
+
+    async fn handle(&self, request: Request) -> Result<Response> {
+        match request {
+            Request::ListSellers { rendezvous_point } => {
+                let sellers = list_sellers(
+                    &rendezvous_point,
+                    rendezvous::XMR_BTC_NAMESPACE,
+                    self.socks5_proxy,
+                )
+                .await?;
+
+                let sellers = sellers
+                    .into_iter()
+                    .map(|seller| SellerSummary {
+                        peer_id: seller.peer_id.to_string(),
+                        addresses: seller.addresses,
+                        quote: match seller.status {
+                            Status::Online(quote) => Some(Quote::from(quote)),
+                            Status::Unreachable => None,
+                        },
+                    })
+                    .collect();
+
+                Ok(Response::Sellers(sellers))
+            }
+            Request::GetQuote { peer_id, address } => {
+                let quote = get_bid_quote(peer_id, address, self.socks5_proxy).await?;
+
+                Ok(Response::Quote(quote.into()))
+            }
+            Request::BuyXmr { peer_id, address } => {
+                self.bitcoin_wallet.sync_wallet().await?;
+                if self.bitcoin_wallet.balance().await? == Amount::ZERO {
+                    bail!(
+                        "No bitcoin to swap, please deposit BTC to {}",
+                        self.bitcoin_wallet.new_address().await?
+                    )
+                }
+                let send_bitcoin = self
+                    .bitcoin_wallet
+                    .max_giveable(TxLock::script_size())
+                    .await?;
+
+                let swap_id = Uuid::new_v4();
+                self.spawn_swap(swap_id, peer_id, address, Some(send_bitcoin))?;
+
+                Ok(Response::Swap(self.summary(swap_id)?))
+            }
+            Request::Resume {
+                swap_id,
+                peer_id,
+                address,
+            } => {
+                self.spawn_swap(swap_id, peer_id, address, None)?;
+
+                Ok(Response::Swap(self.summary(swap_id)?))
+            }
+            Request::Cancel { swap_id, force } => {
+                let state = self.idle_swap(swap_id)?;
+
+                match bob::cancel(
+                    swap_id,
+                    state,
+                    self.bitcoin_wallet.clone(),
+                    self.db.clone(),
+                    force,
+                )
+                .await?
+                {
+                    Ok((txid, state)) => Ok(Response::Transaction {
+                        txid,
+                        state: state.to_string(),
+                    }),
+                    Err(error) => Err(error.into()),
+                }
+            }
+            Request::Refund { swap_id, force } => {
+                let state = self.idle_swap(swap_id)?;
+
+                let state = bob::refund(
+                    swap_id,
+                    state,
+                    self.execution_params,
+                    self.bitcoin_wallet.clone(),
+                    self.db.clone(),
+                    force,
+                )
+                .await??;
+
+                Ok(Response::State {
+                    state: state.to_string(),
+                })
+            }
+            Request::History => {
+                let swaps = self
+                    .db
+                    .all()?
+                    .into_iter()
+                    .filter_map(|(swap_id, swap)| match swap {
+                        Swap::Bob(state) => Some(self.summarize(swap_id, state.into())),
+                        Swap::Alice(_) => None,
+                    })
+                    .collect();
+
+                Ok(Response::Swaps(swaps))
+            }
+            Request::GetSwap { swap_id } => Ok(Response::Swap(self.summary(swap_id)?)),
+        }
+    }
+
+    /// Starts executing the swap in the background. A new swap is started if
+    /// `btc_amount` is set, otherwise the swap is resumed from the database.
+    fn spawn_swap(
+        &self,
+        swap_id: Uuid,
+        alice_peer_id: PeerId,
+        alice_addr: Multiaddr,
+        btc_amount: Option<Amount>,
+    ) -> Result<()> {
+        let mut running = self
+            .running
+            .lock()
+            .expect("no panic while holding the lock");
+        if let Some(running) = *running {
+            bail!(
+                "Swap {} is still running, only one swap runs at a time",
+                running
+            )
+        }
+
+        let (event_loop, event_loop_handle) = EventLoop::new(
+            &self.seed.derive_libp2p_identity(),
+            alice_peer_id,
+            alice_addr,
+            self.bitcoin_wallet.clone(),
+            self.db.clone(),
+            self.socks5_proxy,
+            self.capabilities.clone(),
+            self.execution_params,
+        )?;
+
+        let builder = Builder::new(
+            self.db.clone(),
+            swap_id,
+            self.bitcoin_wallet.clone(),
+            self.monero_wallet.clone(),
+            self.monero_daemon.clone(),
+            self.execution_params,
+            event_loop_handle,
+        );
+        let swap = match btc_amount {
+            Some(btc_amount) => builder.with_init_params(btc_amount),
+            None => builder,
+        }
+        .build()?;
+
+        *running = Some(swap_id);
+        let slot = self.running.clone();
+        tokio::spawn(async move {
+            tokio::select! {
+                result = event_loop.run() => match result {
+                    Ok(never) => match never {},
+                    Err(error) => error!("Event loop of swap {} failed: {:#}", swap_id, error),
+                },
+                result = bob::run(swap) => match result {
+                    Ok(state) => info!("Swap {} finished in state {}", swap_id, state),
+                    Err(error) => error!("Swap {} failed: {:#}", swap_id, error),
+                },
+            }
+
+            *slot.lock().expect("no panic while holding the lock") = None;
+        });
+
+        Ok(())
+    }
+
+    /// Loads the state of a swap that is not being executed at the moment.
+    fn idle_swap(&self, swap_id: Uuid) -> Result<BobState> {
+        if *self
+            .running
+            .lock()
+            .expect("no panic while holding the lock")
+            == Some(swap_id)
+        {
+            bail!("Swap {} is running", swap_id)
+        }
+
+        Ok(self.db.get_state(swap_id)?.try_into_bob()?.into())
+    }
+
+    fn summary(&self, swap_id: Uuid) -> Result<SwapSummary> {
+        let state = self.db.get_state(swap_id);
+
+        // A new swap is only saved once the execution setup is done.
+        let state: BobState = match state {
+            Ok(state) => state.try_into_bob()?.into(),
+            Err(_) if self.is_running(swap_id) => {
+                return Ok(SwapSummary {
+                    swap_id,
+                    state: "Started".to_string(),
+                    running: true,
+                    tx_lock_id: None,
+                })
+            }
+            Err(error) => return Err(error),
+        };
+
+        Ok(self.summarize(swap_id, state))
+    }
+
+    fn summarize(&self, swap_id: Uuid, state: BobState) -> SwapSummary {
+        let tx_lock_id = match &state {
+            BobState::BtcLocked(state3) | BobState::XmrLockProofReceived { state: state3, .. } => {
+                Some(state3.tx_lock_id())
+            }
+            BobState::XmrLocked(state4)
+            | BobState::EncSigSent(state4)
+            | BobState::CancelTimelockExpired(state4)
+            | BobState::BtcCancelled(state4)
+            | BobState::BtcRefunded(state4) => Some(state4.tx_lock_id()),
+            BobState::BtcRedeemed(state5) => Some(state5.tx_lock_id()),
+            BobState::XmrRedeemed { tx_lock_id } | BobState::BtcPunished { tx_lock_id, .. } => {
+                Some(*tx_lock_id)
+            }
+            BobState::Started { .. }
+            | BobState::ExecutionSetupDone(_)
+            | BobState::SafelyAborted => None,
+        };
+
+        SwapSummary {
+            swap_id,
+            state: state.to_string(),
+            running: self.is_running(swap_id),
+            tx_lock_id,
+        }
+    }
+
+    fn is_running(&self, swap_id: Uuid) -> bool {
+        *self
+            .running
+            .lock()
+            .expect("no panic while holding the lock")
+            == Some(swap_id)
+    }
+}
+
+impl From<BidQuote> for Quote {
+    fn from(quote: BidQuote) -> Self {
+        Self {
+            price: quote.price.to_string(),
+            min_quantity: quote.min_quantity.to_string(),
+            max_quantity: quote.max_quantity.to_string(),
+        }
+    }
+}
+
+/// Streams every state a swap of this daemon enters to the websocket clients
+/// connected to `address`. Clients have to authenticate like for the RPC API.
+async fn serve_state_updates(address: SocketAddr, token: String, db: Database) -> Result<()> {
+    let listener = TcpListener::bind(address)
+        .await
+        .with_context(|| format!("Failed to bind websocket server to {}", address))?;
+
+    info!("Streaming swap states on ws://{}", address);
+
+    loop {
+        let (stream, peer) = listener.accept().await?;
+        let token = token.clone();
+        let db = db.clone();
+
+        tokio::spawn(async move {
+            match stream_state_updates(stream, token, db).await {
+                Ok(()) => debug!("Websocket client {} disconnected", peer),
+                Err(error) => warn!("Websocket client {} failed: {:#}", peer, error),
+            }
+        });
+    }
+}
+
+async fn stream_state_updates(stream: TcpStream, token: String, db: Database) -> Result<()> {
+    let authorize = |request: &HandshakeRequest, response: HandshakeResponse| {
+        let header = request
+            .headers()
+            .get(AUTHORIZATION)
+            .and_then(|header| header.to_str().ok());
+
+        if rpc::is_authorized(header, &token) {
+            Ok(response)
+        } else {
+            let mut response = ErrorResponse::new(None);
+            *response.status_mut() = StatusCode::UNAUTHORIZED;
+            Err(response)
+        }
+    };
+    let mut websocket = tokio_tungstenite::accept_hdr_async(stream, authorize).await?;
+
+    // Subscribe only after the handshake so that unauthorized clients do not
+    // cost us anything.
+    let mut updates = db.watch();
+
+    loop {
+        tokio::select! {
+            update = updates.next() => match update {
+                Some(Ok((swap_id, Swap::Bob(state)))) => {
+                    let state = BobState::from(state).to_string();
+                    let update = json!({ "swap_id": swap_id, "state": state });
+                    websocket.send(Message::Text(update.to_string())).await?;
+                }
+                Some(Ok((_, Swap::Alice(_)))) => {}
+                Some(Err(error)) => warn!("Failed to read state update: {:#}", error),
+                None => return Ok(()),
+            },
+            message = websocket.next() => match message {
+                Some(Ok(Message::Close(_))) | None => return Ok(()),
+                Some(Ok(_)) => {}
+                Some(Err(error)) => return Err(error.into()),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rpc::parse_request;
+
+    #[test]
+    fn parses_methods_with_and_without_params() {
+        assert_eq!(
+            parse_request::<Request>("history".to_string(), serde_json::Value::Null).unwrap(),
+            Request::History
+        );
+
+        let swap_id = Uuid::new_v4();
+        assert_eq!(
+            parse_request::<Request>("cancel".to_string(), json!({ "swap_id": swap_id })).unwrap(),
+            Request::Cancel {
+                swap_id,
+                force: false
+            }
+        );
+
+        let peer_id = PeerId::random();
+        let address = "/ip4/127.0.0.1/tcp/9876".parse::<Multiaddr>().unwrap();
+        assert_eq!(
+            parse_request::<Request>(
+                "buy_xmr".to_string(),
+                json!({ "peer_id": peer_id.to_string(), "address": address })
+            )
+            .unwrap(),
+            Request::BuyXmr { peer_id, address }
+        );
+    }
+}
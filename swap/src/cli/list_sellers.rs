@@ -0,0 +1,269 @@
+use crate::{
+    network::{
+        peer_tracker::{self, PeerTracker},
+        rendezvous,
+        rendezvous::{parse_rendezvous_point, Registration},
+        request_response::{is_retryable, BidQuoteProtocol, CborCodec, MAX_RETRIES, QUOTE_TIMEOUT},
+        transport, TokioExecutor,
+    },
+    protocol::alice::{BidQuote, BidQuoteRequest},
+};
+use anyhow::{anyhow, Error, Result};
+use libp2p::{
+    core::identity,
+    request_response::{
+        ProtocolSupport, RequestId, RequestResponse, RequestResponseConfig, RequestResponseEvent,
+        RequestResponseMessage,
+    },
+    Multiaddr, NetworkBehaviour, PeerId, Swarm,
+};
+use std::{collections::HashMap, net::SocketAddr};
+use tracing::debug;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Seller {
+    pub peer_id: PeerId,
+    pub addresses: Vec<Multiaddr>,
+    pub status: Status,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Status {
+    Online(BidQuote),
+    Unreachable,
+}
+
+/// Discovers the sellers registered under `namespace` at the rendezvous point
+/// and asks each of them for their current offer.
+///
+/// A throwaway identity is used so that sellers cannot link the query to a
+/// later swap. If `socks5_proxy` is set all connections go through Tor.
+pub async fn list_sellers(
+    rendezvous_point: &Multiaddr,
+    namespace: &str,
+    socks5_proxy: Option<SocketAddr>,
+) -> Result<Vec<Seller>> {
+    let (rendezvous_peer_id, rendezvous_address) = parse_rendezvous_point(rendezvous_point)?;
+
+    let mut swarm = new_swarm(socks5_proxy)?;
+
+    swarm.pt.add_address(rendezvous_peer_id, rendezvous_address);
+    swarm.rendezvous.discover(rendezvous_peer_id, namespace);
+
+    let registrations = loop {
+        match swarm.next().await {
+            OutEvent::Discovered(registrations) => break registrations,
+            OutEvent::DiscoveryFailed(error) => {
+                return Err(error.context(format!(
+                    "Failed to discover sellers at {}",
+                    rendezvous_point
+                )))
+            }
+            _ => {}
+        }
+    };
+
+    // Sellers that do not answer after `MAX_RETRIES` retries are listed as
+    // unreachable.
+    let mut pending = HashMap::<RequestId, (Registration, u32)>::new();
+    for registration in registrations {
+        // The peer tracker only dials one address per peer.
+        if let Some(address) = registration.addresses.first() {
+            swarm.pt.add_address(registration.peer_id, address.clone());
+        }
+
+        let request_id = swarm
+            .bid_quote
+            .send_request(&registration.peer_id, BidQuoteRequest);
+        pending.insert(request_id, (registration, 0));
+    }
+
+    let mut sellers = Vec::new();
+    while !pending.is_empty() {
+        let (registration, status) = match swarm.next().await {
+            OutEvent::BidQuote { request_id, quote } => match pending.remove(&request_id) {
+                Some((registration, _)) => (registration, Status::Online(quote)),
+                None => continue,
+            },
+            OutEvent::BidQuoteFailed {
+                request_id,
+                error,
+                retryable,
+            } => match pending.remove(&request_id) {
+                Some((registration, attempts)) if retryable && attempts < MAX_RETRIES => {
+                    debug!(
+                        "Failed to get bid quote from {}, retrying: {:#}",
+                        registration.peer_id, error
+                    );
+                    let request_id = swarm
+                        .bid_quote
+                        .send_request(&registration.peer_id, BidQuoteRequest);
+                    pending.insert(request_id, (registration, attempts + 1));
+                    continue;
+                }
+                Some((registration, _)) => {
+                    debug!(
+                        "Failed to get bid quote from {}: {:#}",
+                        registration.peer_id, error
+                    );
+                    (registration, Status::Unreachable)
+                }
+                None => continue,
+            },
+            _ => continue,
+        };
+
+        sellers.push(Seller {
+            peer_id: registration.peer_id,
+            addresses: registration.addresses,
+            status,
+        });
+    }
+
+    Ok(sellers)
+}
+
+/// Asks the seller `peer_id` at `address` for its current offer, like
+/// [`list_sellers`] does for every seller it discovers.
+pub async fn get_bid_quote(
+    peer_id: PeerId,
+    address: Multiaddr,
+    socks5_proxy: Option<SocketAddr>,
+) -> Result<BidQuote> {
+    let mut swarm = new_swarm(socks5_proxy)?;
+    swarm.pt.add_address(peer_id, address);
+
+    let mut pending = swarm.bid_quote.send_request(&peer_id, BidQuoteRequest);
+    let mut attempts = 0;
+
+    loop {
+        match swarm.next().await {
+            OutEvent::BidQuote { request_id, quote } if request_id == pending => return Ok(quote),
+            OutEvent::BidQuoteFailed {
+                request_id,
+                error,
+                retryable,
+            } if request_id == pending => {
+                if !retryable || attempts >= MAX_RETRIES {
+                    return Err(error.context(format!("Failed to get bid quote from {}", peer_id)));
+                }
+
+                debug!(
+                    "Failed to get bid quote from {}, retrying: {:#}",
+                    peer_id, error
+                );
+                pending = swarm.bid_quote.send_request(&peer_id, BidQuoteRequest);
+                attempts += 1;
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Builds a swarm with a throwaway identity.
+fn new_swarm(socks5_proxy: Option<SocketAddr>) -> Result<Swarm<Behaviour>> {
+    let identity = identity::Keypair::generate_ed25519();
+    let transport = match socks5_proxy {
+        Some(socks5_proxy) => transport::build_with_tor(&identity, socks5_proxy)?,
+        None => transport::build(&identity)?,
+    };
+
+    let swarm = libp2p::swarm::SwarmBuilder::new(
+        transport,
+        Behaviour::default(),
+        identity.public().into_peer_id(),
+    )
+    .executor(Box::new(TokioExecutor {
+        handle: tokio::runtime::Handle::current(),
+    }))
+    .build();
+
+    Ok(swarm)
+}
+
+#[derive(Debug)]
+enum OutEvent {
+    Discovered(Vec<Registration>),
+    DiscoveryFailed(Error),
+    BidQuote {
+        request_id: RequestId,
+        quote: BidQuote,
+    },
+    BidQuoteFailed {
+        request_id: RequestId,
+        error: Error,
+        retryable: bool,
+    },
+    Ignored,
+}
+
+impl From<peer_tracker::OutEvent> for OutEvent {
+    fn from(_: peer_tracker::OutEvent) -> Self {
+        OutEvent::Ignored
+    }
+}
+
+impl From<rendezvous::OutEvent> for OutEvent {
+    fn from(event: rendezvous::OutEvent) -> Self {
+        match event {
+            rendezvous::OutEvent::Discovered { registrations, .. } => {
+                OutEvent::Discovered(registrations)
+            }
+            rendezvous::OutEvent::RequestFailed { error, .. }
+            | rendezvous::OutEvent::Failure(error) => OutEvent::DiscoveryFailed(error),
+            rendezvous::OutEvent::Registered { .. } => OutEvent::Ignored,
+        }
+    }
+}
+
+impl From<RequestResponseEvent<BidQuoteRequest, BidQuote>> for OutEvent {
+    fn from(event: RequestResponseEvent<BidQuoteRequest, BidQuote>) -> Self {
+        match event {
+            RequestResponseEvent::Message {
+                message:
+                    RequestResponseMessage::Response {
+                        request_id,
+                        response,
+                    },
+                ..
+            } => OutEvent::BidQuote {
+                request_id,
+                quote: response,
+            },
+            RequestResponseEvent::OutboundFailure {
+                request_id, error, ..
+            } => OutEvent::BidQuoteFailed {
+                request_id,
+                retryable: is_retryable(&error),
+                error: anyhow!("Outbound failure: {:?}", error),
+            },
+            _ => OutEvent::Ignored,
+        }
+    }
+}
+
+#[derive(NetworkBehaviour)]
+#[behaviour(out_event = "OutEvent", event_process = false)]
+#[allow(missing_debug_implementations)]
+struct Behaviour {
+    pt: PeerTracker,
+    rendezvous: rendezvous::Behaviour,
+    bid_quote: RequestResponse<CborCodec<BidQuoteProtocol, BidQuoteRequest, BidQuote>>,
+}
+
+impl Default for Behaviour {
+    fn default() -> Self {
+        let mut config = RequestResponseConfig::default();
+        config.set_request_timeout(QUOTE_TIMEOUT);
+
+        Self {
+            pt: PeerTracker::default(),
+            rendezvous: rendezvous::Behaviour::default(),
+            bid_quote: RequestResponse::new(
+                CborCodec::default(),
+                vec![(BidQuoteProtocol, ProtocolSupport::Outbound)],
+                config,
+            ),
+        }
+    }
+}
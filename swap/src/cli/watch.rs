@@ -0,0 +1,313 @@
+//! Gets the Bitcoin of swaps back once their cancel timelock expired. None of
+//! this needs Alice, so it also works if she disappeared.
+
+use crate::{
+    bitcoin,
+    bitcoin::ExpiredTimelocks,
+    database::{Database, Swap},
+    execution_params::ExecutionParams,
+    monero,
+    protocol::bob::{
+        self,
+        cancel::CancelError,
+        swap::{is_complete, receive_xmr},
+        BobState, State4,
+    },
+};
+use anyhow::{bail, Result};
+use futures::{
+    future::{select, Either},
+    stream::FuturesUnordered,
+    FutureExt, StreamExt,
+};
+use std::{collections::HashSet, sync::Arc, time::Duration};
+use tracing::{info, warn};
+use uuid::Uuid;
+
+/// How long to wait before watching a swap again after a failure, e.g. when
+/// the Electrum server was unreachable.
+const RETRY_INTERVAL: Duration = Duration::from_secs(60);
+
+/// How often the database is checked for swaps to watch.
+const POLL_INTERVAL: Duration = Duration::from_secs(60);
+
+/// The state needed to cancel and refund, `None` if the swap did not lock
+/// any Bitcoin or is already finished.
+fn refundable(state: &BobState) -> Option<State4> {
+    match state {
+        BobState::BtcLocked(state3) => Some(state3.cancel()),
+        BobState::XmrLockProofReceived { state, .. } => Some(state.cancel()),
+        BobState::XmrLocked(state4)
+        | BobState::EncSigSent(state4)
+        | BobState::CancelTimelockExpired(state4)
+        | BobState::BtcCancelled(state4) => Some(state4.clone()),
+        _ => None,
+    }
+}
+
+/// Swaps that locked Bitcoin and are not finished yet.
+fn is_watched(state: &BobState) -> bool {
+    matches!(state, BobState::BtcRedeemed(_)) || refundable(state).is_some()
+}
+
+/// Whether the swap locked Bitcoin and its cancel timelock expired.
+pub async fn is_cancel_timelock_expired(
+    state: &BobState,
+    bitcoin_wallet: &bitcoin::Wallet,
+) -> Result<bool> {
+    match refundable(state) {
+        Some(state4) => Ok(!matches!(
+            state4.expired_timelock(bitcoin_wallet).await?,
+            ExpiredTimelocks::None
+        )),
+        None => Ok(false),
+    }
+}
+
+/// Checks whether Alice already redeemed the Bitcoin of a swap that sent the
+/// encrypted signature. Returns the state needed to redeem the Monero if so.
+pub async fn btc_redeemed(
+    state: &BobState,
+    bitcoin_wallet: &bitcoin::Wallet,
+) -> Result<Option<bob::State5>> {
+    match state {
+        BobState::EncSigSent(state4)
+            if bitcoin_wallet.is_published(state4.tx_redeem_id()).await? =>
+        {
+            Ok(Some(state4.watch_for_redeem_btc(bitcoin_wallet).await?))
+        }
+        _ => Ok(None),
+    }
+}
+
+/// Publishes the cancel transaction unless it was already published and then
+/// refunds the Bitcoin. Ends in [`BobState::BtcPunished`] if Alice punished the
+/// swap first. Fails if the cancel timelock did not expire yet.
+pub async fn cancel_and_refund(
+    swap_id: Uuid,
+    state: BobState,
+    execution_params: ExecutionParams,
+    bitcoin_wallet: Arc<bitcoin::Wallet>,
+    db: Database,
+) -> Result<BobState> {
+    let state = match state {
+        BobState::BtcCancelled(_) => state,
+        state => {
+            match bob::cancel(swap_id, state, bitcoin_wallet.clone(), db.clone(), false).await? {
+                Ok((txid, state)) => {
+                    info!("Published cancel transaction {} of swap {}", txid, swap_id);
+                    state
+                }
+                // The swap was saved as cancelled.
+                Err(CancelError::CancelTxAlreadyPublished) => {
+                    db.get_state(swap_id)?.try_into_bob()?.into()
+                }
+                Err(error) => bail!(error),
+            }
+        }
+    };
+
+    match bob::refund(swap_id, state, execution_params, bitcoin_wallet, db, false).await? {
+        Ok(state) => Ok(state),
+        Err(error) => bail!(error),
+    }
+}
+
+/// Watches all unfinished swaps that locked Bitcoin. They are cancelled and
+/// refunded as soon as their cancel timelock expires, unless Alice redeems the
+/// Bitcoin first, in which case the Monero is redeemed. Keeps running and
+/// checks the database every [`POLL_INTERVAL`] for swaps that need to be
+/// watched.
+pub async fn watch(
+    db: Database,
+    bitcoin_wallet: Arc<bitcoin::Wallet>,
+    monero_wallet: Arc<monero::Wallet>,
+    execution_params: ExecutionParams,
+) {
+    let mut watched = HashSet::new();
+    let mut watchers = FuturesUnordered::new();
+    let mut poll = tokio::time::interval(POLL_INTERVAL);
+
+    loop {
+        tokio::select! {
+            _ = poll.tick() => {
+                let swaps = match unfinished_swaps(&db) {
+                    Ok(swaps) => swaps,
+                    Err(error) => {
+                        warn!("Failed to load unfinished swaps: {:#}", error);
+                        continue;
+                    }
+                };
+
+                for (swap_id, state) in swaps {
+                    if !watched.insert(swap_id) {
+                        continue;
+                    }
+
+                    info!("Watching swap {} in state {}", swap_id, state);
+                    let watcher = watch_swap_until_finished(
+                        swap_id,
+                        state,
+                        execution_params,
+                        bitcoin_wallet.clone(),
+                        monero_wallet.clone(),
+                        db.clone(),
+                    );
+                    watchers.push(watcher.map(move |_| swap_id));
+                }
+            }
+            Some(swap_id) = watchers.next() => {
+                watched.remove(&swap_id);
+            }
+        }
+    }
+}
+
+fn unfinished_swaps(db: &Database) -> Result<Vec<(Uuid, BobState)>> {
+    Ok(db
+        .all()?
+        .into_iter()
+        .filter_map(|(swap_id, swap)| match swap {
+            Swap::Bob(state) => Some((swap_id, BobState::from(state))),
+            Swap::Alice(_) => None,
+        })
+        .filter(|(_, state)| is_watched(state))
+        .collect())
+}
+
+async fn watch_swap_until_finished(
+    swap_id: Uuid,
+    mut state: BobState,
+    execution_params: ExecutionParams,
+    bitcoin_wallet: Arc<bitcoin::Wallet>,
+    monero_wallet: Arc<monero::Wallet>,
+    db: Database,
+) {
+    loop {
+        match watch_swap(
+            swap_id,
+            state.clone(),
+            execution_params,
+            bitcoin_wallet.clone(),
+            monero_wallet.clone(),
+            db.clone(),
+        )
+        .await
+        {
+            Ok(state) => {
+                info!("Swap {} finished in state {}", swap_id, state);
+                return;
+            }
+            Err(error) => {
+                warn!(
+                    "Failed to finish swap {}, retrying in {}s: {:#}",
+                    swap_id,
+                    RETRY_INTERVAL.as_secs(),
+                    error
+                );
+
+                // Every failed attempt brings the punish timelock closer. Errors are ignored
+                // since the reason for the failure likely prevents the warning too.
+                if let (Ok(true), Some(state4)) = (
+                    is_cancel_timelock_expired(&state, &bitcoin_wallet).await,
+                    refundable(&state),
+                ) {
+                    let _ = bob::refund::warn_about_punish_timelock(
+                        swap_id,
+                        &state4,
+                        bitcoin_wallet.as_ref(),
+                    )
+                    .await;
+                }
+
+                tokio::time::sleep(RETRY_INTERVAL).await;
+
+                // Continue from wherever the swap got before it failed.
+                match db.get_state(swap_id).and_then(|swap| swap.try_into_bob()) {
+                    Ok(saved) => state = saved.into(),
+                    Err(error) => warn!("Failed to reload swap {}: {:#}", swap_id, error),
+                }
+            }
+        }
+    }
+}
+
+async fn watch_swap(
+    swap_id: Uuid,
+    state: BobState,
+    execution_params: ExecutionParams,
+    bitcoin_wallet: Arc<bitcoin::Wallet>,
+    monero_wallet: Arc<monero::Wallet>,
+    db: Database,
+) -> Result<BobState> {
+    if is_complete(&state) {
+        return Ok(state);
+    }
+
+    let state4 = match (&state, refundable(&state)) {
+        (BobState::BtcRedeemed(state5), _) => {
+            return claim_xmr(swap_id, state5.clone(), monero_wallet, db).await
+        }
+        (_, Some(state4)) => state4,
+        (_, None) => bail!("Swap {} cannot be refunded in state {}", swap_id, state),
+    };
+
+    // After the encrypted signature was sent, Alice may still redeem the
+    // Bitcoin before the cancel timelock expires.
+    if let BobState::EncSigSent(_) = state {
+        let redeemed = Box::pin(state4.watch_for_redeem_btc(bitcoin_wallet.as_ref()));
+        let cancel_timelock_expires =
+            Box::pin(state4.wait_for_cancel_timelock_to_expire(bitcoin_wallet.as_ref()));
+
+        match select(redeemed, cancel_timelock_expires).await {
+            Either::Left((state5, _)) => {
+                return redeem_xmr(swap_id, state5?, monero_wallet, db).await;
+            }
+            Either::Right((expired, _)) => expired?,
+        }
+    } else {
+        state4
+            .wait_for_cancel_timelock_to_expire(bitcoin_wallet.as_ref())
+            .await?;
+    }
+
+    info!(
+        "The cancel timelock of swap {} expired, refunding the Bitcoin",
+        swap_id
+    );
+    cancel_and_refund(swap_id, state, execution_params, bitcoin_wallet, db).await
+}
+
+/// Saves that Alice redeemed the Bitcoin and redeems the Monero.
+pub async fn redeem_xmr(
+    swap_id: Uuid,
+    state5: bob::State5,
+    monero_wallet: Arc<monero::Wallet>,
+    db: Database,
+) -> Result<BobState> {
+    let db_state = BobState::BtcRedeemed(state5.clone()).into();
+    db.insert_latest_state(swap_id, Swap::Bob(db_state)).await?;
+
+    claim_xmr(swap_id, state5, monero_wallet, db).await
+}
+
+async fn claim_xmr(
+    swap_id: Uuid,
+    state5: bob::State5,
+    monero_wallet: Arc<monero::Wallet>,
+    db: Database,
+) -> Result<BobState> {
+    info!(
+        "Alice redeemed the Bitcoin of swap {}, redeeming the Monero",
+        swap_id
+    );
+    receive_xmr(swap_id, &state5, monero_wallet.as_ref(), &db).await?;
+
+    let state = BobState::XmrRedeemed {
+        tx_lock_id: state5.tx_lock_id(),
+    };
+    let db_state = state.clone().into();
+    db.insert_latest_state(swap_id, Swap::Bob(db_state)).await?;
+
+    Ok(state)
+}
@@ -1,6 +1,7 @@
 pub use alice::Alice;
 pub use bob::Bob;
 
+use crate::monero;
 use anyhow::{anyhow, bail, Context, Result};
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use std::{fmt::Display, path::Path};
@@ -9,6 +10,9 @@ use uuid::Uuid;
 mod alice;
 mod bob;
 
+const MONERO_ADDRESSES: &str = "monero_addresses";
+const BTC_LOCK_BROADCASTS: &str = "btc_lock_broadcasts";
+
 #[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
 pub enum Swap {
     Alice(Alice),
@@ -43,8 +47,16 @@ impl Swap {
             Swap::Alice(_) => bail!("Swap instance is not Bob"),
         }
     }
+
+    pub fn try_into_alice(self) -> Result<Alice> {
+        match self {
+            Swap::Alice(alice) => Ok(alice),
+            Swap::Bob(_) => bail!("Swap instance is not Alice"),
+        }
+    }
 }
 
+#[derive(Clone, Debug)]
 pub struct Database(sled::Db);
 
 impl Database {
@@ -104,6 +116,153 @@ impl Database {
             })
             .collect()
     }
+
+    /// Persists a protocol message for the counterparty of `swap_id` that has
+    /// not been acknowledged yet. Every outbox is stored in its own tree.
+    pub async fn insert_outbox_message<T>(
+        &self,
+        outbox: &str,
+        swap_id: Uuid,
+        message: &T,
+    ) -> Result<()>
+    where
+        T: Serialize,
+    {
+        let key = serialize(&swap_id)?;
+        let value = serialize(message).context("Could not serialize outbox message")?;
+
+        self.0
+            .open_tree(outbox)?
+            .insert(key, value)
+            .context("Could not write in the DB")?;
+
+        self.0
+            .flush_async()
+            .await
+            .map(|_| ())
+            .context("Could not flush db")
+    }
+
+    pub async fn remove_outbox_message(&self, outbox: &str, swap_id: Uuid) -> Result<()> {
+        let key = serialize(&swap_id)?;
+
+        self.0
+            .open_tree(outbox)?
+            .remove(key)
+            .context("Could not remove from the DB")?;
+
+        self.0
+            .flush_async()
+            .await
+            .map(|_| ())
+            .context("Could not flush db")
+    }
+
+    pub fn outbox_messages<T>(&self, outbox: &str) -> Result<Vec<(Uuid, T)>>
+    where
+        T: DeserializeOwned,
+    {
+        self.0
+            .open_tree(outbox)?
+            .iter()
+            .map(|item| {
+                let (key, value) = item.context("failed to retrieve outbox message from DB")?;
+                let swap_id = deserialize::<Uuid>(&key)?;
+                let message =
+                    deserialize::<T>(&value).context("failed to deserialize outbox message")?;
+
+                Ok((swap_id, message))
+            })
+            .collect()
+    }
+
+    /// Remembers the address that the Monero of `swap_id` is sent to once it
+    /// is redeemed.
+    pub async fn insert_monero_address(
+        &self,
+        swap_id: Uuid,
+        address: monero::Address,
+    ) -> Result<()> {
+        let key = serialize(&swap_id)?;
+        let value = serialize(&address).context("Could not serialize Monero address")?;
+
+        self.0
+            .open_tree(MONERO_ADDRESSES)?
+            .insert(key, value)
+            .context("Could not write in the DB")?;
+
+        self.0
+            .flush_async()
+            .await
+            .map(|_| ())
+            .context("Could not flush db")
+    }
+
+    /// The address that the Monero of `swap_id` is sent to, `None` if it is
+    /// kept in a wallet of its own.
+    pub fn get_monero_address(&self, swap_id: Uuid) -> Result<Option<monero::Address>> {
+        let key = serialize(&swap_id)?;
+
+        self.0
+            .open_tree(MONERO_ADDRESSES)?
+            .get(key)?
+            .map(|value| deserialize(&value).context("Could not deserialize Monero address"))
+            .transpose()
+    }
+
+    /// Records that the Bitcoin lock transaction of `swap_id` is about to be
+    /// broadcast. From then on the Bitcoin may be locked even if the
+    /// transaction cannot be found yet.
+    pub async fn insert_btc_lock_broadcast(&self, swap_id: Uuid) -> Result<()> {
+        let key = serialize(&swap_id)?;
+
+        self.0
+            .open_tree(BTC_LOCK_BROADCASTS)?
+            .insert(key, vec![])
+            .context("Could not write in the DB")?;
+
+        self.0
+            .flush_async()
+            .await
+            .map(|_| ())
+            .context("Could not flush db")
+    }
+
+    /// Whether the Bitcoin lock transaction of `swap_id` may have been
+    /// broadcast, see [`Database::insert_btc_lock_broadcast`].
+    pub fn is_btc_lock_broadcast(&self, swap_id: Uuid) -> Result<bool> {
+        let key = serialize(&swap_id)?;
+
+        Ok(self.0.open_tree(BTC_LOCK_BROADCASTS)?.contains_key(key)?)
+    }
+
+    /// Subscribes to the states that are saved from now on.
+    pub fn watch(&self) -> StateUpdates {
+        StateUpdates(self.0.watch_prefix(vec![]))
+    }
+}
+
+/// The states of swaps in the order they are saved.
+pub struct StateUpdates(sled::Subscriber);
+
+impl StateUpdates {
+    /// Waits for the next saved state, returns `None` once the database is
+    /// dropped.
+    pub async fn next(&mut self) -> Option<Result<(Uuid, Swap)>> {
+        loop {
+            match (&mut self.0).await? {
+                sled::Event::Insert { key, value } => {
+                    let update = deserialize::<Uuid>(&key).and_then(|swap_id| {
+                        let swap =
+                            deserialize::<Swap>(&value).context("failed to deserialize swap")?;
+                        Ok((swap_id, swap))
+                    });
+                    return Some(update);
+                }
+                sled::Event::Remove { .. } => continue,
+            }
+        }
+    }
 }
 
 pub fn serialize<T>(t: &T) -> Result<Vec<u8>>
@@ -127,6 +286,7 @@ mod tests {
         alice::{Alice, AliceEndState},
         bob::{Bob, BobEndState},
     };
+    use rand::rngs::OsRng;
 
     #[tokio::test]
     async fn can_write_and_read_to_multiple_keys() {
@@ -207,4 +367,85 @@ mod tests {
         assert!(swaps.contains(&(swap_id_1, state_1)));
         assert!(swaps.contains(&(swap_id_2, state_2)));
     }
+
+    #[tokio::test]
+    async fn outbox_messages_are_kept_apart_from_swaps() {
+        let db_dir = tempfile::tempdir().unwrap();
+        let db = Database::open(db_dir.path()).unwrap();
+
+        let state = Swap::Bob(Bob::Done(BobEndState::SafelyAborted));
+        let swap_id = Uuid::new_v4();
+        db.insert_latest_state(swap_id, state.clone())
+            .await
+            .expect("Failed to save state");
+        db.insert_outbox_message("outbox", swap_id, &"message".to_string())
+            .await
+            .expect("Failed to save outbox message");
+
+        assert_eq!(db.all().unwrap(), vec![(swap_id, state)]);
+        assert_eq!(db.outbox_messages::<String>("outbox").unwrap(), vec![(
+            swap_id,
+            "message".to_string()
+        )]);
+
+        db.remove_outbox_message("outbox", swap_id)
+            .await
+            .expect("Failed to remove outbox message");
+
+        assert!(db.outbox_messages::<String>("outbox").unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn can_write_and_read_monero_address() {
+        let db_dir = tempfile::tempdir().unwrap();
+        let db = Database::open(db_dir.path()).unwrap();
+
+        let swap_id = Uuid::new_v4();
+        assert_eq!(db.get_monero_address(swap_id).unwrap(), None);
+
+        let key = monero::PrivateKey::from_scalar(monero::Scalar::random(&mut OsRng));
+        let address = monero::Address::standard(
+            monero::Network::Stagenet,
+            monero::PublicKey::from_private_key(&key),
+            monero::PublicKey::from_private_key(&key),
+        );
+        db.insert_monero_address(swap_id, address)
+            .await
+            .expect("Failed to save Monero address");
+
+        assert_eq!(db.get_monero_address(swap_id).unwrap(), Some(address));
+    }
+
+    #[tokio::test]
+    async fn can_write_and_read_btc_lock_broadcast() {
+        let db_dir = tempfile::tempdir().unwrap();
+        let db = Database::open(db_dir.path()).unwrap();
+
+        let swap_id = Uuid::new_v4();
+        assert!(!db.is_btc_lock_broadcast(swap_id).unwrap());
+
+        db.insert_btc_lock_broadcast(swap_id)
+            .await
+            .expect("Failed to save Bitcoin lock broadcast");
+
+        assert!(db.is_btc_lock_broadcast(swap_id).unwrap());
+        assert!(!db.is_btc_lock_broadcast(Uuid::new_v4()).unwrap());
+    }
+
+    #[tokio::test]
+    async fn saved_states_are_sent_to_watchers() {
+        let db_dir = tempfile::tempdir().unwrap();
+        let db = Database::open(db_dir.path()).unwrap();
+        let mut updates = db.watch();
+
+        let swap_id = Uuid::new_v4();
+        let state = Swap::Bob(Bob::Done(BobEndState::SafelyAborted));
+        db.insert_latest_state(swap_id, state.clone())
+            .await
+            .expect("Failed to save state");
+
+        let (updated_swap_id, updated_state) = updates.next().await.unwrap().unwrap();
+        assert_eq!(updated_swap_id, swap_id);
+        assert_eq!(updated_state, state);
+    }
 }
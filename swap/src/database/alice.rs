@@ -24,6 +24,18 @@ pub enum Alice {
         #[serde(with = "crate::serde_peer_id")]
         bob_peer_id: PeerId,
     },
+    XmrLockTransactionSent {
+        monero_wallet_restore_blockheight: BlockHeight,
+        transfer_proof: monero::TransferProof,
+        state3: alice::State3,
+        #[serde(with = "crate::serde_peer_id")]
+        bob_peer_id: PeerId,
+    },
+    XmrLockTransferProofSent {
+        monero_wallet_restore_blockheight: BlockHeight,
+        transfer_proof: monero::TransferProof,
+        state3: alice::State3,
+    },
     XmrLocked {
         monero_wallet_restore_blockheight: BlockHeight,
         state3: alice::State3,
@@ -51,6 +63,9 @@ pub enum Alice {
         #[serde(with = "monero_private_key")]
         spend_key: monero::PrivateKey,
     },
+    BtcPunished {
+        state3: alice::State3,
+    },
     Done(AliceEndState),
 }
 
@@ -79,6 +94,26 @@ impl From<&AliceState> for Alice {
                 state3: state3.as_ref().clone(),
                 bob_peer_id: *bob_peer_id,
             },
+            AliceState::XmrLockTransactionSent {
+                bob_peer_id,
+                monero_wallet_restore_blockheight,
+                transfer_proof,
+                state3,
+            } => Alice::XmrLockTransactionSent {
+                monero_wallet_restore_blockheight: *monero_wallet_restore_blockheight,
+                transfer_proof: transfer_proof.clone(),
+                state3: state3.as_ref().clone(),
+                bob_peer_id: *bob_peer_id,
+            },
+            AliceState::XmrLockTransferProofSent {
+                monero_wallet_restore_blockheight,
+                transfer_proof,
+                state3,
+            } => Alice::XmrLockTransferProofSent {
+                monero_wallet_restore_blockheight: *monero_wallet_restore_blockheight,
+                transfer_proof: transfer_proof.clone(),
+                state3: state3.as_ref().clone(),
+            },
             AliceState::XmrLocked {
                 monero_wallet_restore_blockheight,
                 state3,
@@ -129,7 +164,12 @@ impl From<&AliceState> for Alice {
                 monero_wallet_restore_blockheight: *monero_wallet_restore_blockheight,
                 state3: state3.as_ref().clone(),
             },
-            AliceState::BtcPunished => Alice::Done(AliceEndState::BtcPunished),
+            AliceState::BtcPunished {
+                state3: Some(state3),
+            } => Alice::BtcPunished {
+                state3: state3.as_ref().clone(),
+            },
+            AliceState::BtcPunished { state3: None } => Alice::Done(AliceEndState::BtcPunished),
             AliceState::SafelyAborted => Alice::Done(AliceEndState::SafelyAborted),
         }
     }
@@ -152,6 +192,26 @@ impl From<Alice> for AliceState {
                 bob_peer_id,
                 state3: Box::new(state3),
             },
+            Alice::XmrLockTransactionSent {
+                monero_wallet_restore_blockheight,
+                transfer_proof,
+                state3,
+                bob_peer_id,
+            } => AliceState::XmrLockTransactionSent {
+                bob_peer_id,
+                monero_wallet_restore_blockheight,
+                transfer_proof,
+                state3: Box::new(state3),
+            },
+            Alice::XmrLockTransferProofSent {
+                monero_wallet_restore_blockheight,
+                transfer_proof,
+                state3,
+            } => AliceState::XmrLockTransferProofSent {
+                monero_wallet_restore_blockheight,
+                transfer_proof,
+                state3: Box::new(state3),
+            },
             Alice::XmrLocked {
                 monero_wallet_restore_blockheight,
                 state3,
@@ -218,11 +278,14 @@ impl From<Alice> for AliceState {
                 spend_key,
                 state3: Box::new(state3),
             },
+            Alice::BtcPunished { state3 } => AliceState::BtcPunished {
+                state3: Some(Box::new(state3)),
+            },
             Alice::Done(end_state) => match end_state {
                 AliceEndState::SafelyAborted => AliceState::SafelyAborted,
                 AliceEndState::BtcRedeemed => AliceState::BtcRedeemed,
                 AliceEndState::XmrRefunded => AliceState::XmrRefunded,
-                AliceEndState::BtcPunished => AliceState::BtcPunished,
+                AliceEndState::BtcPunished => AliceState::BtcPunished { state3: None },
             },
         }
     }
@@ -233,11 +296,16 @@ impl Display for Alice {
         match self {
             Alice::Started { .. } => write!(f, "Started"),
             Alice::BtcLocked { .. } => f.write_str("Bitcoin locked"),
+            Alice::XmrLockTransactionSent { .. } => f.write_str("Monero lock transaction sent"),
+            Alice::XmrLockTransferProofSent { .. } => {
+                f.write_str("Monero lock transfer proof sent")
+            }
             Alice::XmrLocked { .. } => f.write_str("Monero locked"),
             Alice::CancelTimelockExpired { .. } => f.write_str("Cancel timelock is expired"),
             Alice::BtcCancelled { .. } => f.write_str("Bitcoin cancel transaction published"),
             Alice::BtcPunishable { .. } => f.write_str("Bitcoin punishable"),
             Alice::BtcRefunded { .. } => f.write_str("Monero refundable"),
+            Alice::BtcPunished { .. } => write!(f, "Done: {}", AliceEndState::BtcPunished),
             Alice::Done(end_state) => write!(f, "Done: {}", end_state),
             Alice::EncSigLearned { .. } => f.write_str("Encrypted signature learned"),
         }
@@ -38,9 +38,15 @@ pub enum Bob {
 #[derive(Clone, strum::Display, Debug, Deserialize, Serialize, PartialEq)]
 pub enum BobEndState {
     SafelyAborted,
-    XmrRedeemed { tx_lock_id: bitcoin::Txid },
+    XmrRedeemed {
+        tx_lock_id: bitcoin::Txid,
+    },
     BtcRefunded(Box<bob::State4>),
-    BtcPunished { tx_lock_id: bitcoin::Txid },
+    BtcPunished {
+        tx_lock_id: bitcoin::Txid,
+        #[serde(default)]
+        state4: Option<Box<bob::State4>>,
+    },
 }
 
 impl From<BobState> for Bob {
@@ -67,9 +73,10 @@ impl From<BobState> for Bob {
             BobState::XmrRedeemed { tx_lock_id } => {
                 Bob::Done(BobEndState::XmrRedeemed { tx_lock_id })
             }
-            BobState::BtcPunished { tx_lock_id } => {
-                Bob::Done(BobEndState::BtcPunished { tx_lock_id })
-            }
+            BobState::BtcPunished { tx_lock_id, state4 } => Bob::Done(BobEndState::BtcPunished {
+                tx_lock_id,
+                state4: state4.map(Box::new),
+            }),
             BobState::SafelyAborted => Bob::Done(BobEndState::SafelyAborted),
         }
     }
@@ -99,7 +106,10 @@ impl From<Bob> for BobState {
                 BobEndState::SafelyAborted => BobState::SafelyAborted,
                 BobEndState::XmrRedeemed { tx_lock_id } => BobState::XmrRedeemed { tx_lock_id },
                 BobEndState::BtcRefunded(state4) => BobState::BtcRefunded(*state4),
-                BobEndState::BtcPunished { tx_lock_id } => BobState::BtcPunished { tx_lock_id },
+                BobEndState::BtcPunished { tx_lock_id, state4 } => BobState::BtcPunished {
+                    tx_lock_id,
+                    state4: state4.map(|state4| *state4),
+                },
             },
         }
     }
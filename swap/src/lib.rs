@@ -23,10 +23,11 @@ pub mod database;
 pub mod execution_params;
 pub mod fs;
 pub mod monero;
+pub mod network;
 pub mod protocol;
+pub mod rpc;
 pub mod seed;
 pub mod trace;
 
 mod monero_ext;
-mod network;
 mod serde_peer_id;
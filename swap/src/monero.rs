@@ -1,10 +1,12 @@
+mod daemon;
 pub mod wallet;
 mod wallet_rpc;
 
-pub use ::monero::{Network, PrivateKey, PublicKey};
+pub use ::monero::{Address, Network, PrivateKey, PublicKey};
 pub use curve25519_dalek::scalar::Scalar;
-pub use wallet::Wallet;
-pub use wallet_rpc::{WalletRpc, WalletRpcProcess};
+pub use daemon::{Daemon, DaemonConnection, DaemonSsl, RpcLogin, SslMode, WrongNetwork};
+pub use wallet::{Wallet, WalletBusy};
+pub use wallet_rpc::{ChecksumMismatch, Download, WalletRpc, WalletRpcProcess, WalletRpcStatus};
 
 use crate::bitcoin;
 use ::bitcoin::hashes::core::fmt::Formatter;
@@ -202,7 +204,7 @@ pub trait WatchForTransfer {
         transfer_proof: TransferProof,
         amount: Amount,
         expected_confirmations: u32,
-    ) -> Result<(), InsufficientFunds>;
+    ) -> Result<Result<(), InsufficientFunds>>;
 }
 
 #[derive(Debug, Clone, Copy, thiserror::Error)]
@@ -228,16 +230,6 @@ pub trait CreateWalletForOutput {
     ) -> Result<()>;
 }
 
-#[async_trait]
-pub trait CreateWalletForOutputThenLoadDefaultWallet {
-    async fn create_and_load_wallet_for_output_then_load_default_wallet(
-        &self,
-        private_spend_key: PrivateKey,
-        private_view_key: PrivateViewKey,
-        restore_height: BlockHeight,
-    ) -> Result<()>;
-}
-
 #[async_trait]
 pub trait OpenWallet {
     async fn open_wallet(&self, file_name: &str) -> Result<()>;
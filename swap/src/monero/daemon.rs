@@ -0,0 +1,520 @@
+use crate::monero::{Amount, InsufficientFunds, PublicViewKey, TransferProof, WatchForTransfer};
+use ::monero::{Network, PrivateKey, PublicKey};
+use anyhow::{bail, Context, Result};
+use async_trait::async_trait;
+use backoff::{backoff::Constant as ConstantBackoff, future::retry};
+use curve25519_dalek::{
+    constants::ED25519_BASEPOINT_TABLE,
+    edwards::{CompressedEdwardsY, EdwardsPoint},
+    scalar::Scalar,
+};
+use monero_rpc::{
+    monerod::{self, TransactionJson},
+    Login,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    convert::TryInto,
+    path::PathBuf,
+    sync::atomic::{AtomicU32, Ordering},
+    time::Duration,
+};
+use tiny_keccak::Keccak;
+use tracing::{info, warn};
+use url::Url;
+
+/// Pedersen commitment generator `H` for amounts, as defined by Monero.
+const H: [u8; 32] = [
+    0x8b, 0x65, 0x59, 0x70, 0x15, 0x37, 0x99, 0xaf, 0x2a, 0xea, 0xdc, 0x9f, 0xf1, 0xad, 0xd0, 0xea,
+    0x6c, 0x72, 0x51, 0xd5, 0x41, 0x54, 0xcf, 0xa9, 0x2c, 0x17, 0x3a, 0x0d, 0xd3, 0x9c, 0x1f, 0x94,
+];
+
+/// RingCT types that encrypt the amount in 8 bytes (`Bulletproof2` and
+/// `CLSAG`).
+const RCT_TYPES_WITH_SHORT_AMOUNT: [u8; 2] = [4, 5];
+
+/// How to reach monerod, both for monero-wallet-rpc and for the requests we
+/// send to it directly.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct DaemonConnection {
+    pub host: String,
+    pub port: u16,
+    /// Credentials if monerod was started with `--rpc-login`.
+    pub login: Option<RpcLogin>,
+    #[serde(default)]
+    pub ssl: DaemonSsl,
+}
+
+/// Credentials of a monerod or monero-wallet-rpc started with `--rpc-login`.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct RpcLogin {
+    pub username: String,
+    pub password: String,
+}
+
+impl From<RpcLogin> for Login {
+    fn from(login: RpcLogin) -> Self {
+        Login {
+            username: login.username,
+            password: login.password,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct DaemonSsl {
+    #[serde(default)]
+    pub mode: SslMode,
+    /// Accept any certificate presented by monerod, e.g. a self-signed one.
+    #[serde(default)]
+    pub allow_any_cert: bool,
+    /// PEM file of certificate authorities to trust in addition to the
+    /// system ones.
+    pub ca_certificates: Option<PathBuf>,
+}
+
+/// Mirrors the `--daemon-ssl` option of monero-wallet-rpc.
+///
+/// monero-wallet-rpc falls back to plain HTTP on `autodetect`, our own
+/// requests to monerod only use TLS if it is `enabled`.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize, strum::Display)]
+#[serde(rename_all = "lowercase")]
+#[strum(serialize_all = "lowercase")]
+pub enum SslMode {
+    Autodetect,
+    Enabled,
+    Disabled,
+}
+
+impl Default for SslMode {
+    fn default() -> Self {
+        SslMode::Autodetect
+    }
+}
+
+impl DaemonConnection {
+    fn client(&self) -> Result<monerod::Client> {
+        let scheme = match self.ssl.mode {
+            SslMode::Enabled => "https",
+            SslMode::Autodetect | SslMode::Disabled => "http",
+        };
+        let url = Url::parse(&format!(
+            "{}://{}:{}/json_rpc",
+            scheme, self.host, self.port
+        ))?;
+
+        let mut http_client =
+            reqwest::Client::builder().danger_accept_invalid_certs(self.ssl.allow_any_cert);
+        if let Some(path) = &self.ssl.ca_certificates {
+            let pem = std::fs::read(path).with_context(|| {
+                format!("failed to read CA certificates from {}", path.display())
+            })?;
+            http_client = http_client.add_root_certificate(reqwest::Certificate::from_pem(&pem)?);
+        }
+
+        Ok(monerod::Client::new_with_http_client(
+            http_client.build()?,
+            url,
+            self.login.clone().map(Login::from),
+        ))
+    }
+}
+
+#[derive(Debug, Clone, thiserror::Error)]
+#[error("monerod runs on {actual} but {expected:?} is required")]
+pub struct WrongNetwork {
+    pub expected: Network,
+    pub actual: String,
+}
+
+/// Watches the Monero blockchain through monerod.
+///
+/// Unlike the wallet, verifying a transfer against the daemon does not require
+/// monero-wallet-rpc to be synced, the outputs of the transaction are derived
+/// locally from the transaction key.
+#[derive(Debug)]
+pub struct Daemon {
+    client: monerod::Client,
+}
+
+impl Daemon {
+    pub fn new(connection: &DaemonConnection) -> Result<Self> {
+        Ok(Self {
+            client: connection.client()?,
+        })
+    }
+
+    pub fn new_with_client(client: monerod::Client) -> Self {
+        Self { client }
+    }
+
+    /// Fails if monerod cannot be reached or runs on a different network.
+    pub async fn ensure_network(&self, network: Network) -> Result<()> {
+        let info = self
+            .client
+            .get_info()
+            .await
+            .context("failed to reach monerod")?;
+
+        let expected = match network {
+            Network::Mainnet => "mainnet",
+            Network::Stagenet => "stagenet",
+            Network::Testnet => "testnet",
+        };
+        if info.nettype != expected {
+            bail!(WrongNetwork {
+                expected: network,
+                actual: info.nettype,
+            })
+        }
+
+        if !info.synchronized {
+            warn!(
+                "monerod is not synchronized yet, currently at height {}",
+                info.height
+            );
+        }
+
+        Ok(())
+    }
+
+    async fn confirmations(&self, tx: &monerod::Transaction) -> Result<u32> {
+        if tx.in_pool {
+            return Ok(0);
+        }
+
+        let header = self
+            .client
+            .get_block_header_by_height(tx.block_height)
+            .await?;
+
+        // The depth of the latest block is zero but it already counts as one
+        // confirmation.
+        Ok(header.depth + 1)
+    }
+}
+
+// TODO: For retry, use `backoff::ExponentialBackoff` in production as opposed
+// to `ConstantBackoff`.
+#[async_trait]
+impl WatchForTransfer for Daemon {
+    async fn watch_for_transfer(
+        &self,
+        public_spend_key: PublicKey,
+        public_view_key: PublicViewKey,
+        transfer_proof: TransferProof,
+        expected_amount: Amount,
+        expected_confirmations: u32,
+    ) -> Result<Result<(), InsufficientFunds>> {
+        enum Error {
+            TxNotFound,
+            InsufficientConfirmations,
+            InsufficientFunds { expected: Amount, actual: Amount },
+            Undecodable(anyhow::Error),
+        }
+
+        let tx_hash = String::from(transfer_proof.tx_hash());
+        let tx_key = transfer_proof.tx_key();
+        let public_view_key = PublicKey::from(public_view_key);
+
+        let confirmations = AtomicU32::new(0u32);
+
+        let res = retry(ConstantBackoff::new(Duration::from_secs(1)), || async {
+            // NOTE: Currently, this is conflicting IO errors with the transaction not being
+            // in the blockchain yet, or not having enough confirmations on it. All these
+            // errors warrant a retry, but the strategy should probably differ per case
+            let tx = self
+                .client
+                .get_transactions(&[tx_hash.clone()])
+                .await
+                .map_err(|_| backoff::Error::Transient(Error::TxNotFound))?
+                .into_iter()
+                .find(|tx| tx.tx_hash == tx_hash)
+                .ok_or(backoff::Error::Transient(Error::TxNotFound))?;
+
+            // A transaction we fail to read may still pay enough, so the swap
+            // fails instead of being refunded.
+            let received = tx
+                .decode()
+                .context("failed to decode transaction")
+                .and_then(|decoded| {
+                    received_amount(&decoded, tx_key, public_spend_key, public_view_key)
+                })
+                .map_err(|e| backoff::Error::Permanent(Error::Undecodable(e)))?;
+
+            if received != expected_amount {
+                return Err(backoff::Error::Permanent(Error::InsufficientFunds {
+                    expected: expected_amount,
+                    actual: received,
+                }));
+            }
+
+            let tx_confirmations = self
+                .confirmations(&tx)
+                .await
+                .map_err(|_| backoff::Error::Transient(Error::TxNotFound))?;
+
+            if tx_confirmations > confirmations.load(Ordering::SeqCst) {
+                confirmations.store(tx_confirmations, Ordering::SeqCst);
+                info!(
+                    "Monero lock tx received {} out of {} confirmations",
+                    tx_confirmations, expected_confirmations
+                );
+            }
+
+            if tx_confirmations < expected_confirmations {
+                return Err(backoff::Error::Transient(Error::InsufficientConfirmations));
+            }
+
+            Ok(())
+        })
+        .await;
+
+        match res {
+            Ok(()) => Ok(Ok(())),
+            Err(Error::InsufficientFunds { expected, actual }) => {
+                Ok(Err(InsufficientFunds { expected, actual }))
+            }
+            Err(Error::Undecodable(e)) => Err(e.context(format!(
+                "failed to compute the amount Monero lock transaction {} pays",
+                tx_hash
+            ))),
+            Err(Error::TxNotFound) | Err(Error::InsufficientConfirmations) => {
+                unreachable!("transient errors are retried")
+            }
+        }
+    }
+}
+
+/// Sums up the amounts of all outputs of `tx` that belong to the address
+/// (`public_spend_key`, `public_view_key`), using the transaction key to derive
+/// the shared secret with the receiver.
+///
+/// Every decrypted amount is checked against the output commitment so a
+/// sender cannot claim an amount it did not commit to.
+fn received_amount(
+    tx: &TransactionJson,
+    tx_key: PrivateKey,
+    public_spend_key: PublicKey,
+    public_view_key: PublicKey,
+) -> Result<Amount> {
+    let rct_type = tx.rct_signatures.rct_type;
+    if !RCT_TYPES_WITH_SHORT_AMOUNT.contains(&rct_type) {
+        bail!("unsupported RingCT type {}", rct_type)
+    }
+
+    let public_spend_key = decompress(public_spend_key.point)?;
+    let public_view_key = decompress(public_view_key.point)?;
+
+    let derivation = (tx_key.scalar * public_view_key).mul_by_cofactor();
+
+    let mut received = 0u64;
+
+    for (index, output) in tx.vout.iter().enumerate() {
+        let shared_secret = derivation_to_scalar(&derivation, index as u64);
+
+        let output_key = decompress(CompressedEdwardsY(decode_hex32(&output.target.key)?))?;
+        let expected_output_key = &shared_secret * &ED25519_BASEPOINT_TABLE + public_spend_key;
+        if output_key != expected_output_key {
+            continue;
+        }
+
+        let ecdh_info = tx
+            .rct_signatures
+            .ecdh_info
+            .get(index)
+            .with_context(|| format!("missing encrypted amount for output {}", index))?;
+        let commitment = tx
+            .rct_signatures
+            .out_pk
+            .get(index)
+            .with_context(|| format!("missing commitment for output {}", index))?;
+
+        let amount = decrypt_amount(&ecdh_info.amount, &shared_secret)?;
+
+        let mask = hash_to_scalar(&[b"commitment_mask", shared_secret.as_bytes()]);
+        let expected_commitment = commit(mask, amount)?;
+        let commitment = decompress(CompressedEdwardsY(decode_hex32(commitment)?))?;
+        if commitment != expected_commitment {
+            bail!("commitment of output {} does not match its amount", index)
+        }
+
+        received = received
+            .checked_add(amount)
+            .context("received amount overflows")?;
+    }
+
+    Ok(Amount::from_piconero(received))
+}
+
+fn derivation_to_scalar(derivation: &EdwardsPoint, index: u64) -> Scalar {
+    hash_to_scalar(&[
+        derivation.compress().as_bytes(),
+        encode_varint(index).as_slice(),
+    ])
+}
+
+fn decrypt_amount(encrypted: &str, shared_secret: &Scalar) -> Result<u64> {
+    let encrypted = hex::decode(encrypted)?;
+    let encrypted: [u8; 8] = encrypted
+        .get(..8)
+        .context("encrypted amount is too short")?
+        .try_into()?;
+
+    let key = keccak256(&[b"amount", shared_secret.as_bytes()]);
+
+    let mut amount = [0u8; 8];
+    for (i, byte) in amount.iter_mut().enumerate() {
+        *byte = encrypted[i] ^ key[i];
+    }
+
+    Ok(u64::from_le_bytes(amount))
+}
+
+fn commit(mask: Scalar, amount: u64) -> Result<EdwardsPoint> {
+    let h = decompress(CompressedEdwardsY(H))?;
+
+    Ok(&mask * &ED25519_BASEPOINT_TABLE + Scalar::from(amount) * h)
+}
+
+fn hash_to_scalar(input: &[&[u8]]) -> Scalar {
+    Scalar::from_bytes_mod_order(keccak256(input))
+}
+
+fn keccak256(input: &[&[u8]]) -> [u8; 32] {
+    let mut keccak = Keccak::new_keccak256();
+    for bytes in input {
+        keccak.update(bytes);
+    }
+
+    let mut out = [0u8; 32];
+    keccak.finalize(&mut out);
+
+    out
+}
+
+fn encode_varint(mut n: u64) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    while n >= 0x80 {
+        bytes.push((n as u8 & 0x7f) | 0x80);
+        n >>= 7;
+    }
+    bytes.push(n as u8);
+
+    bytes
+}
+
+fn decode_hex32(hex: &str) -> Result<[u8; 32]> {
+    let bytes = hex::decode(hex)?;
+    let bytes = bytes.as_slice().try_into().context("expected 32 bytes")?;
+
+    Ok(bytes)
+}
+
+fn decompress(point: CompressedEdwardsY) -> Result<EdwardsPoint> {
+    point.decompress().context("invalid curve point")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use monero_rpc::monerod::{EcdhInfo, RctSignatures, TxOut, TxOutTarget};
+    use rand::rngs::OsRng;
+
+    fn random_key() -> PrivateKey {
+        PrivateKey::from_scalar(Scalar::random(&mut OsRng))
+    }
+
+    /// Builds a transaction the way a sender does, i.e. deriving the shared
+    /// secret from the transaction key and the receiver's public view key.
+    fn transaction_to(
+        tx_key: PrivateKey,
+        public_spend_key: PublicKey,
+        public_view_key: PublicKey,
+        amount: u64,
+    ) -> TransactionJson {
+        let view = public_view_key.point.decompress().unwrap();
+        let spend = public_spend_key.point.decompress().unwrap();
+        let derivation = (tx_key.scalar * view).mul_by_cofactor();
+        let shared_secret = derivation_to_scalar(&derivation, 0);
+
+        let output_key = &shared_secret * &ED25519_BASEPOINT_TABLE + spend;
+        let key = keccak256(&[b"amount", shared_secret.as_bytes()]);
+        let mut encrypted = amount.to_le_bytes();
+        for (i, byte) in encrypted.iter_mut().enumerate() {
+            *byte ^= key[i];
+        }
+        let mask = hash_to_scalar(&[b"commitment_mask", shared_secret.as_bytes()]);
+        let commitment = commit(mask, amount).unwrap();
+
+        TransactionJson {
+            version: 2,
+            vout: vec![TxOut {
+                amount: 0,
+                target: TxOutTarget {
+                    key: hex::encode(output_key.compress().as_bytes()),
+                },
+            }],
+            rct_signatures: RctSignatures {
+                rct_type: 5,
+                ecdh_info: vec![EcdhInfo {
+                    mask: None,
+                    amount: hex::encode(encrypted),
+                }],
+                out_pk: vec![hex::encode(commitment.compress().as_bytes())],
+            },
+        }
+    }
+
+    #[test]
+    fn received_amount_of_output_to_address() {
+        let tx_key = random_key();
+        let spend = PublicKey::from_private_key(&random_key());
+        let view = PublicKey::from_private_key(&random_key());
+
+        let tx = transaction_to(tx_key, spend, view, 1_234_567);
+
+        let amount = received_amount(&tx, tx_key, spend, view).unwrap();
+
+        assert_eq!(amount, Amount::from_piconero(1_234_567));
+    }
+
+    #[test]
+    fn nothing_received_by_other_address() {
+        let tx_key = random_key();
+        let spend = PublicKey::from_private_key(&random_key());
+        let view = PublicKey::from_private_key(&random_key());
+        let other_view = PublicKey::from_private_key(&random_key());
+
+        let tx = transaction_to(tx_key, spend, view, 1_234_567);
+
+        let amount = received_amount(&tx, tx_key, spend, other_view).unwrap();
+
+        assert_eq!(amount, Amount::ZERO);
+    }
+
+    #[test]
+    fn commitment_not_matching_amount_is_rejected() {
+        let tx_key = random_key();
+        let spend = PublicKey::from_private_key(&random_key());
+        let view = PublicKey::from_private_key(&random_key());
+
+        let mut tx = transaction_to(tx_key, spend, view, 1_234_567);
+        tx.rct_signatures.out_pk = transaction_to(tx_key, spend, view, 1).rct_signatures.out_pk;
+
+        let result = received_amount(&tx, tx_key, spend, view);
+
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn varint_encoding() {
+        assert_eq!(encode_varint(0), vec![0x00]);
+        assert_eq!(encode_varint(127), vec![0x7f]);
+        assert_eq!(encode_varint(128), vec![0x80, 0x01]);
+        assert_eq!(encode_varint(300), vec![0xac, 0x02]);
+    }
+}
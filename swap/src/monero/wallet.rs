@@ -1,36 +1,52 @@
 use crate::monero::{
-    Amount, CreateWallet, CreateWalletForOutput, CreateWalletForOutputThenLoadDefaultWallet,
-    InsufficientFunds, OpenWallet, PrivateViewKey, PublicViewKey, Transfer, TransferProof, TxHash,
-    WatchForTransfer,
+    Amount, CreateWallet, CreateWalletForOutput, OpenWallet, PrivateViewKey, PublicViewKey,
+    Transfer, TransferProof, TxHash, WalletRpcStatus,
 };
 use ::monero::{Address, Network, PrivateKey, PublicKey};
-use anyhow::Result;
+use anyhow::{anyhow, bail, Context, Result};
 use async_trait::async_trait;
 use backoff::{backoff::Constant as ConstantBackoff, future::retry};
-use bitcoin::hashes::core::sync::atomic::AtomicU32;
 use monero_rpc::{
     wallet,
     wallet::{BlockHeight, Refreshed},
 };
-use std::{str::FromStr, sync::atomic::Ordering, time::Duration};
-use tokio::sync::Mutex;
+use std::{
+    str::FromStr,
+    sync::atomic::{AtomicU32, Ordering},
+    time::Duration,
+};
+use tokio::sync::{watch, Mutex, MutexGuard};
 use tracing::info;
 use url::Url;
 
+/// How long to wait before trying again to sweep an output that is not
+/// unlocked yet.
+const SWEEP_RETRY_INTERVAL: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Clone, Copy, thiserror::Error)]
+#[error("The Monero wallet is in use")]
+pub struct WalletBusy;
+
+/// The output to sweep has not been unlocked yet.
+#[derive(Debug, Clone, Copy, thiserror::Error)]
+#[error("The output is not unlocked yet")]
+struct OutputLocked;
+
 #[derive(Debug)]
 pub struct Wallet {
     inner: Mutex<wallet::Client>,
     network: Network,
     default_wallet_name: String,
+    rpc_status: Option<watch::Receiver<WalletRpcStatus>>,
+    /// Wallet that is open in monero-wallet-rpc, it has to be opened again
+    /// after the process restarted.
+    opened_wallet: Mutex<Option<String>>,
+    known_restarts: AtomicU32,
 }
 
 impl Wallet {
     pub fn new(url: Url, network: Network, default_wallet_name: String) -> Self {
-        Self {
-            inner: Mutex::new(wallet::Client::new(url)),
-            network,
-            default_wallet_name,
-        }
+        Self::new_with_client(wallet::Client::new(url), network, default_wallet_name)
     }
 
     pub fn new_with_client(
@@ -42,27 +58,247 @@ impl Wallet {
             inner: Mutex::new(client),
             network,
             default_wallet_name,
+            rpc_status: None,
+            opened_wallet: Mutex::new(None),
+            known_restarts: AtomicU32::new(0),
+        }
+    }
+
+    /// Waits for a supervised monero-wallet-rpc to come back up instead of
+    /// failing while it restarts.
+    pub fn with_rpc_status(self, rpc_status: watch::Receiver<WalletRpcStatus>) -> Self {
+        Self {
+            rpc_status: Some(rpc_status),
+            ..self
         }
     }
 
     /// Get the balance of the primary account.
     pub async fn get_balance(&self) -> Result<Amount> {
-        let amount = self.inner.lock().await.get_balance(0).await?;
+        let amount = self.client().await?.get_balance(0).await?;
+
+        Ok(Amount::from_piconero(amount))
+    }
+
+    /// Like [`Wallet::get_balance`] but fails with [`WalletBusy`] instead of
+    /// waiting while the wallet is in use, e.g. by a sweep. Meant for callers
+    /// that must not be held up, like the event loop.
+    pub async fn try_get_balance(&self) -> Result<Amount> {
+        let amount = self.try_client().await?.get_balance(0).await?;
 
         Ok(Amount::from_piconero(amount))
     }
 
     pub async fn block_height(&self) -> Result<BlockHeight> {
-        self.inner.lock().await.block_height().await
+        self.client().await?.block_height().await
     }
 
     pub async fn get_main_address(&self) -> Result<Address> {
-        let address = self.inner.lock().await.get_address(0).await?;
+        let address = self.client().await?.get_address(0).await?;
         Ok(Address::from_str(address.address.as_str())?)
     }
 
     pub async fn refresh(&self) -> Result<Refreshed> {
-        self.inner.lock().await.refresh().await
+        self.client().await?.refresh().await
+    }
+
+    /// Waits until the transaction of `transfer_proof` to the address of
+    /// `public_spend_key` and `public_view_key` has `expected_confirmations`.
+    ///
+    /// Unlike `Daemon::watch_for_transfer` this trusts monero-wallet-rpc, it is
+    /// meant for transfers we made ourselves.
+    pub async fn wait_for_confirmations(
+        &self,
+        public_spend_key: PublicKey,
+        public_view_key: PublicViewKey,
+        transfer_proof: &TransferProof,
+        expected_confirmations: u32,
+    ) -> Result<()> {
+        let address = Address::standard(self.network, public_spend_key, public_view_key.into());
+
+        retry(ConstantBackoff::new(Duration::from_secs(1)), || async {
+            let proof = self
+                .client()
+                .await
+                .map_err(backoff::Error::Transient)?
+                .check_tx_key(
+                    &String::from(transfer_proof.tx_hash()),
+                    &transfer_proof.tx_key().to_string(),
+                    &address.to_string(),
+                )
+                .await
+                .map_err(backoff::Error::Transient)?;
+
+            if proof.confirmations < expected_confirmations {
+                return Err(backoff::Error::Transient(anyhow::anyhow!(
+                    "Monero lock tx has {} out of {} confirmations",
+                    proof.confirmations,
+                    expected_confirmations
+                )));
+            }
+
+            Ok(())
+        })
+        .await
+    }
+
+    /// Sweeps the output controlled by `private_spend_key` and
+    /// `private_view_key` into the default wallet. Waits until the output can
+    /// be spent and returns the sweep transactions, none if it was swept
+    /// already.
+    pub async fn sweep_output_into_default_wallet(
+        &self,
+        private_spend_key: PrivateKey,
+        private_view_key: PrivateViewKey,
+        restore_height: BlockHeight,
+    ) -> Result<Vec<TxHash>> {
+        let default_address = self.get_main_address().await?;
+
+        self.sweep_output(
+            private_spend_key,
+            private_view_key,
+            restore_height,
+            default_address,
+        )
+        .await
+    }
+
+    /// Like [`Wallet::sweep_output_into_default_wallet`] but sweeps the output
+    /// to `destination`.
+    pub async fn sweep_output(
+        &self,
+        private_spend_key: PrivateKey,
+        private_view_key: PrivateViewKey,
+        restore_height: BlockHeight,
+        destination: Address,
+    ) -> Result<Vec<TxHash>> {
+        let public_spend_key = PublicKey::from_private_key(&private_spend_key);
+        let public_view_key = PublicKey::from_private_key(&private_view_key.into());
+        let address = Address::standard(self.network, public_spend_key, public_view_key);
+
+        retry(ConstantBackoff::new(SWEEP_RETRY_INTERVAL), || async {
+            self.try_sweep_output(
+                &address,
+                private_spend_key,
+                private_view_key,
+                restore_height,
+                &destination,
+            )
+            .await
+            .map_err(|error| {
+                if error.is::<OutputLocked>() {
+                    info!("Output of {} is not unlocked yet, retrying", address);
+                    backoff::Error::Transient(error)
+                } else {
+                    backoff::Error::Permanent(error)
+                }
+            })
+        })
+        .await
+    }
+
+    async fn try_sweep_output(
+        &self,
+        address: &Address,
+        private_spend_key: PrivateKey,
+        private_view_key: PrivateViewKey,
+        restore_height: BlockHeight,
+        destination: &Address,
+    ) -> Result<Vec<TxHash>> {
+        let file_name = PrivateKey::from(private_view_key).to_string();
+
+        let client = self.client().await?;
+
+        // The default wallet is reopened below whatever fails in here.
+        let swept = async {
+            // The wallet of the output exists already if an earlier attempt failed.
+            if client.open_wallet(&file_name).await.is_err() {
+                client
+                    .generate_from_keys(
+                        &address.to_string(),
+                        &private_spend_key.to_string(),
+                        &file_name,
+                        restore_height.height,
+                    )
+                    .await?;
+            }
+
+            client.refresh().await?;
+            if client.get_balance(0).await? == 0 {
+                return Ok(vec![]);
+            }
+            if client.get_unlocked_balance(0).await? == 0 {
+                bail!(OutputLocked)
+            }
+
+            let sweep = client.sweep_all(&destination.to_string()).await?;
+            Result::<_, anyhow::Error>::Ok(sweep.tx_hash_list.into_iter().map(TxHash).collect())
+        }
+        .await;
+
+        client.open_wallet(&self.default_wallet_name).await?;
+        self.set_opened_wallet(&self.default_wallet_name).await;
+
+        swept
+    }
+
+    /// The endpoint of the monero-wallet-rpc this wallet talks to.
+    pub async fn rpc_url(&self) -> Url {
+        self.inner.lock().await.url.clone()
+    }
+
+    async fn client(&self) -> Result<MutexGuard<'_, wallet::Client>> {
+        let client = self.inner.lock().await;
+
+        self.reopen_after_restart(client).await
+    }
+
+    /// Like [`Wallet::client`] but fails if the wallet is in use.
+    async fn try_client(&self) -> Result<MutexGuard<'_, wallet::Client>> {
+        let client = self.inner.try_lock().map_err(|_| anyhow!(WalletBusy))?;
+
+        self.reopen_after_restart(client).await
+    }
+
+    /// Waits for a supervised monero-wallet-rpc that is restarting and opens
+    /// the wallet again once it restarted.
+    async fn reopen_after_restart<'a>(
+        &self,
+        client: MutexGuard<'a, wallet::Client>,
+    ) -> Result<MutexGuard<'a, wallet::Client>> {
+        let mut rpc_status = match &self.rpc_status {
+            Some(rpc_status) => rpc_status.clone(),
+            None => return Ok(client),
+        };
+
+        let restarts = loop {
+            if let WalletRpcStatus::Running { restarts } = *rpc_status.borrow() {
+                break restarts;
+            }
+
+            info!("Waiting for monero-wallet-rpc to restart");
+            rpc_status
+                .changed()
+                .await
+                .context("monero-wallet-rpc is no longer supervised")?;
+        };
+
+        if restarts != self.known_restarts.load(Ordering::SeqCst) {
+            if let Some(file_name) = self.opened_wallet.lock().await.as_deref() {
+                info!(
+                    "Reopening wallet {} after monero-wallet-rpc restarted",
+                    file_name
+                );
+                client.open_wallet(file_name).await?;
+            }
+            self.known_restarts.store(restarts, Ordering::SeqCst);
+        }
+
+        Ok(client)
+    }
+
+    async fn set_opened_wallet(&self, file_name: &str) {
+        *self.opened_wallet.lock().await = Some(file_name.to_string());
     }
 
     pub fn static_tx_fee_estimate(&self) -> Amount {
@@ -83,9 +319,8 @@ impl Transfer for Wallet {
             Address::standard(self.network, public_spend_key, public_view_key.into());
 
         let res = self
-            .inner
-            .lock()
-            .await
+            .client()
+            .await?
             .transfer(0, amount.as_piconero(), &destination_address.to_string())
             .await?;
 
@@ -116,49 +351,20 @@ impl CreateWalletForOutput for Wallet {
 
         let address = Address::standard(self.network, public_spend_key, public_view_key);
 
-        let _ = self
-            .inner
-            .lock()
-            .await
-            .generate_from_keys(
-                &address.to_string(),
-                &private_spend_key.to_string(),
-                &PrivateKey::from(private_view_key).to_string(),
-                restore_height.height,
-            )
-            .await?;
-
-        Ok(())
-    }
-}
-
-#[async_trait]
-impl CreateWalletForOutputThenLoadDefaultWallet for Wallet {
-    async fn create_and_load_wallet_for_output_then_load_default_wallet(
-        &self,
-        private_spend_key: PrivateKey,
-        private_view_key: PrivateViewKey,
-        restore_height: BlockHeight,
-    ) -> Result<()> {
-        let public_spend_key = PublicKey::from_private_key(&private_spend_key);
-        let public_view_key = PublicKey::from_private_key(&private_view_key.into());
-
-        let address = Address::standard(self.network, public_spend_key, public_view_key);
-
-        let wallet = self.inner.lock().await;
+        let file_name = PrivateKey::from(private_view_key).to_string();
 
-        let _ = wallet
+        let _ = self
+            .client()
+            .await?
             .generate_from_keys(
                 &address.to_string(),
                 &private_spend_key.to_string(),
-                &PrivateKey::from(private_view_key).to_string(),
+                &file_name,
                 restore_height.height,
             )
             .await?;
 
-        let _ = wallet
-            .open_wallet(self.default_wallet_name.as_str())
-            .await?;
+        self.set_opened_wallet(&file_name).await;
 
         Ok(())
     }
@@ -167,7 +373,8 @@ impl CreateWalletForOutputThenLoadDefaultWallet for Wallet {
 #[async_trait]
 impl OpenWallet for Wallet {
     async fn open_wallet(&self, file_name: &str) -> Result<()> {
-        self.inner.lock().await.open_wallet(file_name).await?;
+        self.client().await?.open_wallet(file_name).await?;
+        self.set_opened_wallet(file_name).await;
         Ok(())
     }
 }
@@ -175,76 +382,8 @@ impl OpenWallet for Wallet {
 #[async_trait]
 impl CreateWallet for Wallet {
     async fn create_wallet(&self, file_name: &str) -> Result<()> {
-        self.inner.lock().await.create_wallet(file_name).await?;
-        Ok(())
-    }
-}
-
-// TODO: For retry, use `backoff::ExponentialBackoff` in production as opposed
-// to `ConstantBackoff`.
-#[async_trait]
-impl WatchForTransfer for Wallet {
-    async fn watch_for_transfer(
-        &self,
-        public_spend_key: PublicKey,
-        public_view_key: PublicViewKey,
-        transfer_proof: TransferProof,
-        expected_amount: Amount,
-        expected_confirmations: u32,
-    ) -> Result<(), InsufficientFunds> {
-        enum Error {
-            TxNotFound,
-            InsufficientConfirmations,
-            InsufficientFunds { expected: Amount, actual: Amount },
-        }
-
-        let address = Address::standard(self.network, public_spend_key, public_view_key.into());
-
-        let confirmations = AtomicU32::new(0u32);
-
-        let res = retry(ConstantBackoff::new(Duration::from_secs(1)), || async {
-            // NOTE: Currently, this is conflicting IO errors with the transaction not being
-            // in the blockchain yet, or not having enough confirmations on it. All these
-            // errors warrant a retry, but the strategy should probably differ per case
-            let proof = self
-                .inner
-                .lock()
-                .await
-                .check_tx_key(
-                    &String::from(transfer_proof.tx_hash()),
-                    &transfer_proof.tx_key().to_string(),
-                    &address.to_string(),
-                )
-                .await
-                .map_err(|_| backoff::Error::Transient(Error::TxNotFound))?;
-
-            if proof.received != expected_amount.as_piconero() {
-                return Err(backoff::Error::Permanent(Error::InsufficientFunds {
-                    expected: expected_amount,
-                    actual: Amount::from_piconero(proof.received),
-                }));
-            }
-
-            if proof.confirmations > confirmations.load(Ordering::SeqCst) {
-                confirmations.store(proof.confirmations, Ordering::SeqCst);
-                info!(
-                    "Monero lock tx received {} out of {} confirmations",
-                    proof.confirmations, expected_confirmations
-                );
-            }
-
-            if proof.confirmations < expected_confirmations {
-                return Err(backoff::Error::Transient(Error::InsufficientConfirmations));
-            }
-
-            Ok(proof)
-        })
-        .await;
-
-        if let Err(Error::InsufficientFunds { expected, actual }) = res {
-            return Err(InsufficientFunds { expected, actual });
-        };
-
+        self.client().await?.create_wallet(file_name).await?;
+        self.set_opened_wallet(file_name).await;
         Ok(())
     }
 }
@@ -1,28 +1,29 @@
+use crate::monero::DaemonConnection;
 use ::monero::Network;
-use anyhow::{Context, Result};
+use anyhow::{bail, Context, Result};
 use big_bytes::BigByte;
-use futures::{StreamExt, TryStreamExt};
+use futures::StreamExt;
 use reqwest::{header::CONTENT_LENGTH, Url};
+use sha2::{Digest, Sha256};
 use std::{
-    io::ErrorKind,
+    ffi::OsString,
     path::{Path, PathBuf},
     process::Stdio,
+    time::Duration,
 };
 use tokio::{
     fs::{remove_file, OpenOptions},
-    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    io::{AsyncBufReadExt, AsyncRead, AsyncWriteExt, BufReader},
     process::{Child, Command},
-};
-use tokio_util::{
-    codec::{BytesCodec, FramedRead},
-    io::StreamReader,
+    sync::watch,
+    task::JoinHandle,
 };
 
 #[cfg(not(any(target_os = "macos", target_os = "linux", target_os = "windows")))]
 compile_error!("unsupported operating system");
 
 #[cfg(target_os = "macos")]
-const DOWNLOAD_URL: &str = "http://downloads.getmonero.org/cli/monero-mac-x64-v0.17.1.9.tar.bz2";
+const DOWNLOAD_URL: &str = "https://downloads.getmonero.org/cli/monero-mac-x64-v0.17.1.9.tar.bz2";
 
 #[cfg(target_os = "linux")]
 const DOWNLOAD_URL: &str = "https://downloads.getmonero.org/cli/monero-linux-x64-v0.17.1.9.tar.bz2";
@@ -30,6 +31,20 @@ const DOWNLOAD_URL: &str = "https://downloads.getmonero.org/cli/monero-linux-x64
 #[cfg(target_os = "windows")]
 const DOWNLOAD_URL: &str = "https://downloads.getmonero.org/cli/monero-win-x64-v0.17.1.9.zip";
 
+// SHA-256 of the archives above as published in https://www.getmonero.org/downloads/hashes.txt
+// Archives without a pinned checksum are not downloaded at all.
+// TODO: Pin the macOS and Windows archives.
+
+#[cfg(target_os = "macos")]
+const DOWNLOAD_SHA256: Option<&str> = None;
+
+#[cfg(target_os = "linux")]
+const DOWNLOAD_SHA256: Option<&str> =
+    Some("0fb6f53b7b9b3b205151c652b6c9ca7e735f80bfe78427d1061f042723ee6381");
+
+#[cfg(target_os = "windows")]
+const DOWNLOAD_SHA256: Option<&str> = None;
+
 #[cfg(any(target_os = "macos", target_os = "linux"))]
 const PACKED_FILE: &str = "monero-wallet-rpc";
 
@@ -40,9 +55,60 @@ const PACKED_FILE: &str = "monero-wallet-rpc.exe";
 #[error("monero wallet rpc executable not found in downloaded archive")]
 pub struct ExecutableNotFoundInArchive;
 
+#[derive(Debug, Clone, thiserror::Error)]
+#[error("downloaded monero wallet rpc archive has SHA-256 {actual} but {expected} was expected")]
+pub struct ChecksumMismatch {
+    pub expected: String,
+    pub actual: String,
+}
+
+/// Archive to download monero-wallet-rpc from.
+#[derive(Debug, Clone)]
+pub struct Download {
+    pub url: Url,
+    /// Hex encoded SHA-256 of the archive.
+    pub sha256: String,
+}
+
+impl Download {
+    /// The pinned release from the official server.
+    pub fn official() -> Result<Self> {
+        Self::mirror(DOWNLOAD_URL.parse().expect("download url to be valid"))
+    }
+
+    /// Downloads the pinned release from `url` instead of the official
+    /// server. The archive is still checked against the pinned checksum.
+    pub fn mirror(url: Url) -> Result<Self> {
+        let sha256 = DOWNLOAD_SHA256.context(
+            "No checksum of monero-wallet-rpc is pinned for this platform, set `monero.wallet_rpc_path` \
+             to use a monero-wallet-rpc binary you installed yourself",
+        )?;
+
+        Ok(Self {
+            url,
+            sha256: sha256.to_string(),
+        })
+    }
+}
+
+const RESTART_DELAY: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum WalletRpcStatus {
+    /// `restarts` counts how often the process had to be restarted, wallets
+    /// opened before the last restart have to be opened again.
+    Running {
+        restarts: u32,
+    },
+    Restarting,
+}
+
+/// A monero-wallet-rpc process that is restarted on the same port whenever it
+/// exits. The process is killed once this is dropped.
 pub struct WalletRpcProcess {
-    _child: Child,
     port: u16,
+    status: watch::Receiver<WalletRpcStatus>,
+    supervisor: JoinHandle<()>,
 }
 
 impl WalletRpcProcess {
@@ -50,14 +116,54 @@ impl WalletRpcProcess {
         Url::parse(&format!("http://127.0.0.1:{}/json_rpc", self.port))
             .expect("Static url template is always valid")
     }
+
+    pub fn status(&self) -> watch::Receiver<WalletRpcStatus> {
+        self.status.clone()
+    }
+}
+
+impl Drop for WalletRpcProcess {
+    fn drop(&mut self) {
+        self.supervisor.abort();
+    }
 }
 
 pub struct WalletRpc {
     working_dir: PathBuf,
+    exec_path: PathBuf,
 }
 
 impl WalletRpc {
     pub async fn new(working_dir: impl AsRef<Path>) -> Result<WalletRpc> {
+        WalletRpc::download(working_dir, Download::official()?).await
+    }
+
+    /// Uses a monero-wallet-rpc binary that is managed by the user, nothing is
+    /// downloaded or verified.
+    pub async fn from_executable(
+        working_dir: impl AsRef<Path>,
+        exec_path: impl AsRef<Path>,
+    ) -> Result<WalletRpc> {
+        let working_dir = working_dir.as_ref();
+        let exec_path = exec_path.as_ref();
+
+        if !exec_path.exists() {
+            bail!("monero-wallet-rpc not found at {}", exec_path.display())
+        }
+
+        if !working_dir.exists() {
+            tokio::fs::create_dir(working_dir).await?;
+        }
+
+        Ok(WalletRpc {
+            working_dir: working_dir.to_path_buf(),
+            exec_path: exec_path.to_path_buf(),
+        })
+    }
+
+    /// Downloads monero-wallet-rpc into `working_dir` unless the archive
+    /// matching `download.sha256` has already been installed there.
+    pub async fn download(working_dir: impl AsRef<Path>, download: Download) -> Result<WalletRpc> {
         let working_dir = working_dir.as_ref();
 
         if !working_dir.exists() {
@@ -66,60 +172,89 @@ impl WalletRpc {
 
         let monero_wallet_rpc = WalletRpc {
             working_dir: working_dir.to_path_buf(),
+            exec_path: working_dir.join(PACKED_FILE),
         };
 
-        if monero_wallet_rpc.archive_path().exists() {
-            remove_file(monero_wallet_rpc.archive_path()).await?;
+        // Leftovers of an interrupted download.
+        for path in &[
+            monero_wallet_rpc.archive_path(),
+            monero_wallet_rpc.staging_path(),
+        ] {
+            if path.exists() {
+                remove_file(path).await?;
+            }
         }
 
-        if !monero_wallet_rpc.exec_path().exists() {
-            let mut options = OpenOptions::new();
-            let mut file = options
-                .read(true)
-                .write(true)
-                .create_new(true)
-                .open(monero_wallet_rpc.archive_path())
-                .await?;
-
-            let response = reqwest::get(DOWNLOAD_URL).await?;
-
-            let content_length = response.headers()[CONTENT_LENGTH]
-                .to_str()
-                .context("failed to convert content-length to string")?
-                .parse::<u64>()?;
-
-            tracing::info!(
-                "Downloading monero-wallet-rpc ({})",
-                content_length.big_byte(2)
-            );
-
-            let byte_stream = response
-                .bytes_stream()
-                .map_err(|err| std::io::Error::new(ErrorKind::Other, err));
-
-            #[cfg(not(target_os = "windows"))]
-            let mut stream = FramedRead::new(
-                async_compression::tokio::bufread::BzDecoder::new(StreamReader::new(byte_stream)),
-                BytesCodec::new(),
-            )
-            .map_ok(|bytes| bytes.freeze());
-
-            #[cfg(target_os = "windows")]
-            let mut stream = FramedRead::new(StreamReader::new(byte_stream), BytesCodec::new())
-                .map_ok(|bytes| bytes.freeze());
-
-            while let Some(chunk) = stream.next().await {
-                file.write(&chunk?).await?;
+        if monero_wallet_rpc.exec_path().exists() {
+            if monero_wallet_rpc.is_up_to_date(&download).await {
+                return Ok(monero_wallet_rpc);
             }
 
-            file.flush().await?;
+            tracing::info!("Updating monero-wallet-rpc");
+        }
 
-            Self::extract_archive(&monero_wallet_rpc).await?;
+        let mut options = OpenOptions::new();
+        let mut file = options
+            .read(true)
+            .write(true)
+            .create_new(true)
+            .open(monero_wallet_rpc.archive_path())
+            .await?;
+
+        let response = reqwest::get(download.url.clone())
+            .await?
+            .error_for_status()?;
+
+        let content_length = response.headers()[CONTENT_LENGTH]
+            .to_str()
+            .context("failed to convert content-length to string")?
+            .parse::<u64>()?;
+
+        tracing::info!(
+            "Downloading monero-wallet-rpc ({}) from {}",
+            content_length.big_byte(2),
+            download.url
+        );
+
+        let mut hasher = Sha256::new();
+        let mut stream = response.bytes_stream();
+
+        while let Some(chunk) = stream.next().await {
+            let chunk = chunk?;
+            hasher.update(&chunk);
+            file.write_all(&chunk).await?;
         }
+
+        file.flush().await?;
+
+        let actual = hex::encode(hasher.finalize());
+        if actual != download.sha256 {
+            remove_file(monero_wallet_rpc.archive_path()).await?;
+            bail!(ChecksumMismatch {
+                expected: download.sha256,
+                actual
+            })
+        }
+
+        // The installed binary is only replaced once the new one is extracted
+        // completely.
+        Self::extract_archive(&monero_wallet_rpc).await?;
+        tokio::fs::rename(
+            monero_wallet_rpc.staging_path(),
+            monero_wallet_rpc.exec_path(),
+        )
+        .await?;
+
+        tokio::fs::write(monero_wallet_rpc.version_path(), download.sha256).await?;
+
         Ok(monero_wallet_rpc)
     }
 
-    pub async fn run(&self, network: Network, daemon_host: &str) -> Result<WalletRpcProcess> {
+    pub async fn run(
+        &self,
+        network: Network,
+        daemon: &DaemonConnection,
+    ) -> Result<WalletRpcProcess> {
         let port = tokio::net::TcpListener::bind("127.0.0.1:0")
             .await?
             .local_addr()?
@@ -127,39 +262,50 @@ impl WalletRpc {
 
         tracing::debug!("Starting monero-wallet-rpc on port {}", port);
 
-        let mut child = Command::new(self.exec_path())
-            .stdout(Stdio::piped())
-            .kill_on_drop(true)
-            .arg(match network {
-                Network::Mainnet => "--mainnet",
-                Network::Stagenet => "--stagenet",
-                Network::Testnet => "--testnet",
-            })
-            .arg("--daemon-host")
-            .arg(daemon_host)
-            .arg("--rpc-bind-port")
-            .arg(format!("{}", port))
-            .arg("--disable-rpc-login")
-            .arg("--wallet-dir")
-            .arg(self.working_dir.join("monero-data"))
-            .spawn()?;
-
-        let stdout = child
-            .stdout
-            .take()
-            .expect("monero wallet rpc stdout was not piped parent process");
-
-        let mut reader = BufReader::new(stdout).lines();
-
-        while let Some(line) = reader.next_line().await? {
-            if line.contains("Starting wallet RPC server") {
-                break;
-            }
+        let network_flag = match network {
+            Network::Mainnet => "--mainnet",
+            Network::Stagenet => "--stagenet",
+            Network::Testnet => "--testnet",
+        };
+
+        let mut args: Vec<OsString> = vec![
+            network_flag.into(),
+            "--daemon-host".into(),
+            daemon.host.clone().into(),
+            "--daemon-port".into(),
+            format!("{}", daemon.port).into(),
+            "--daemon-ssl".into(),
+            daemon.ssl.mode.to_string().into(),
+        ];
+
+        if let Some(login) = &daemon.login {
+            args.push("--daemon-login".into());
+            args.push(format!("{}:{}", login.username, login.password).into());
+        }
+        if daemon.ssl.allow_any_cert {
+            args.push("--daemon-ssl-allow-any-cert".into());
+        }
+        if let Some(ca_certificates) = &daemon.ssl.ca_certificates {
+            args.push("--daemon-ssl-ca-certificates".into());
+            args.push(ca_certificates.into());
         }
 
+        args.push("--rpc-bind-port".into());
+        args.push(format!("{}", port).into());
+        args.push("--disable-rpc-login".into());
+        args.push("--wallet-dir".into());
+        args.push(self.working_dir.join("monero-data").into());
+
+        let exec_path = self.exec_path();
+        let child = start(&exec_path, &args).await?;
+
+        let (status_sender, status) = watch::channel(WalletRpcStatus::Running { restarts: 0 });
+        let supervisor = tokio::spawn(supervise(child, exec_path, args, status_sender));
+
         Ok(WalletRpcProcess {
-            _child: child,
             port,
+            status,
+            supervisor,
         })
     }
 
@@ -168,12 +314,33 @@ impl WalletRpc {
     }
 
     fn exec_path(&self) -> PathBuf {
-        self.working_dir.join(PACKED_FILE)
+        self.exec_path.clone()
+    }
+
+    /// The binary is extracted here before it replaces the installed one.
+    fn staging_path(&self) -> PathBuf {
+        self.working_dir.join(format!("{}.download", PACKED_FILE))
+    }
+
+    /// Holds the SHA-256 of the archive the installed binary was extracted
+    /// from.
+    fn version_path(&self) -> PathBuf {
+        self.working_dir.join("monero-wallet-rpc.version")
+    }
+
+    async fn installed_version(&self) -> Option<String> {
+        let version = tokio::fs::read_to_string(self.version_path()).await.ok()?;
+
+        Some(version.trim().to_string())
+    }
+
+    async fn is_up_to_date(&self, download: &Download) -> bool {
+        self.installed_version().await.as_deref() == Some(download.sha256.as_str())
     }
 
     #[cfg(not(target_os = "windows"))]
     async fn extract_archive(monero_wallet_rpc: &Self) -> Result<()> {
-        use anyhow::bail;
+        use async_compression::tokio::bufread::BzDecoder;
         use tokio_tar::Archive;
 
         let mut options = OpenOptions::new();
@@ -182,7 +349,7 @@ impl WalletRpc {
             .open(monero_wallet_rpc.archive_path())
             .await?;
 
-        let mut ar = Archive::new(file);
+        let mut ar = Archive::new(BzDecoder::new(BufReader::new(file)));
         let mut entries = ar.entries()?;
 
         loop {
@@ -194,7 +361,7 @@ impl WalletRpc {
                         .context("Could not find convert path to str in tar ball")?
                         .contains(PACKED_FILE)
                     {
-                        f.unpack(monero_wallet_rpc.exec_path()).await?;
+                        f.unpack(monero_wallet_rpc.staging_path()).await?;
                         break;
                     }
                 }
@@ -214,7 +381,7 @@ impl WalletRpc {
         use zip::ZipArchive;
 
         let archive_path = monero_wallet_rpc.archive_path();
-        let exec_path = monero_wallet_rpc.exec_path();
+        let staging_path = monero_wallet_rpc.staging_path();
 
         let extract: JoinHandle<Result<()>> = tokio::task::spawn_blocking(|| {
             let file = File::open(archive_path)?;
@@ -227,7 +394,7 @@ impl WalletRpc {
                 .to_string();
 
             let mut rpc = zip.by_name(&name)?;
-            let mut file = File::create(exec_path)?;
+            let mut file = File::create(staging_path)?;
             std::io::copy(&mut rpc, &mut file)?;
             Ok(())
         });
@@ -238,3 +405,237 @@ impl WalletRpc {
         Ok(())
     }
 }
+
+/// Spawns monero-wallet-rpc and waits until its RPC server is up.
+async fn start(exec_path: &Path, args: &[OsString]) -> Result<Child> {
+    let mut child = Command::new(exec_path)
+        .args(args)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()?;
+
+    let stdout = child
+        .stdout
+        .take()
+        .expect("monero wallet rpc stdout was not piped parent process");
+    let stderr = child
+        .stderr
+        .take()
+        .expect("monero wallet rpc stderr was not piped parent process");
+
+    tokio::spawn(log_stderr(stderr));
+
+    let mut reader = BufReader::new(stdout).lines();
+
+    loop {
+        match reader.next_line().await? {
+            Some(line) => {
+                tracing::debug!("monero-wallet-rpc: {}", line);
+
+                if line.contains("Starting wallet RPC server") {
+                    break;
+                }
+            }
+            None => bail!("monero-wallet-rpc exited before starting its RPC server"),
+        }
+    }
+
+    tokio::spawn(async move {
+        while let Ok(Some(line)) = reader.next_line().await {
+            tracing::debug!("monero-wallet-rpc: {}", line);
+        }
+    });
+
+    Ok(child)
+}
+
+async fn log_stderr(stderr: impl AsyncRead + Unpin) {
+    let mut lines = BufReader::new(stderr).lines();
+
+    while let Ok(Some(line)) = lines.next_line().await {
+        tracing::warn!("monero-wallet-rpc: {}", line);
+    }
+}
+
+/// Restarts monero-wallet-rpc with the same arguments, and hence on the same
+/// port and with the same wallet dir, whenever it exits.
+async fn supervise(
+    mut child: Child,
+    exec_path: PathBuf,
+    args: Vec<OsString>,
+    status: watch::Sender<WalletRpcStatus>,
+) {
+    let mut restarts = 0;
+
+    loop {
+        match child.wait().await {
+            Ok(exit_status) => {
+                tracing::warn!("monero-wallet-rpc exited with {}, restarting", exit_status)
+            }
+            Err(e) => {
+                tracing::warn!("Failed to watch monero-wallet-rpc, restarting: {:#}", e);
+                let _ = child.kill().await;
+            }
+        }
+
+        let _ = status.send(WalletRpcStatus::Restarting);
+
+        child = loop {
+            match start(&exec_path, &args).await {
+                Ok(child) => break child,
+                Err(e) => {
+                    tracing::warn!("Failed to restart monero-wallet-rpc: {:#}", e);
+                    tokio::time::sleep(RESTART_DELAY).await;
+                }
+            }
+        };
+
+        restarts += 1;
+        tracing::info!("Restarted monero-wallet-rpc");
+        let _ = status.send(WalletRpcStatus::Running { restarts });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hyper::{
+        service::{make_service_fn, service_fn},
+        Body, Response, Server,
+    };
+    use std::convert::Infallible;
+    use tempfile::tempdir;
+
+    const NOT_THE_ARCHIVE: &[u8] = b"not the monero wallet rpc archive";
+
+    fn pinned_sha256() -> String {
+        hex::encode(Sha256::digest(b"the monero wallet rpc archive"))
+    }
+
+    fn pinned_download(url: Url) -> Download {
+        Download {
+            url,
+            sha256: pinned_sha256(),
+        }
+    }
+
+    /// Serves `archive` for every request on a local port.
+    fn serve(archive: &'static [u8]) -> Url {
+        let make_service = make_service_fn(move |_| async move {
+            Ok::<_, Infallible>(service_fn(move |_| async move {
+                Ok::<_, Infallible>(Response::new(Body::from(archive)))
+            }))
+        });
+        let server = Server::bind(&([127, 0, 0, 1], 0).into()).serve(make_service);
+        let url = format!("http://{}/monero.tar.bz2", server.local_addr())
+            .parse()
+            .unwrap();
+
+        tokio::spawn(server);
+
+        url
+    }
+
+    async fn install(working_dir: &Path, version: &str) {
+        tokio::fs::write(working_dir.join(PACKED_FILE), b"monero-wallet-rpc")
+            .await
+            .unwrap();
+        tokio::fs::write(working_dir.join("monero-wallet-rpc.version"), version)
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn rejects_archive_with_wrong_checksum() {
+        let url = serve(NOT_THE_ARCHIVE);
+        let working_dir = tempdir().unwrap();
+
+        let error = match WalletRpc::download(working_dir.path(), pinned_download(url)).await {
+            Ok(_) => panic!("archive with wrong checksum must be rejected"),
+            Err(error) => error,
+        };
+
+        let mismatch = error.downcast::<ChecksumMismatch>().unwrap();
+        assert_eq!(mismatch.expected, pinned_sha256());
+        assert_eq!(
+            mismatch.actual,
+            hex::encode(Sha256::digest(NOT_THE_ARCHIVE))
+        );
+        assert!(!working_dir.path().join(PACKED_FILE).exists());
+        assert!(!working_dir
+            .path()
+            .join("monero-cli-wallet.archive")
+            .exists());
+    }
+
+    #[tokio::test]
+    async fn keeps_installed_binary_of_pinned_version() {
+        let url = serve(NOT_THE_ARCHIVE);
+        let working_dir = tempdir().unwrap();
+        install(working_dir.path(), &pinned_sha256()).await;
+
+        let wallet_rpc = WalletRpc::download(working_dir.path(), pinned_download(url))
+            .await
+            .unwrap();
+
+        assert_eq!(wallet_rpc.exec_path(), working_dir.path().join(PACKED_FILE));
+        assert!(wallet_rpc.exec_path().exists());
+    }
+
+    #[tokio::test]
+    async fn keeps_installed_binary_of_other_version_if_update_fails() {
+        let url = serve(NOT_THE_ARCHIVE);
+        let working_dir = tempdir().unwrap();
+        install(working_dir.path(), "outdated").await;
+
+        let result = WalletRpc::download(working_dir.path(), pinned_download(url)).await;
+
+        assert!(result.is_err());
+        assert_eq!(
+            tokio::fs::read(working_dir.path().join(PACKED_FILE))
+                .await
+                .unwrap(),
+            b"monero-wallet-rpc"
+        );
+    }
+
+    #[cfg(not(target_os = "windows"))]
+    #[tokio::test]
+    async fn restarts_exited_process() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let working_dir = tempdir().unwrap();
+        let exec_path = working_dir.path().join("fake-wallet-rpc");
+        tokio::fs::write(
+            &exec_path,
+            "#!/bin/sh\necho 'Starting wallet RPC server'\nsleep 1\n",
+        )
+        .await
+        .unwrap();
+        tokio::fs::set_permissions(&exec_path, std::fs::Permissions::from_mode(0o755))
+            .await
+            .unwrap();
+        let daemon = DaemonConnection {
+            host: "localhost".to_string(),
+            port: 38081,
+            login: None,
+            ssl: Default::default(),
+        };
+
+        let wallet_rpc = WalletRpc::from_executable(working_dir.path(), &exec_path)
+            .await
+            .unwrap();
+        let process = wallet_rpc.run(Network::Stagenet, &daemon).await.unwrap();
+        let mut status = process.status();
+
+        assert_eq!(*status.borrow(), WalletRpcStatus::Running { restarts: 0 });
+        tokio::time::timeout(Duration::from_secs(10), async {
+            while *status.borrow() != (WalletRpcStatus::Running { restarts: 1 }) {
+                status.changed().await.unwrap();
+            }
+        })
+        .await
+        .unwrap();
+    }
+}
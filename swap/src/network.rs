@@ -1,5 +1,9 @@
+pub mod handshake;
+pub mod outbox;
 pub mod peer_tracker;
+pub mod rendezvous;
 pub mod request_response;
+pub mod tor;
 pub mod transport;
 
 use futures::prelude::*;
@@ -0,0 +1,273 @@
+use crate::{
+    bitcoin, monero,
+    network::request_response::{is_retryable, CborCodec, HandshakeProtocol, HANDSHAKE_TIMEOUT},
+};
+use anyhow::{anyhow, Error, Result};
+use libp2p::{
+    request_response::{
+        OutboundFailure, ProtocolSupport, RequestId, RequestResponse, RequestResponseConfig,
+        RequestResponseEvent, RequestResponseMessage, ResponseChannel,
+    },
+    NetworkBehaviour, PeerId,
+};
+use serde::{Deserialize, Serialize};
+use tracing::debug;
+
+/// Version of the messages exchanged during a swap.
+///
+/// Bump this on any change to the execution setup messages (`Message0` to
+/// `Message4`), the transfer proof or the encrypted signature. Peers without
+/// a common version refuse to swap with each other.
+pub const SWAP_PROTOCOL_VERSION: u32 = 1;
+
+/// What a peer tells the other party about itself right after connecting.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct Capabilities {
+    pub software_version: String,
+    pub bitcoin_network: bitcoin::Network,
+    pub monero_network: monero::Network,
+    pub protocol_versions: Vec<u32>,
+}
+
+impl Capabilities {
+    pub fn new(bitcoin_network: bitcoin::Network, monero_network: monero::Network) -> Self {
+        Self {
+            software_version: env!("CARGO_PKG_VERSION").to_string(),
+            bitcoin_network,
+            monero_network,
+            protocol_versions: vec![SWAP_PROTOCOL_VERSION],
+        }
+    }
+
+    /// Checks whether we can swap with a peer that announced `theirs`.
+    pub fn check_compatible(&self, theirs: &Capabilities) -> Result<(), Incompatible> {
+        if self.bitcoin_network != theirs.bitcoin_network {
+            return Err(Incompatible::BitcoinNetwork {
+                ours: self.bitcoin_network,
+                theirs: theirs.bitcoin_network,
+            });
+        }
+
+        if self.monero_network != theirs.monero_network {
+            return Err(Incompatible::MoneroNetwork {
+                ours: self.monero_network,
+                theirs: theirs.monero_network,
+            });
+        }
+
+        if !self
+            .protocol_versions
+            .iter()
+            .any(|version| theirs.protocol_versions.contains(version))
+        {
+            return Err(Incompatible::ProtocolVersion {
+                software_version: theirs.software_version.clone(),
+                ours: self.protocol_versions.clone(),
+                theirs: theirs.protocol_versions.clone(),
+            });
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug, thiserror::Error, PartialEq)]
+pub enum Incompatible {
+    #[error("peer is on Bitcoin network {theirs} but we are on {ours}")]
+    BitcoinNetwork {
+        ours: bitcoin::Network,
+        theirs: bitcoin::Network,
+    },
+    #[error("peer is on Monero network {theirs:?} but we are on {ours:?}")]
+    MoneroNetwork {
+        ours: monero::Network,
+        theirs: monero::Network,
+    },
+    #[error("peer runs version {software_version} which supports swap protocol versions {theirs:?}, we support {ours:?}")]
+    ProtocolVersion {
+        software_version: String,
+        ours: Vec<u32>,
+        theirs: Vec<u32>,
+    },
+}
+
+#[derive(Debug)]
+pub enum OutEvent {
+    /// The peer sent us its capabilities and waits for ours.
+    Request {
+        peer: PeerId,
+        capabilities: Capabilities,
+        channel: ResponseChannel<Capabilities>,
+    },
+    /// The peer answered our handshake with its capabilities.
+    Response {
+        peer: PeerId,
+        capabilities: Capabilities,
+    },
+    ResponseSent,
+    /// The handshake failed, sending it again may help if it is `retryable`.
+    Failure {
+        peer: PeerId,
+        error: Error,
+        retryable: bool,
+    },
+}
+
+impl From<RequestResponseEvent<Capabilities, Capabilities>> for OutEvent {
+    fn from(event: RequestResponseEvent<Capabilities, Capabilities>) -> Self {
+        match event {
+            RequestResponseEvent::Message {
+                peer,
+                message: RequestResponseMessage::Request {
+                    request, channel, ..
+                },
+            } => {
+                debug!("Received handshake from {}", peer);
+                OutEvent::Request {
+                    peer,
+                    capabilities: request,
+                    channel,
+                }
+            }
+            RequestResponseEvent::Message {
+                peer,
+                message: RequestResponseMessage::Response { response, .. },
+            } => OutEvent::Response {
+                peer,
+                capabilities: response,
+            },
+            RequestResponseEvent::OutboundFailure {
+                peer,
+                error: OutboundFailure::UnsupportedProtocols,
+                ..
+            } => OutEvent::Failure {
+                peer,
+                error: anyhow!("Peer does not support the handshake protocol, it most likely runs an incompatible version"),
+                retryable: false,
+            },
+            RequestResponseEvent::OutboundFailure { peer, error, .. } => OutEvent::Failure {
+                peer,
+                retryable: is_retryable(&error),
+                error: anyhow!("Outbound failure: {:?}", error),
+            },
+            RequestResponseEvent::InboundFailure { peer, error, .. } => OutEvent::Failure {
+                peer,
+                error: anyhow!("Inbound failure: {:?}", error),
+                retryable: false,
+            },
+            RequestResponseEvent::ResponseSent { .. } => OutEvent::ResponseSent,
+        }
+    }
+}
+
+/// A `NetworkBehaviour` that exchanges [`Capabilities`] with a peer. The
+/// dialer sends its capabilities and the listener answers with its own.
+#[derive(NetworkBehaviour)]
+#[behaviour(out_event = "OutEvent", event_process = false)]
+#[allow(missing_debug_implementations)]
+pub struct Behaviour {
+    rr: RequestResponse<CborCodec<HandshakeProtocol, Capabilities, Capabilities>>,
+    #[behaviour(ignore)]
+    capabilities: Capabilities,
+}
+
+impl Behaviour {
+    pub fn new(capabilities: Capabilities) -> Self {
+        let mut config = RequestResponseConfig::default();
+        config.set_request_timeout(HANDSHAKE_TIMEOUT);
+
+        Self {
+            rr: RequestResponse::new(
+                CborCodec::default(),
+                vec![(HandshakeProtocol, ProtocolSupport::Full)],
+                config,
+            ),
+            capabilities,
+        }
+    }
+
+    pub fn capabilities(&self) -> &Capabilities {
+        &self.capabilities
+    }
+
+    /// Sends our capabilities to `peer`, the answer is reported as
+    /// [`OutEvent::Response`].
+    pub fn handshake(&mut self, peer: PeerId) -> RequestId {
+        self.rr.send_request(&peer, self.capabilities.clone())
+    }
+
+    pub fn respond(&mut self, channel: ResponseChannel<Capabilities>) -> Result<()> {
+        self.rr
+            .send_response(channel, self.capabilities.clone())
+            .map_err(|_| anyhow!("failed to send handshake response"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn capabilities() -> Capabilities {
+        Capabilities::new(bitcoin::Network::Testnet, monero::Network::Stagenet)
+    }
+
+    #[test]
+    fn peers_with_a_common_protocol_version_are_compatible() {
+        let ours = capabilities();
+        let theirs = Capabilities {
+            software_version: "0.0.0".to_string(),
+            protocol_versions: vec![SWAP_PROTOCOL_VERSION, SWAP_PROTOCOL_VERSION + 1],
+            ..capabilities()
+        };
+
+        assert_eq!(ours.check_compatible(&theirs), Ok(()));
+    }
+
+    #[test]
+    fn peers_on_different_networks_are_incompatible() {
+        let ours = capabilities();
+
+        let theirs = Capabilities {
+            bitcoin_network: bitcoin::Network::Bitcoin,
+            ..capabilities()
+        };
+        assert_eq!(
+            ours.check_compatible(&theirs),
+            Err(Incompatible::BitcoinNetwork {
+                ours: bitcoin::Network::Testnet,
+                theirs: bitcoin::Network::Bitcoin,
+            })
+        );
+
+        let theirs = Capabilities {
+            monero_network: monero::Network::Mainnet,
+            ..capabilities()
+        };
+        assert_eq!(
+            ours.check_compatible(&theirs),
+            Err(Incompatible::MoneroNetwork {
+                ours: monero::Network::Stagenet,
+                theirs: monero::Network::Mainnet,
+            })
+        );
+    }
+
+    #[test]
+    fn peers_without_a_common_protocol_version_are_incompatible() {
+        let ours = capabilities();
+        let theirs = Capabilities {
+            software_version: "9.9.9".to_string(),
+            protocol_versions: vec![SWAP_PROTOCOL_VERSION + 1],
+            ..capabilities()
+        };
+
+        assert_eq!(
+            ours.check_compatible(&theirs),
+            Err(Incompatible::ProtocolVersion {
+                software_version: "9.9.9".to_string(),
+                ours: vec![SWAP_PROTOCOL_VERSION],
+                theirs: vec![SWAP_PROTOCOL_VERSION + 1],
+            })
+        );
+    }
+}
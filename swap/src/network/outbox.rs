@@ -0,0 +1,208 @@
+use crate::{bitcoin, bitcoin::CancelTimelock, database::Database};
+use anyhow::Result;
+use libp2p::{request_response::RequestId, PeerId};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::{collections::HashMap, sync::Arc, time::Duration};
+use tokio::sync::mpsc;
+use tracing::{debug, info};
+use uuid::Uuid;
+
+/// How often undelivered messages are sent again.
+pub const RETRY_INTERVAL: Duration = Duration::from_secs(10);
+
+/// The wallet retries until the Bitcoin backend answers, a message whose
+/// expiry could not be checked in time is checked again with the next retry.
+const EXPIRY_CHECK_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// A protocol message the counterparty has to receive for the swap to
+/// succeed.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct PendingMessage<M> {
+    #[serde(with = "crate::serde_peer_id")]
+    pub peer_id: PeerId,
+    pub message: M,
+    /// Once the cancel timelock of this lock transaction expired the swap is
+    /// cancelled and the message is pointless.
+    pub tx_lock_id: bitcoin::Txid,
+    pub cancel_timelock: CancelTimelock,
+}
+
+impl<M> PendingMessage<M> {
+    async fn is_expired(&self, bitcoin_wallet: &bitcoin::Wallet) -> Result<bool> {
+        let current_block_height = bitcoin_wallet.get_block_height().await?;
+        let tx_lock_height = bitcoin_wallet
+            .transaction_block_height(self.tx_lock_id)
+            .await?;
+
+        Ok(current_block_height >= tx_lock_height + self.cancel_timelock)
+    }
+}
+
+/// Keeps the protocol messages of an event loop until the counterparty
+/// acknowledged them.
+///
+/// Messages are persisted when they are queued, see [`Outbox::persist`], so
+/// that a dropped connection or a restart does not lose them. The event loop
+/// sends every message that is not in flight on reconnect and every
+/// [`RETRY_INTERVAL`].
+#[derive(Debug)]
+pub struct Outbox<M> {
+    name: &'static str,
+    db: Database,
+    pending: HashMap<Uuid, PendingMessage<M>>,
+    in_flight: HashMap<RequestId, Uuid>,
+    /// Whether a spawned expiry check has not reported back yet.
+    checking_expiry: bool,
+    expired_sender: mpsc::Sender<Vec<Uuid>>,
+    expired: mpsc::Receiver<Vec<Uuid>>,
+}
+
+impl<M> Outbox<M>
+where
+    M: Clone + Serialize + DeserializeOwned,
+{
+    /// Loads the messages of the outbox `name` that were not acknowledged
+    /// before the last shutdown.
+    pub fn load(name: &'static str, db: Database) -> Result<Self> {
+        let pending = db.outbox_messages(name)?.into_iter().collect();
+        let (expired_sender, expired) = mpsc::channel(1);
+
+        Ok(Self {
+            name,
+            db,
+            pending,
+            in_flight: HashMap::new(),
+            checking_expiry: false,
+            expired_sender,
+            expired,
+        })
+    }
+
+    /// Stores `message` in the outbox `name`. Once this returns the message
+    /// survives a restart.
+    pub async fn persist(
+        name: &'static str,
+        db: &Database,
+        swap_id: Uuid,
+        message: &PendingMessage<M>,
+    ) -> Result<()> {
+        db.insert_outbox_message(name, swap_id, message).await
+    }
+
+    /// Queues a message that was persisted with [`Outbox::persist`].
+    pub fn push(&mut self, swap_id: Uuid, message: PendingMessage<M>) {
+        self.pending.insert(swap_id, message);
+    }
+
+    /// Messages that are neither acknowledged nor in flight.
+    pub fn unsent(&self) -> Vec<(Uuid, PendingMessage<M>)> {
+        self.pending
+            .iter()
+            .filter(|(swap_id, _)| !self.in_flight.values().any(|id| id == *swap_id))
+            .map(|(swap_id, message)| (*swap_id, message.clone()))
+            .collect()
+    }
+
+    /// Like [`Outbox::unsent`] but only the messages for `peer_id`.
+    pub fn unsent_to(&self, peer_id: PeerId) -> Vec<(Uuid, PendingMessage<M>)> {
+        self.unsent()
+            .into_iter()
+            .filter(|(_, message)| message.peer_id == peer_id)
+            .collect()
+    }
+
+    /// Whether any message for `peer_id` is still waiting for an
+    /// acknowledgement.
+    pub fn has_pending(&self, peer_id: PeerId) -> bool {
+        self.pending
+            .values()
+            .any(|message| message.peer_id == peer_id)
+    }
+
+    pub fn sent(&mut self, swap_id: Uuid, request_id: RequestId) {
+        self.in_flight.insert(request_id, swap_id);
+    }
+
+    /// The request did not reach the counterparty, the message is sent again
+    /// later.
+    pub fn undelivered(&mut self, request_id: RequestId) {
+        self.in_flight.remove(&request_id);
+    }
+
+    pub async fn acknowledged(&mut self, request_id: RequestId) -> Result<()> {
+        if let Some(swap_id) = self.in_flight.remove(&request_id) {
+            self.remove(swap_id).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Checks in the background which messages are past their cancel
+    /// timelock, there is no point in delivering them anymore. The check
+    /// waits for the Bitcoin wallet and must not hold up the event loop, its
+    /// result is picked up with [`Outbox::next_expired`].
+    pub fn check_expiry(&mut self, bitcoin_wallet: Arc<bitcoin::Wallet>) {
+        let messages = self.unsent();
+        if self.checking_expiry || messages.is_empty() {
+            return;
+        }
+
+        self.checking_expiry = true;
+        let sender = self.expired_sender.clone();
+
+        tokio::spawn(async move {
+            let mut expired = Vec::new();
+
+            for (swap_id, message) in messages {
+                match tokio::time::timeout(
+                    EXPIRY_CHECK_TIMEOUT,
+                    message.is_expired(&bitcoin_wallet),
+                )
+                .await
+                {
+                    Ok(Ok(true)) => expired.push(swap_id),
+                    Ok(Ok(false)) => {}
+                    Ok(Err(error)) => debug!(
+                        "Failed to check whether the message of swap {} expired: {:#}",
+                        swap_id, error
+                    ),
+                    Err(_) => debug!(
+                        "Timed out checking whether the message of swap {} expired",
+                        swap_id
+                    ),
+                }
+            }
+
+            let _ = sender.send(expired).await;
+        });
+    }
+
+    /// Waits for the swaps whose messages were found to be expired by
+    /// [`Outbox::check_expiry`].
+    pub async fn next_expired(&mut self) -> Vec<Uuid> {
+        // The outbox holds a sender itself, the channel is never closed.
+        self.expired.recv().await.unwrap_or_default()
+    }
+
+    /// Drops the messages of the swaps returned by [`Outbox::next_expired`].
+    pub async fn discard_expired(&mut self, swap_ids: Vec<Uuid>) -> Result<()> {
+        self.checking_expiry = false;
+
+        for swap_id in swap_ids {
+            if self.pending.contains_key(&swap_id) {
+                info!(
+                    "Giving up on delivering message of swap {} because the cancel timelock expired",
+                    swap_id
+                );
+                self.remove(swap_id).await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn remove(&mut self, swap_id: Uuid) -> Result<()> {
+        self.pending.remove(&swap_id);
+        self.db.remove_outbox_message(self.name, swap_id).await
+    }
+}
@@ -12,18 +12,42 @@ use std::{
     task::Poll,
 };
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq)]
 pub enum OutEvent {
+    /// The first connection to the peer was established.
     ConnectionEstablished(PeerId),
+    /// The last connection to the peer was closed.
+    ConnectionClosed(PeerId),
 }
 
-/// A NetworkBehaviour that tracks connections to the counterparty. Although the
-/// libp2p `NetworkBehaviour` abstraction encompasses connections to multiple
-/// peers we only ever connect to a single counterparty. Peer Tracker tracks
-/// that connection.
+/// The connection state of a single peer.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Peer {
+    /// Number of open connections, libp2p may keep more than one per peer.
+    connections: usize,
+    /// The address of the most recently established connection.
+    last_seen_address: Option<Multiaddr>,
+}
+
+impl Peer {
+    pub fn is_connected(&self) -> bool {
+        self.connections > 0
+    }
+
+    pub fn last_seen_address(&self) -> Option<&Multiaddr> {
+        self.last_seen_address.as_ref()
+    }
+}
+
+/// A NetworkBehaviour that tracks the connections to all counterparties.
+///
+/// Bob only ever talks to a single Alice but Alice serves any number of Bobs
+/// at the same time, hence the connection state is kept per peer. The
+/// addresses added through [`PeerTracker::add_address`] are the ones we dial,
+/// the address of the last connection is kept as a fallback.
 #[derive(Default, Debug)]
 pub struct PeerTracker {
-    connected: Option<(PeerId, Multiaddr)>,
+    peers: HashMap<PeerId, Peer>,
     address_of_peer: HashMap<PeerId, Multiaddr>,
     events: VecDeque<OutEvent>,
 }
@@ -31,34 +55,47 @@ pub struct PeerTracker {
 impl PeerTracker {
     /// Return whether we are connected to the given peer.
     pub fn is_connected(&self, peer_id: &PeerId) -> bool {
-        if let Some((connected_peer_id, _)) = &self.connected {
-            if connected_peer_id == peer_id {
-                return true;
-            }
-        }
-        false
+        self.peers
+            .get(peer_id)
+            .map(Peer::is_connected)
+            .unwrap_or(false)
     }
 
-    /// Returns the peer id of counterparty if we are connected.
-    pub fn counterparty_peer_id(&self) -> Option<PeerId> {
-        if let Some((id, _)) = &self.connected {
-            return Some(*id);
-        }
-        None
+    /// Returns the peer ids of all peers we are currently connected to.
+    pub fn connected_peers(&self) -> Vec<PeerId> {
+        self.peers
+            .iter()
+            .filter(|(_, peer)| peer.is_connected())
+            .map(|(peer_id, _)| *peer_id)
+            .collect()
     }
 
-    /// Returns the peer_id and multiaddr of counterparty if we are connected.
-    pub fn counterparty(&self) -> Option<(PeerId, Multiaddr)> {
-        if let Some((peer_id, addr)) = &self.connected {
-            return Some((*peer_id, addr.clone()));
-        }
-        None
+    /// Returns the connection state of the given peer if we ever were
+    /// connected to it.
+    pub fn peer(&self, peer_id: &PeerId) -> Option<&Peer> {
+        self.peers.get(peer_id)
     }
 
     /// Add an address for a given peer. We only store one address per peer.
     pub fn add_address(&mut self, peer_id: PeerId, address: Multiaddr) {
         self.address_of_peer.insert(peer_id, address);
     }
+
+    /// Forgets the connection state and the address of every peer we were
+    /// connected to but are not anymore, unless `keep` returns true for it.
+    pub fn forget_disconnected_peers(&mut self, keep: impl Fn(&PeerId) -> bool) {
+        let forgotten = self
+            .peers
+            .iter()
+            .filter(|(peer_id, peer)| !peer.is_connected() && !keep(peer_id))
+            .map(|(peer_id, _)| *peer_id)
+            .collect::<Vec<_>>();
+
+        for peer_id in forgotten {
+            self.peers.remove(&peer_id);
+            self.address_of_peer.remove(&peer_id);
+        }
+    }
 }
 
 impl NetworkBehaviour for PeerTracker {
@@ -72,16 +109,20 @@ impl NetworkBehaviour for PeerTracker {
     fn addresses_of_peer(&mut self, peer_id: &PeerId) -> Vec<Multiaddr> {
         let mut addresses: Vec<Multiaddr> = vec![];
 
-        if let Some((counterparty_peer_id, addr)) = self.counterparty() {
-            if counterparty_peer_id == *peer_id {
-                addresses.push(addr)
-            }
-        }
-
         if let Some(addr) = self.address_of_peer.get(peer_id) {
             addresses.push(addr.clone());
         }
 
+        if let Some(addr) = self
+            .peers
+            .get(peer_id)
+            .and_then(|peer| peer.last_seen_address.clone())
+        {
+            if !addresses.contains(&addr) {
+                addresses.push(addr)
+            }
+        }
+
         addresses
     }
 
@@ -91,28 +132,39 @@ impl NetworkBehaviour for PeerTracker {
 
     fn inject_connection_established(
         &mut self,
-        peer: &PeerId,
+        peer_id: &PeerId,
         _: &ConnectionId,
         point: &ConnectedPoint,
     ) {
-        match point {
-            ConnectedPoint::Dialer { address } => {
-                self.connected = Some((*peer, address.clone()));
-            }
+        let address = match point {
+            ConnectedPoint::Dialer { address } => address.clone(),
             ConnectedPoint::Listener {
                 local_addr: _,
                 send_back_addr,
-            } => {
-                self.connected = Some((*peer, send_back_addr.clone()));
-            }
-        }
+            } => send_back_addr.clone(),
+        };
 
-        self.events
-            .push_back(OutEvent::ConnectionEstablished(*peer));
+        let peer = self.peers.entry(*peer_id).or_insert(Peer {
+            connections: 0,
+            last_seen_address: None,
+        });
+        peer.connections += 1;
+        peer.last_seen_address = Some(address);
+
+        if peer.connections == 1 {
+            self.events
+                .push_back(OutEvent::ConnectionEstablished(*peer_id));
+        }
     }
 
-    fn inject_connection_closed(&mut self, _: &PeerId, _: &ConnectionId, _: &ConnectedPoint) {
-        self.connected = None;
+    fn inject_connection_closed(&mut self, peer_id: &PeerId, _: &ConnectionId, _: &ConnectedPoint) {
+        if let Some(peer) = self.peers.get_mut(peer_id) {
+            peer.connections = peer.connections.saturating_sub(1);
+
+            if peer.connections == 0 {
+                self.events.push_back(OutEvent::ConnectionClosed(*peer_id));
+            }
+        }
     }
 
     fn inject_event(&mut self, _: PeerId, _: ConnectionId, _: void::Void) {}
@@ -129,3 +181,84 @@ impl NetworkBehaviour for PeerTracker {
         Poll::Pending
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn listener(port: u16) -> ConnectedPoint {
+        ConnectedPoint::Listener {
+            local_addr: "/ip4/127.0.0.1/tcp/9939".parse().unwrap(),
+            send_back_addr: format!("/ip4/127.0.0.1/tcp/{}", port).parse().unwrap(),
+        }
+    }
+
+    #[test]
+    fn tracks_connections_of_multiple_peers() {
+        let mut tracker = PeerTracker::default();
+        let bob_1 = PeerId::random();
+        let bob_2 = PeerId::random();
+
+        tracker.inject_connection_established(&bob_1, &ConnectionId::new(0), &listener(1001));
+        tracker.inject_connection_established(&bob_2, &ConnectionId::new(1), &listener(1002));
+        tracker.inject_connection_established(&bob_1, &ConnectionId::new(2), &listener(1003));
+
+        assert!(tracker.is_connected(&bob_1));
+        assert!(tracker.is_connected(&bob_2));
+        assert_eq!(tracker.connected_peers().len(), 2);
+        assert_eq!(
+            tracker.peer(&bob_1).unwrap().last_seen_address(),
+            Some(&"/ip4/127.0.0.1/tcp/1003".parse().unwrap())
+        );
+
+        tracker.inject_connection_closed(&bob_1, &ConnectionId::new(0), &listener(1001));
+        assert!(tracker.is_connected(&bob_1));
+
+        tracker.inject_connection_closed(&bob_1, &ConnectionId::new(2), &listener(1003));
+        assert!(!tracker.is_connected(&bob_1));
+        assert!(tracker.is_connected(&bob_2));
+
+        assert_eq!(tracker.events, vec![
+            OutEvent::ConnectionEstablished(bob_1),
+            OutEvent::ConnectionEstablished(bob_2),
+            OutEvent::ConnectionClosed(bob_1),
+        ]);
+    }
+
+    #[test]
+    fn added_address_is_dialed_before_last_seen_address() {
+        let mut tracker = PeerTracker::default();
+        let alice = PeerId::random();
+        let address: Multiaddr = "/ip4/127.0.0.1/tcp/9939".parse().unwrap();
+
+        tracker.add_address(alice, address.clone());
+        tracker.inject_connection_established(&alice, &ConnectionId::new(0), &listener(1001));
+
+        assert_eq!(tracker.addresses_of_peer(&alice), vec![
+            address,
+            "/ip4/127.0.0.1/tcp/1001".parse().unwrap()
+        ]);
+    }
+
+    #[test]
+    fn forgets_disconnected_peers_that_are_not_kept() {
+        let mut tracker = PeerTracker::default();
+        let bob_1 = PeerId::random();
+        let bob_2 = PeerId::random();
+        let bob_3 = PeerId::random();
+
+        tracker.inject_connection_established(&bob_1, &ConnectionId::new(0), &listener(1001));
+        tracker.inject_connection_established(&bob_2, &ConnectionId::new(1), &listener(1002));
+        tracker.inject_connection_established(&bob_3, &ConnectionId::new(2), &listener(1003));
+        tracker.add_address(bob_1, "/ip4/127.0.0.1/tcp/2001".parse().unwrap());
+        tracker.inject_connection_closed(&bob_1, &ConnectionId::new(0), &listener(1001));
+        tracker.inject_connection_closed(&bob_2, &ConnectionId::new(1), &listener(1002));
+
+        tracker.forget_disconnected_peers(|peer_id| *peer_id == bob_2);
+
+        assert_eq!(tracker.peer(&bob_1), None);
+        assert!(tracker.addresses_of_peer(&bob_1).is_empty());
+        assert!(tracker.peer(&bob_2).is_some());
+        assert!(tracker.is_connected(&bob_3));
+    }
+}
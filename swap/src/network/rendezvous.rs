@@ -0,0 +1,604 @@
+//! A minimal rendezvous protocol for ASBs to announce themselves to CLIs.
+//!
+//! ASBs register their external addresses under a namespace at a rendezvous
+//! point, CLIs ask the rendezvous point for all registrations of that
+//! namespace. Registrations expire after their TTL, ASBs refresh them
+//! periodically.
+use crate::network::{
+    request_response::{CborCodec, RendezvousProtocol, RENDEZVOUS_TIMEOUT},
+    transport, TokioExecutor,
+};
+use anyhow::{anyhow, bail, Context, Error, Result};
+use libp2p::{
+    core::multiaddr::Protocol,
+    request_response::{
+        ProtocolSupport, RequestId, RequestResponse, RequestResponseConfig, RequestResponseEvent,
+        RequestResponseMessage, ResponseChannel,
+    },
+    Multiaddr, NetworkBehaviour, PeerId, Swarm,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+use tracing::{debug, info, warn};
+
+/// The namespace ASBs register under.
+pub const XMR_BTC_NAMESPACE: &str = "xmr-btc-swap-testnet";
+
+/// How long a registration is valid if it is not refreshed.
+pub const DEFAULT_TTL: Duration = Duration::from_secs(2 * 60 * 60);
+
+/// Registrations asking for a longer TTL are capped to this.
+pub const MAX_TTL: Duration = Duration::from_secs(72 * 60 * 60);
+
+/// A rendezvous point keeps at most this many namespaces.
+pub const MAX_NAMESPACES: usize = 100;
+
+/// Namespaces longer than this many bytes are rejected.
+pub const MAX_NAMESPACE_LENGTH: usize = 255;
+
+/// A rendezvous point keeps at most this many registrations per namespace.
+pub const MAX_REGISTRATIONS_PER_NAMESPACE: usize = 1000;
+
+/// Registrations with more addresses than this are rejected.
+pub const MAX_ADDRESSES_PER_REGISTRATION: usize = 16;
+
+/// How often an ASB refreshes its registration. Well within the TTL so a
+/// failed registration is retried before the previous one expires.
+pub const REFRESH_INTERVAL: Duration = Duration::from_secs(5 * 60);
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub enum Request {
+    Register {
+        namespace: String,
+        addresses: Vec<Multiaddr>,
+        ttl: u64,
+    },
+    Discover {
+        namespace: String,
+    },
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub enum Response {
+    Registered { ttl: u64 },
+    Discovered(Vec<Registration>),
+    Rejected(String),
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct Registration {
+    #[serde(with = "crate::serde_peer_id")]
+    pub peer_id: PeerId,
+    pub addresses: Vec<Multiaddr>,
+}
+
+/// Splits a rendezvous point given as `<multiaddr>/p2p/<peer id>` into its
+/// peer id and address.
+pub fn parse_rendezvous_point(address: &Multiaddr) -> Result<(PeerId, Multiaddr)> {
+    let mut without_peer_id = address.clone();
+
+    match without_peer_id.pop() {
+        Some(Protocol::P2p(hash)) => {
+            let peer_id = PeerId::from_multihash(hash)
+                .map_err(|_| anyhow!("Invalid peer id in rendezvous point {}", address))?;
+            Ok((peer_id, without_peer_id))
+        }
+        _ => bail!("Rendezvous point {} must end with /p2p/<peer id>", address),
+    }
+}
+
+#[derive(Debug)]
+pub enum OutEvent {
+    Registered {
+        rendezvous_node: PeerId,
+        ttl: Duration,
+    },
+    Discovered {
+        request_id: RequestId,
+        registrations: Vec<Registration>,
+    },
+    /// The rendezvous point could not be reached or rejected the request.
+    RequestFailed {
+        request_id: RequestId,
+        error: Error,
+    },
+    Failure(Error),
+}
+
+impl From<RequestResponseEvent<Request, Response>> for OutEvent {
+    fn from(event: RequestResponseEvent<Request, Response>) -> Self {
+        match event {
+            RequestResponseEvent::Message {
+                message: RequestResponseMessage::Request { .. },
+                ..
+            } => OutEvent::Failure(anyhow!("Only the rendezvous point handles requests")),
+            RequestResponseEvent::Message {
+                peer,
+                message:
+                    RequestResponseMessage::Response {
+                        request_id,
+                        response,
+                    },
+                ..
+            } => match response {
+                Response::Registered { ttl } => OutEvent::Registered {
+                    rendezvous_node: peer,
+                    ttl: Duration::from_secs(ttl),
+                },
+                Response::Discovered(registrations) => OutEvent::Discovered {
+                    request_id,
+                    registrations,
+                },
+                Response::Rejected(reason) => OutEvent::RequestFailed {
+                    request_id,
+                    error: anyhow!("Rejected by rendezvous point: {}", reason),
+                },
+            },
+            RequestResponseEvent::InboundFailure { error, .. } => {
+                OutEvent::Failure(anyhow!("Inbound failure: {:?}", error))
+            }
+            RequestResponseEvent::OutboundFailure {
+                request_id, error, ..
+            } => OutEvent::RequestFailed {
+                request_id,
+                error: anyhow!("Outbound failure: {:?}", error),
+            },
+            RequestResponseEvent::ResponseSent { .. } => {
+                OutEvent::Failure(anyhow!("Only the rendezvous point sends responses"))
+            }
+        }
+    }
+}
+
+/// A `NetworkBehaviour` to register at and discover peers through a
+/// rendezvous point.
+#[derive(NetworkBehaviour)]
+#[behaviour(out_event = "OutEvent", event_process = false)]
+#[allow(missing_debug_implementations)]
+pub struct Behaviour {
+    rr: RequestResponse<CborCodec<RendezvousProtocol, Request, Response>>,
+}
+
+impl Behaviour {
+    pub fn register(
+        &mut self,
+        rendezvous_node: PeerId,
+        namespace: &str,
+        addresses: Vec<Multiaddr>,
+        ttl: Duration,
+    ) -> RequestId {
+        self.rr.send_request(&rendezvous_node, Request::Register {
+            namespace: namespace.to_string(),
+            addresses,
+            ttl: ttl.as_secs(),
+        })
+    }
+
+    pub fn discover(&mut self, rendezvous_node: PeerId, namespace: &str) -> RequestId {
+        self.rr.send_request(&rendezvous_node, Request::Discover {
+            namespace: namespace.to_string(),
+        })
+    }
+}
+
+impl Default for Behaviour {
+    fn default() -> Self {
+        let mut config = RequestResponseConfig::default();
+        config.set_request_timeout(RENDEZVOUS_TIMEOUT);
+
+        Self {
+            rr: RequestResponse::new(
+                CborCodec::default(),
+                vec![(RendezvousProtocol, ProtocolSupport::Outbound)],
+                config,
+            ),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, thiserror::Error)]
+pub enum RegistrationRejected {
+    #[error("Namespace is longer than {max} bytes")]
+    NamespaceTooLong { max: usize },
+    #[error("Registration has more than {max} addresses")]
+    TooManyAddresses { max: usize },
+    #[error("Rendezvous point already keeps the maximum of {max} namespaces")]
+    TooManyNamespaces { max: usize },
+    #[error("Namespace already has the maximum of {max} registrations")]
+    NamespaceFull { max: usize },
+}
+
+/// The registrations known to a rendezvous point.
+#[derive(Debug, Default)]
+pub struct Registrations {
+    namespaces: HashMap<String, HashMap<PeerId, (Vec<Multiaddr>, Instant)>>,
+}
+
+impl Registrations {
+    /// Adds or refreshes the registration of `peer_id`, returns the TTL that
+    /// was granted. Refreshing a registration always succeeds, new ones are
+    /// rejected once the limits are reached.
+    pub fn add(
+        &mut self,
+        namespace: String,
+        peer_id: PeerId,
+        addresses: Vec<Multiaddr>,
+        ttl: Duration,
+    ) -> Result<Duration, RegistrationRejected> {
+        if namespace.len() > MAX_NAMESPACE_LENGTH {
+            return Err(RegistrationRejected::NamespaceTooLong {
+                max: MAX_NAMESPACE_LENGTH,
+            });
+        }
+        if addresses.len() > MAX_ADDRESSES_PER_REGISTRATION {
+            return Err(RegistrationRejected::TooManyAddresses {
+                max: MAX_ADDRESSES_PER_REGISTRATION,
+            });
+        }
+
+        let now = Instant::now();
+        self.remove_expired(now);
+
+        if !self.namespaces.contains_key(&namespace) && self.namespaces.len() >= MAX_NAMESPACES {
+            return Err(RegistrationRejected::TooManyNamespaces {
+                max: MAX_NAMESPACES,
+            });
+        }
+
+        let registrations = self.namespaces.entry(namespace).or_default();
+        if !registrations.contains_key(&peer_id)
+            && registrations.len() >= MAX_REGISTRATIONS_PER_NAMESPACE
+        {
+            return Err(RegistrationRejected::NamespaceFull {
+                max: MAX_REGISTRATIONS_PER_NAMESPACE,
+            });
+        }
+
+        let ttl = ttl.min(MAX_TTL);
+        registrations.insert(peer_id, (addresses, now + ttl));
+
+        Ok(ttl)
+    }
+
+    /// Drops expired registrations of all namespaces and namespaces without
+    /// registrations.
+    fn remove_expired(&mut self, now: Instant) {
+        self.namespaces.retain(|_, registrations| {
+            registrations.retain(|_, (_, expires_at)| *expires_at > now);
+            !registrations.is_empty()
+        });
+    }
+
+    /// Returns all registrations of `namespace` that did not expire.
+    pub fn get(&mut self, namespace: &str) -> Vec<Registration> {
+        let now = Instant::now();
+
+        match self.namespaces.get_mut(namespace) {
+            Some(registrations) => {
+                registrations.retain(|_, (_, expires_at)| *expires_at > now);
+                registrations
+                    .iter()
+                    .map(|(peer_id, (addresses, _))| Registration {
+                        peer_id: *peer_id,
+                        addresses: addresses.clone(),
+                    })
+                    .collect()
+            }
+            None => vec![],
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ServerOutEvent {
+    Request {
+        peer_id: PeerId,
+        request: Request,
+        channel: ResponseChannel<Response>,
+    },
+    ResponseSent,
+    Failure(Error),
+}
+
+impl From<RequestResponseEvent<Request, Response>> for ServerOutEvent {
+    fn from(event: RequestResponseEvent<Request, Response>) -> Self {
+        match event {
+            RequestResponseEvent::Message {
+                peer,
+                message:
+                    RequestResponseMessage::Request {
+                        request, channel, ..
+                    },
+                ..
+            } => ServerOutEvent::Request {
+                peer_id: peer,
+                request,
+                channel,
+            },
+            RequestResponseEvent::Message {
+                message: RequestResponseMessage::Response { .. },
+                ..
+            } => ServerOutEvent::Failure(anyhow!("Rendezvous point should not get a Response")),
+            RequestResponseEvent::InboundFailure { error, .. } => {
+                ServerOutEvent::Failure(anyhow!("Inbound failure: {:?}", error))
+            }
+            RequestResponseEvent::OutboundFailure { error, .. } => {
+                ServerOutEvent::Failure(anyhow!("Outbound failure: {:?}", error))
+            }
+            RequestResponseEvent::ResponseSent { .. } => ServerOutEvent::ResponseSent,
+        }
+    }
+}
+
+/// The `NetworkBehaviour` of a rendezvous point.
+#[derive(NetworkBehaviour)]
+#[behaviour(out_event = "ServerOutEvent", event_process = false)]
+#[allow(missing_debug_implementations)]
+pub struct ServerBehaviour {
+    rr: RequestResponse<CborCodec<RendezvousProtocol, Request, Response>>,
+}
+
+impl Default for ServerBehaviour {
+    fn default() -> Self {
+        let mut config = RequestResponseConfig::default();
+        config.set_request_timeout(RENDEZVOUS_TIMEOUT);
+
+        Self {
+            rr: RequestResponse::new(
+                CborCodec::default(),
+                vec![(RendezvousProtocol, ProtocolSupport::Inbound)],
+                config,
+            ),
+        }
+    }
+}
+
+/// A rendezvous point, see the module documentation.
+#[allow(missing_debug_implementations)]
+pub struct Server {
+    swarm: Swarm<ServerBehaviour>,
+    peer_id: PeerId,
+    registrations: Registrations,
+}
+
+impl Server {
+    pub fn new(
+        identity: &libp2p::core::identity::Keypair,
+        listen_address: Multiaddr,
+    ) -> Result<Self> {
+        let peer_id = identity.public().into_peer_id();
+        let transport = transport::build(identity)?;
+
+        let mut swarm =
+            libp2p::swarm::SwarmBuilder::new(transport, ServerBehaviour::default(), peer_id)
+                .executor(Box::new(TokioExecutor {
+                    handle: tokio::runtime::Handle::current(),
+                }))
+                .build();
+
+        Swarm::listen_on(&mut swarm, listen_address.clone())
+            .with_context(|| format!("Address is not supported: {:#}", listen_address))?;
+
+        Ok(Self {
+            swarm,
+            peer_id,
+            registrations: Registrations::default(),
+        })
+    }
+
+    pub fn peer_id(&self) -> PeerId {
+        self.peer_id
+    }
+
+    pub async fn run(mut self) {
+        loop {
+            match self.swarm.next().await {
+                ServerOutEvent::Request {
+                    peer_id,
+                    request,
+                    channel,
+                } => {
+                    let response = self.handle_request(peer_id, request);
+                    if self.swarm.rr.send_response(channel, response).is_err() {
+                        debug!("Failed to respond to {}, connection closed", peer_id);
+                    }
+                }
+                ServerOutEvent::ResponseSent => {}
+                ServerOutEvent::Failure(error) => {
+                    warn!("Rendezvous request failed: {:#}", error);
+                }
+            }
+        }
+    }
+
+    fn handle_request(&mut self, peer_id: PeerId, request: Request) -> Response {
+        match request {
+            Request::Register { addresses, .. } if addresses.is_empty() => {
+                Response::Rejected("No addresses to register".to_string())
+            }
+            Request::Register {
+                namespace,
+                addresses,
+                ttl,
+            } => {
+                match self.registrations.add(
+                    namespace.clone(),
+                    peer_id,
+                    addresses,
+                    Duration::from_secs(ttl),
+                ) {
+                    Ok(ttl) => {
+                        info!("Registered {} in namespace {}", peer_id, namespace);
+                        Response::Registered { ttl: ttl.as_secs() }
+                    }
+                    Err(reason) => {
+                        debug!("Rejected registration of {}: {}", peer_id, reason);
+                        Response::Rejected(reason.to_string())
+                    }
+                }
+            }
+            Request::Discover { namespace } => {
+                Response::Discovered(self.registrations.get(&namespace))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn registrations_expire_after_ttl() {
+        let mut registrations = Registrations::default();
+        let alice_1 = PeerId::random();
+        let alice_2 = PeerId::random();
+        let address: Multiaddr = "/ip4/127.0.0.1/tcp/9939".parse().unwrap();
+
+        registrations
+            .add(
+                XMR_BTC_NAMESPACE.to_string(),
+                alice_1,
+                vec![address.clone()],
+                DEFAULT_TTL,
+            )
+            .unwrap();
+        registrations
+            .add(
+                XMR_BTC_NAMESPACE.to_string(),
+                alice_2,
+                vec![address.clone()],
+                Duration::from_secs(0),
+            )
+            .unwrap();
+
+        assert_eq!(registrations.get(XMR_BTC_NAMESPACE), vec![Registration {
+            peer_id: alice_1,
+            addresses: vec![address]
+        }]);
+        assert!(registrations.get("other-namespace").is_empty());
+    }
+
+    #[test]
+    fn ttl_is_capped() {
+        let mut registrations = Registrations::default();
+
+        let ttl = registrations
+            .add(
+                XMR_BTC_NAMESPACE.to_string(),
+                PeerId::random(),
+                vec![],
+                MAX_TTL * 2,
+            )
+            .unwrap();
+
+        assert_eq!(ttl, MAX_TTL);
+    }
+
+    #[test]
+    fn expired_registrations_of_other_namespaces_are_removed() {
+        let mut registrations = Registrations::default();
+
+        registrations
+            .add(
+                "other-namespace".to_string(),
+                PeerId::random(),
+                vec![],
+                Duration::from_secs(0),
+            )
+            .unwrap();
+        registrations
+            .add(
+                XMR_BTC_NAMESPACE.to_string(),
+                PeerId::random(),
+                vec![],
+                DEFAULT_TTL,
+            )
+            .unwrap();
+
+        assert_eq!(registrations.namespaces.len(), 1);
+    }
+
+    #[test]
+    fn registrations_over_the_limits_are_rejected() {
+        let mut registrations = Registrations::default();
+        let address: Multiaddr = "/ip4/127.0.0.1/tcp/9939".parse().unwrap();
+
+        assert_eq!(
+            registrations.add(
+                "a".repeat(MAX_NAMESPACE_LENGTH + 1),
+                PeerId::random(),
+                vec![],
+                DEFAULT_TTL,
+            ),
+            Err(RegistrationRejected::NamespaceTooLong {
+                max: MAX_NAMESPACE_LENGTH
+            })
+        );
+        assert_eq!(
+            registrations.add(
+                XMR_BTC_NAMESPACE.to_string(),
+                PeerId::random(),
+                vec![address; MAX_ADDRESSES_PER_REGISTRATION + 1],
+                DEFAULT_TTL,
+            ),
+            Err(RegistrationRejected::TooManyAddresses {
+                max: MAX_ADDRESSES_PER_REGISTRATION
+            })
+        );
+
+        for i in 0..MAX_NAMESPACES {
+            registrations
+                .add(i.to_string(), PeerId::random(), vec![], DEFAULT_TTL)
+                .unwrap();
+        }
+        assert_eq!(
+            registrations.add(
+                XMR_BTC_NAMESPACE.to_string(),
+                PeerId::random(),
+                vec![],
+                DEFAULT_TTL
+            ),
+            Err(RegistrationRejected::TooManyNamespaces {
+                max: MAX_NAMESPACES
+            })
+        );
+
+        let alice = PeerId::random();
+        registrations
+            .add("0".to_string(), alice, vec![], DEFAULT_TTL)
+            .unwrap();
+        for _ in 2..MAX_REGISTRATIONS_PER_NAMESPACE {
+            registrations
+                .add("0".to_string(), PeerId::random(), vec![], DEFAULT_TTL)
+                .unwrap();
+        }
+        assert_eq!(
+            registrations.add("0".to_string(), PeerId::random(), vec![], DEFAULT_TTL),
+            Err(RegistrationRejected::NamespaceFull {
+                max: MAX_REGISTRATIONS_PER_NAMESPACE
+            })
+        );
+        // Refreshing an existing registration still works.
+        assert!(registrations
+            .add("0".to_string(), alice, vec![], DEFAULT_TTL)
+            .is_ok());
+    }
+
+    #[test]
+    fn parses_rendezvous_point() {
+        let peer_id = PeerId::random();
+        let address: Multiaddr = format!("/ip4/127.0.0.1/tcp/8888/p2p/{}", peer_id)
+            .parse()
+            .unwrap();
+
+        let (parsed_peer_id, parsed_address) = parse_rendezvous_point(&address).unwrap();
+
+        assert_eq!(parsed_peer_id, peer_id);
+        assert_eq!(parsed_address, "/ip4/127.0.0.1/tcp/8888".parse().unwrap());
+        assert!(parse_rendezvous_point(&parsed_address).is_err());
+    }
+}
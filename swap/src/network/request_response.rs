@@ -2,16 +2,74 @@ use async_trait::async_trait;
 use futures::prelude::*;
 use libp2p::{
     core::{upgrade, upgrade::ReadOneError},
-    request_response::{ProtocolName, RequestResponseCodec},
+    request_response::{OutboundFailure, ProtocolName, RequestResponseCodec},
 };
 use serde::{de::DeserializeOwned, Serialize};
-use std::{fmt::Debug, io, marker::PhantomData};
+use std::{fmt::Debug, io, marker::PhantomData, time::Duration};
 
-/// Time to wait for a response back once we send a request.
-pub const TIMEOUT: u64 = 3600; // One hour.
+/// Quotes, handshakes, aborts and cooperative redeems are answered right away,
+/// a peer that takes longer is most likely gone.
+pub const QUOTE_TIMEOUT: Duration = Duration::from_secs(30);
+pub const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(30);
+pub const ABORT_TIMEOUT: Duration = Duration::from_secs(30);
+pub const COOPERATIVE_REDEEM_TIMEOUT: Duration = Duration::from_secs(30);
+pub const RENDEZVOUS_TIMEOUT: Duration = Duration::from_secs(60);
 
-/// Message receive buffer.
-pub const BUF_SIZE: usize = 1024 * 1024;
+/// The execution setup takes several round trips and both parties build and
+/// sign transactions in between.
+pub const EXECUTION_SETUP_TIMEOUT: Duration = Duration::from_secs(5 * 60);
+/// Largest message accepted during the execution setup, the DLEQ proofs make
+/// up most of it.
+pub const EXECUTION_SETUP_MAX_MESSAGE_SIZE: usize = 1024 * 1024;
+
+/// How often idempotent requests, like handshakes and quote requests, are sent
+/// again after a retryable failure.
+pub const MAX_RETRIES: u32 = 2;
+
+/// Time to wait for the acknowledgement of a transfer proof or encrypted
+/// signature. They are sent again if it does not arrive, so a few attempts
+/// should fit into the time Bob has to act.
+pub fn acknowledgement_timeout(bob_time_to_act: Duration) -> Duration {
+    bob_time_to_act / 4
+}
+
+/// Whether sending a request again may succeed where it just failed.
+pub fn is_retryable(failure: &OutboundFailure) -> bool {
+    matches!(
+        failure,
+        OutboundFailure::Timeout | OutboundFailure::ConnectionClosed | OutboundFailure::DialFailure
+    )
+}
+
+/// Limits of a request/response protocol enforced by the [`CborCodec`].
+pub trait ProtocolLimits {
+    /// Largest request or response in bytes. Bigger messages are neither sent
+    /// nor read.
+    const MAX_MESSAGE_SIZE: usize;
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error("{protocol} message of {size} bytes exceeds the limit of {max} bytes")]
+pub struct MessageTooLarge {
+    pub protocol: String,
+    pub size: usize,
+    pub max: usize,
+}
+
+impl MessageTooLarge {
+    fn new<P: ProtocolName + ProtocolLimits>(protocol: &P, size: usize) -> Self {
+        Self {
+            protocol: String::from_utf8_lossy(protocol.protocol_name()).into_owned(),
+            size,
+            max: P::MAX_MESSAGE_SIZE,
+        }
+    }
+
+    fn into_io_error(self) -> io::Error {
+        tracing::warn!("{}", self);
+        io::Error::new(io::ErrorKind::InvalidData, self)
+    }
+}
 
 #[derive(Debug, Clone, Copy, Default)]
 pub struct Swap;
@@ -22,6 +80,21 @@ pub struct TransferProofProtocol;
 #[derive(Debug, Clone, Copy, Default)]
 pub struct EncryptedSignatureProtocol;
 
+#[derive(Debug, Clone, Copy, Default)]
+pub struct BidQuoteProtocol;
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RendezvousProtocol;
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct HandshakeProtocol;
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct AbortProtocol;
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CooperativeRedeemProtocol;
+
 impl ProtocolName for Swap {
     fn protocol_name(&self) -> &[u8] {
         b"/comit/xmr/btc/swap/1.0.0"
@@ -40,6 +113,68 @@ impl ProtocolName for EncryptedSignatureProtocol {
     }
 }
 
+impl ProtocolName for BidQuoteProtocol {
+    fn protocol_name(&self) -> &[u8] {
+        b"/comit/xmr/btc/bid-quote/1.0.0"
+    }
+}
+
+impl ProtocolName for RendezvousProtocol {
+    fn protocol_name(&self) -> &[u8] {
+        b"/comit/xmr/btc/rendezvous/1.0.0"
+    }
+}
+
+impl ProtocolName for HandshakeProtocol {
+    fn protocol_name(&self) -> &[u8] {
+        b"/comit/xmr/btc/handshake/1.0.0"
+    }
+}
+
+impl ProtocolName for AbortProtocol {
+    fn protocol_name(&self) -> &[u8] {
+        b"/comit/xmr/btc/abort/1.0.0"
+    }
+}
+
+impl ProtocolName for CooperativeRedeemProtocol {
+    fn protocol_name(&self) -> &[u8] {
+        b"/comit/xmr/btc/cooperative_redeem/1.0.0"
+    }
+}
+
+impl ProtocolLimits for Swap {
+    const MAX_MESSAGE_SIZE: usize = 1024;
+}
+
+impl ProtocolLimits for TransferProofProtocol {
+    const MAX_MESSAGE_SIZE: usize = 4 * 1024;
+}
+
+impl ProtocolLimits for EncryptedSignatureProtocol {
+    const MAX_MESSAGE_SIZE: usize = 4 * 1024;
+}
+
+impl ProtocolLimits for BidQuoteProtocol {
+    const MAX_MESSAGE_SIZE: usize = 1024;
+}
+
+impl ProtocolLimits for RendezvousProtocol {
+    const MAX_MESSAGE_SIZE: usize = 1024 * 1024;
+}
+
+impl ProtocolLimits for HandshakeProtocol {
+    const MAX_MESSAGE_SIZE: usize = 4 * 1024;
+}
+
+impl ProtocolLimits for AbortProtocol {
+    const MAX_MESSAGE_SIZE: usize = 1024;
+}
+
+impl ProtocolLimits for CooperativeRedeemProtocol {
+    const MAX_MESSAGE_SIZE: usize = 4 * 1024;
+}
+
 #[derive(Clone, Copy, Debug)]
 pub struct CborCodec<P, Req, Res> {
     phantom: PhantomData<(P, Req, Res)>,
@@ -56,7 +191,7 @@ impl<P, Req, Res> Default for CborCodec<P, Req, Res> {
 #[async_trait]
 impl<P, Req, Res> RequestResponseCodec for CborCodec<P, Req, Res>
 where
-    P: ProtocolName + Send + Sync + Clone,
+    P: ProtocolName + ProtocolLimits + Send + Sync + Clone,
     Req: DeserializeOwned + Serialize + Send,
     Res: DeserializeOwned + Serialize + Send,
 {
@@ -64,14 +199,15 @@ where
     type Request = Req;
     type Response = Res;
 
-    async fn read_request<T>(&mut self, _: &Self::Protocol, io: &mut T) -> io::Result<Self::Request>
+    async fn read_request<T>(
+        &mut self,
+        protocol: &Self::Protocol,
+        io: &mut T,
+    ) -> io::Result<Self::Request>
     where
         T: AsyncRead + Unpin + Send,
     {
-        let message = upgrade::read_one(io, BUF_SIZE).await.map_err(|e| match e {
-            ReadOneError::Io(err) => err,
-            e => io::Error::new(io::ErrorKind::Other, e),
-        })?;
+        let message = read_one(protocol, io).await?;
         let mut de = serde_cbor::Deserializer::from_slice(&message);
         let msg = Req::deserialize(&mut de).map_err(|e| {
             tracing::debug!("serde read_request error: {:?}", e);
@@ -83,15 +219,13 @@ where
 
     async fn read_response<T>(
         &mut self,
-        _: &Self::Protocol,
+        protocol: &Self::Protocol,
         io: &mut T,
     ) -> io::Result<Self::Response>
     where
         T: AsyncRead + Unpin + Send,
     {
-        let message = upgrade::read_one(io, BUF_SIZE)
-            .await
-            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        let message = read_one(protocol, io).await?;
         let mut de = serde_cbor::Deserializer::from_slice(&message);
         let msg = Res::deserialize(&mut de).map_err(|e| {
             tracing::debug!("serde read_response error: {:?}", e);
@@ -103,7 +237,7 @@ where
 
     async fn write_request<T>(
         &mut self,
-        _: &Self::Protocol,
+        protocol: &Self::Protocol,
         io: &mut T,
         req: Self::Request,
     ) -> io::Result<()>
@@ -113,14 +247,14 @@ where
         let bytes =
             serde_cbor::to_vec(&req).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
 
-        upgrade::write_one(io, &bytes).await?;
+        write_one(protocol, io, &bytes).await?;
 
         Ok(())
     }
 
     async fn write_response<T>(
         &mut self,
-        _: &Self::Protocol,
+        protocol: &Self::Protocol,
         io: &mut T,
         res: Self::Response,
     ) -> io::Result<()>
@@ -131,8 +265,70 @@ where
             tracing::debug!("serde write_response error: {:?}", e);
             io::Error::new(io::ErrorKind::InvalidData, e)
         })?;
-        upgrade::write_one(io, &bytes).await?;
+        write_one(protocol, io, &bytes).await?;
 
         Ok(())
     }
 }
+
+async fn read_one<P, T>(protocol: &P, io: &mut T) -> io::Result<Vec<u8>>
+where
+    P: ProtocolName + ProtocolLimits,
+    T: AsyncRead + Unpin + Send,
+{
+    upgrade::read_one(io, P::MAX_MESSAGE_SIZE)
+        .await
+        .map_err(|e| match e {
+            ReadOneError::Io(err) => err,
+            ReadOneError::TooLarge { requested, .. } => {
+                MessageTooLarge::new(protocol, requested).into_io_error()
+            }
+        })
+}
+
+async fn write_one<P, T>(protocol: &P, io: &mut T, bytes: &[u8]) -> io::Result<()>
+where
+    P: ProtocolName + ProtocolLimits,
+    T: AsyncWrite + Unpin + Send,
+{
+    if bytes.len() > P::MAX_MESSAGE_SIZE {
+        return Err(MessageTooLarge::new(protocol, bytes.len()).into_io_error());
+    }
+
+    upgrade::write_one(io, bytes).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::io::Cursor;
+
+    #[tokio::test]
+    async fn rejects_oversized_messages() {
+        let mut codec = CborCodec::<Swap, Vec<u8>, ()>::default();
+        let request = vec![0u8; Swap::MAX_MESSAGE_SIZE];
+
+        let mut buffer = Vec::new();
+        let error = codec
+            .write_request(&Swap, &mut buffer, request.clone())
+            .await
+            .unwrap_err();
+        assert!(error.get_ref().unwrap().is::<MessageTooLarge>());
+        assert!(buffer.is_empty());
+
+        let mut buffer = Vec::new();
+        let bytes = serde_cbor::to_vec(&request).unwrap();
+        upgrade::write_one(&mut buffer, &bytes).await.unwrap();
+        let error = codec
+            .read_request(&Swap, &mut Cursor::new(buffer))
+            .await
+            .unwrap_err();
+        let error = error
+            .into_inner()
+            .unwrap()
+            .downcast::<MessageTooLarge>()
+            .unwrap();
+        assert_eq!(error.size, bytes.len());
+        assert_eq!(error.max, Swap::MAX_MESSAGE_SIZE);
+    }
+}
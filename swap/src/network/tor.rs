@@ -0,0 +1,522 @@
+use anyhow::{anyhow, bail, Context, Result};
+use data_encoding::BASE32;
+use futures::future::{BoxFuture, FutureExt};
+use libp2p::{
+    core::{
+        multiaddr::Protocol,
+        transport::{ListenerEvent, TransportError},
+        Transport,
+    },
+    Multiaddr,
+};
+use std::{
+    convert::TryFrom,
+    io,
+    net::SocketAddr,
+    path::Path,
+    pin::Pin,
+    task::{Context as TaskContext, Poll},
+};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader, ReadBuf},
+    net::TcpStream,
+};
+use tracing::debug;
+
+const SOCKS5_VERSION: u8 = 0x05;
+const SOCKS5_NO_AUTHENTICATION: u8 = 0x00;
+const SOCKS5_CONNECT: u8 = 0x01;
+const SOCKS5_ATYP_IPV4: u8 = 0x01;
+const SOCKS5_ATYP_DOMAIN: u8 = 0x03;
+const SOCKS5_ATYP_IPV6: u8 = 0x04;
+
+/// A transport that dials every address through a SOCKS5 proxy, usually the
+/// one of a local Tor daemon. This allows dialing `/onion3` addresses and
+/// leaves name resolution of `/dns` addresses to the proxy.
+///
+/// Listening is not supported, use an onion service for that.
+#[derive(Clone, Copy, Debug)]
+pub struct Socks5Transport {
+    proxy: SocketAddr,
+}
+
+impl Socks5Transport {
+    pub fn new(proxy: SocketAddr) -> Self {
+        Self { proxy }
+    }
+}
+
+impl Transport for Socks5Transport {
+    type Output = Socks5Stream;
+    type Error = io::Error;
+    type Listener = futures::stream::Pending<
+        Result<ListenerEvent<Self::ListenerUpgrade, Self::Error>, Self::Error>,
+    >;
+    type ListenerUpgrade = futures::future::Pending<Result<Self::Output, Self::Error>>;
+    type Dial = BoxFuture<'static, Result<Self::Output, Self::Error>>;
+
+    fn listen_on(self, addr: Multiaddr) -> Result<Self::Listener, TransportError<Self::Error>> {
+        Err(TransportError::MultiaddrNotSupported(addr))
+    }
+
+    fn dial(self, addr: Multiaddr) -> Result<Self::Dial, TransportError<Self::Error>> {
+        let (host, port) = match socks5_target(&addr) {
+            Some(target) => target,
+            None => return Err(TransportError::MultiaddrNotSupported(addr)),
+        };
+
+        debug!(
+            "Dialing {}:{} through SOCKS5 proxy {}",
+            host, port, self.proxy
+        );
+
+        Ok(async move {
+            let stream = socks5_connect(self.proxy, &host, port).await?;
+            Ok(Socks5Stream(stream))
+        }
+        .boxed())
+    }
+}
+
+/// A connection established through a SOCKS5 proxy.
+#[derive(Debug)]
+pub struct Socks5Stream(TcpStream);
+
+impl futures::io::AsyncRead for Socks5Stream {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut TaskContext<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let mut read_buf = ReadBuf::new(buf);
+        match tokio::io::AsyncRead::poll_read(Pin::new(&mut self.get_mut().0), cx, &mut read_buf) {
+            Poll::Ready(Ok(())) => Poll::Ready(Ok(read_buf.filled().len())),
+            Poll::Ready(Err(e)) => Poll::Ready(Err(e)),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+impl futures::io::AsyncWrite for Socks5Stream {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut TaskContext<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        tokio::io::AsyncWrite::poll_write(Pin::new(&mut self.get_mut().0), cx, buf)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut TaskContext<'_>) -> Poll<io::Result<()>> {
+        tokio::io::AsyncWrite::poll_flush(Pin::new(&mut self.get_mut().0), cx)
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut TaskContext<'_>) -> Poll<io::Result<()>> {
+        tokio::io::AsyncWrite::poll_shutdown(Pin::new(&mut self.get_mut().0), cx)
+    }
+}
+
+/// The host and port the proxy has to connect to in order to reach
+/// `address`.
+fn socks5_target(address: &Multiaddr) -> Option<(String, u16)> {
+    let mut protocols = address.iter();
+
+    let target = match protocols.next()? {
+        Protocol::Onion3(onion) => (
+            format!("{}.onion", BASE32.encode(onion.hash()).to_lowercase()),
+            onion.port(),
+        ),
+        Protocol::Dns(host) | Protocol::Dns4(host) | Protocol::Dns6(host) => {
+            (host.to_string(), tcp_port(protocols.next()?)?)
+        }
+        Protocol::Ip4(ip) => (ip.to_string(), tcp_port(protocols.next()?)?),
+        Protocol::Ip6(ip) => (ip.to_string(), tcp_port(protocols.next()?)?),
+        _ => return None,
+    };
+
+    // The peer id is not part of the target and checked by the swarm.
+    match protocols.next() {
+        None | Some(Protocol::P2p(_)) => Some(target),
+        Some(_) => None,
+    }
+}
+
+fn tcp_port(protocol: Protocol<'_>) -> Option<u16> {
+    match protocol {
+        Protocol::Tcp(port) => Some(port),
+        _ => None,
+    }
+}
+
+/// Opens a connection to `host:port` through the SOCKS5 proxy at `proxy`.
+///
+/// The host is always sent as a domain name so that it is resolved by the
+/// proxy and never locally.
+async fn socks5_connect(proxy: SocketAddr, host: &str, port: u16) -> io::Result<TcpStream> {
+    let mut stream = TcpStream::connect(proxy).await?;
+
+    stream
+        .write_all(&[SOCKS5_VERSION, 1, SOCKS5_NO_AUTHENTICATION])
+        .await?;
+    let mut method = [0u8; 2];
+    stream.read_exact(&mut method).await?;
+    if method != [SOCKS5_VERSION, SOCKS5_NO_AUTHENTICATION] {
+        return Err(io::Error::new(
+            io::ErrorKind::Other,
+            "SOCKS5 proxy requires authentication",
+        ));
+    }
+
+    let host_len = u8::try_from(host.len())
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "host name too long"))?;
+    let mut request = vec![
+        SOCKS5_VERSION,
+        SOCKS5_CONNECT,
+        0x00,
+        SOCKS5_ATYP_DOMAIN,
+        host_len,
+    ];
+    request.extend_from_slice(host.as_bytes());
+    request.extend_from_slice(&port.to_be_bytes());
+    stream.write_all(&request).await?;
+
+    let mut reply = [0u8; 4];
+    stream.read_exact(&mut reply).await?;
+    if reply[1] != 0x00 {
+        return Err(io::Error::new(
+            io::ErrorKind::ConnectionRefused,
+            format!(
+                "SOCKS5 proxy failed to connect to {}:{}: {}",
+                host,
+                port,
+                socks5_error(reply[1])
+            ),
+        ));
+    }
+
+    // The address the proxy bound to is of no use to us.
+    let bound_address_len = match reply[3] {
+        SOCKS5_ATYP_IPV4 => 4,
+        SOCKS5_ATYP_IPV6 => 16,
+        SOCKS5_ATYP_DOMAIN => {
+            let mut len = [0u8; 1];
+            stream.read_exact(&mut len).await?;
+            len[0] as usize
+        }
+        atyp => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("unknown SOCKS5 address type {}", atyp),
+            ))
+        }
+    };
+    let mut bound_address = vec![0u8; bound_address_len + 2];
+    stream.read_exact(&mut bound_address).await?;
+
+    Ok(stream)
+}
+
+fn socks5_error(code: u8) -> &'static str {
+    match code {
+        0x01 => "general failure",
+        0x02 => "connection not allowed by ruleset",
+        0x03 => "network unreachable",
+        0x04 => "host unreachable",
+        0x05 => "connection refused",
+        0x06 => "TTL expired",
+        0x07 => "command not supported",
+        0x08 => "address type not supported",
+        _ => "unknown error",
+    }
+}
+
+/// A connection to the control port of a Tor daemon.
+///
+/// Onion services added through it are removed by Tor once it is dropped.
+#[derive(Debug)]
+pub struct TorControl {
+    stream: BufReader<TcpStream>,
+}
+
+impl TorControl {
+    /// Connects and authenticates with the password if one is given and the
+    /// auth cookie or no authentication otherwise.
+    pub async fn connect(address: SocketAddr, password: Option<&str>) -> Result<Self> {
+        let stream = TcpStream::connect(address)
+            .await
+            .with_context(|| format!("Failed to connect to Tor control port at {}", address))?;
+        let mut control = Self {
+            stream: BufReader::new(stream),
+        };
+
+        control.authenticate(password).await?;
+
+        Ok(control)
+    }
+
+    /// Publishes an onion service on `virtual_port` that forwards to
+    /// `target_port` on localhost and returns its address.
+    ///
+    /// The key of the service is stored in `key_file` so that the address
+    /// stays the same across restarts.
+    pub async fn add_onion(
+        &mut self,
+        key_file: &Path,
+        virtual_port: u16,
+        target_port: u16,
+    ) -> Result<Multiaddr> {
+        let key = match tokio::fs::read_to_string(key_file).await {
+            Ok(key) => Some(key.trim().to_string()),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+            Err(e) => {
+                return Err(e).with_context(|| {
+                    format!("Failed to read onion service key {}", key_file.display())
+                })
+            }
+        };
+
+        let reply = self
+            .command(&format!(
+                "ADD_ONION {} Port={},127.0.0.1:{}",
+                key.as_deref().unwrap_or("NEW:ED25519-V3"),
+                virtual_port,
+                target_port
+            ))
+            .await
+            .context("Failed to add onion service")?;
+
+        let service_id = reply_value(&reply, "ServiceID")
+            .ok_or_else(|| anyhow!("Tor did not return the onion service id"))?;
+
+        if key.is_none() {
+            let key = reply_value(&reply, "PrivateKey")
+                .ok_or_else(|| anyhow!("Tor did not return the onion service key"))?;
+            if let Some(dir) = key_file.parent() {
+                tokio::fs::create_dir_all(dir).await?;
+            }
+            // Whoever has the key can impersonate the onion service.
+            let mut options = tokio::fs::OpenOptions::new();
+            options.write(true).create_new(true);
+            #[cfg(unix)]
+            options.mode(0o600);
+            options
+                .open(key_file)
+                .await?
+                .write_all(key.as_bytes())
+                .await
+                .with_context(|| {
+                    format!("Failed to store onion service key {}", key_file.display())
+                })?;
+        }
+
+        let address = format!("/onion3/{}:{}", service_id, virtual_port)
+            .parse()
+            .with_context(|| format!("Tor returned invalid onion service id {}", service_id))?;
+
+        Ok(address)
+    }
+
+    async fn authenticate(&mut self, password: Option<&str>) -> Result<()> {
+        let protocol_info = self.command("PROTOCOLINFO 1").await?;
+        let auth = protocol_info
+            .iter()
+            .find_map(|line| line.strip_prefix("AUTH "))
+            .ok_or_else(|| anyhow!("Tor did not announce its authentication methods"))?;
+        let methods = auth
+            .split(' ')
+            .find_map(|field| field.strip_prefix("METHODS="))
+            .unwrap_or_default()
+            .split(',')
+            .collect::<Vec<_>>();
+
+        let command = if methods.contains(&"NULL") {
+            "AUTHENTICATE".to_string()
+        } else if let Some(password) = password {
+            format!(
+                "AUTHENTICATE \"{}\"",
+                password.replace('\\', "\\\\").replace('"', "\\\"")
+            )
+        } else if methods.contains(&"COOKIE") {
+            let cookie_file = auth
+                .split(' ')
+                .find_map(|field| field.strip_prefix("COOKIEFILE="))
+                .map(|path| path.trim_matches('"'))
+                .ok_or_else(|| anyhow!("Tor did not announce its cookie file"))?;
+            let cookie = tokio::fs::read(cookie_file)
+                .await
+                .with_context(|| format!("Failed to read Tor auth cookie {}", cookie_file))?;
+            format!("AUTHENTICATE {}", hex::encode(cookie))
+        } else {
+            bail!(
+                "No supported authentication method for the Tor control port, available: {}",
+                methods.join(",")
+            )
+        };
+
+        self.command(&command)
+            .await
+            .context("Failed to authenticate with the Tor control port")?;
+
+        Ok(())
+    }
+
+    /// Sends a command and returns the lines of the reply without their
+    /// status codes.
+    async fn command(&mut self, command: &str) -> Result<Vec<String>> {
+        self.stream
+            .get_mut()
+            .write_all(format!("{}\r\n", command).as_bytes())
+            .await?;
+
+        let mut lines = Vec::new();
+        loop {
+            let mut line = String::new();
+            if self.stream.read_line(&mut line).await? == 0 {
+                bail!("Tor closed the control connection");
+            }
+            let line = line.trim_end();
+
+            if line.len() < 4 {
+                bail!("Malformed reply from Tor: {}", line);
+            }
+            let (status, separator, content) = (&line[..3], &line[3..4], &line[4..]);
+            if status != "250" {
+                bail!("Tor rejected the command: {}", line);
+            }
+
+            lines.push(content.to_string());
+
+            if separator == " " {
+                return Ok(lines);
+            }
+        }
+    }
+}
+
+fn reply_value<'a>(reply: &'a [String], key: &str) -> Option<&'a str> {
+    reply.iter().find_map(|line| {
+        line.strip_prefix(key)
+            .and_then(|rest| rest.strip_prefix('='))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+    use tokio::net::TcpListener;
+
+    const ONION: &str = "vww6ybal4bd7szmgncyruucpgfkqahzddi37ktceo3ah7ngmcopnpyyd";
+
+    #[test]
+    fn socks5_target_of_supported_addresses() {
+        let target = |address: &str| socks5_target(&address.parse().unwrap());
+
+        assert_eq!(
+            target(&format!("/onion3/{}:9939", ONION)),
+            Some((format!("{}.onion", ONION), 9939))
+        );
+        assert_eq!(
+            target("/dns4/example.com/tcp/9939"),
+            Some(("example.com".to_string(), 9939))
+        );
+        assert_eq!(
+            target("/ip4/10.0.0.1/tcp/9939/p2p/QmY7Yh4UquoXHLPFo2XbhXkhBvFoPwmQUSa92pxnxjQuPU"),
+            Some(("10.0.0.1".to_string(), 9939))
+        );
+        assert_eq!(target("/ip4/10.0.0.1/udp/9939"), None);
+        assert_eq!(target("/ip4/10.0.0.1/tcp/9939/ws"), None);
+    }
+
+    #[tokio::test]
+    async fn connects_through_socks5_proxy() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let proxy = listener.local_addr().unwrap();
+
+        let stub = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+
+            let mut greeting = [0u8; 3];
+            stream.read_exact(&mut greeting).await.unwrap();
+            assert_eq!(greeting, [5, 1, 0]);
+            stream.write_all(&[5, 0]).await.unwrap();
+
+            let mut header = [0u8; 5];
+            stream.read_exact(&mut header).await.unwrap();
+            assert_eq!(header[..4], [5, 1, 0, 3]);
+            let mut host = vec![0u8; header[4] as usize];
+            stream.read_exact(&mut host).await.unwrap();
+            let mut port = [0u8; 2];
+            stream.read_exact(&mut port).await.unwrap();
+            stream
+                .write_all(&[5, 0, 0, 1, 0, 0, 0, 0, 0, 0])
+                .await
+                .unwrap();
+
+            let mut ping = [0u8; 4];
+            stream.read_exact(&mut ping).await.unwrap();
+            stream.write_all(&ping).await.unwrap();
+
+            (String::from_utf8(host).unwrap(), u16::from_be_bytes(port))
+        });
+
+        let host = format!("{}.onion", ONION);
+        let mut stream = socks5_connect(proxy, &host, 9939).await.unwrap();
+        stream.write_all(b"ping").await.unwrap();
+        let mut pong = [0u8; 4];
+        stream.read_exact(&mut pong).await.unwrap();
+
+        assert_eq!(&pong, b"ping");
+        assert_eq!(stub.await.unwrap(), (host, 9939));
+    }
+
+    #[tokio::test]
+    async fn adds_onion_service_and_stores_its_key() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let control_port = listener.local_addr().unwrap();
+
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut stream = BufReader::new(stream);
+
+            let mut line = String::new();
+            while stream.read_line(&mut line).await.unwrap() > 0 {
+                let reply = match line.trim_end() {
+                    "PROTOCOLINFO 1" => {
+                        "250-PROTOCOLINFO 1\r\n250-AUTH METHODS=NULL\r\n250 OK\r\n".to_string()
+                    }
+                    "AUTHENTICATE" => "250 OK\r\n".to_string(),
+                    "ADD_ONION NEW:ED25519-V3 Port=9939,127.0.0.1:9940" => format!(
+                        "250-ServiceID={}\r\n250-PrivateKey=ED25519-V3:a2V5\r\n250 OK\r\n",
+                        ONION
+                    ),
+                    _ => "510 Unrecognized command\r\n".to_string(),
+                };
+                stream.get_mut().write_all(reply.as_bytes()).await.unwrap();
+                line.clear();
+            }
+        });
+
+        let dir = tempdir().unwrap();
+        let key_file = dir.path().join("tor").join("onion_service_key");
+
+        let mut control = TorControl::connect(control_port, None).await.unwrap();
+        let address = control.add_onion(&key_file, 9939, 9940).await.unwrap();
+
+        assert_eq!(
+            address,
+            format!("/onion3/{}:9939", ONION)
+                .parse::<Multiaddr>()
+                .unwrap()
+        );
+        assert_eq!(
+            std::fs::read_to_string(&key_file).unwrap(),
+            "ED25519-V3:a2V5"
+        );
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(&key_file).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+    }
+}
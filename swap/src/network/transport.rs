@@ -1,4 +1,6 @@
+use crate::network::tor::Socks5Transport;
 use anyhow::Result;
+use futures::{AsyncRead, AsyncWrite};
 use libp2p::{
     core::{
         identity,
@@ -10,24 +12,52 @@ use libp2p::{
     dns::DnsConfig,
     mplex::MplexConfig,
     noise::{self, NoiseConfig, X25519Spec},
+    websocket::WsConfig,
     yamux, PeerId,
 };
+use std::net::SocketAddr;
 
 /// Builds a libp2p transport with the following features:
 /// - TcpConnection
+/// - Websocket connections on `/ws` addresses
 /// - DNS name resolution
 /// - authentication via noise
 /// - multiplexing via yamux or mplex
 pub fn build(id_keys: &identity::Keypair) -> Result<SwapTransport> {
     use libp2p::tcp::TokioTcpConfig;
 
-    let dh_keys = noise::Keypair::<X25519Spec>::new().into_authentic(id_keys)?;
-    let noise = NoiseConfig::xx(dh_keys).into_authenticated();
-
     let tcp = TokioTcpConfig::new().nodelay(true);
     let dns = DnsConfig::new(tcp)?;
+    let ws = WsConfig::new(dns.clone());
+
+    upgrade(dns.or_transport(ws), id_keys)
+}
+
+/// Builds a libp2p transport that dials all connections through the SOCKS5
+/// proxy of a Tor daemon, with the same upgrades as [`build`].
+///
+/// Besides `/ip4`, `/ip6` and `/dns` addresses this can dial `/onion3`
+/// addresses. It cannot listen.
+pub fn build_with_tor(
+    id_keys: &identity::Keypair,
+    socks5_proxy: SocketAddr,
+) -> Result<SwapTransport> {
+    upgrade(Socks5Transport::new(socks5_proxy), id_keys)
+}
+
+fn upgrade<T>(transport: T, id_keys: &identity::Keypair) -> Result<SwapTransport>
+where
+    T: Transport + Clone + Send + Sync + 'static,
+    T::Output: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    T::Error: Send + Sync + 'static,
+    T::Listener: Send + 'static,
+    T::ListenerUpgrade: Send + 'static,
+    T::Dial: Send + 'static,
+{
+    let dh_keys = noise::Keypair::<X25519Spec>::new().into_authentic(id_keys)?;
+    let noise = NoiseConfig::xx(dh_keys).into_authenticated();
 
-    let transport = dns
+    let transport = transport
         .upgrade(Version::V1)
         .authenticate(noise)
         .multiplex(SelectUpgrade::new(
@@ -41,3 +71,38 @@ pub fn build(id_keys: &identity::Keypair) -> Result<SwapTransport> {
 }
 
 pub type SwapTransport = Boxed<(PeerId, StreamMuxerBox)>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::StreamExt;
+    use libp2p::core::transport::ListenerEvent;
+
+    #[tokio::test]
+    async fn upgrades_websocket_connections() {
+        let alice = identity::Keypair::generate_ed25519();
+        let bob = identity::Keypair::generate_ed25519();
+
+        let mut listener = build(&alice)
+            .unwrap()
+            .listen_on("/ip4/127.0.0.1/tcp/0/ws".parse().unwrap())
+            .unwrap();
+        let address = match listener.next().await {
+            Some(Ok(ListenerEvent::NewAddress(address))) => address,
+            _ => panic!("expected the listen address"),
+        };
+
+        let (bob_seen_by_alice, alice_seen_by_bob) = tokio::join!(
+            async {
+                match listener.next().await {
+                    Some(Ok(ListenerEvent::Upgrade { upgrade, .. })) => upgrade.await.unwrap().0,
+                    _ => panic!("expected an incoming connection"),
+                }
+            },
+            async { build(&bob).unwrap().dial(address).unwrap().await.unwrap().0 }
+        );
+
+        assert_eq!(bob_seen_by_alice, bob.public().into_peer_id());
+        assert_eq!(alice_seen_by_bob, alice.public().into_peer_id());
+    }
+}
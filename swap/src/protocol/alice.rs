@@ -1,25 +1,39 @@
 //! Run an XMR/BTC swap in the role of Alice.
 //! Alice holds XMR and wishes receive BTC.
-use crate::{bitcoin, database::Database, execution_params::ExecutionParams, monero};
+use crate::{
+    bitcoin, database::Database, execution_params::ExecutionParams, monero,
+    protocol::progress::ProgressEvent,
+};
 use std::sync::Arc;
+use tokio::sync::broadcast;
 use uuid::Uuid;
 
 pub use self::{
     behaviour::{Behaviour, OutEvent},
+    bid_quote::{BidQuote, BidQuoteRequest},
+    cancel::cancel,
     event_loop::{EventLoop, EventLoopHandle},
     execution_setup::Message1,
+    punish::punish,
     quote_response::*,
+    refund::refund,
     state::*,
     swap::{run, run_until},
     transfer_proof::TransferProof,
 };
 pub use execution_setup::Message3;
 
+mod abort_request;
 mod behaviour;
+mod bid_quote;
+pub mod cancel;
+mod cooperative_redeem_request;
 mod encrypted_signature;
 pub mod event_loop;
 mod execution_setup;
+pub mod punish;
 mod quote_response;
+pub mod refund;
 pub mod state;
 mod steps;
 pub mod swap;
@@ -33,4 +47,13 @@ pub struct Swap {
     pub execution_params: ExecutionParams,
     pub swap_id: Uuid,
     pub db: Arc<Database>,
+    pub progress: broadcast::Sender<ProgressEvent>,
+}
+
+impl Swap {
+    /// Subscribes to the progress of the swap, the events are published once
+    /// the swap runs.
+    pub fn subscribe(&self) -> broadcast::Receiver<ProgressEvent> {
+        self.progress.subscribe()
+    }
 }
@@ -0,0 +1,89 @@
+use crate::{
+    network::request_response::{AbortProtocol, CborCodec, ABORT_TIMEOUT},
+    protocol::bob::AbortRequest,
+};
+use anyhow::{anyhow, Error, Result};
+use libp2p::{
+    request_response::{
+        ProtocolSupport, RequestResponse, RequestResponseConfig, RequestResponseEvent,
+        RequestResponseMessage, ResponseChannel,
+    },
+    NetworkBehaviour, PeerId,
+};
+use tracing::debug;
+
+#[derive(Debug)]
+pub enum OutEvent {
+    MsgReceived {
+        msg: AbortRequest,
+        channel: ResponseChannel<()>,
+        bob_peer_id: PeerId,
+    },
+    AckSent,
+    Failure(Error),
+}
+
+/// A `NetworkBehaviour` that represents receiving the request of Bob to abort
+/// a swap before he locked the Bitcoin.
+#[derive(NetworkBehaviour)]
+#[behaviour(out_event = "OutEvent", event_process = false)]
+#[allow(missing_debug_implementations)]
+pub struct Behaviour {
+    rr: RequestResponse<CborCodec<AbortProtocol, AbortRequest, ()>>,
+}
+
+impl Behaviour {
+    pub fn send_ack(&mut self, channel: ResponseChannel<()>) -> Result<()> {
+        self.rr
+            .send_response(channel, ())
+            .map_err(|err| anyhow!("Failed to ack abort request: {:?}", err))
+    }
+}
+
+impl Default for Behaviour {
+    fn default() -> Self {
+        let mut config = RequestResponseConfig::default();
+        config.set_request_timeout(ABORT_TIMEOUT);
+
+        Self {
+            rr: RequestResponse::new(
+                CborCodec::default(),
+                vec![(AbortProtocol, ProtocolSupport::Inbound)],
+                config,
+            ),
+        }
+    }
+}
+
+impl From<RequestResponseEvent<AbortRequest, ()>> for OutEvent {
+    fn from(event: RequestResponseEvent<AbortRequest, ()>) -> Self {
+        match event {
+            RequestResponseEvent::Message {
+                peer,
+                message:
+                    RequestResponseMessage::Request {
+                        request, channel, ..
+                    },
+                ..
+            } => {
+                debug!("Received abort request from {}", peer);
+                OutEvent::MsgReceived {
+                    msg: request,
+                    channel,
+                    bob_peer_id: peer,
+                }
+            }
+            RequestResponseEvent::Message {
+                message: RequestResponseMessage::Response { .. },
+                ..
+            } => OutEvent::Failure(anyhow!("Alice should not get a Response")),
+            RequestResponseEvent::InboundFailure { error, .. } => {
+                OutEvent::Failure(anyhow!("Inbound failure: {:?}", error))
+            }
+            RequestResponseEvent::OutboundFailure { error, .. } => {
+                OutEvent::Failure(anyhow!("Outbound failure: {:?}", error))
+            }
+            RequestResponseEvent::ResponseSent { .. } => OutEvent::AckSent,
+        }
+    }
+}
@@ -1,34 +1,78 @@
 use crate::{
-    network::{peer_tracker, peer_tracker::PeerTracker},
+    execution_params::ExecutionParams,
+    network::{
+        handshake, handshake::Capabilities, peer_tracker, peer_tracker::PeerTracker, rendezvous,
+        request_response::acknowledgement_timeout,
+    },
     protocol::{
         alice::{
-            encrypted_signature, execution_setup, quote_response, transfer_proof, QuoteResponse,
-            State0, State3, TransferProof,
+            abort_request, bid_quote, cooperative_redeem_request, encrypted_signature,
+            execution_setup, quote_response, transfer_proof, BidQuote, QuoteResponse, State0,
+            State3, TransferProof,
+        },
+        bob::{
+            AbortRequest, CooperativeRedeemRequest, CooperativeRedeemResponse, EncryptedSignature,
+            QuoteRequest,
         },
-        bob::{EncryptedSignature, QuoteRequest},
     },
 };
-use anyhow::{Error, Result};
-use libp2p::{request_response::ResponseChannel, NetworkBehaviour, PeerId};
+use anyhow::{anyhow, Error, Result};
+use libp2p::{
+    request_response::{RequestId, ResponseChannel},
+    Multiaddr, NetworkBehaviour, PeerId,
+};
+use std::time::Duration;
 use tracing::debug;
 
 #[derive(Debug)]
 pub enum OutEvent {
     ConnectionEstablished(PeerId),
+    ConnectionClosed(PeerId),
+    Handshake {
+        bob_peer_id: PeerId,
+        capabilities: Capabilities,
+        channel: ResponseChannel<Capabilities>,
+    },
     QuoteRequest {
         msg: QuoteRequest,
         channel: ResponseChannel<QuoteResponse>,
         bob_peer_id: PeerId,
     },
+    BidQuoteRequest {
+        channel: ResponseChannel<BidQuote>,
+        bob_peer_id: PeerId,
+    },
+    Registered {
+        rendezvous_node: PeerId,
+        ttl: Duration,
+    },
     ExecutionSetupDone {
         bob_peer_id: PeerId,
         state3: Box<State3>,
     },
-    TransferProofAcknowledged,
+    ExecutionSetupFailed {
+        bob_peer_id: PeerId,
+        error: Error,
+    },
+    TransferProofAcknowledged(RequestId),
+    TransferProofUndelivered {
+        request_id: RequestId,
+        error: Error,
+    },
     EncryptedSignature {
         msg: Box<EncryptedSignature>,
         channel: ResponseChannel<()>,
     },
+    AbortRequest {
+        msg: AbortRequest,
+        channel: ResponseChannel<()>,
+        bob_peer_id: PeerId,
+    },
+    CooperativeRedeemRequest {
+        msg: CooperativeRedeemRequest,
+        channel: ResponseChannel<CooperativeRedeemResponse>,
+        bob_peer_id: PeerId,
+    },
     ResponseSent, // Same variant is used for all messages as no processing is done
     Failure(Error),
 }
@@ -39,6 +83,31 @@ impl From<peer_tracker::OutEvent> for OutEvent {
             peer_tracker::OutEvent::ConnectionEstablished(id) => {
                 OutEvent::ConnectionEstablished(id)
             }
+            peer_tracker::OutEvent::ConnectionClosed(id) => OutEvent::ConnectionClosed(id),
+        }
+    }
+}
+
+impl From<handshake::OutEvent> for OutEvent {
+    fn from(event: handshake::OutEvent) -> Self {
+        use crate::network::handshake::OutEvent::*;
+        match event {
+            Request {
+                peer,
+                capabilities,
+                channel,
+            } => OutEvent::Handshake {
+                bob_peer_id: peer,
+                capabilities,
+                channel,
+            },
+            Response { peer, .. } => {
+                OutEvent::Failure(anyhow!("Unexpected handshake response from {}", peer))
+            }
+            ResponseSent => OutEvent::ResponseSent,
+            Failure { peer, error, .. } => {
+                OutEvent::Failure(error.context(format!("Handshake with {} failed", peer)))
+            }
         }
     }
 }
@@ -62,6 +131,42 @@ impl From<quote_response::OutEvent> for OutEvent {
     }
 }
 
+impl From<bid_quote::OutEvent> for OutEvent {
+    fn from(event: bid_quote::OutEvent) -> Self {
+        use crate::protocol::alice::bid_quote::OutEvent::*;
+        match event {
+            MsgReceived {
+                channel,
+                bob_peer_id,
+            } => OutEvent::BidQuoteRequest {
+                channel,
+                bob_peer_id,
+            },
+            ResponseSent => OutEvent::ResponseSent,
+            Failure(err) => OutEvent::Failure(err.context("Bid quote failure")),
+        }
+    }
+}
+
+impl From<rendezvous::OutEvent> for OutEvent {
+    fn from(event: rendezvous::OutEvent) -> Self {
+        use crate::network::rendezvous::OutEvent::*;
+        match event {
+            Registered {
+                rendezvous_node,
+                ttl,
+            } => OutEvent::Registered {
+                rendezvous_node,
+                ttl,
+            },
+            Discovered { .. } => OutEvent::Failure(anyhow!("Alice does not discover other peers")),
+            RequestFailed { error, .. } | Failure(error) => {
+                OutEvent::Failure(error.context("Failed to register at rendezvous point"))
+            }
+        }
+    }
+}
+
 impl From<execution_setup::OutEvent> for OutEvent {
     fn from(event: execution_setup::OutEvent) -> Self {
         use crate::protocol::alice::execution_setup::OutEvent::*;
@@ -73,7 +178,7 @@ impl From<execution_setup::OutEvent> for OutEvent {
                 bob_peer_id,
                 state3: Box::new(state3),
             },
-            Failure(err) => OutEvent::Failure(err),
+            Failure { bob_peer_id, error } => OutEvent::ExecutionSetupFailed { bob_peer_id, error },
         }
     }
 }
@@ -82,7 +187,10 @@ impl From<transfer_proof::OutEvent> for OutEvent {
     fn from(event: transfer_proof::OutEvent) -> Self {
         use crate::protocol::alice::transfer_proof::OutEvent::*;
         match event {
-            Acknowledged => OutEvent::TransferProofAcknowledged,
+            Acknowledged(request_id) => OutEvent::TransferProofAcknowledged(request_id),
+            Undelivered { request_id, error } => {
+                OutEvent::TransferProofUndelivered { request_id, error }
+            }
             Failure(err) => OutEvent::Failure(err.context("Failure with Transfer Proof")),
         }
     }
@@ -102,19 +210,90 @@ impl From<encrypted_signature::OutEvent> for OutEvent {
     }
 }
 
+impl From<abort_request::OutEvent> for OutEvent {
+    fn from(event: abort_request::OutEvent) -> Self {
+        use crate::protocol::alice::abort_request::OutEvent::*;
+        match event {
+            MsgReceived {
+                msg,
+                channel,
+                bob_peer_id,
+            } => OutEvent::AbortRequest {
+                msg,
+                channel,
+                bob_peer_id,
+            },
+            AckSent => OutEvent::ResponseSent,
+            Failure(err) => OutEvent::Failure(err.context("Failure with Abort Request")),
+        }
+    }
+}
+
+impl From<cooperative_redeem_request::OutEvent> for OutEvent {
+    fn from(event: cooperative_redeem_request::OutEvent) -> Self {
+        use crate::protocol::alice::cooperative_redeem_request::OutEvent::*;
+        match event {
+            MsgReceived {
+                msg,
+                channel,
+                bob_peer_id,
+            } => OutEvent::CooperativeRedeemRequest {
+                msg,
+                channel,
+                bob_peer_id,
+            },
+            ResponseSent => OutEvent::ResponseSent,
+            Failure(err) => {
+                OutEvent::Failure(err.context("Failure with Cooperative Redeem Request"))
+            }
+        }
+    }
+}
+
 /// A `NetworkBehaviour` that represents an XMR/BTC swap node as Alice.
-#[derive(NetworkBehaviour, Default)]
+#[derive(NetworkBehaviour)]
 #[behaviour(out_event = "OutEvent", event_process = false)]
 #[allow(missing_debug_implementations)]
 pub struct Behaviour {
     pt: PeerTracker,
+    handshake: handshake::Behaviour,
     quote_response: quote_response::Behaviour,
+    bid_quote: bid_quote::Behaviour,
+    rendezvous: rendezvous::Behaviour,
     execution_setup: execution_setup::Behaviour,
     transfer_proof: transfer_proof::Behaviour,
     encrypted_signature: encrypted_signature::Behaviour,
+    abort_request: abort_request::Behaviour,
+    cooperative_redeem_request: cooperative_redeem_request::Behaviour,
 }
 
 impl Behaviour {
+    pub fn new(capabilities: Capabilities, execution_params: ExecutionParams) -> Self {
+        let ack_timeout = acknowledgement_timeout(execution_params.bob_time_to_act);
+
+        Self {
+            pt: PeerTracker::default(),
+            handshake: handshake::Behaviour::new(capabilities),
+            quote_response: quote_response::Behaviour::default(),
+            bid_quote: bid_quote::Behaviour::default(),
+            rendezvous: rendezvous::Behaviour::default(),
+            execution_setup: execution_setup::Behaviour::default(),
+            transfer_proof: transfer_proof::Behaviour::new(ack_timeout),
+            encrypted_signature: encrypted_signature::Behaviour::new(ack_timeout),
+            abort_request: abort_request::Behaviour::default(),
+            cooperative_redeem_request: cooperative_redeem_request::Behaviour::default(),
+        }
+    }
+
+    pub fn capabilities(&self) -> &Capabilities {
+        self.handshake.capabilities()
+    }
+
+    /// Answers the handshake of Bob with our capabilities.
+    pub fn respond_to_handshake(&mut self, channel: ResponseChannel<Capabilities>) -> Result<()> {
+        self.handshake.respond(channel)
+    }
+
     pub fn send_quote_response(
         &mut self,
         channel: ResponseChannel<QuoteResponse>,
@@ -124,17 +303,60 @@ impl Behaviour {
         Ok(())
     }
 
+    pub fn send_bid_quote(
+        &mut self,
+        channel: ResponseChannel<BidQuote>,
+        bid_quote: BidQuote,
+    ) -> Result<()> {
+        self.bid_quote.send(channel, bid_quote)
+    }
+
+    /// Registers our `addresses` at the rendezvous point, the registration is
+    /// confirmed with an [`OutEvent::Registered`].
+    pub fn register(
+        &mut self,
+        rendezvous_node: PeerId,
+        rendezvous_address: Multiaddr,
+        addresses: Vec<Multiaddr>,
+    ) {
+        self.pt.add_address(rendezvous_node, rendezvous_address);
+        let _ = self.rendezvous.register(
+            rendezvous_node,
+            rendezvous::XMR_BTC_NAMESPACE,
+            addresses,
+            rendezvous::DEFAULT_TTL,
+        );
+    }
+
+    /// See [`PeerTracker::forget_disconnected_peers`].
+    pub fn forget_disconnected_peers(&mut self, keep: impl Fn(&PeerId) -> bool) {
+        self.pt.forget_disconnected_peers(keep);
+    }
+
     pub fn start_execution_setup(&mut self, bob_peer_id: PeerId, state0: State0) {
         self.execution_setup.run(bob_peer_id, state0);
     }
 
     /// Send Transfer Proof to Bob.
-    pub fn send_transfer_proof(&mut self, bob: PeerId, msg: TransferProof) {
-        self.transfer_proof.send(bob, msg);
+    pub fn send_transfer_proof(&mut self, bob: PeerId, msg: TransferProof) -> RequestId {
+        let request_id = self.transfer_proof.send(bob, msg);
         debug!("Sent Transfer Proof");
+        request_id
     }
 
     pub fn send_encrypted_signature_ack(&mut self, channel: ResponseChannel<()>) -> Result<()> {
         self.encrypted_signature.send_ack(channel)
     }
+
+    pub fn send_abort_request_ack(&mut self, channel: ResponseChannel<()>) -> Result<()> {
+        self.abort_request.send_ack(channel)
+    }
+
+    pub fn send_cooperative_redeem_response(
+        &mut self,
+        channel: ResponseChannel<CooperativeRedeemResponse>,
+        response: CooperativeRedeemResponse,
+    ) -> Result<()> {
+        self.cooperative_redeem_request.send(channel, response)
+    }
 }
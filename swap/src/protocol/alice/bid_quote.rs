@@ -0,0 +1,103 @@
+use crate::{
+    bitcoin, monero,
+    network::request_response::{BidQuoteProtocol, CborCodec, QUOTE_TIMEOUT},
+};
+use anyhow::{anyhow, Error, Result};
+use libp2p::{
+    request_response::{
+        ProtocolSupport, RequestResponse, RequestResponseConfig, RequestResponseEvent,
+        RequestResponseMessage, ResponseChannel,
+    },
+    NetworkBehaviour, PeerId,
+};
+use serde::{Deserialize, Serialize};
+use tracing::debug;
+
+/// Asks Alice for her current offer. Unlike a quote request this does not
+/// start a swap.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct BidQuoteRequest;
+
+/// The offer of Alice at the time of the request.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq)]
+pub struct BidQuote {
+    /// The price in BTC for 1 XMR.
+    #[serde(with = "::bitcoin::util::amount::serde::as_sat")]
+    pub price: bitcoin::Amount,
+    /// The smallest amount of XMR Alice is willing to sell.
+    pub min_quantity: monero::Amount,
+    /// The largest amount of XMR Alice is willing to sell.
+    pub max_quantity: monero::Amount,
+}
+
+#[derive(Debug)]
+pub enum OutEvent {
+    MsgReceived {
+        channel: ResponseChannel<BidQuote>,
+        bob_peer_id: PeerId,
+    },
+    ResponseSent,
+    Failure(Error),
+}
+
+impl From<RequestResponseEvent<BidQuoteRequest, BidQuote>> for OutEvent {
+    fn from(event: RequestResponseEvent<BidQuoteRequest, BidQuote>) -> Self {
+        match event {
+            RequestResponseEvent::Message {
+                peer,
+                message: RequestResponseMessage::Request { channel, .. },
+                ..
+            } => {
+                debug!("Received bid quote request from {}", peer);
+                OutEvent::MsgReceived {
+                    channel,
+                    bob_peer_id: peer,
+                }
+            }
+            RequestResponseEvent::Message {
+                message: RequestResponseMessage::Response { .. },
+                ..
+            } => OutEvent::Failure(anyhow!("Alice should not get a Response")),
+            RequestResponseEvent::InboundFailure { error, .. } => {
+                OutEvent::Failure(anyhow!("Inbound failure: {:?}", error))
+            }
+            RequestResponseEvent::OutboundFailure { error, .. } => {
+                OutEvent::Failure(anyhow!("Outbound failure: {:?}", error))
+            }
+            RequestResponseEvent::ResponseSent { .. } => OutEvent::ResponseSent,
+        }
+    }
+}
+
+/// A `NetworkBehaviour` that lets anyone query the current offer of Alice.
+#[derive(NetworkBehaviour)]
+#[behaviour(out_event = "OutEvent", event_process = false)]
+#[allow(missing_debug_implementations)]
+pub struct Behaviour {
+    rr: RequestResponse<CborCodec<BidQuoteProtocol, BidQuoteRequest, BidQuote>>,
+}
+
+impl Behaviour {
+    pub fn send(&mut self, channel: ResponseChannel<BidQuote>, msg: BidQuote) -> Result<()> {
+        self.rr
+            .send_response(channel, msg)
+            .map_err(|_| anyhow!("failed to send bid quote"))?;
+
+        Ok(())
+    }
+}
+
+impl Default for Behaviour {
+    fn default() -> Self {
+        let mut config = RequestResponseConfig::default();
+        config.set_request_timeout(QUOTE_TIMEOUT);
+
+        Self {
+            rr: RequestResponse::new(
+                CborCodec::default(),
+                vec![(BidQuoteProtocol, ProtocolSupport::Inbound)],
+                config,
+            ),
+        }
+    }
+}
@@ -0,0 +1,80 @@
+use crate::{
+    bitcoin::{ExpiredTimelocks, Txid, Wallet},
+    database::{Database, Swap},
+    protocol::alice::{steps::publish_cancel_transaction, AliceState},
+};
+use anyhow::{bail, Result};
+use std::sync::Arc;
+use uuid::Uuid;
+
+#[derive(Debug, thiserror::Error, Clone, Copy)]
+#[error("The cancel timelock has not expired yet.")]
+pub struct CancelTimelockNotExpiredYet;
+
+/// Publishes the cancel transaction of a swap in which we locked the Monero.
+///
+/// This does not stop the swap if it is still running, it notices the
+/// cancel transaction on its own.
+pub async fn cancel(
+    swap_id: Uuid,
+    state: AliceState,
+    bitcoin_wallet: Arc<Wallet>,
+    db: Arc<Database>,
+) -> Result<(Txid, AliceState)> {
+    let (state3, monero_wallet_restore_blockheight) = match state {
+        AliceState::XmrLockTransactionSent {
+            state3,
+            monero_wallet_restore_blockheight,
+            ..
+        }
+        | AliceState::XmrLockTransferProofSent {
+            state3,
+            monero_wallet_restore_blockheight,
+            ..
+        }
+        | AliceState::XmrLocked {
+            state3,
+            monero_wallet_restore_blockheight,
+        }
+        | AliceState::EncSigLearned {
+            state3,
+            monero_wallet_restore_blockheight,
+            ..
+        }
+        | AliceState::CancelTimelockExpired {
+            state3,
+            monero_wallet_restore_blockheight,
+        } => (state3, monero_wallet_restore_blockheight),
+        _ => bail!(
+            "Cannot cancel swap {} because it is in state {} which is not cancelable.",
+            swap_id,
+            state
+        ),
+    };
+
+    if let ExpiredTimelocks::None = state3.expired_timelocks(bitcoin_wallet.as_ref()).await? {
+        bail!(CancelTimelockNotExpiredYet)
+    }
+
+    let tx_cancel = publish_cancel_transaction(
+        state3.tx_lock.clone(),
+        state3.a.clone(),
+        state3.B,
+        state3.cancel_timelock,
+        state3.tx_cancel_sig_bob.clone(),
+        bitcoin_wallet,
+    )
+    .await?;
+    let txid = tx_cancel.txid();
+
+    let state = AliceState::BtcCancelled {
+        monero_wallet_restore_blockheight,
+        tx_cancel: Box::new(tx_cancel),
+        state3,
+    };
+    let db_state = (&state).into();
+    db.insert_latest_state(swap_id, Swap::Alice(db_state))
+        .await?;
+
+    Ok((txid, state))
+}
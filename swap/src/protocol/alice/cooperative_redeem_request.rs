@@ -0,0 +1,97 @@
+use crate::{
+    network::request_response::{CborCodec, CooperativeRedeemProtocol, COOPERATIVE_REDEEM_TIMEOUT},
+    protocol::bob::{CooperativeRedeemRequest, CooperativeRedeemResponse},
+};
+use anyhow::{anyhow, Error, Result};
+use libp2p::{
+    request_response::{
+        ProtocolSupport, RequestResponse, RequestResponseConfig, RequestResponseEvent,
+        RequestResponseMessage, ResponseChannel,
+    },
+    NetworkBehaviour, PeerId,
+};
+use tracing::debug;
+
+#[derive(Debug)]
+pub enum OutEvent {
+    MsgReceived {
+        msg: CooperativeRedeemRequest,
+        channel: ResponseChannel<CooperativeRedeemResponse>,
+        bob_peer_id: PeerId,
+    },
+    ResponseSent,
+    Failure(Error),
+}
+
+/// A `NetworkBehaviour` that represents receiving the request of Bob to redeem
+/// the Monero of a swap we punished.
+#[derive(NetworkBehaviour)]
+#[behaviour(out_event = "OutEvent", event_process = false)]
+#[allow(missing_debug_implementations)]
+pub struct Behaviour {
+    rr: RequestResponse<
+        CborCodec<CooperativeRedeemProtocol, CooperativeRedeemRequest, CooperativeRedeemResponse>,
+    >,
+}
+
+impl Behaviour {
+    pub fn send(
+        &mut self,
+        channel: ResponseChannel<CooperativeRedeemResponse>,
+        msg: CooperativeRedeemResponse,
+    ) -> Result<()> {
+        self.rr
+            .send_response(channel, msg)
+            .map_err(|err| anyhow!("Failed to send cooperative redeem response: {:?}", err))
+    }
+}
+
+impl Default for Behaviour {
+    fn default() -> Self {
+        let mut config = RequestResponseConfig::default();
+        config.set_request_timeout(COOPERATIVE_REDEEM_TIMEOUT);
+
+        Self {
+            rr: RequestResponse::new(
+                CborCodec::default(),
+                vec![(CooperativeRedeemProtocol, ProtocolSupport::Inbound)],
+                config,
+            ),
+        }
+    }
+}
+
+impl From<RequestResponseEvent<CooperativeRedeemRequest, CooperativeRedeemResponse>> for OutEvent {
+    fn from(
+        event: RequestResponseEvent<CooperativeRedeemRequest, CooperativeRedeemResponse>,
+    ) -> Self {
+        match event {
+            RequestResponseEvent::Message {
+                peer,
+                message:
+                    RequestResponseMessage::Request {
+                        request, channel, ..
+                    },
+                ..
+            } => {
+                debug!("Received cooperative redeem request from {}", peer);
+                OutEvent::MsgReceived {
+                    msg: request,
+                    channel,
+                    bob_peer_id: peer,
+                }
+            }
+            RequestResponseEvent::Message {
+                message: RequestResponseMessage::Response { .. },
+                ..
+            } => OutEvent::Failure(anyhow!("Alice should not get a Response")),
+            RequestResponseEvent::InboundFailure { error, .. } => {
+                OutEvent::Failure(anyhow!("Inbound failure: {:?}", error))
+            }
+            RequestResponseEvent::OutboundFailure { error, .. } => {
+                OutEvent::Failure(anyhow!("Outbound failure: {:?}", error))
+            }
+            RequestResponseEvent::ResponseSent { .. } => OutEvent::ResponseSent,
+        }
+    }
+}
@@ -1,5 +1,5 @@
 use crate::{
-    network::request_response::{CborCodec, EncryptedSignatureProtocol, TIMEOUT},
+    network::request_response::{CborCodec, EncryptedSignatureProtocol},
     protocol::bob::EncryptedSignature,
 };
 use anyhow::{anyhow, Error, Result};
@@ -40,9 +40,9 @@ impl Behaviour {
     }
 }
 
-impl Default for Behaviour {
-    fn default() -> Self {
-        let timeout = Duration::from_secs(TIMEOUT);
+impl Behaviour {
+    /// How long we may take to acknowledge an encrypted signature.
+    pub fn new(timeout: Duration) -> Self {
         let mut config = RequestResponseConfig::default();
         config.set_request_timeout(timeout);
 
@@ -1,31 +1,60 @@
 use crate::{
-    asb::LatestRate,
-    bitcoin,
+    asb::{
+        access_control::{AccessControl, Refused},
+        config::Limits,
+        metrics, rpc, LatestRate, Rate,
+    },
+    bitcoin, database,
     database::Database,
     execution_params::ExecutionParams,
     monero,
-    monero::{Amount, BalanceTooLow},
-    network::{transport, TokioExecutor},
+    monero::{Amount, BalanceTooLow, WalletBusy},
+    monero_ext::ScalarExt,
+    network::{
+        handshake::Capabilities,
+        outbox::{Outbox, PendingMessage, RETRY_INTERVAL},
+        rendezvous,
+        request_response::EXECUTION_SETUP_TIMEOUT,
+        transport, TokioExecutor,
+    },
     protocol::{
         alice,
         alice::{
-            AliceState, Behaviour, OutEvent, QuoteResponse, State0, State3, Swap, TransferProof,
+            AliceState, Behaviour, BidQuote, OutEvent, QuoteResponse, State0, State3, Swap,
+            TransferProof,
         },
-        bob::{EncryptedSignature, QuoteRequest},
+        bob::{
+            CooperativeRedeemRejected, CooperativeRedeemRequest, CooperativeRedeemResponse,
+            EncryptedSignature, QuoteRequest,
+        },
+        progress::{self, ProgressEvent},
     },
     seed::Seed,
 };
-use anyhow::{bail, Context, Result};
-use futures::future::RemoteHandle;
+use anyhow::{anyhow, bail, Context, Result};
+use futures::future::{Future, RemoteHandle};
 use libp2p::{
-    core::Multiaddr, futures::FutureExt, request_response::ResponseChannel, PeerId, Swarm,
+    core::{multiaddr::Protocol, Multiaddr},
+    futures::FutureExt,
+    request_response::ResponseChannel,
+    PeerId, Swarm,
 };
 use rand::rngs::OsRng;
-use std::sync::Arc;
-use tokio::sync::{broadcast, mpsc, mpsc::error::SendError};
-use tracing::{debug, error, info, trace};
+use rust_decimal::Decimal;
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use tokio::sync::{broadcast, mpsc, mpsc::error::SendError, oneshot};
+use tracing::{debug, error, info, trace, warn};
 use uuid::Uuid;
 
+const TRANSFER_PROOF_OUTBOX: &str = "transfer_proof_outbox";
+
+/// How often wallet balances and the rate are sampled for the metrics.
+const METRICS_INTERVAL: Duration = Duration::from_secs(30);
+
 #[allow(missing_debug_implementations)]
 pub struct MpscChannels<T> {
     sender: mpsc::Sender<T>,
@@ -59,8 +88,10 @@ where
 
 #[derive(Debug)]
 pub struct EventLoopHandle {
+    db: Database,
     recv_encrypted_signature: broadcast::Receiver<EncryptedSignature>,
-    send_transfer_proof: mpsc::Sender<(PeerId, TransferProof)>,
+    recv_abort_request: broadcast::Receiver<(PeerId, bitcoin::Txid)>,
+    send_transfer_proof: mpsc::Sender<(Uuid, PendingMessage<TransferProof>)>,
 }
 
 impl EventLoopHandle {
@@ -70,13 +101,69 @@ impl EventLoopHandle {
             .await
             .context("Failed to receive Bitcoin encrypted signature from Bob")
     }
-    pub async fn send_transfer_proof(&mut self, bob: PeerId, msg: TransferProof) -> Result<()> {
-        let _ = self.send_transfer_proof.send((bob, msg)).await?;
+
+    /// Waits until `bob` asks to abort the swap with the lock transaction
+    /// `tx_lock_id`. Requests for other swaps are ignored.
+    pub async fn recv_abort_request(
+        &mut self,
+        bob: PeerId,
+        tx_lock_id: bitcoin::Txid,
+    ) -> Result<()> {
+        loop {
+            match self.recv_abort_request.recv().await {
+                Ok((peer, txid)) if peer == bob && txid == tx_lock_id => return Ok(()),
+                Ok(_) | Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                Err(broadcast::error::RecvError::Closed) => {
+                    bail!("Failed to receive abort request from Bob")
+                }
+            }
+        }
+    }
+
+    /// Queues the transfer proof for delivery to Bob. It is persisted before
+    /// this returns and sent until Bob acknowledges it.
+    pub async fn send_transfer_proof(
+        &mut self,
+        swap_id: Uuid,
+        msg: PendingMessage<TransferProof>,
+    ) -> Result<()> {
+        Outbox::persist(TRANSFER_PROOF_OUTBOX, &self.db, swap_id, &msg).await?;
+        let _ = self.send_transfer_proof.send((swap_id, msg)).await?;
 
         Ok(())
     }
 }
 
+type ControlRequest = (rpc::Request, oneshot::Sender<Result<rpc::Response>>);
+
+/// Steers a running [`EventLoop`], e.g. from the RPC server.
+#[derive(Debug, Clone)]
+pub struct ControlHandle {
+    sender: mpsc::Sender<ControlRequest>,
+}
+
+impl ControlHandle {
+    pub async fn send(&self, request: rpc::Request) -> Result<rpc::Response> {
+        let (sender, receiver) = oneshot::channel();
+        self.sender
+            .send((request, sender))
+            .await
+            .map_err(|_| anyhow!("The event loop is not running"))?;
+
+        receiver
+            .await
+            .context("The event loop dropped the request")?
+    }
+}
+
+/// Where and under which addresses the ASB announces itself.
+#[derive(Debug, Clone)]
+struct RendezvousPoint {
+    peer_id: PeerId,
+    address: Multiaddr,
+    announced_addresses: Vec<Multiaddr>,
+}
+
 #[allow(missing_debug_implementations)]
 pub struct EventLoop<RS> {
     swarm: libp2p::Swarm<Behaviour>,
@@ -87,14 +174,40 @@ pub struct EventLoop<RS> {
     db: Arc<Database>,
     rate_service: RS,
     max_sell: Amount,
+    /// Added to the ask of the rate service.
+    spread: Decimal,
+    quoting_paused: bool,
+    listen_addresses: Vec<Multiaddr>,
+    rendezvous_point: Option<RendezvousPoint>,
+    /// Peers that completed the handshake with compatible capabilities. Swaps
+    /// are only started with them.
+    compatible_peers: HashSet<PeerId>,
+    access_control: AccessControl,
+    max_concurrent_execution_setups: usize,
+    /// Peers we are currently running the execution setup with and when it
+    /// was started.
+    execution_setups: HashMap<PeerId, Instant>,
+    /// Whether Bob gets our key share of swaps we punished if he asks for it.
+    cooperative_redeem: bool,
+    /// Swaps that are executed or manually intervened in at the moment. Both
+    /// save the state of the swap, so only one of them may run at a time.
+    running_swaps: Arc<Mutex<HashSet<Uuid>>>,
 
     recv_encrypted_signature: broadcast::Sender<EncryptedSignature>,
-    send_transfer_proof: mpsc::Receiver<(PeerId, TransferProof)>,
+    recv_abort_request: broadcast::Sender<(PeerId, bitcoin::Txid)>,
+    send_transfer_proof: mpsc::Receiver<(Uuid, PendingMessage<TransferProof>)>,
+    transfer_proof_outbox: Outbox<TransferProof>,
 
     // Only used to produce new handles
-    send_transfer_proof_sender: mpsc::Sender<(PeerId, TransferProof)>,
+    send_transfer_proof_sender: mpsc::Sender<(Uuid, PendingMessage<TransferProof>)>,
 
     swap_handle_sender: mpsc::Sender<RemoteHandle<Result<AliceState>>>,
+
+    control: mpsc::Receiver<ControlRequest>,
+    // Only used to produce new control handles
+    control_sender: mpsc::Sender<ControlRequest>,
+
+    progress: broadcast::Sender<ProgressEvent>,
 }
 
 impl<RS> EventLoop<RS>
@@ -111,9 +224,11 @@ where
         db: Arc<Database>,
         rate_service: RS,
         max_sell: Amount,
+        capabilities: Capabilities,
+        limits: Limits,
     ) -> Result<(Self, mpsc::Receiver<RemoteHandle<Result<AliceState>>>)> {
         let identity = seed.derive_libp2p_identity();
-        let behaviour = Behaviour::default();
+        let behaviour = Behaviour::new(capabilities, execution_params);
         let transport = transport::build(&identity)?;
         let peer_id = PeerId::from(identity.public());
 
@@ -126,9 +241,21 @@ where
         Swarm::listen_on(&mut swarm, listen_address.clone())
             .with_context(|| format!("Address is not supported: {:#}", listen_address))?;
 
+        let access_control = AccessControl::new(
+            limits.allow_list,
+            limits.deny_list,
+            limits.max_requests_per_minute,
+        );
+        for peer in access_control.deny_list() {
+            Swarm::ban_peer_id(&mut swarm, *peer);
+        }
+
         let recv_encrypted_signature = BroadcastChannels::default();
+        let recv_abort_request = BroadcastChannels::default();
         let send_transfer_proof = MpscChannels::default();
         let swap_handle = MpscChannels::default();
+        let control = MpscChannels::default();
+        let transfer_proof_outbox = Outbox::load(TRANSFER_PROOF_OUTBOX, db.as_ref().clone())?;
 
         let event_loop = EventLoop {
             swarm,
@@ -139,43 +266,198 @@ where
             db,
             rate_service,
             recv_encrypted_signature: recv_encrypted_signature.sender,
+            recv_abort_request: recv_abort_request.sender,
             send_transfer_proof: send_transfer_proof.receiver,
+            transfer_proof_outbox,
             send_transfer_proof_sender: send_transfer_proof.sender,
             swap_handle_sender: swap_handle.sender,
             max_sell,
+            spread: Decimal::from(0),
+            quoting_paused: false,
+            listen_addresses: vec![listen_address],
+            rendezvous_point: None,
+            compatible_peers: HashSet::new(),
+            access_control,
+            max_concurrent_execution_setups: limits.max_concurrent_execution_setups,
+            execution_setups: HashMap::new(),
+            cooperative_redeem: false,
+            running_swaps: Arc::new(Mutex::new(HashSet::new())),
+            control: control.receiver,
+            control_sender: control.sender,
+            progress: progress::channel(),
         };
         Ok((event_loop, swap_handle.receiver))
     }
 
     pub fn new_handle(&self) -> EventLoopHandle {
         EventLoopHandle {
+            db: self.db.as_ref().clone(),
             recv_encrypted_signature: self.recv_encrypted_signature.subscribe(),
+            recv_abort_request: self.recv_abort_request.subscribe(),
             send_transfer_proof: self.send_transfer_proof_sender.clone(),
         }
     }
 
+    pub fn control_handle(&self) -> ControlHandle {
+        ControlHandle {
+            sender: self.control_sender.clone(),
+        }
+    }
+
+    /// Subscribes to the progress of all swaps started or resumed by this
+    /// event loop.
+    pub fn subscribe_progress(&self) -> broadcast::Receiver<ProgressEvent> {
+        self.progress.subscribe()
+    }
+
     pub fn peer_id(&self) -> PeerId {
         self.peer_id
     }
 
+    /// Listens on an additional address, e.g. a `/ws` address for browser
+    /// based takers.
+    pub fn listen_on(&mut self, address: Multiaddr) -> Result<()> {
+        Swarm::listen_on(&mut self.swarm, address.clone())
+            .with_context(|| format!("Address is not supported: {:#}", address))?;
+        self.listen_addresses.push(address);
+
+        Ok(())
+    }
+
+    /// Registers the ASB at the given rendezvous point and keeps the
+    /// registration alive. Without `external_addresses` the listen addresses
+    /// added so far are announced, except for unspecified ones like
+    /// `/ip4/0.0.0.0` that buyers cannot dial.
+    pub fn register_at(
+        &mut self,
+        rendezvous_peer_id: PeerId,
+        rendezvous_address: Multiaddr,
+        external_addresses: Vec<Multiaddr>,
+    ) -> Result<()> {
+        let announced_addresses = if external_addresses.is_empty() {
+            self.listen_addresses
+                .iter()
+                .filter(|address| !is_unspecified(address))
+                .cloned()
+                .collect()
+        } else {
+            external_addresses
+        };
+
+        if announced_addresses.is_empty() {
+            bail!("None of the listen addresses can be dialed by buyers, external addresses are required to register at a rendezvous point")
+        }
+
+        self.rendezvous_point = Some(RendezvousPoint {
+            peer_id: rendezvous_peer_id,
+            address: rendezvous_address,
+            announced_addresses,
+        });
+
+        Ok(())
+    }
+
+    /// Lets Bob redeem the Monero of swaps we punished by handing out our key
+    /// share. We cannot redeem it without his anyway.
+    pub fn enable_cooperative_redeem(&mut self) {
+        self.cooperative_redeem = true;
+    }
+
+    /// Runs the swarm and the swaps started through it. Swaps that were
+    /// interrupted by a restart are resumed first.
     pub async fn run(mut self) {
+        if let Err(error) = self.resume_unfinished_swaps().await {
+            error!("Failed to resume unfinished swaps: {:#}", error);
+        }
+
+        let mut retry_interval = tokio::time::interval(RETRY_INTERVAL);
+        let mut registration_interval = tokio::time::interval(rendezvous::REFRESH_INTERVAL);
+        let mut metrics_interval = tokio::time::interval(METRICS_INTERVAL);
+
+        // The balances are sampled separately, the Monero wallet can be busy
+        // for a while, e.g. while the Monero of a refunded swap is swept.
+        // Dropping the handle stops the sampling once the event loop ends.
+        let (sample_balances, _sample_balances_handle) =
+            sample_balances(self.bitcoin_wallet.clone(), self.monero_wallet.clone())
+                .remote_handle();
+        tokio::spawn(sample_balances);
+
         loop {
             tokio::select! {
                 swarm_event = self.swarm.next().fuse() => {
                     match swarm_event {
-                        OutEvent::ConnectionEstablished(alice) => {
-                            debug!("Connection Established with {}", alice);
+                        OutEvent::ConnectionEstablished(bob) => {
+                            debug!("Connection Established with {}", bob);
+                            metrics::peer_connected();
+                            let unsent = self.transfer_proof_outbox.unsent_to(bob);
+                            self.send_transfer_proofs(unsent);
+                        }
+                        OutEvent::ConnectionClosed(bob) => {
+                            debug!("Connection closed with {}", bob);
+                            metrics::peer_disconnected();
+                            self.compatible_peers.remove(&bob);
+                            if self.transfer_proof_outbox.has_pending(bob) {
+                                debug!("Redialing {} to deliver pending transfer proofs", bob);
+                                if let Err(error) = Swarm::dial(&mut self.swarm, &bob) {
+                                    debug!("Failed to redial {}: {:?}", bob, error);
+                                }
+                            }
+                            self.forget_disconnected_peers();
+                        }
+                        OutEvent::Handshake { bob_peer_id, capabilities, channel } => {
+                            match self.swarm.capabilities().check_compatible(&capabilities) {
+                                Ok(()) => {
+                                    debug!("Completed handshake with {} running version {}", bob_peer_id, capabilities.software_version);
+                                    self.compatible_peers.insert(bob_peer_id);
+                                }
+                                Err(incompatible) => {
+                                    warn!("Refusing to swap with {}: {}", bob_peer_id, incompatible);
+                                }
+                            }
+                            // Answer either way so that Bob can tell why we refuse to swap.
+                            if let Err(error) = self.swarm.respond_to_handshake(channel) {
+                                error!("Failed to answer handshake of {}: {:#}", bob_peer_id, error);
+                            }
                         }
                         OutEvent::QuoteRequest { msg, channel, bob_peer_id } => {
-                            if let Err(error) = self.handle_quote_request(msg, channel, bob_peer_id, self.monero_wallet.clone()).await {
-                                error!("Failed to handle quote request: {:#}", error);
+                            match self.handle_quote_request(msg, channel, bob_peer_id, self.monero_wallet.clone()).await {
+                                Ok(()) => metrics::quote_served(metrics::QUOTE),
+                                Err(error) => {
+                                    metrics::quote_rejected(metrics::QUOTE, rejection_reason(&error));
+                                    error!("Failed to handle quote request: {:#}", error);
+                                }
+                            }
+                        }
+                        OutEvent::BidQuoteRequest { channel, bob_peer_id } => {
+                            match self.handle_bid_quote_request(channel, bob_peer_id).await {
+                                Ok(()) => metrics::quote_served(metrics::BID_QUOTE),
+                                Err(error) => {
+                                    metrics::quote_rejected(metrics::BID_QUOTE, rejection_reason(&error));
+                                    error!("Failed to send bid quote to {}: {:#}", bob_peer_id, error);
+                                }
                             }
                         }
+                        OutEvent::Registered { rendezvous_node, ttl } => {
+                            debug!("Registered at rendezvous point {} for {}s", rendezvous_node, ttl.as_secs());
+                        }
                         OutEvent::ExecutionSetupDone{bob_peer_id, state3} => {
-                            let _ = self.handle_execution_setup_done(bob_peer_id, *state3).await;
+                            self.execution_setups.remove(&bob_peer_id);
+                            self.handle_execution_setup_done(bob_peer_id, *state3).await;
+                        }
+                        OutEvent::ExecutionSetupFailed{bob_peer_id, error} => {
+                            self.execution_setups.remove(&bob_peer_id);
+                            error!("Execution setup with {} failed: {:#}", bob_peer_id, error);
                         }
-                        OutEvent::TransferProofAcknowledged => {
+                        OutEvent::TransferProofAcknowledged(request_id) => {
                             trace!("Bob acknowledged transfer proof");
+                            if let Err(error) = self.transfer_proof_outbox.acknowledged(request_id).await {
+                                error!("Failed to remove acknowledged transfer proof: {:#}", error);
+                            }
+                            self.forget_disconnected_peers();
+                        }
+                        OutEvent::TransferProofUndelivered { request_id, error } => {
+                            debug!("Failed to deliver transfer proof, will retry: {:#}", error);
+                            self.transfer_proof_outbox.undelivered(request_id);
                         }
                         OutEvent::EncryptedSignature{ msg, channel } => {
                             let _ = self.recv_encrypted_signature.send(*msg);
@@ -184,6 +466,28 @@ where
                                 error!("Failed to send Encrypted Signature ack: {:?}", error);
                             }
                         }
+                        OutEvent::AbortRequest { msg, channel, bob_peer_id } => {
+                            info!("{} aborted the swap with lock transaction {}", bob_peer_id, msg.tx_lock_id);
+                            let _ = self.recv_abort_request.send((bob_peer_id, msg.tx_lock_id));
+                            if let Err(error) = self.swarm.send_abort_request_ack(channel) {
+                                error!("Failed to send Abort Request ack: {:?}", error);
+                            }
+                        }
+                        OutEvent::CooperativeRedeemRequest { msg, channel, bob_peer_id } => {
+                            let tx_lock_id = msg.tx_lock_id;
+                            match self.handle_cooperative_redeem_request(msg, bob_peer_id) {
+                                Ok(response) => {
+                                    match &response {
+                                        CooperativeRedeemResponse::Fulfilled { .. } => info!("Handing out our key share of the punished swap with lock transaction {} to {}", tx_lock_id, bob_peer_id),
+                                        CooperativeRedeemResponse::Rejected(reason) => info!("Refusing cooperative redeem of the swap with lock transaction {} to {}: {}", tx_lock_id, bob_peer_id, reason),
+                                    }
+                                    if let Err(error) = self.swarm.send_cooperative_redeem_response(channel, response) {
+                                        error!("Failed to send cooperative redeem response: {:#}", error);
+                                    }
+                                }
+                                Err(error) => error!("Failed to handle cooperative redeem request of {}: {:#}", bob_peer_id, error),
+                            }
+                        }
                         OutEvent::ResponseSent => {}
                         OutEvent::Failure(err) => {
                             error!("Communication error: {:#}", err);
@@ -191,14 +495,181 @@ where
                     }
                 },
                 transfer_proof = self.send_transfer_proof.recv().fuse() => {
-                    if let Some((bob_peer_id, msg)) = transfer_proof  {
-                      self.swarm.send_transfer_proof(bob_peer_id, msg);
+                    if let Some((swap_id, msg)) = transfer_proof  {
+                        self.transfer_proof_outbox.push(swap_id, msg.clone());
+                        self.send_transfer_proofs(vec![(swap_id, msg)]);
                     }
                 },
+                expired = self.transfer_proof_outbox.next_expired().fuse() => {
+                    if let Err(error) = self.transfer_proof_outbox.discard_expired(expired).await {
+                        error!("Failed to discard expired transfer proofs: {:#}", error);
+                    }
+                    self.forget_disconnected_peers();
+                }
+                _ = retry_interval.tick().fuse() => {
+                    self.transfer_proof_outbox.check_expiry(self.bitcoin_wallet.clone());
+                    let unsent = self.transfer_proof_outbox.unsent();
+                    self.send_transfer_proofs(unsent);
+                }
+                _ = registration_interval.tick().fuse() => {
+                    if let Some(rendezvous_point) = self.rendezvous_point.clone() {
+                        self.swarm.register(rendezvous_point.peer_id, rendezvous_point.address, rendezvous_point.announced_addresses);
+                    }
+                }
+                _ = metrics_interval.tick().fuse() => {
+                    metrics::rate(self.rate_service.latest_rate().ok());
+                }
+                control_request = self.control.recv().fuse() => {
+                    if let Some((request, responder)) = control_request {
+                        self.handle_control_request(request, responder).await;
+                    }
+                }
+            }
+        }
+    }
+
+    async fn handle_control_request(
+        &mut self,
+        request: rpc::Request,
+        responder: oneshot::Sender<Result<rpc::Response>>,
+    ) {
+        let response = match request {
+            rpc::Request::ListSwaps => self.list_swaps(),
+            rpc::Request::GetBalances => self.balances().await,
+            rpc::Request::SetMaxSell { xmr } => {
+                info!("Changing the maximum sell amount to {}", xmr);
+                self.max_sell = xmr;
+                Ok(rpc::Response::Done)
             }
+            rpc::Request::SetSpread { spread } => {
+                info!("Changing the spread to {}", spread);
+                self.spread = spread;
+                Ok(rpc::Response::Done)
+            }
+            rpc::Request::PauseQuoting => {
+                info!("Pausing quoting");
+                self.quoting_paused = true;
+                Ok(rpc::Response::Done)
+            }
+            rpc::Request::ResumeQuoting => {
+                info!("Resuming quoting");
+                self.quoting_paused = false;
+                Ok(rpc::Response::Done)
+            }
+            request => match request.swap_id() {
+                Some(swap_id) if self.is_running(swap_id) => Err(anyhow!(
+                    "Swap {} is running, it can only be changed manually once it stopped",
+                    swap_id
+                )),
+                swap_id => {
+                    let running = swap_id
+                        .map(|swap_id| RunningSwap::new(swap_id, self.running_swaps.clone()));
+                    // Manual interventions wait for the wallets, the swarm has to
+                    // be polled in the meantime.
+                    let bitcoin_wallet = self.bitcoin_wallet.clone();
+                    let monero_wallet = self.monero_wallet.clone();
+                    let db = self.db.clone();
+                    tokio::spawn(async move {
+                        let _running = running;
+                        let response = intervene(request, bitcoin_wallet, monero_wallet, db).await;
+                        let _ = responder.send(response);
+                    });
+                    return;
+                }
+            },
+        };
+
+        let _ = responder.send(response);
+    }
+
+    fn is_running(&self, swap_id: Uuid) -> bool {
+        self.running_swaps
+            .lock()
+            .expect("no panic while holding the lock")
+            .contains(&swap_id)
+    }
+
+    fn list_swaps(&self) -> Result<rpc::Response> {
+        let swaps = self
+            .db
+            .all()?
+            .into_iter()
+            .map(|(swap_id, state)| rpc::SwapSummary {
+                swap_id,
+                state: state.to_string(),
+            })
+            .collect();
+
+        Ok(rpc::Response::Swaps(swaps))
+    }
+
+    async fn balances(&self) -> Result<rpc::Response> {
+        Ok(rpc::Response::Balances {
+            bitcoin: self.bitcoin_wallet.balance().await?.to_string(),
+            monero: self.monero_wallet.try_get_balance().await?.to_string(),
+        })
+    }
+
+    /// The rate of the rate service with our spread added.
+    fn latest_rate(&mut self) -> Result<Rate> {
+        self.rate_service
+            .latest_rate()
+            .context("Failed to get latest rate")?
+            .with_spread(self.spread)
+    }
+
+    /// Drops the connection state of peers we are neither connected to nor
+    /// have to deliver transfer proofs to.
+    fn forget_disconnected_peers(&mut self) {
+        let outbox = &self.transfer_proof_outbox;
+        let rendezvous_point = self.rendezvous_point.as_ref().map(|point| point.peer_id);
+
+        self.swarm.forget_disconnected_peers(|peer_id| {
+            outbox.has_pending(*peer_id) || Some(*peer_id) == rendezvous_point
+        });
+    }
+
+    fn send_transfer_proofs(&mut self, messages: Vec<(Uuid, PendingMessage<TransferProof>)>) {
+        for (swap_id, pending) in messages {
+            let request_id = self
+                .swarm
+                .send_transfer_proof(pending.peer_id, pending.message);
+            self.transfer_proof_outbox.sent(swap_id, request_id);
         }
     }
 
+    async fn handle_bid_quote_request(
+        &mut self,
+        channel: ResponseChannel<BidQuote>,
+        bob_peer_id: PeerId,
+    ) -> Result<()> {
+        self.access_control.check_request(bob_peer_id)?;
+
+        if self.quoting_paused {
+            bail!(QuotingPaused)
+        }
+
+        let rate = self.latest_rate()?;
+
+        let xmr_balance = self.monero_wallet.try_get_balance().await?;
+        let xmr_lock_fees = self.monero_wallet.static_tx_fee_estimate();
+        let max_quantity = if xmr_balance <= xmr_lock_fees {
+            Amount::ZERO
+        } else if xmr_balance - xmr_lock_fees < self.max_sell {
+            xmr_balance - xmr_lock_fees
+        } else {
+            self.max_sell
+        };
+
+        let min_quantity = rate.sell_quote(bitcoin::Amount::from_sat(bitcoin::MIN_LOCK_AMOUNT))?;
+
+        self.swarm.send_bid_quote(channel, BidQuote {
+            price: rate.ask,
+            min_quantity,
+            max_quantity,
+        })
+    }
+
     async fn handle_quote_request(
         &mut self,
         quote_request: QuoteRequest,
@@ -209,12 +680,45 @@ where
         // 1. Check if acceptable request
         // 2. Send response
 
-        let rate = self
-            .rate_service
-            .latest_rate()
-            .context("Failed to get latest rate")?;
+        self.access_control.check_request(bob_peer_id)?;
+
+        if self.quoting_paused {
+            bail!(QuotingPaused)
+        }
+
+        if !self.compatible_peers.contains(&bob_peer_id) {
+            bail!(
+                "{} did not complete a handshake with compatible capabilities",
+                bob_peer_id
+            )
+        }
+
+        // Bob may never open the substream for the execution setup, in which
+        // case we do not hear about it again.
+        self.execution_setups
+            .retain(|_, started| started.elapsed() < EXECUTION_SETUP_TIMEOUT);
+
+        if self.execution_setups.contains_key(&bob_peer_id) {
+            bail!("{} already has an execution setup in progress", bob_peer_id)
+        }
+
+        if self.execution_setups.len() >= self.max_concurrent_execution_setups {
+            bail!(TooManyExecutionSetups {
+                max: self.max_concurrent_execution_setups
+            })
+        }
+
+        let rate = self.latest_rate()?;
 
         let btc_amount = quote_request.btc_amount;
+        let min_btc_amount = bitcoin::Amount::from_sat(bitcoin::MIN_LOCK_AMOUNT);
+        if btc_amount < min_btc_amount {
+            bail!(AmountBelowMinimum {
+                actual: btc_amount,
+                min: min_btc_amount
+            })
+        }
+
         let xmr_amount = rate.sell_quote(btc_amount)?;
 
         if xmr_amount > self.max_sell {
@@ -224,7 +728,7 @@ where
             })
         }
 
-        let xmr_balance = monero_wallet.get_balance().await?;
+        let xmr_balance = monero_wallet.try_get_balance().await?;
         let xmr_lock_fees = monero_wallet.static_tx_fee_estimate();
 
         if xmr_balance < xmr_amount + xmr_lock_fees {
@@ -256,34 +760,134 @@ where
         );
 
         self.swarm.start_execution_setup(bob_peer_id, state0);
+        self.execution_setups.insert(bob_peer_id, Instant::now());
         // Continues once the execution setup protocol is done
         Ok(())
     }
 
-    async fn handle_execution_setup_done(
+    /// Hands out our key share of a punished swap if the encrypted signature
+    /// proves that Bob is the one we swapped with.
+    fn handle_cooperative_redeem_request(
         &mut self,
+        msg: CooperativeRedeemRequest,
         bob_peer_id: PeerId,
-        state3: State3,
-    ) -> Result<()> {
+    ) -> Result<CooperativeRedeemResponse> {
+        // Every request scans the database, so the same limits as for quotes
+        // apply.
+        self.access_control.check_request(bob_peer_id)?;
+
+        if !self.compatible_peers.contains(&bob_peer_id) {
+            bail!(
+                "{} did not complete a handshake with compatible capabilities",
+                bob_peer_id
+            )
+        }
+
+        if !self.cooperative_redeem {
+            return Ok(CooperativeRedeemResponse::Rejected(
+                CooperativeRedeemRejected::Disabled,
+            ));
+        }
+
+        let state3 = self
+            .db
+            .all()?
+            .into_iter()
+            .find_map(|(_, state)| match state {
+                database::Swap::Alice(state) => match AliceState::from(state) {
+                    AliceState::BtcPunished {
+                        state3: Some(state3),
+                    } if state3.tx_lock.txid() == msg.tx_lock_id => Some(state3),
+                    _ => None,
+                },
+                database::Swap::Bob(_) => None,
+            });
+        let state3 = match state3 {
+            Some(state3) => state3,
+            None => {
+                return Ok(CooperativeRedeemResponse::Rejected(
+                    CooperativeRedeemRejected::NotPunished,
+                ))
+            }
+        };
+
+        let tx_redeem = bitcoin::TxRedeem::new(&state3.tx_lock, &state3.redeem_address);
+        if bitcoin::verify_encsig(
+            state3.B,
+            bitcoin::PublicKey::from(state3.s_a.to_secpfun_scalar()),
+            &tx_redeem.digest(),
+            &msg.tx_redeem_encsig,
+        )
+        .is_err()
+        {
+            return Ok(CooperativeRedeemResponse::Rejected(
+                CooperativeRedeemRejected::InvalidEncryptedSignature,
+            ));
+        }
+
+        Ok(CooperativeRedeemResponse::Fulfilled { s_a: state3.s_a })
+    }
+
+    async fn handle_execution_setup_done(&mut self, bob_peer_id: PeerId, state3: State3) {
         let swap_id = Uuid::new_v4();
-        let handle = self.new_handle();
 
         let initial_state = AliceState::Started {
             state3: Box::new(state3),
             bob_peer_id,
         };
 
-        let swap = Swap {
-            event_loop_handle: handle,
+        let swap = self.new_swap(swap_id, initial_state);
+        self.spawn_swap(swap_id, alice::run(swap)).await;
+    }
+
+    /// Continues the swaps that were interrupted by a restart from the state
+    /// they were persisted in.
+    ///
+    /// Only the database is read here so that the swarm is polled right away,
+    /// everything that waits for the wallets happens in the spawned swaps.
+    async fn resume_unfinished_swaps(&mut self) -> Result<()> {
+        for (swap_id, state) in self.db.all()? {
+            let state = match state {
+                database::Swap::Alice(state) => AliceState::from(state),
+                database::Swap::Bob(_) => continue,
+            };
+
+            if alice::swap::is_complete(&state) {
+                continue;
+            }
+
+            info!("Resuming swap {} in state {}", swap_id, state);
+            let swap = self.new_swap(swap_id, state);
+            self.spawn_swap(swap_id, resume(swap)).await;
+        }
+
+        Ok(())
+    }
+
+    fn new_swap(&self, swap_id: Uuid, state: AliceState) -> Swap {
+        Swap {
+            event_loop_handle: self.new_handle(),
             bitcoin_wallet: self.bitcoin_wallet.clone(),
             monero_wallet: self.monero_wallet.clone(),
             execution_params: self.execution_params,
             db: self.db.clone(),
-            state: initial_state,
+            state,
             swap_id,
-        };
+            progress: self.progress.clone(),
+        }
+    }
 
-        let (swap, swap_handle) = alice::run(swap).remote_handle();
+    async fn spawn_swap(
+        &mut self,
+        swap_id: Uuid,
+        swap: impl Future<Output = Result<AliceState>> + Send + 'static,
+    ) {
+        let running = RunningSwap::new(swap_id, self.running_swaps.clone());
+        let (swap, swap_handle) = async move {
+            let _running = running;
+            swap.await
+        }
+        .remote_handle();
         tokio::spawn(swap);
 
         // For testing purposes the handle is currently sent via a channel so we can
@@ -293,9 +897,179 @@ where
         if let Err(SendError(handle)) = self.swap_handle_sender.send(swap_handle).await {
             handle.forget();
         }
+    }
+}
 
-        Ok(())
+/// Marks a swap as running until it is dropped, also if the future holding it
+/// is dropped before it completes.
+struct RunningSwap {
+    swap_id: Uuid,
+    running_swaps: Arc<Mutex<HashSet<Uuid>>>,
+}
+
+impl RunningSwap {
+    fn new(swap_id: Uuid, running_swaps: Arc<Mutex<HashSet<Uuid>>>) -> Self {
+        running_swaps
+            .lock()
+            .expect("no panic while holding the lock")
+            .insert(swap_id);
+
+        Self {
+            swap_id,
+            running_swaps,
+        }
+    }
+}
+
+impl Drop for RunningSwap {
+    fn drop(&mut self) {
+        self.running_swaps
+            .lock()
+            .expect("no panic while holding the lock")
+            .remove(&self.swap_id);
+    }
+}
+
+/// Runs a swap that was interrupted by a restart.
+async fn resume(mut swap: Swap) -> Result<AliceState> {
+    // Otherwise a swap Bob refunded while we were offline tries to punish him
+    // once it resumes instead of taking back the Monero.
+    match alice::refund::detect_refund(swap.swap_id, &swap.state, &swap.bitcoin_wallet, &swap.db)
+        .await
+    {
+        Ok(Some(state)) => swap.state = state,
+        Ok(None) => {}
+        Err(error) => warn!(
+            "Failed to check whether Bob refunded swap {}: {:#}",
+            swap.swap_id, error
+        ),
     }
+
+    alice::run(swap).await
+}
+
+/// Updates the wallet balances of the metrics every [`METRICS_INTERVAL`].
+async fn sample_balances(bitcoin_wallet: Arc<bitcoin::Wallet>, monero_wallet: Arc<monero::Wallet>) {
+    let mut interval = tokio::time::interval(METRICS_INTERVAL);
+
+    loop {
+        interval.tick().await;
+
+        match (
+            bitcoin_wallet.balance().await,
+            monero_wallet.get_balance().await,
+        ) {
+            (Ok(bitcoin), Ok(monero)) => metrics::balances(bitcoin, monero),
+            (Err(error), _) | (_, Err(error)) => {
+                debug!("Failed to get wallet balances for metrics: {:#}", error)
+            }
+        }
+    }
+}
+
+/// Carries out a manual cancel, refund or punish requested by the operator.
+async fn intervene(
+    request: rpc::Request,
+    bitcoin_wallet: Arc<bitcoin::Wallet>,
+    monero_wallet: Arc<monero::Wallet>,
+    db: Arc<Database>,
+) -> Result<rpc::Response> {
+    match request {
+        rpc::Request::Cancel { swap_id } => {
+            let state = load_state(&db, swap_id)?;
+            let (txid, state) = alice::cancel(swap_id, state, bitcoin_wallet, db).await?;
+            info!(
+                "Manually cancelled swap {} with transaction {}",
+                swap_id, txid
+            );
+
+            Ok(rpc::Response::Transaction {
+                txid,
+                state: state.to_string(),
+            })
+        }
+        rpc::Request::Refund { swap_id } => {
+            let state = load_state(&db, swap_id)?;
+            let state = alice::refund(swap_id, state, bitcoin_wallet, monero_wallet, db).await?;
+            info!("Manually refunded swap {}", swap_id);
+
+            Ok(rpc::Response::State {
+                state: state.to_string(),
+            })
+        }
+        rpc::Request::Punish { swap_id } => {
+            let state = load_state(&db, swap_id)?;
+            let (txid, state) = alice::punish(swap_id, state, bitcoin_wallet, db).await?;
+            info!(
+                "Manually punished swap {} with transaction {}",
+                swap_id, txid
+            );
+
+            Ok(rpc::Response::Transaction {
+                txid,
+                state: state.to_string(),
+            })
+        }
+        request => bail!("{:?} is not a manual intervention", request),
+    }
+}
+
+/// Whether `address` is a wildcard address like `/ip4/0.0.0.0` that only
+/// makes sense to listen on.
+fn is_unspecified(address: &Multiaddr) -> bool {
+    match address.iter().next() {
+        Some(Protocol::Ip4(ip)) => ip.is_unspecified(),
+        Some(Protocol::Ip6(ip)) => ip.is_unspecified(),
+        _ => false,
+    }
+}
+
+fn load_state(db: &Database, swap_id: Uuid) -> Result<AliceState> {
+    Ok(db.get_state(swap_id)?.try_into_alice()?.into())
+}
+
+/// Tells the metrics why a quote request was rejected.
+fn rejection_reason(error: &anyhow::Error) -> &'static str {
+    if let Some(refused) = error.downcast_ref::<Refused>() {
+        return match refused {
+            Refused::Denied(_) => "denied",
+            Refused::NotAllowed(_) => "not_allowed",
+            Refused::RateLimited { .. } => "rate_limited",
+        };
+    }
+
+    if error.is::<QuotingPaused>() {
+        "paused"
+    } else if error.is::<AmountBelowMinimum>() {
+        "below_minimum"
+    } else if error.is::<MaximumSellAmountExceeded>() {
+        "max_sell_exceeded"
+    } else if error.is::<BalanceTooLow>() {
+        "balance_too_low"
+    } else if error.is::<TooManyExecutionSetups>() {
+        "too_many_execution_setups"
+    } else if error.is::<WalletBusy>() {
+        "wallet_busy"
+    } else {
+        "other"
+    }
+}
+
+#[derive(Debug, Clone, Copy, thiserror::Error)]
+#[error("Quoting is paused by the operator")]
+pub struct QuotingPaused;
+
+#[derive(Debug, Clone, Copy, thiserror::Error)]
+#[error("Already running the maximum of {max} concurrent execution setups")]
+pub struct TooManyExecutionSetups {
+    pub max: usize,
+}
+
+#[derive(Debug, Clone, Copy, thiserror::Error)]
+#[error("The amount {actual} is below the minimum of {min}")]
+pub struct AmountBelowMinimum {
+    pub actual: bitcoin::Amount,
+    pub min: bitcoin::Amount,
 }
 
 #[derive(Debug, Clone, Copy, thiserror::Error)]
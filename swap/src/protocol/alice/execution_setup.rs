@@ -2,13 +2,13 @@ use crate::{
     bitcoin,
     bitcoin::{EncryptedSignature, Signature},
     monero,
-    network::request_response::BUF_SIZE,
+    network::request_response::{EXECUTION_SETUP_MAX_MESSAGE_SIZE, EXECUTION_SETUP_TIMEOUT},
     protocol::{
         alice::{State0, State3},
         bob::{Message0, Message2, Message4},
     },
 };
-use anyhow::{Context, Error};
+use anyhow::{anyhow, Context, Error};
 use libp2p::PeerId;
 use libp2p_async_await::BehaviourOutEvent;
 use serde::{Deserialize, Serialize};
@@ -34,7 +34,7 @@ pub struct Message3 {
 #[derive(Debug)]
 pub enum OutEvent {
     Done { bob_peer_id: PeerId, state3: State3 },
-    Failure(Error),
+    Failure { bob_peer_id: PeerId, error: Error },
 }
 
 impl From<BehaviourOutEvent<(PeerId, State3), (), Error>> for OutEvent {
@@ -44,7 +44,9 @@ impl From<BehaviourOutEvent<(PeerId, State3), (), Error>> for OutEvent {
                 bob_peer_id,
                 state3,
             },
-            BehaviourOutEvent::Inbound(_, Err(e)) => OutEvent::Failure(e),
+            BehaviourOutEvent::Inbound(bob_peer_id, Err(error)) => {
+                OutEvent::Failure { bob_peer_id, error }
+            }
             BehaviourOutEvent::Outbound(..) => unreachable!("Alice only supports inbound"),
         }
     }
@@ -68,36 +70,57 @@ impl Behaviour {
     pub fn run(&mut self, bob: PeerId, state0: State0) {
         self.inner
             .do_protocol_listener(bob, move |mut substream| async move {
-                let message0 =
-                    serde_cbor::from_slice::<Message0>(&substream.read_message(BUF_SIZE).await?)
-                        .context("failed to deserialize message0")?;
-                let state1 = state0.receive(message0)?;
+                let protocol = async move {
+                    let message0 = serde_cbor::from_slice::<Message0>(
+                        &substream
+                            .read_message(EXECUTION_SETUP_MAX_MESSAGE_SIZE)
+                            .await?,
+                    )
+                    .context("failed to deserialize message0")?;
+                    let state1 = state0.receive(message0)?;
+
+                    substream
+                        .write_message(
+                            &serde_cbor::to_vec(&state1.next_message())
+                                .context("failed to serialize message1")?,
+                        )
+                        .await?;
 
-                substream
-                    .write_message(
-                        &serde_cbor::to_vec(&state1.next_message())
-                            .context("failed to serialize message1")?,
+                    let message2 = serde_cbor::from_slice::<Message2>(
+                        &substream
+                            .read_message(EXECUTION_SETUP_MAX_MESSAGE_SIZE)
+                            .await?,
                     )
-                    .await?;
+                    .context("failed to deserialize message2")?;
+                    let state2 = state1.receive(message2);
 
-                let message2 =
-                    serde_cbor::from_slice::<Message2>(&substream.read_message(BUF_SIZE).await?)
-                        .context("failed to deserialize message2")?;
-                let state2 = state1.receive(message2);
+                    substream
+                        .write_message(
+                            &serde_cbor::to_vec(&state2.next_message())
+                                .context("failed to serialize message3")?,
+                        )
+                        .await?;
 
-                substream
-                    .write_message(
-                        &serde_cbor::to_vec(&state2.next_message())
-                            .context("failed to serialize message3")?,
+                    let message4 = serde_cbor::from_slice::<Message4>(
+                        &substream
+                            .read_message(EXECUTION_SETUP_MAX_MESSAGE_SIZE)
+                            .await?,
                     )
-                    .await?;
+                    .context("failed to deserialize message4")?;
+                    let state3 = state2.receive(message4)?;
 
-                let message4 =
-                    serde_cbor::from_slice::<Message4>(&substream.read_message(BUF_SIZE).await?)
-                        .context("failed to deserialize message4")?;
-                let state3 = state2.receive(message4)?;
+                    Ok::<_, Error>((bob, state3))
+                };
 
-                Ok((bob, state3))
+                tokio::time::timeout(EXECUTION_SETUP_TIMEOUT, protocol)
+                    .await
+                    .map_err(|_| {
+                        anyhow!(
+                            "Execution setup with {} timed out after {}s",
+                            bob,
+                            EXECUTION_SETUP_TIMEOUT.as_secs()
+                        )
+                    })?
             })
     }
 }
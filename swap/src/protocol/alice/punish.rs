@@ -0,0 +1,59 @@
+use crate::{
+    bitcoin::{ExpiredTimelocks, Txid, Wallet},
+    database::{Database, Swap},
+    protocol::alice::{steps::build_bitcoin_punish_transaction, AliceState},
+};
+use anyhow::{bail, Result};
+use std::sync::Arc;
+use uuid::Uuid;
+
+#[derive(Debug, thiserror::Error, Clone, Copy)]
+#[error("The punish timelock has not expired yet.")]
+pub struct PunishTimelockNotExpiredYet;
+
+/// Publishes the punish transaction of a cancelled swap in which Bob did not
+/// refund in time.
+pub async fn punish(
+    swap_id: Uuid,
+    state: AliceState,
+    bitcoin_wallet: Arc<Wallet>,
+    db: Arc<Database>,
+) -> Result<(Txid, AliceState)> {
+    let state3 = match state {
+        AliceState::BtcCancelled { state3, .. } | AliceState::BtcPunishable { state3, .. } => {
+            state3
+        }
+        _ => bail!(
+            "Cannot punish swap {} because it is in state {} which is not punishable.",
+            swap_id,
+            state
+        ),
+    };
+
+    if !matches!(
+        state3.expired_timelocks(bitcoin_wallet.as_ref()).await?,
+        ExpiredTimelocks::Punish
+    ) {
+        bail!(PunishTimelockNotExpiredYet)
+    }
+
+    let tx_punish = build_bitcoin_punish_transaction(
+        &state3.tx_lock,
+        state3.cancel_timelock,
+        &state3.punish_address,
+        state3.punish_timelock,
+        state3.tx_punish_sig_bob.clone(),
+        state3.a.clone(),
+        state3.B,
+    )?;
+    let txid = bitcoin_wallet.broadcast(tx_punish, "punish").await?;
+
+    let state = AliceState::BtcPunished {
+        state3: Some(state3),
+    };
+    let db_state = (&state).into();
+    db.insert_latest_state(swap_id, Swap::Alice(db_state))
+        .await?;
+
+    Ok((txid, state))
+}
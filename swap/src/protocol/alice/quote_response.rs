@@ -1,6 +1,6 @@
 use crate::{
     monero,
-    network::request_response::{CborCodec, Swap, TIMEOUT},
+    network::request_response::{CborCodec, Swap, QUOTE_TIMEOUT},
     protocol::bob::QuoteRequest,
 };
 use anyhow::{anyhow, Error, Result};
@@ -12,7 +12,6 @@ use libp2p::{
     NetworkBehaviour, PeerId,
 };
 use serde::{Deserialize, Serialize};
-use std::time::Duration;
 use tracing::debug;
 
 #[derive(Debug)]
@@ -93,10 +92,8 @@ impl Behaviour {
 
 impl Default for Behaviour {
     fn default() -> Self {
-        let timeout = Duration::from_secs(TIMEOUT);
-
         let mut config = RequestResponseConfig::default();
-        config.set_request_timeout(timeout);
+        config.set_request_timeout(QUOTE_TIMEOUT);
 
         Self {
             rr: RequestResponse::new(
@@ -0,0 +1,181 @@
+use crate::{
+    bitcoin,
+    bitcoin::TxRefund,
+    database::{Database, Swap},
+    monero,
+    protocol::{
+        alice,
+        alice::{steps::extract_monero_private_key, AliceState},
+    },
+};
+use anyhow::{bail, Context, Result};
+use monero_rpc::wallet::BlockHeight;
+use std::sync::Arc;
+use tracing::info;
+use uuid::Uuid;
+
+/// Takes back the Monero of a swap that Bob refunded.
+///
+/// The Monero spend key is recovered from Bob's refund transaction and the
+/// locked Monero is swept into our wallet.
+pub async fn refund(
+    swap_id: Uuid,
+    state: AliceState,
+    bitcoin_wallet: Arc<bitcoin::Wallet>,
+    monero_wallet: Arc<monero::Wallet>,
+    db: Arc<Database>,
+) -> Result<AliceState> {
+    let (spend_key, state3, monero_wallet_restore_blockheight) = match state {
+        AliceState::BtcRefunded {
+            spend_key,
+            state3,
+            monero_wallet_restore_blockheight,
+        } => (spend_key, state3, monero_wallet_restore_blockheight),
+        AliceState::BtcCancelled {
+            state3,
+            tx_cancel,
+            monero_wallet_restore_blockheight,
+        } => {
+            let tx_refund = TxRefund::new(&tx_cancel, &state3.refund_address);
+            let spend_key = recover_spend_key(&tx_refund, &state3, &bitcoin_wallet).await?;
+            (spend_key, state3, monero_wallet_restore_blockheight)
+        }
+        AliceState::BtcPunishable {
+            state3,
+            tx_refund,
+            monero_wallet_restore_blockheight,
+        } => {
+            let spend_key = recover_spend_key(&tx_refund, &state3, &bitcoin_wallet).await?;
+            (spend_key, state3, monero_wallet_restore_blockheight)
+        }
+        _ => bail!(
+            "Cannot refund swap {} because it is in state {} which is not refundable.",
+            swap_id,
+            state
+        ),
+    };
+
+    sweep_xmr(
+        swap_id,
+        spend_key,
+        &state3,
+        monero_wallet_restore_blockheight,
+        monero_wallet.as_ref(),
+    )
+    .await?;
+
+    let state = AliceState::XmrRefunded;
+    let db_state = (&state).into();
+    db.insert_latest_state(swap_id, Swap::Alice(db_state))
+        .await?;
+
+    Ok(state)
+}
+
+/// Sweeps the Monero we locked for a swap that Bob refunded into our wallet.
+/// It is safe to call again if it failed or was interrupted.
+pub async fn sweep_xmr(
+    swap_id: Uuid,
+    spend_key: monero::PrivateKey,
+    state3: &alice::State3,
+    monero_wallet_restore_blockheight: BlockHeight,
+    monero_wallet: &monero::Wallet,
+) -> Result<()> {
+    let tx_hashes = monero_wallet
+        .sweep_output_into_default_wallet(spend_key, state3.v, monero_wallet_restore_blockheight)
+        .await?;
+
+    for tx_hash in tx_hashes {
+        info!(
+            "Swept the Monero of swap {} into our wallet with transaction {}",
+            swap_id, tx_hash.0
+        );
+    }
+
+    Ok(())
+}
+
+/// Moves a swap to [`AliceState::BtcRefunded`] if Bob published the refund
+/// transaction, e.g. while we were offline. `None` if he did not or if no
+/// Monero was locked yet.
+pub async fn detect_refund(
+    swap_id: Uuid,
+    state: &AliceState,
+    bitcoin_wallet: &bitcoin::Wallet,
+    db: &Database,
+) -> Result<Option<AliceState>> {
+    let (state3, monero_wallet_restore_blockheight) = match state {
+        AliceState::XmrLockTransactionSent {
+            state3,
+            monero_wallet_restore_blockheight,
+            ..
+        }
+        | AliceState::XmrLockTransferProofSent {
+            state3,
+            monero_wallet_restore_blockheight,
+            ..
+        }
+        | AliceState::XmrLocked {
+            state3,
+            monero_wallet_restore_blockheight,
+        }
+        | AliceState::EncSigLearned {
+            state3,
+            monero_wallet_restore_blockheight,
+            ..
+        }
+        | AliceState::CancelTimelockExpired {
+            state3,
+            monero_wallet_restore_blockheight,
+        }
+        | AliceState::BtcCancelled {
+            state3,
+            monero_wallet_restore_blockheight,
+            ..
+        }
+        | AliceState::BtcPunishable {
+            state3,
+            monero_wallet_restore_blockheight,
+            ..
+        } => (state3, *monero_wallet_restore_blockheight),
+        _ => return Ok(None),
+    };
+
+    let tx_refund = state3.tx_refund();
+    if !bitcoin_wallet.is_published(tx_refund.txid()).await? {
+        return Ok(None);
+    }
+
+    info!("Bob refunded swap {}, taking back the Monero", swap_id);
+    let spend_key = recover_spend_key(&tx_refund, state3, bitcoin_wallet).await?;
+
+    let state = AliceState::BtcRefunded {
+        spend_key,
+        state3: state3.clone(),
+        monero_wallet_restore_blockheight,
+    };
+    let db_state = (&state).into();
+    db.insert_latest_state(swap_id, Swap::Alice(db_state))
+        .await?;
+
+    Ok(Some(state))
+}
+
+async fn recover_spend_key(
+    tx_refund: &TxRefund,
+    state3: &alice::State3,
+    bitcoin_wallet: &bitcoin::Wallet,
+) -> Result<monero::PrivateKey> {
+    let published_refund_tx = bitcoin_wallet
+        .get_raw_transaction(tx_refund.txid())
+        .await
+        .context("Bob has not published the refund transaction yet")?;
+
+    extract_monero_private_key(
+        published_refund_tx,
+        tx_refund,
+        state3.s_a,
+        state3.a.clone(),
+        state3.S_b_bitcoin,
+    )
+}
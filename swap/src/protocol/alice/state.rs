@@ -30,6 +30,17 @@ pub enum AliceState {
         bob_peer_id: PeerId,
         state3: Box<State3>,
     },
+    XmrLockTransactionSent {
+        bob_peer_id: PeerId,
+        monero_wallet_restore_blockheight: BlockHeight,
+        transfer_proof: monero::TransferProof,
+        state3: Box<State3>,
+    },
+    XmrLockTransferProofSent {
+        monero_wallet_restore_blockheight: BlockHeight,
+        transfer_proof: monero::TransferProof,
+        state3: Box<State3>,
+    },
     XmrLocked {
         monero_wallet_restore_blockheight: BlockHeight,
         state3: Box<State3>,
@@ -60,7 +71,11 @@ pub enum AliceState {
         monero_wallet_restore_blockheight: BlockHeight,
         state3: Box<State3>,
     },
-    BtcPunished,
+    /// Keeps the state to hand out our key share if Bob asks for it, `None`
+    /// for swaps punished before it was kept.
+    BtcPunished {
+        state3: Option<Box<State3>>,
+    },
     SafelyAborted,
 }
 
@@ -69,12 +84,16 @@ impl fmt::Display for AliceState {
         match self {
             AliceState::Started { .. } => write!(f, "started"),
             AliceState::BtcLocked { .. } => write!(f, "btc is locked"),
+            AliceState::XmrLockTransactionSent { .. } => write!(f, "xmr lock transaction sent"),
+            AliceState::XmrLockTransferProofSent { .. } => {
+                write!(f, "xmr lock transfer proof sent")
+            }
             AliceState::XmrLocked { .. } => write!(f, "xmr is locked"),
             AliceState::EncSigLearned { .. } => write!(f, "encrypted signature is learned"),
             AliceState::BtcRedeemed => write!(f, "btc is redeemed"),
             AliceState::BtcCancelled { .. } => write!(f, "btc is cancelled"),
             AliceState::BtcRefunded { .. } => write!(f, "btc is refunded"),
-            AliceState::BtcPunished => write!(f, "btc is punished"),
+            AliceState::BtcPunished { .. } => write!(f, "btc is punished"),
             AliceState::SafelyAborted => write!(f, "safely aborted"),
             AliceState::BtcPunishable { .. } => write!(f, "btc is punishable"),
             AliceState::XmrRefunded => write!(f, "xmr is refunded"),
@@ -324,6 +343,13 @@ pub struct State3 {
 }
 
 impl State3 {
+    /// The refund transaction Bob publishes to get his Bitcoin back, it reveals
+    /// his Monero key share to us.
+    pub fn tx_refund(&self) -> TxRefund {
+        let tx_cancel = TxCancel::new(&self.tx_lock, self.cancel_timelock, self.a.public(), self.B);
+        TxRefund::new(&tx_cancel, &self.refund_address)
+    }
+
     pub async fn wait_for_cancel_timelock_to_expire(
         &self,
         bitcoin_wallet: &bitcoin::Wallet,
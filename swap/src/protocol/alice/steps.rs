@@ -7,10 +7,7 @@ use crate::{
     execution_params::ExecutionParams,
     monero,
     monero::Transfer,
-    protocol::{
-        alice,
-        alice::{event_loop::EventLoopHandle, TransferProof},
-    },
+    protocol::{alice, alice::event_loop::EventLoopHandle},
 };
 use anyhow::{Context, Result};
 use ecdsa_fun::{
@@ -21,7 +18,6 @@ use futures::{
     future::{select, Either},
     pin_mut,
 };
-use libp2p::PeerId;
 use sha2::Sha256;
 use std::sync::Arc;
 use tokio::time::timeout;
@@ -32,6 +28,7 @@ pub async fn wait_for_locked_bitcoin(
     lock_bitcoin_txid: bitcoin::Txid,
     bitcoin_wallet: &bitcoin::Wallet,
     execution_params: ExecutionParams,
+    progress: impl FnMut(u32) + Send,
 ) -> Result<()> {
     // We assume we will see Bob's transaction in the mempool first.
     timeout(
@@ -43,18 +40,13 @@ pub async fn wait_for_locked_bitcoin(
 
     // // We saw the transaction in the mempool, waiting for it to be confirmed.
     bitcoin_wallet
-        .wait_for_transaction_finality(lock_bitcoin_txid, execution_params)
+        .wait_for_transaction_finality_with_progress(lock_bitcoin_txid, execution_params, progress)
         .await?;
 
     Ok(())
 }
 
-pub async fn lock_xmr<W>(
-    bob_peer_id: PeerId,
-    state3: alice::State3,
-    event_loop_handle: &mut EventLoopHandle,
-    monero_wallet: Arc<W>,
-) -> Result<()>
+pub async fn lock_xmr<W>(state3: &alice::State3, monero_wallet: &W) -> Result<monero::TransferProof>
 where
     W: Transfer,
 {
@@ -67,18 +59,24 @@ where
         .transfer(public_spend_key, public_view_key, state3.xmr)
         .await?;
 
-    // TODO(Franck): Wait for Monero to be confirmed once
-    //  Waiting for XMR confirmations should not be done in here, but in a separate
-    //  state! We have to record that Alice has already sent the transaction.
-    //  Otherwise Alice might publish the lock tx twice!
+    Ok(transfer_proof)
+}
 
-    event_loop_handle
-        .send_transfer_proof(bob_peer_id, TransferProof {
-            tx_lock_proof: transfer_proof,
-        })
-        .await?;
+pub async fn wait_for_locked_monero(
+    state3: &alice::State3,
+    transfer_proof: &monero::TransferProof,
+    monero_wallet: &monero::Wallet,
+) -> Result<()> {
+    let S_a = monero::PublicKey::from_private_key(&monero::PrivateKey { scalar: state3.s_a });
 
-    Ok(())
+    let public_spend_key = S_a + state3.S_b_monero;
+    let public_view_key = state3.v.public();
+
+    // Bob waits for finality himself before he sends the encrypted signature, we
+    // only make sure that our own transaction made it into a block.
+    monero_wallet
+        .wait_for_confirmations(public_spend_key, public_view_key, transfer_proof, 1)
+        .await
 }
 
 pub async fn wait_for_bitcoin_encrypted_signature(
@@ -1,14 +1,15 @@
 //! Run an XMR/BTC swap in the role of Alice.
 //! Alice holds XMR and wishes receive BTC.
 use crate::{
+    asb::metrics,
     bitcoin,
     bitcoin::ExpiredTimelocks,
     database,
     database::Database,
     execution_params::ExecutionParams,
     monero,
-    monero::CreateWalletForOutputThenLoadDefaultWallet,
     monero_ext::ScalarExt,
+    network::outbox::PendingMessage,
     protocol::{
         alice,
         alice::{
@@ -17,10 +18,11 @@ use crate::{
                 build_bitcoin_punish_transaction, build_bitcoin_redeem_transaction,
                 extract_monero_private_key, lock_xmr, publish_cancel_transaction,
                 wait_for_bitcoin_encrypted_signature, wait_for_bitcoin_refund,
-                wait_for_locked_bitcoin,
+                wait_for_locked_bitcoin, wait_for_locked_monero,
             },
-            AliceState,
+            AliceState, TransferProof,
         },
+        progress::{Reporter, Timelock},
     },
 };
 use anyhow::{bail, Result};
@@ -30,7 +32,7 @@ use futures::{
     pin_mut,
 };
 use rand::{CryptoRng, RngCore};
-use std::sync::Arc;
+use std::{sync::Arc, time::Instant};
 use tracing::{error, info};
 use uuid::Uuid;
 
@@ -43,7 +45,7 @@ pub fn is_complete(state: &AliceState) -> bool {
         state,
         AliceState::XmrRefunded
             | AliceState::BtcRedeemed
-            | AliceState::BtcPunished
+            | AliceState::BtcPunished { .. }
             | AliceState::SafelyAborted
     )
 }
@@ -57,7 +59,11 @@ pub async fn run_until(
     swap: alice::Swap,
     is_target_state: fn(&AliceState) -> bool,
 ) -> Result<AliceState> {
-    run_until_internal(
+    // The duration of resumed swaps is unknown as they started before a restart.
+    let started = Instant::now();
+    let is_new_swap = matches!(swap.state, AliceState::Started { .. });
+
+    let state = run_until_internal(
         swap.state,
         is_target_state,
         swap.event_loop_handle,
@@ -66,8 +72,15 @@ pub async fn run_until(
         swap.execution_params,
         swap.swap_id,
         swap.db,
+        Reporter::new(swap.swap_id, swap.progress),
     )
-    .await
+    .await?;
+
+    if is_new_swap && is_complete(&state) {
+        metrics::swap_finished(&state, started.elapsed());
+    }
+
+    Ok(state)
 }
 
 // State machine driver for swap execution
@@ -82,8 +95,11 @@ async fn run_until_internal(
     execution_params: ExecutionParams,
     swap_id: Uuid,
     db: Arc<Database>,
+    progress: Reporter,
 ) -> Result<AliceState> {
     info!("Current state: {}", state);
+    metrics::swap_state(swap_id, &state);
+    progress.state_entered(&state);
     if is_target_state(&state) {
         Ok(state)
     } else {
@@ -92,16 +108,34 @@ async fn run_until_internal(
                 state3,
                 bob_peer_id,
             } => {
-                let _ = wait_for_locked_bitcoin(
-                    state3.tx_lock.txid(),
-                    &bitcoin_wallet,
-                    execution_params,
-                )
-                .await?;
+                let tx_lock_id = state3.tx_lock.txid();
+                let required = execution_params.bitcoin_finality_confirmations;
+                let state = {
+                    let btc_locked = wait_for_locked_bitcoin(
+                        tx_lock_id,
+                        &bitcoin_wallet,
+                        execution_params,
+                        |confirmations| progress.confirmations(tx_lock_id, confirmations, required),
+                    );
+                    // Bob may tell us that he is not going to lock the Bitcoin at all.
+                    let aborted = event_loop_handle.recv_abort_request(bob_peer_id, tx_lock_id);
 
-                let state = AliceState::BtcLocked {
-                    bob_peer_id,
-                    state3,
+                    pin_mut!(btc_locked);
+                    pin_mut!(aborted);
+
+                    match select(btc_locked, aborted).await {
+                        Either::Left((btc_locked, _)) => {
+                            btc_locked?;
+                            AliceState::BtcLocked {
+                                bob_peer_id,
+                                state3,
+                            }
+                        }
+                        Either::Right((aborted, _)) => {
+                            aborted?;
+                            AliceState::SafelyAborted
+                        }
+                    }
                 };
 
                 let db_state = (&state).into();
@@ -116,6 +150,7 @@ async fn run_until_internal(
                     execution_params,
                     swap_id,
                     db,
+                    progress,
                 )
                 .await
             }
@@ -127,17 +162,109 @@ async fn run_until_internal(
                 // block 0 for scenarios where we create a refund wallet.
                 let monero_wallet_restore_blockheight = monero_wallet.block_height().await?;
 
-                lock_xmr(
+                let transfer_proof = lock_xmr(&state3, monero_wallet.as_ref()).await?;
+
+                // The transfer has to be recorded before anything else can fail, otherwise
+                // Alice would lock the Monero a second time when the swap is resumed.
+                let state = AliceState::XmrLockTransactionSent {
                     bob_peer_id,
-                    *state3.clone(),
-                    &mut event_loop_handle,
-                    monero_wallet.clone(),
+                    monero_wallet_restore_blockheight,
+                    transfer_proof,
+                    state3,
+                };
+
+                let db_state = (&state).into();
+                db.insert_latest_state(swap_id, database::Swap::Alice(db_state))
+                    .await?;
+                run_until_internal(
+                    state,
+                    is_target_state,
+                    event_loop_handle,
+                    bitcoin_wallet,
+                    monero_wallet,
+                    execution_params,
+                    swap_id,
+                    db,
+                    progress,
                 )
-                .await?;
+                .await
+            }
+            AliceState::XmrLockTransactionSent {
+                bob_peer_id,
+                monero_wallet_restore_blockheight,
+                transfer_proof,
+                state3,
+            } => {
+                event_loop_handle
+                    .send_transfer_proof(swap_id, PendingMessage {
+                        peer_id: bob_peer_id,
+                        message: TransferProof {
+                            tx_lock_proof: transfer_proof.clone(),
+                        },
+                        tx_lock_id: state3.tx_lock.txid(),
+                        cancel_timelock: state3.cancel_timelock,
+                    })
+                    .await?;
 
-                let state = AliceState::XmrLocked {
-                    state3,
+                let state = AliceState::XmrLockTransferProofSent {
                     monero_wallet_restore_blockheight,
+                    transfer_proof,
+                    state3,
+                };
+
+                let db_state = (&state).into();
+                db.insert_latest_state(swap_id, database::Swap::Alice(db_state))
+                    .await?;
+                run_until_internal(
+                    state,
+                    is_target_state,
+                    event_loop_handle,
+                    bitcoin_wallet,
+                    monero_wallet,
+                    execution_params,
+                    swap_id,
+                    db,
+                    progress,
+                )
+                .await
+            }
+            AliceState::XmrLockTransferProofSent {
+                monero_wallet_restore_blockheight,
+                transfer_proof,
+                state3,
+            } => {
+                let state = match state3.expired_timelocks(bitcoin_wallet.as_ref()).await? {
+                    ExpiredTimelocks::None => {
+                        let state3_clone = state3.clone();
+                        let xmr_locked =
+                            wait_for_locked_monero(&state3_clone, &transfer_proof, &monero_wallet);
+                        let cancel_timelock_expires = state3_clone
+                            .wait_for_cancel_timelock_to_expire(bitcoin_wallet.as_ref());
+
+                        pin_mut!(xmr_locked);
+                        pin_mut!(cancel_timelock_expires);
+
+                        match select(cancel_timelock_expires, xmr_locked).await {
+                            Either::Left((timelock_expired, _)) => {
+                                timelock_expired?;
+                                AliceState::CancelTimelockExpired {
+                                    state3,
+                                    monero_wallet_restore_blockheight,
+                                }
+                            }
+                            Either::Right((xmr_locked, _)) => {
+                                xmr_locked?;
+                                AliceState::XmrLocked {
+                                    state3,
+                                    monero_wallet_restore_blockheight,
+                                }
+                            }
+                        }
+                    }
+                    _ => AliceState::CancelTimelockExpired {
+                        state3,
+                        monero_wallet_restore_blockheight,
+                    },
                 };
 
                 let db_state = (&state).into();
@@ -152,6 +279,7 @@ async fn run_until_internal(
                     execution_params,
                     swap_id,
                     db,
+                    progress,
                 )
                 .await
             }
@@ -200,6 +328,7 @@ async fn run_until_internal(
                     execution_params,
                     swap_id,
                     db,
+                    progress,
                 )
                 .await
             }
@@ -220,8 +349,21 @@ async fn run_until_internal(
                         ) {
                             Ok(tx) => match bitcoin_wallet.broadcast(tx, "redeem").await {
                                 Ok(txid) => {
+                                    progress.transaction_broadcast("redeem", txid);
+
+                                    let required = execution_params.bitcoin_finality_confirmations;
                                     let publishded_redeem_tx = bitcoin_wallet
-                                        .wait_for_transaction_finality(txid, execution_params)
+                                        .wait_for_transaction_finality_with_progress(
+                                            txid,
+                                            execution_params,
+                                            |confirmations| {
+                                                progress.confirmations(
+                                                    txid,
+                                                    confirmations,
+                                                    required,
+                                                )
+                                            },
+                                        )
                                         .await;
 
                                     match publishded_redeem_tx {
@@ -233,6 +375,7 @@ async fn run_until_internal(
                                 }
                                 Err(e) => {
                                     error!("Publishing the redeem transaction failed with {}, attempting to wait for cancellation now. If you restart the application before the timelock is expired publishing the redeem transaction will be retried.", e);
+                                    progress.waiting_for_timelock(Timelock::Cancel);
                                     state3
                                         .wait_for_cancel_timelock_to_expire(bitcoin_wallet.as_ref())
                                         .await?;
@@ -245,6 +388,7 @@ async fn run_until_internal(
                             },
                             Err(e) => {
                                 error!("Constructing the redeem transaction failed with {}, attempting to wait for cancellation now.", e);
+                                progress.waiting_for_timelock(Timelock::Cancel);
                                 state3
                                     .wait_for_cancel_timelock_to_expire(bitcoin_wallet.as_ref())
                                     .await?;
@@ -274,6 +418,7 @@ async fn run_until_internal(
                     execution_params,
                     swap_id,
                     db,
+                    progress,
                 )
                 .await
             }
@@ -290,6 +435,7 @@ async fn run_until_internal(
                     bitcoin_wallet.clone(),
                 )
                 .await?;
+                progress.transaction_broadcast("cancel", tx_cancel.txid());
 
                 let state = AliceState::BtcCancelled {
                     state3,
@@ -308,6 +454,7 @@ async fn run_until_internal(
                     execution_params,
                     swap_id,
                     db,
+                    progress,
                 )
                 .await
             }
@@ -320,6 +467,9 @@ async fn run_until_internal(
                     .transaction_block_height(tx_cancel.txid())
                     .await?;
 
+                // Bob can refund until the punish timelock expires.
+                progress.waiting_for_timelock(Timelock::Punish);
+
                 let (tx_refund, published_refund_tx) = wait_for_bitcoin_refund(
                     &tx_cancel,
                     tx_cancel_height,
@@ -350,6 +500,7 @@ async fn run_until_internal(
                             execution_params,
                             swap_id,
                             db,
+                            progress,
                         )
                         .await
                     }
@@ -379,6 +530,7 @@ async fn run_until_internal(
                             execution_params,
                             swap_id,
                             db,
+                            progress,
                         )
                         .await
                     }
@@ -389,15 +541,14 @@ async fn run_until_internal(
                 state3,
                 monero_wallet_restore_blockheight,
             } => {
-                let view_key = state3.v;
-
-                monero_wallet
-                    .create_and_load_wallet_for_output_then_load_default_wallet(
-                        spend_key,
-                        view_key,
-                        monero_wallet_restore_blockheight,
-                    )
-                    .await?;
+                alice::refund::sweep_xmr(
+                    swap_id,
+                    spend_key,
+                    &state3,
+                    monero_wallet_restore_blockheight,
+                    monero_wallet.as_ref(),
+                )
+                .await?;
 
                 let state = AliceState::XmrRefunded;
                 let db_state = (&state).into();
@@ -422,9 +573,15 @@ async fn run_until_internal(
 
                 let punish_tx_finalised = async {
                     let txid = bitcoin_wallet.broadcast(signed_tx_punish, "punish").await?;
+                    progress.transaction_broadcast("punish", txid);
 
+                    let required = execution_params.bitcoin_finality_confirmations;
                     bitcoin_wallet
-                        .wait_for_transaction_finality(txid, execution_params)
+                        .wait_for_transaction_finality_with_progress(
+                            txid,
+                            execution_params,
+                            |confirmations| progress.confirmations(txid, confirmations, required),
+                        )
                         .await?;
 
                     Result::<_, anyhow::Error>::Ok(txid)
@@ -461,11 +618,14 @@ async fn run_until_internal(
                             execution_params,
                             swap_id,
                             db,
+                            progress.clone(),
                         )
                         .await
                     }
                     Either::Right(_) => {
-                        let state = AliceState::BtcPunished;
+                        let state = AliceState::BtcPunished {
+                            state3: Some(state3),
+                        };
                         let db_state = (&state).into();
                         db.insert_latest_state(swap_id, database::Swap::Alice(db_state))
                             .await?;
@@ -478,6 +638,7 @@ async fn run_until_internal(
                             execution_params,
                             swap_id,
                             db,
+                            progress.clone(),
                         )
                         .await
                     }
@@ -485,7 +646,7 @@ async fn run_until_internal(
             }
             AliceState::XmrRefunded => Ok(AliceState::XmrRefunded),
             AliceState::BtcRedeemed => Ok(AliceState::BtcRedeemed),
-            AliceState::BtcPunished => Ok(AliceState::BtcPunished),
+            AliceState::BtcPunished { state3 } => Ok(AliceState::BtcPunished { state3 }),
             AliceState::SafelyAborted => Ok(AliceState::SafelyAborted),
         }
     }
@@ -1,11 +1,11 @@
 use crate::{
     monero,
-    network::request_response::{CborCodec, TransferProofProtocol, TIMEOUT},
+    network::request_response::{CborCodec, TransferProofProtocol},
 };
 use anyhow::{anyhow, Error};
 use libp2p::{
     request_response::{
-        ProtocolSupport, RequestResponse, RequestResponseConfig, RequestResponseEvent,
+        ProtocolSupport, RequestId, RequestResponse, RequestResponseConfig, RequestResponseEvent,
         RequestResponseMessage,
     },
     NetworkBehaviour, PeerId,
@@ -20,7 +20,12 @@ pub struct TransferProof {
 
 #[derive(Debug)]
 pub enum OutEvent {
-    Acknowledged,
+    Acknowledged(RequestId),
+    /// The transfer proof did not reach Bob and has to be sent again.
+    Undelivered {
+        request_id: RequestId,
+        error: Error,
+    },
     Failure(Error),
 }
 
@@ -34,14 +39,14 @@ pub struct Behaviour {
 }
 
 impl Behaviour {
-    pub fn send(&mut self, bob: PeerId, msg: TransferProof) {
-        let _id = self.rr.send_request(&bob, msg);
+    pub fn send(&mut self, bob: PeerId, msg: TransferProof) -> RequestId {
+        self.rr.send_request(&bob, msg)
     }
 }
 
-impl Default for Behaviour {
-    fn default() -> Self {
-        let timeout = Duration::from_secs(TIMEOUT);
+impl Behaviour {
+    /// How long to wait for Bob to acknowledge a transfer proof.
+    pub fn new(timeout: Duration) -> Self {
         let mut config = RequestResponseConfig::default();
         config.set_request_timeout(timeout);
 
@@ -65,15 +70,18 @@ impl From<RequestResponseEvent<TransferProof, ()>> for OutEvent {
                 "Alice should never get a transfer proof request from Bob"
             )),
             RequestResponseEvent::Message {
-                message: RequestResponseMessage::Response { .. },
+                message: RequestResponseMessage::Response { request_id, .. },
                 ..
-            } => OutEvent::Acknowledged,
+            } => OutEvent::Acknowledged(request_id),
             RequestResponseEvent::InboundFailure { error, .. } => {
                 OutEvent::Failure(anyhow!("Inbound failure: {:?}", error))
             }
-            RequestResponseEvent::OutboundFailure { error, .. } => {
-                OutEvent::Failure(anyhow!("Outbound failure: {:?}", error))
-            }
+            RequestResponseEvent::OutboundFailure {
+                request_id, error, ..
+            } => OutEvent::Undelivered {
+                request_id,
+                error: anyhow!("Outbound failure: {:?}", error),
+            },
             RequestResponseEvent::ResponseSent { .. } => {
                 OutEvent::Failure(anyhow!("Alice should not send a response"))
             }
@@ -5,28 +5,49 @@ use crate::{
     database::Database,
     execution_params::ExecutionParams,
     monero,
-    network::peer_tracker::{self, PeerTracker},
-    protocol::{alice, alice::TransferProof, bob},
+    network::{
+        handshake,
+        handshake::Capabilities,
+        peer_tracker::{self, PeerTracker},
+        request_response::acknowledgement_timeout,
+    },
+    protocol::{
+        alice,
+        alice::TransferProof,
+        bob,
+        progress::{self, ProgressEvent},
+    },
 };
 use anyhow::{Error, Result};
 use libp2p::{core::Multiaddr, NetworkBehaviour, PeerId};
 use std::sync::Arc;
+use tokio::sync::broadcast;
 use tracing::debug;
 use uuid::Uuid;
 
 pub use self::{
+    abort::abort,
+    abort_request::AbortRequest,
     cancel::cancel,
+    cooperative_redeem::cooperative_redeem,
+    cooperative_redeem_request::{
+        CooperativeRedeemRejected, CooperativeRedeemRequest, CooperativeRedeemResponse,
+    },
     encrypted_signature::EncryptedSignature,
-    event_loop::{EventLoop, EventLoopHandle},
+    event_loop::{AbortHandle, EventLoop, EventLoopHandle},
     quote_request::*,
     refund::refund,
     state::*,
     swap::{run, run_until},
 };
 pub use execution_setup::{Message0, Message2, Message4};
-use libp2p::request_response::ResponseChannel;
+use libp2p::request_response::{RequestId, ResponseChannel};
 
+pub mod abort;
+mod abort_request;
 pub mod cancel;
+pub mod cooperative_redeem;
+mod cooperative_redeem_request;
 mod encrypted_signature;
 pub mod event_loop;
 mod execution_setup;
@@ -42,8 +63,10 @@ pub struct Swap {
     pub db: Database,
     pub bitcoin_wallet: Arc<bitcoin::Wallet>,
     pub monero_wallet: Arc<monero::Wallet>,
+    pub monero_daemon: Arc<monero::Daemon>,
     pub execution_params: ExecutionParams,
     pub swap_id: Uuid,
+    pub progress: broadcast::Sender<ProgressEvent>,
 }
 
 pub struct Builder {
@@ -52,11 +75,14 @@ pub struct Builder {
 
     bitcoin_wallet: Arc<bitcoin::Wallet>,
     monero_wallet: Arc<monero::Wallet>,
+    monero_daemon: Arc<monero::Daemon>,
 
     init_params: InitParams,
     execution_params: ExecutionParams,
 
     event_loop_handle: bob::EventLoopHandle,
+
+    progress: broadcast::Sender<ProgressEvent>,
 }
 
 enum InitParams {
@@ -71,6 +97,7 @@ impl Builder {
         swap_id: Uuid,
         bitcoin_wallet: Arc<bitcoin::Wallet>,
         monero_wallet: Arc<monero::Wallet>,
+        monero_daemon: Arc<monero::Daemon>,
         execution_params: ExecutionParams,
         event_loop_handle: bob::EventLoopHandle,
     ) -> Self {
@@ -79,12 +106,26 @@ impl Builder {
             db,
             bitcoin_wallet,
             monero_wallet,
+            monero_daemon,
             init_params: InitParams::None,
             execution_params,
             event_loop_handle,
+            progress: progress::channel(),
         }
     }
 
+    /// Subscribes to the progress of the swap, the events are published once
+    /// the swap runs.
+    pub fn subscribe(&self) -> broadcast::Receiver<ProgressEvent> {
+        self.progress.subscribe()
+    }
+
+    /// Publishes the progress on `progress` instead of a channel of its own,
+    /// e.g. to follow several swaps through one channel.
+    pub fn with_progress(self, progress: broadcast::Sender<ProgressEvent>) -> Self {
+        Self { progress, ..self }
+    }
+
     pub fn with_init_params(self, btc_amount: bitcoin::Amount) -> Self {
         Self {
             init_params: InitParams::New { btc_amount },
@@ -104,8 +145,10 @@ impl Builder {
             db: self.db,
             bitcoin_wallet: self.bitcoin_wallet.clone(),
             monero_wallet: self.monero_wallet.clone(),
+            monero_daemon: self.monero_daemon.clone(),
             swap_id: self.swap_id,
             execution_params: self.execution_params,
+            progress: self.progress,
         })
     }
 }
@@ -113,13 +156,49 @@ impl Builder {
 #[derive(Debug)]
 pub enum OutEvent {
     ConnectionEstablished(PeerId),
-    QuoteResponse(alice::QuoteResponse),
+    ConnectionClosed(PeerId),
+    HandshakeRequest(ResponseChannel<Capabilities>),
+    HandshakeResponse {
+        peer: PeerId,
+        capabilities: Capabilities,
+    },
+    HandshakeFailed {
+        peer: PeerId,
+        error: Error,
+        retryable: bool,
+    },
+    QuoteResponse {
+        request_id: RequestId,
+        msg: alice::QuoteResponse,
+    },
+    QuoteRequestFailed {
+        request_id: RequestId,
+        error: Error,
+        retryable: bool,
+    },
     ExecutionSetupDone(Result<Box<State2>>),
     TransferProof {
         msg: Box<TransferProof>,
         channel: ResponseChannel<()>,
     },
-    EncryptedSignatureAcknowledged,
+    EncryptedSignatureAcknowledged(RequestId),
+    EncryptedSignatureUndelivered {
+        request_id: RequestId,
+        error: Error,
+    },
+    AbortRequestAcknowledged(RequestId),
+    AbortRequestUndelivered {
+        request_id: RequestId,
+        error: Error,
+    },
+    CooperativeRedeemResponse {
+        request_id: RequestId,
+        response: CooperativeRedeemResponse,
+    },
+    CooperativeRedeemRequestUndelivered {
+        request_id: RequestId,
+        error: Error,
+    },
     ResponseSent, // Same variant is used for all messages as no processing is done
     CommunicationError(Error),
 }
@@ -130,6 +209,27 @@ impl From<peer_tracker::OutEvent> for OutEvent {
             peer_tracker::OutEvent::ConnectionEstablished(id) => {
                 OutEvent::ConnectionEstablished(id)
             }
+            peer_tracker::OutEvent::ConnectionClosed(id) => OutEvent::ConnectionClosed(id),
+        }
+    }
+}
+
+impl From<handshake::OutEvent> for OutEvent {
+    fn from(event: handshake::OutEvent) -> Self {
+        use handshake::OutEvent::*;
+        match event {
+            Request { channel, .. } => OutEvent::HandshakeRequest(channel),
+            Response { peer, capabilities } => OutEvent::HandshakeResponse { peer, capabilities },
+            ResponseSent => OutEvent::ResponseSent,
+            Failure {
+                peer,
+                error,
+                retryable,
+            } => OutEvent::HandshakeFailed {
+                peer,
+                error,
+                retryable,
+            },
         }
     }
 }
@@ -138,7 +238,22 @@ impl From<quote_request::OutEvent> for OutEvent {
     fn from(event: quote_request::OutEvent) -> Self {
         use quote_request::OutEvent::*;
         match event {
-            MsgReceived(quote_response) => OutEvent::QuoteResponse(quote_response),
+            MsgReceived {
+                request_id,
+                response,
+            } => OutEvent::QuoteResponse {
+                request_id,
+                msg: response,
+            },
+            RequestFailed {
+                request_id,
+                error,
+                retryable,
+            } => OutEvent::QuoteRequestFailed {
+                request_id,
+                error,
+                retryable,
+            },
             Failure(err) => OutEvent::CommunicationError(err.context("Failure with Quote Request")),
         }
     }
@@ -172,7 +287,10 @@ impl From<encrypted_signature::OutEvent> for OutEvent {
     fn from(event: encrypted_signature::OutEvent) -> Self {
         use encrypted_signature::OutEvent::*;
         match event {
-            Acknowledged => OutEvent::EncryptedSignatureAcknowledged,
+            Acknowledged(request_id) => OutEvent::EncryptedSignatureAcknowledged(request_id),
+            Undelivered { request_id, error } => {
+                OutEvent::EncryptedSignatureUndelivered { request_id, error }
+            }
             Failure(err) => {
                 OutEvent::CommunicationError(err.context("Failure with Encrypted Signature"))
             }
@@ -180,22 +298,88 @@ impl From<encrypted_signature::OutEvent> for OutEvent {
     }
 }
 
+impl From<abort_request::OutEvent> for OutEvent {
+    fn from(event: abort_request::OutEvent) -> Self {
+        use abort_request::OutEvent::*;
+        match event {
+            Acknowledged(request_id) => OutEvent::AbortRequestAcknowledged(request_id),
+            Undelivered { request_id, error } => {
+                OutEvent::AbortRequestUndelivered { request_id, error }
+            }
+            Failure(err) => OutEvent::CommunicationError(err.context("Failure with Abort Request")),
+        }
+    }
+}
+
+impl From<cooperative_redeem_request::OutEvent> for OutEvent {
+    fn from(event: cooperative_redeem_request::OutEvent) -> Self {
+        use cooperative_redeem_request::OutEvent::*;
+        match event {
+            Response {
+                request_id,
+                response,
+            } => OutEvent::CooperativeRedeemResponse {
+                request_id,
+                response,
+            },
+            Undelivered { request_id, error } => {
+                OutEvent::CooperativeRedeemRequestUndelivered { request_id, error }
+            }
+            Failure(err) => {
+                OutEvent::CommunicationError(err.context("Failure with Cooperative Redeem Request"))
+            }
+        }
+    }
+}
+
 /// A `NetworkBehaviour` that represents an XMR/BTC swap node as Bob.
-#[derive(NetworkBehaviour, Default)]
+#[derive(NetworkBehaviour)]
 #[behaviour(out_event = "OutEvent", event_process = false)]
 #[allow(missing_debug_implementations)]
 pub struct Behaviour {
     pt: PeerTracker,
+    handshake: handshake::Behaviour,
     quote_request: quote_request::Behaviour,
     execution_setup: execution_setup::Behaviour,
     transfer_proof: transfer_proof::Behaviour,
     encrypted_signature: encrypted_signature::Behaviour,
+    abort_request: abort_request::Behaviour,
+    cooperative_redeem_request: cooperative_redeem_request::Behaviour,
 }
 
 impl Behaviour {
+    pub fn new(capabilities: Capabilities, execution_params: ExecutionParams) -> Self {
+        let ack_timeout = acknowledgement_timeout(execution_params.bob_time_to_act);
+
+        Self {
+            pt: PeerTracker::default(),
+            handshake: handshake::Behaviour::new(capabilities),
+            quote_request: quote_request::Behaviour::default(),
+            execution_setup: execution_setup::Behaviour::default(),
+            transfer_proof: transfer_proof::Behaviour::new(ack_timeout),
+            encrypted_signature: encrypted_signature::Behaviour::new(ack_timeout),
+            abort_request: abort_request::Behaviour::default(),
+            cooperative_redeem_request: cooperative_redeem_request::Behaviour::default(),
+        }
+    }
+
+    pub fn capabilities(&self) -> &Capabilities {
+        self.handshake.capabilities()
+    }
+
+    /// Sends our capabilities to Alice, her answer is reported as
+    /// [`OutEvent::HandshakeResponse`].
+    pub fn handshake(&mut self, alice: PeerId) {
+        let _ = self.handshake.handshake(alice);
+    }
+
+    pub fn respond_to_handshake(&mut self, channel: ResponseChannel<Capabilities>) -> Result<()> {
+        self.handshake.respond(channel)
+    }
+
     /// Sends a quote request to Alice to retrieve the rate.
-    pub fn send_quote_request(&mut self, alice: PeerId, quote_request: QuoteRequest) {
-        let _ = self.quote_request.send(alice, quote_request);
+    pub fn send_quote_request(&mut self, alice: PeerId, quote_request: QuoteRequest) -> RequestId {
+        self.quote_request.send(alice, quote_request)
     }
 
     pub fn start_execution_setup(
@@ -211,11 +395,30 @@ impl Behaviour {
     pub fn send_encrypted_signature(
         &mut self,
         alice: PeerId,
-        tx_redeem_encsig: bitcoin::EncryptedSignature,
-    ) {
-        let msg = EncryptedSignature { tx_redeem_encsig };
-        self.encrypted_signature.send(alice, msg);
+        msg: EncryptedSignature,
+    ) -> RequestId {
+        let request_id = self.encrypted_signature.send(alice, msg);
         debug!("Encrypted signature sent");
+
+        request_id
+    }
+
+    pub fn send_abort_request(&mut self, alice: PeerId, msg: AbortRequest) -> RequestId {
+        let request_id = self.abort_request.send(alice, msg);
+        debug!("Abort request sent");
+
+        request_id
+    }
+
+    pub fn send_cooperative_redeem_request(
+        &mut self,
+        alice: PeerId,
+        msg: CooperativeRedeemRequest,
+    ) -> RequestId {
+        let request_id = self.cooperative_redeem_request.send(alice, msg);
+        debug!("Cooperative redeem request sent");
+
+        request_id
     }
 
     /// Add a known address for the given peer
@@ -0,0 +1,53 @@
+use crate::{
+    bitcoin::{Txid, Wallet},
+    database::{Database, Swap},
+    protocol::bob::{swap::is_complete, BobState},
+};
+use anyhow::{bail, Result};
+use std::sync::Arc;
+use uuid::Uuid;
+
+#[derive(Debug, thiserror::Error, Clone, Copy)]
+pub enum AbortError {
+    #[error("The Bitcoin may already be locked, the swap can only be cancelled and refunded.")]
+    BtcAlreadyLocked,
+}
+
+/// Aborts a swap before the Bitcoin is locked. If the execution setup was
+/// done, the id of the lock transaction that Alice waits for is returned so
+/// that she can be told about it.
+pub async fn abort(
+    swap_id: Uuid,
+    state: BobState,
+    bitcoin_wallet: Arc<Wallet>,
+    db: Database,
+) -> Result<Result<Option<Txid>, AbortError>> {
+    let tx_lock_id = match state {
+        BobState::Started { .. } => None,
+        BobState::ExecutionSetupDone(state2) => {
+            let tx_lock_id = state2.tx_lock_id();
+
+            // The swap may have been stopped while the lock transaction was
+            // broadcast, before the new state was saved. Electrum may not know
+            // about the transaction yet, in which case only the marker saved
+            // before the broadcast tells that the Bitcoin may be locked.
+            if db.is_btc_lock_broadcast(swap_id)? || bitcoin_wallet.is_published(tx_lock_id).await?
+            {
+                return Ok(Err(AbortError::BtcAlreadyLocked));
+            }
+
+            Some(tx_lock_id)
+        }
+        state if is_complete(&state) => bail!(
+            "Cannot abort swap {} because it is already complete in state {}.",
+            swap_id,
+            state
+        ),
+        _ => return Ok(Err(AbortError::BtcAlreadyLocked)),
+    };
+
+    let db_state = BobState::SafelyAborted.into();
+    db.insert_latest_state(swap_id, Swap::Bob(db_state)).await?;
+
+    Ok(Ok(tx_lock_id))
+}
@@ -0,0 +1,80 @@
+use crate::network::request_response::{AbortProtocol, CborCodec, ABORT_TIMEOUT};
+use anyhow::{anyhow, Error};
+use libp2p::{
+    request_response::{
+        ProtocolSupport, RequestId, RequestResponse, RequestResponseConfig, RequestResponseEvent,
+        RequestResponseMessage,
+    },
+    NetworkBehaviour, PeerId,
+};
+use serde::{Deserialize, Serialize};
+
+/// Tells Alice that Bob will not lock the Bitcoin of the swap with the given
+/// lock transaction.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct AbortRequest {
+    pub tx_lock_id: crate::bitcoin::Txid,
+}
+
+#[derive(Debug)]
+pub enum OutEvent {
+    Acknowledged(RequestId),
+    Undelivered { request_id: RequestId, error: Error },
+    Failure(Error),
+}
+
+/// A `NetworkBehaviour` that represents asking Alice to abort a swap.
+#[derive(NetworkBehaviour)]
+#[behaviour(out_event = "OutEvent", event_process = false)]
+#[allow(missing_debug_implementations)]
+pub struct Behaviour {
+    rr: RequestResponse<CborCodec<AbortProtocol, AbortRequest, ()>>,
+}
+
+impl Behaviour {
+    pub fn send(&mut self, alice: PeerId, msg: AbortRequest) -> RequestId {
+        self.rr.send_request(&alice, msg)
+    }
+}
+
+impl Default for Behaviour {
+    fn default() -> Self {
+        let mut config = RequestResponseConfig::default();
+        config.set_request_timeout(ABORT_TIMEOUT);
+
+        Self {
+            rr: RequestResponse::new(
+                CborCodec::default(),
+                vec![(AbortProtocol, ProtocolSupport::Outbound)],
+                config,
+            ),
+        }
+    }
+}
+
+impl From<RequestResponseEvent<AbortRequest, ()>> for OutEvent {
+    fn from(event: RequestResponseEvent<AbortRequest, ()>) -> Self {
+        match event {
+            RequestResponseEvent::Message {
+                message: RequestResponseMessage::Request { .. },
+                ..
+            } => OutEvent::Failure(anyhow!("Bob should never get an abort request from Alice")),
+            RequestResponseEvent::Message {
+                message: RequestResponseMessage::Response { request_id, .. },
+                ..
+            } => OutEvent::Acknowledged(request_id),
+            RequestResponseEvent::InboundFailure { error, .. } => {
+                OutEvent::Failure(anyhow!("Inbound failure: {:?}", error))
+            }
+            RequestResponseEvent::OutboundFailure {
+                request_id, error, ..
+            } => OutEvent::Undelivered {
+                request_id,
+                error: anyhow!("Outbound failure: {:?}", error),
+            },
+            RequestResponseEvent::ResponseSent { .. } => {
+                OutEvent::Failure(anyhow!("Bob does not send the abort response to Alice"))
+            }
+        }
+    }
+}
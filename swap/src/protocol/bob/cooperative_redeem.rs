@@ -0,0 +1,69 @@
+use crate::{
+    database::{Database, Swap},
+    monero,
+    protocol::bob::{
+        swap::receive_xmr, BobState, CooperativeRedeemRejected, CooperativeRedeemRequest,
+        CooperativeRedeemResponse, EventLoopHandle,
+    },
+};
+use anyhow::{bail, Result};
+use std::sync::Arc;
+use tracing::info;
+use uuid::Uuid;
+
+/// Asks Alice for her key share of a swap she punished and redeems the Monero
+/// with it. Alice only answers if she is configured to do so.
+pub async fn cooperative_redeem(
+    swap_id: Uuid,
+    state: BobState,
+    event_loop_handle: &mut EventLoopHandle,
+    monero_wallet: Arc<monero::Wallet>,
+    db: Database,
+) -> Result<Result<BobState, CooperativeRedeemRejected>> {
+    let state4 = match state {
+        BobState::BtcPunished {
+            state4: Some(state4),
+            ..
+        } => state4,
+        BobState::BtcPunished { state4: None, .. } => bail!(
+            "Swap {} was punished before the keys to redeem the Monero were kept",
+            swap_id
+        ),
+        _ => bail!(
+            "Cannot redeem swap {} cooperatively because it is in state {} and was not punished.",
+            swap_id,
+            state
+        ),
+    };
+
+    // Alice only answers peers that completed the handshake.
+    event_loop_handle.dial().await?;
+    let response = event_loop_handle
+        .request_cooperative_redeem(CooperativeRedeemRequest {
+            tx_lock_id: state4.tx_lock_id(),
+            tx_redeem_encsig: state4.tx_redeem_encsig(),
+        })
+        .await?;
+
+    let s_a = match response {
+        CooperativeRedeemResponse::Fulfilled { s_a } => s_a,
+        CooperativeRedeemResponse::Rejected(reason) => return Ok(Err(reason)),
+    };
+
+    let state5 = state4.redeem_with_key_share(s_a)?;
+    info!("Alice handed out her key share of swap {}", swap_id);
+
+    // Resuming the swap redeems the Monero if anything below fails.
+    let db_state = BobState::BtcRedeemed(state5.clone()).into();
+    db.insert_latest_state(swap_id, Swap::Bob(db_state)).await?;
+
+    receive_xmr(swap_id, &state5, monero_wallet.as_ref(), &db).await?;
+
+    let state = BobState::XmrRedeemed {
+        tx_lock_id: state5.tx_lock_id(),
+    };
+    let db_state = state.clone().into();
+    db.insert_latest_state(swap_id, Swap::Bob(db_state)).await?;
+
+    Ok(Ok(state))
+}
@@ -0,0 +1,120 @@
+use crate::{
+    monero,
+    network::request_response::{CborCodec, CooperativeRedeemProtocol, COOPERATIVE_REDEEM_TIMEOUT},
+};
+use anyhow::{anyhow, Error};
+use libp2p::{
+    request_response::{
+        ProtocolSupport, RequestId, RequestResponse, RequestResponseConfig, RequestResponseEvent,
+        RequestResponseMessage,
+    },
+    NetworkBehaviour, PeerId,
+};
+use serde::{Deserialize, Serialize};
+
+/// Asks Alice for her Monero key share of a swap she punished. The encrypted
+/// signature of the redeem transaction proves that we are the Bob of the swap.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct CooperativeRedeemRequest {
+    pub tx_lock_id: crate::bitcoin::Txid,
+    pub tx_redeem_encsig: crate::bitcoin::EncryptedSignature,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum CooperativeRedeemResponse {
+    Fulfilled { s_a: monero::Scalar },
+    Rejected(CooperativeRedeemRejected),
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, thiserror::Error)]
+pub enum CooperativeRedeemRejected {
+    #[error("Alice does not hand out her key share after punishing a swap")]
+    Disabled,
+    #[error("Alice did not punish a swap with this lock transaction")]
+    NotPunished,
+    #[error("Alice could not verify the encrypted signature")]
+    InvalidEncryptedSignature,
+}
+
+#[derive(Debug)]
+pub enum OutEvent {
+    Response {
+        request_id: RequestId,
+        response: CooperativeRedeemResponse,
+    },
+    Undelivered {
+        request_id: RequestId,
+        error: Error,
+    },
+    Failure(Error),
+}
+
+/// A `NetworkBehaviour` that represents asking Alice to let us redeem the
+/// Monero of a swap she punished.
+#[derive(NetworkBehaviour)]
+#[behaviour(out_event = "OutEvent", event_process = false)]
+#[allow(missing_debug_implementations)]
+pub struct Behaviour {
+    rr: RequestResponse<
+        CborCodec<CooperativeRedeemProtocol, CooperativeRedeemRequest, CooperativeRedeemResponse>,
+    >,
+}
+
+impl Behaviour {
+    pub fn send(&mut self, alice: PeerId, msg: CooperativeRedeemRequest) -> RequestId {
+        self.rr.send_request(&alice, msg)
+    }
+}
+
+impl Default for Behaviour {
+    fn default() -> Self {
+        let mut config = RequestResponseConfig::default();
+        config.set_request_timeout(COOPERATIVE_REDEEM_TIMEOUT);
+
+        Self {
+            rr: RequestResponse::new(
+                CborCodec::default(),
+                vec![(CooperativeRedeemProtocol, ProtocolSupport::Outbound)],
+                config,
+            ),
+        }
+    }
+}
+
+impl From<RequestResponseEvent<CooperativeRedeemRequest, CooperativeRedeemResponse>> for OutEvent {
+    fn from(
+        event: RequestResponseEvent<CooperativeRedeemRequest, CooperativeRedeemResponse>,
+    ) -> Self {
+        match event {
+            RequestResponseEvent::Message {
+                message: RequestResponseMessage::Request { .. },
+                ..
+            } => OutEvent::Failure(anyhow!(
+                "Bob should never get a cooperative redeem request from Alice"
+            )),
+            RequestResponseEvent::Message {
+                message:
+                    RequestResponseMessage::Response {
+                        request_id,
+                        response,
+                    },
+                ..
+            } => OutEvent::Response {
+                request_id,
+                response,
+            },
+            RequestResponseEvent::InboundFailure { error, .. } => {
+                OutEvent::Failure(anyhow!("Inbound failure: {:?}", error))
+            }
+            RequestResponseEvent::OutboundFailure {
+                request_id, error, ..
+            } => OutEvent::Undelivered {
+                request_id,
+                error: anyhow!("Outbound failure: {:?}", error),
+            },
+            RequestResponseEvent::ResponseSent { .. } => OutEvent::Failure(anyhow!(
+                "Bob does not send the cooperative redeem response to Alice"
+            )),
+        }
+    }
+}
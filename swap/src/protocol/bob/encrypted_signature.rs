@@ -1,8 +1,8 @@
-use crate::network::request_response::{CborCodec, EncryptedSignatureProtocol, TIMEOUT};
+use crate::network::request_response::{CborCodec, EncryptedSignatureProtocol};
 use anyhow::{anyhow, Error};
 use libp2p::{
     request_response::{
-        ProtocolSupport, RequestResponse, RequestResponseConfig, RequestResponseEvent,
+        ProtocolSupport, RequestId, RequestResponse, RequestResponseConfig, RequestResponseEvent,
         RequestResponseMessage,
     },
     NetworkBehaviour, PeerId,
@@ -17,7 +17,12 @@ pub struct EncryptedSignature {
 
 #[derive(Debug)]
 pub enum OutEvent {
-    Acknowledged,
+    Acknowledged(RequestId),
+    /// The encrypted signature did not reach Alice and has to be sent again.
+    Undelivered {
+        request_id: RequestId,
+        error: Error,
+    },
     Failure(Error),
 }
 
@@ -30,14 +35,14 @@ pub struct Behaviour {
 }
 
 impl Behaviour {
-    pub fn send(&mut self, alice: PeerId, msg: EncryptedSignature) {
-        let _id = self.rr.send_request(&alice, msg);
+    pub fn send(&mut self, alice: PeerId, msg: EncryptedSignature) -> RequestId {
+        self.rr.send_request(&alice, msg)
     }
 }
 
-impl Default for Behaviour {
-    fn default() -> Self {
-        let timeout = Duration::from_secs(TIMEOUT);
+impl Behaviour {
+    /// How long to wait for Alice to acknowledge an encrypted signature.
+    pub fn new(timeout: Duration) -> Self {
         let mut config = RequestResponseConfig::default();
         config.set_request_timeout(timeout);
 
@@ -59,15 +64,18 @@ impl From<RequestResponseEvent<EncryptedSignature, ()>> for OutEvent {
                 ..
             } => OutEvent::Failure(anyhow!("Bob should never get a request from Alice")),
             RequestResponseEvent::Message {
-                message: RequestResponseMessage::Response { .. },
+                message: RequestResponseMessage::Response { request_id, .. },
                 ..
-            } => OutEvent::Acknowledged,
+            } => OutEvent::Acknowledged(request_id),
             RequestResponseEvent::InboundFailure { error, .. } => {
                 OutEvent::Failure(anyhow!("Inbound failure: {:?}", error))
             }
-            RequestResponseEvent::OutboundFailure { error, .. } => {
-                OutEvent::Failure(anyhow!("Outbound failure: {:?}", error))
-            }
+            RequestResponseEvent::OutboundFailure {
+                request_id, error, ..
+            } => OutEvent::Undelivered {
+                request_id,
+                error: anyhow!("Outbound failure: {:?}", error),
+            },
             RequestResponseEvent::ResponseSent { .. } => OutEvent::Failure(anyhow!(
                 "Bob does not send the encrypted signature response to Alice"
             )),
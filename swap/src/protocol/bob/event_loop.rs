@@ -1,18 +1,34 @@
 use crate::{
     bitcoin,
-    bitcoin::EncryptedSignature,
-    network::{transport, TokioExecutor},
+    bitcoin::CancelTimelock,
+    database::Database,
+    execution_params::ExecutionParams,
+    network::{
+        handshake::Capabilities,
+        outbox::{Outbox, PendingMessage, RETRY_INTERVAL},
+        request_response::MAX_RETRIES,
+        transport, TokioExecutor,
+    },
     protocol::{
         alice::{QuoteResponse, TransferProof},
-        bob::{Behaviour, OutEvent, QuoteRequest, State0, State2},
+        bob::{
+            AbortRequest, Behaviour, CooperativeRedeemRequest, CooperativeRedeemResponse,
+            EncryptedSignature, OutEvent, QuoteRequest, State0, State2,
+        },
     },
 };
 use anyhow::{anyhow, bail, Context, Result};
 use futures::FutureExt;
-use libp2p::{core::Multiaddr, PeerId};
-use std::{convert::Infallible, sync::Arc};
-use tokio::sync::mpsc::{Receiver, Sender};
+use libp2p::{core::Multiaddr, request_response::RequestId, PeerId};
+use std::{collections::HashMap, convert::Infallible, net::SocketAddr, sync::Arc};
+use tokio::sync::{
+    mpsc::{Receiver, Sender},
+    oneshot,
+};
 use tracing::{debug, error, trace};
+use uuid::Uuid;
+
+const ENCRYPTED_SIGNATURE_OUTBOX: &str = "encrypted_signature_outbox";
 
 #[derive(Debug)]
 pub struct Channels<T> {
@@ -42,7 +58,10 @@ pub struct EventLoopHandle {
     conn_established: Receiver<PeerId>,
     dial_alice: Sender<()>,
     send_quote_request: Sender<QuoteRequest>,
-    send_encrypted_signature: Sender<EncryptedSignature>,
+    send_encrypted_signature: Sender<(Uuid, PendingMessage<EncryptedSignature>)>,
+    send_cooperative_redeem_request: Sender<PendingCooperativeRedeemRequest>,
+    alice_peer_id: PeerId,
+    db: Database,
 }
 
 impl EventLoopHandle {
@@ -69,8 +88,8 @@ impl EventLoopHandle {
             .ok_or_else(|| anyhow!("Failed to receive transfer proof from Alice"))
     }
 
-    /// Dials other party and wait for the connection to be established.
-    /// Do nothing if we are already connected
+    /// Dials other party and wait for the connection to be established and
+    /// the handshake to complete. Do nothing if we are already connected
     pub async fn dial(&mut self) -> Result<()> {
         let _ = self.dial_alice.send(()).await?;
 
@@ -87,14 +106,73 @@ impl EventLoopHandle {
         Ok(())
     }
 
+    /// Queues the encrypted signature for delivery to Alice. It is persisted
+    /// before this returns and sent until Alice acknowledges it.
     pub async fn send_encrypted_signature(
         &mut self,
-        tx_redeem_encsig: EncryptedSignature,
+        swap_id: Uuid,
+        tx_redeem_encsig: bitcoin::EncryptedSignature,
+        tx_lock_id: bitcoin::Txid,
+        cancel_timelock: CancelTimelock,
     ) -> Result<()> {
-        self.send_encrypted_signature.send(tx_redeem_encsig).await?;
+        let msg = PendingMessage {
+            peer_id: self.alice_peer_id,
+            message: EncryptedSignature { tx_redeem_encsig },
+            tx_lock_id,
+            cancel_timelock,
+        };
+
+        Outbox::persist(ENCRYPTED_SIGNATURE_OUTBOX, &self.db, swap_id, &msg).await?;
+        self.send_encrypted_signature.send((swap_id, msg)).await?;
 
         Ok(())
     }
+
+    /// Asks Alice for her Monero key share of a swap she punished and waits
+    /// for her answer.
+    pub async fn request_cooperative_redeem(
+        &mut self,
+        request: CooperativeRedeemRequest,
+    ) -> Result<CooperativeRedeemResponse> {
+        let (sender, receiver) = oneshot::channel();
+        self.send_cooperative_redeem_request
+            .send((request, sender))
+            .await
+            .map_err(|_| anyhow!("The event loop is not running"))?;
+
+        receiver
+            .await
+            .context("The event loop dropped the cooperative redeem request")?
+    }
+}
+
+type PendingAbortRequest = (AbortRequest, oneshot::Sender<Result<()>>);
+type PendingCooperativeRedeemRequest = (
+    CooperativeRedeemRequest,
+    oneshot::Sender<Result<CooperativeRedeemResponse>>,
+);
+
+/// Asks Alice to abort a swap. Unlike the [`EventLoopHandle`] it is not
+/// owned by the swap, so it can be used after the swap was stopped.
+#[derive(Debug, Clone)]
+pub struct AbortHandle {
+    sender: Sender<PendingAbortRequest>,
+}
+
+impl AbortHandle {
+    /// Tells Alice that we will not lock the Bitcoin of the swap with the
+    /// lock transaction `tx_lock_id` and waits for her acknowledgement.
+    pub async fn abort(&self, tx_lock_id: bitcoin::Txid) -> Result<()> {
+        let (sender, receiver) = oneshot::channel();
+        self.sender
+            .send((AbortRequest { tx_lock_id }, sender))
+            .await
+            .map_err(|_| anyhow!("The event loop is not running"))?;
+
+        receiver
+            .await
+            .context("The event loop dropped the abort request")?
+    }
 }
 
 #[allow(missing_debug_implementations)]
@@ -102,6 +180,13 @@ pub struct EventLoop {
     swarm: libp2p::Swarm<Behaviour>,
     bitcoin_wallet: Arc<bitcoin::Wallet>,
     alice_peer_id: PeerId,
+    /// Whether Alice answered our handshake on the current connection with
+    /// compatible capabilities.
+    alice_compatible: bool,
+    handshake_retries: u32,
+    /// Quote requests sent to Alice and how often they were retried, by the
+    /// id of the request.
+    pending_quote_requests: HashMap<RequestId, (QuoteRequest, u32)>,
     recv_quote_response: Sender<QuoteResponse>,
     start_execution_setup: Receiver<State0>,
     done_execution_setup: Sender<Result<State2>>,
@@ -109,18 +194,37 @@ pub struct EventLoop {
     dial_alice: Receiver<()>,
     conn_established: Sender<PeerId>,
     send_quote_request: Receiver<QuoteRequest>,
-    send_encrypted_signature: Receiver<EncryptedSignature>,
+    send_encrypted_signature: Receiver<(Uuid, PendingMessage<EncryptedSignature>)>,
+    encrypted_signature_outbox: Outbox<EncryptedSignature>,
+    send_abort_request: Receiver<PendingAbortRequest>,
+    /// Abort requests sent to Alice, by the id of the request.
+    pending_abort_requests: HashMap<RequestId, oneshot::Sender<Result<()>>>,
+    send_cooperative_redeem_request: Receiver<PendingCooperativeRedeemRequest>,
+    /// Cooperative redeem requests sent to Alice, by the id of the request.
+    pending_cooperative_redeem_requests:
+        HashMap<RequestId, oneshot::Sender<Result<CooperativeRedeemResponse>>>,
+
+    // Only used to produce new abort handles
+    send_abort_request_sender: Sender<PendingAbortRequest>,
 }
 
 impl EventLoop {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         identity: &libp2p::core::identity::Keypair,
         alice_peer_id: PeerId,
         alice_addr: Multiaddr,
         bitcoin_wallet: Arc<bitcoin::Wallet>,
+        db: Database,
+        socks5_proxy: Option<SocketAddr>,
+        capabilities: Capabilities,
+        execution_params: ExecutionParams,
     ) -> Result<(Self, EventLoopHandle)> {
-        let behaviour = Behaviour::default();
-        let transport = transport::build(identity)?;
+        let behaviour = Behaviour::new(capabilities, execution_params);
+        let transport = match socks5_proxy {
+            Some(socks5_proxy) => transport::build_with_tor(identity, socks5_proxy)?,
+            None => transport::build(identity)?,
+        };
 
         let mut swarm = libp2p::swarm::SwarmBuilder::new(
             transport,
@@ -142,10 +246,17 @@ impl EventLoop {
         let conn_established = Channels::new();
         let send_quote_request = Channels::new();
         let send_encrypted_signature = Channels::new();
+        let send_abort_request = Channels::new();
+        let send_cooperative_redeem_request = Channels::new();
+
+        let encrypted_signature_outbox = Outbox::load(ENCRYPTED_SIGNATURE_OUTBOX, db.clone())?;
 
         let event_loop = EventLoop {
             swarm,
             alice_peer_id,
+            alice_compatible: false,
+            handshake_retries: 0,
+            pending_quote_requests: HashMap::new(),
             bitcoin_wallet,
             recv_quote_response: quote_response.sender,
             start_execution_setup: start_execution_setup.receiver,
@@ -155,6 +266,12 @@ impl EventLoop {
             dial_alice: dial_alice.receiver,
             send_quote_request: send_quote_request.receiver,
             send_encrypted_signature: send_encrypted_signature.receiver,
+            encrypted_signature_outbox,
+            send_abort_request: send_abort_request.receiver,
+            pending_abort_requests: HashMap::new(),
+            send_abort_request_sender: send_abort_request.sender,
+            send_cooperative_redeem_request: send_cooperative_redeem_request.receiver,
+            pending_cooperative_redeem_requests: HashMap::new(),
         };
 
         let handle = EventLoopHandle {
@@ -166,22 +283,89 @@ impl EventLoop {
             dial_alice: dial_alice.sender,
             send_quote_request: send_quote_request.sender,
             send_encrypted_signature: send_encrypted_signature.sender,
+            send_cooperative_redeem_request: send_cooperative_redeem_request.sender,
+            alice_peer_id,
+            db,
         };
 
         Ok((event_loop, handle))
     }
 
+    pub fn abort_handle(&self) -> AbortHandle {
+        AbortHandle {
+            sender: self.send_abort_request_sender.clone(),
+        }
+    }
+
     pub async fn run(mut self) -> Result<Infallible> {
+        let mut retry_interval = tokio::time::interval(RETRY_INTERVAL);
+
         loop {
             tokio::select! {
                 swarm_event = self.swarm.next().fuse() => {
                     match swarm_event {
                         OutEvent::ConnectionEstablished(peer_id) => {
-                            let _ = self.conn_established.send(peer_id).await;
+                            let unsent = self.encrypted_signature_outbox.unsent_to(peer_id);
+                            self.send_encrypted_signatures(unsent);
+                            if peer_id == self.alice_peer_id {
+                                self.swarm.handshake(peer_id);
+                            }
                         }
-                        OutEvent::QuoteResponse(msg) => {
+                        OutEvent::ConnectionClosed(peer_id) => {
+                            debug!("Connection closed with {}", peer_id);
+                            if peer_id == self.alice_peer_id {
+                                self.alice_compatible = false;
+                            }
+                            if self.encrypted_signature_outbox.has_pending(peer_id) {
+                                debug!("Redialing {} to deliver pending encrypted signatures", peer_id);
+                                if let Err(error) = libp2p::Swarm::dial(&mut self.swarm, &peer_id) {
+                                    debug!("Failed to redial {}: {:?}", peer_id, error);
+                                }
+                            }
+                        }
+                        OutEvent::HandshakeRequest(channel) => {
+                            if let Err(error) = self.swarm.respond_to_handshake(channel) {
+                                error!("Failed to answer handshake: {:#}", error);
+                            }
+                        }
+                        OutEvent::HandshakeResponse { peer, capabilities } => {
+                            if let Err(incompatible) = self.swarm.capabilities().check_compatible(&capabilities) {
+                                bail!("Refusing to swap with {}: {}", peer, incompatible)
+                            }
+                            debug!("Completed handshake with {} running version {}", peer, capabilities.software_version);
+                            if peer == self.alice_peer_id {
+                                self.alice_compatible = true;
+                                self.handshake_retries = 0;
+                                let _ = self.conn_established.send(peer).await;
+                            }
+                        }
+                        OutEvent::HandshakeFailed { peer, error, retryable } => {
+                            if !retryable || self.handshake_retries >= MAX_RETRIES {
+                                bail!("Handshake with {} failed: {:#}", peer, error)
+                            }
+                            self.handshake_retries += 1;
+                            debug!("Handshake with {} failed, retrying: {:#}", peer, error);
+                            if self.swarm.pt.is_connected(&peer) {
+                                self.swarm.handshake(peer);
+                            } else {
+                                // The handshake is sent again once the connection is established.
+                                libp2p::Swarm::dial(&mut self.swarm, &peer).context("failed to dial alice")?;
+                            }
+                        }
+                        OutEvent::QuoteResponse { request_id, msg } => {
+                            self.pending_quote_requests.remove(&request_id);
                             let _ = self.recv_quote_response.send(msg).await;
                         },
+                        OutEvent::QuoteRequestFailed { request_id, error, retryable } => {
+                            if let Some((quote_request, retries)) = self.pending_quote_requests.remove(&request_id) {
+                                if !retryable || retries >= MAX_RETRIES {
+                                    bail!("Quote request failed: {:#}", error)
+                                }
+                                debug!("Quote request failed, retrying: {:#}", error);
+                                let request_id = self.swarm.send_quote_request(self.alice_peer_id, quote_request);
+                                self.pending_quote_requests.insert(request_id, (quote_request, retries + 1));
+                            }
+                        }
                         OutEvent::ExecutionSetupDone(res) => {
                             let _ = self.done_execution_setup.send(res.map(|state|*state)).await;
                         }
@@ -192,8 +376,36 @@ impl EventLoop {
                                 error!("Failed to send Transfer Proof ack: {:?}", error);
                             }
                         }
-                        OutEvent::EncryptedSignatureAcknowledged => {
+                        OutEvent::EncryptedSignatureAcknowledged(request_id) => {
                             debug!("Alice acknowledged encrypted signature");
+                            if let Err(error) = self.encrypted_signature_outbox.acknowledged(request_id).await {
+                                error!("Failed to remove acknowledged encrypted signature: {:#}", error);
+                            }
+                        }
+                        OutEvent::EncryptedSignatureUndelivered { request_id, error } => {
+                            debug!("Failed to deliver encrypted signature, will retry: {:#}", error);
+                            self.encrypted_signature_outbox.undelivered(request_id);
+                        }
+                        OutEvent::AbortRequestAcknowledged(request_id) => {
+                            debug!("Alice acknowledged abort request");
+                            if let Some(responder) = self.pending_abort_requests.remove(&request_id) {
+                                let _ = responder.send(Ok(()));
+                            }
+                        }
+                        OutEvent::AbortRequestUndelivered { request_id, error } => {
+                            if let Some(responder) = self.pending_abort_requests.remove(&request_id) {
+                                let _ = responder.send(Err(error));
+                            }
+                        }
+                        OutEvent::CooperativeRedeemResponse { request_id, response } => {
+                            if let Some(responder) = self.pending_cooperative_redeem_requests.remove(&request_id) {
+                                let _ = responder.send(Ok(response));
+                            }
+                        }
+                        OutEvent::CooperativeRedeemRequestUndelivered { request_id, error } => {
+                            if let Some(responder) = self.pending_cooperative_redeem_requests.remove(&request_id) {
+                                let _ = responder.send(Err(error));
+                            }
                         }
                         OutEvent::ResponseSent => {}
                         OutEvent::CommunicationError(err) => {
@@ -203,10 +415,12 @@ impl EventLoop {
                 },
                 option = self.dial_alice.recv().fuse() => {
                     if option.is_some() {
-                           let peer_id = self.alice_peer_id;
-                        if self.swarm.pt.is_connected(&peer_id) {
+                        let peer_id = self.alice_peer_id;
+                        if self.alice_compatible {
                             trace!("Already connected to Alice at {}", peer_id);
                             let _ = self.conn_established.send(peer_id).await;
+                        } else if self.swarm.pt.is_connected(&peer_id) {
+                            trace!("Waiting for the handshake with Alice at {} to complete", peer_id);
                         } else {
                             debug!("Dialing alice at {}", peer_id);
                             libp2p::Swarm::dial(&mut self.swarm, &peer_id).context("failed to dial alice")?;
@@ -215,7 +429,8 @@ impl EventLoop {
                 },
                 quote_request = self.send_quote_request.recv().fuse() =>  {
                     if let Some(quote_request) = quote_request {
-                        self.swarm.send_quote_request(self.alice_peer_id, quote_request);
+                        let request_id = self.swarm.send_quote_request(self.alice_peer_id, quote_request);
+                        self.pending_quote_requests.insert(request_id, (quote_request, 0));
                     }
                 },
                 option = self.start_execution_setup.recv().fuse() => {
@@ -226,11 +441,46 @@ impl EventLoop {
                     }
                 },
                 encrypted_signature = self.send_encrypted_signature.recv().fuse() => {
-                    if let Some(tx_redeem_encsig) = encrypted_signature {
-                        self.swarm.send_encrypted_signature(self.alice_peer_id, tx_redeem_encsig);
+                    if let Some((swap_id, msg)) = encrypted_signature {
+                        self.encrypted_signature_outbox.push(swap_id, msg.clone());
+                        self.send_encrypted_signatures(vec![(swap_id, msg)]);
+                    }
+                }
+                abort_request = self.send_abort_request.recv().fuse() => {
+                    if let Some((msg, responder)) = abort_request {
+                        let request_id = self.swarm.send_abort_request(self.alice_peer_id, msg);
+                        self.pending_abort_requests.insert(request_id, responder);
+                    }
+                }
+                cooperative_redeem_request = self.send_cooperative_redeem_request.recv().fuse() => {
+                    if let Some((msg, responder)) = cooperative_redeem_request {
+                        let request_id = self.swarm.send_cooperative_redeem_request(self.alice_peer_id, msg);
+                        self.pending_cooperative_redeem_requests.insert(request_id, responder);
+                    }
+                }
+                expired = self.encrypted_signature_outbox.next_expired().fuse() => {
+                    if let Err(error) = self.encrypted_signature_outbox.discard_expired(expired).await {
+                        error!("Failed to discard expired encrypted signatures: {:#}", error);
                     }
                 }
+                _ = retry_interval.tick().fuse() => {
+                    self.encrypted_signature_outbox.check_expiry(self.bitcoin_wallet.clone());
+                    let unsent = self.encrypted_signature_outbox.unsent();
+                    self.send_encrypted_signatures(unsent);
+                }
             }
         }
     }
+
+    fn send_encrypted_signatures(
+        &mut self,
+        messages: Vec<(Uuid, PendingMessage<EncryptedSignature>)>,
+    ) {
+        for (swap_id, pending) in messages {
+            let request_id = self
+                .swarm
+                .send_encrypted_signature(pending.peer_id, pending.message);
+            self.encrypted_signature_outbox.sent(swap_id, request_id);
+        }
+    }
 }
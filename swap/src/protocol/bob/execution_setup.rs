@@ -1,12 +1,12 @@
 use crate::{
     bitcoin::Signature,
-    network::request_response::BUF_SIZE,
+    network::request_response::{EXECUTION_SETUP_MAX_MESSAGE_SIZE, EXECUTION_SETUP_TIMEOUT},
     protocol::{
         alice::{Message1, Message3},
         bob::{State0, State2},
     },
 };
-use anyhow::{Context, Error, Result};
+use anyhow::{anyhow, Context, Error, Result};
 use libp2p::PeerId;
 use libp2p_async_await::BehaviourOutEvent;
 use serde::{Deserialize, Serialize};
@@ -72,40 +72,58 @@ impl Behaviour {
     ) {
         self.inner
             .do_protocol_dialer(alice, move |mut substream| async move {
-                tracing::debug!("Starting execution setup with {}", alice);
+                let protocol = async move {
+                    tracing::debug!("Starting execution setup with {}", alice);
 
-                substream
-                    .write_message(
-                        &serde_cbor::to_vec(&state0.next_message())
-                            .context("failed to serialize message0")?,
+                    substream
+                        .write_message(
+                            &serde_cbor::to_vec(&state0.next_message())
+                                .context("failed to serialize message0")?,
+                        )
+                        .await?;
+
+                    let message1 = serde_cbor::from_slice::<Message1>(
+                        &substream
+                            .read_message(EXECUTION_SETUP_MAX_MESSAGE_SIZE)
+                            .await?,
                     )
-                    .await?;
+                    .context("failed to deserialize message1")?;
+                    let state1 = state0.receive(bitcoin_wallet.as_ref(), message1).await?;
 
-                let message1 =
-                    serde_cbor::from_slice::<Message1>(&substream.read_message(BUF_SIZE).await?)
-                        .context("failed to deserialize message1")?;
-                let state1 = state0.receive(bitcoin_wallet.as_ref(), message1).await?;
+                    substream
+                        .write_message(
+                            &serde_cbor::to_vec(&state1.next_message())
+                                .context("failed to serialize message2")?,
+                        )
+                        .await?;
 
-                substream
-                    .write_message(
-                        &serde_cbor::to_vec(&state1.next_message())
-                            .context("failed to serialize message2")?,
+                    let message3 = serde_cbor::from_slice::<Message3>(
+                        &substream
+                            .read_message(EXECUTION_SETUP_MAX_MESSAGE_SIZE)
+                            .await?,
                     )
-                    .await?;
+                    .context("failed to deserialize message3")?;
+                    let state2 = state1.receive(message3)?;
 
-                let message3 =
-                    serde_cbor::from_slice::<Message3>(&substream.read_message(BUF_SIZE).await?)
-                        .context("failed to deserialize message3")?;
-                let state2 = state1.receive(message3)?;
+                    substream
+                        .write_message(
+                            &serde_cbor::to_vec(&state2.next_message())
+                                .context("failed to serialize message4")?,
+                        )
+                        .await?;
 
-                substream
-                    .write_message(
-                        &serde_cbor::to_vec(&state2.next_message())
-                            .context("failed to serialize message4")?,
-                    )
-                    .await?;
+                    Ok::<_, Error>(state2)
+                };
 
-                Ok(state2)
+                tokio::time::timeout(EXECUTION_SETUP_TIMEOUT, protocol)
+                    .await
+                    .map_err(|_| {
+                        anyhow!(
+                            "Execution setup with {} timed out after {}s",
+                            alice,
+                            EXECUTION_SETUP_TIMEOUT.as_secs()
+                        )
+                    })?
             })
     }
 }
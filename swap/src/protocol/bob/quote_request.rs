@@ -1,8 +1,8 @@
 use crate::{
-    network::request_response::{CborCodec, Swap, TIMEOUT},
+    network::request_response::{is_retryable, CborCodec, Swap, QUOTE_TIMEOUT},
     protocol::alice::QuoteResponse,
 };
-use anyhow::{anyhow, Error, Result};
+use anyhow::{anyhow, Error};
 use libp2p::{
     request_response::{
         ProtocolSupport, RequestId, RequestResponse, RequestResponseConfig, RequestResponseEvent,
@@ -11,7 +11,6 @@ use libp2p::{
     NetworkBehaviour, PeerId,
 };
 use serde::{Deserialize, Serialize};
-use std::time::Duration;
 use tracing::debug;
 
 #[derive(Clone, Copy, Debug, Serialize, Deserialize)]
@@ -22,7 +21,17 @@ pub struct QuoteRequest {
 
 #[derive(Debug)]
 pub enum OutEvent {
-    MsgReceived(QuoteResponse),
+    MsgReceived {
+        request_id: RequestId,
+        response: QuoteResponse,
+    },
+    /// The quote request did not get an answer, sending it again may help if
+    /// it is `retryable`.
+    RequestFailed {
+        request_id: RequestId,
+        error: Error,
+        retryable: bool,
+    },
     Failure(Error),
 }
 
@@ -35,21 +44,17 @@ pub struct Behaviour {
 }
 
 impl Behaviour {
-    pub fn send(&mut self, alice: PeerId, quote_request: QuoteRequest) -> Result<RequestId> {
+    pub fn send(&mut self, alice: PeerId, quote_request: QuoteRequest) -> RequestId {
         debug!("Requesting quote for {}", quote_request.btc_amount);
 
-        let id = self.rr.send_request(&alice, quote_request);
-
-        Ok(id)
+        self.rr.send_request(&alice, quote_request)
     }
 }
 
 impl Default for Behaviour {
     fn default() -> Self {
-        let timeout = Duration::from_secs(TIMEOUT);
-
         let mut config = RequestResponseConfig::default();
-        config.set_request_timeout(timeout);
+        config.set_request_timeout(QUOTE_TIMEOUT);
 
         Self {
             rr: RequestResponse::new(
@@ -69,15 +74,26 @@ impl From<RequestResponseEvent<QuoteRequest, QuoteResponse>> for OutEvent {
                 ..
             } => OutEvent::Failure(anyhow!("Bob should never get a request from Alice")),
             RequestResponseEvent::Message {
-                message: RequestResponseMessage::Response { response, .. },
+                message:
+                    RequestResponseMessage::Response {
+                        request_id,
+                        response,
+                    },
                 ..
-            } => OutEvent::MsgReceived(response),
+            } => OutEvent::MsgReceived {
+                request_id,
+                response,
+            },
             RequestResponseEvent::InboundFailure { error, .. } => {
                 OutEvent::Failure(anyhow!("Inbound failure: {:?}", error))
             }
-            RequestResponseEvent::OutboundFailure { error, .. } => {
-                OutEvent::Failure(anyhow!("Outbound failure: {:?}", error))
-            }
+            RequestResponseEvent::OutboundFailure {
+                request_id, error, ..
+            } => OutEvent::RequestFailed {
+                request_id,
+                retryable: is_retryable(&error),
+                error: anyhow!("Outbound failure: {:?}", error),
+            },
             RequestResponseEvent::ResponseSent { .. } => {
                 OutEvent::Failure(anyhow!("Bob does not send a quote response to Alice"))
             }
@@ -1,11 +1,12 @@
 use crate::{
-    bitcoin::Wallet,
+    bitcoin::{Txid, Wallet},
     database::{Database, Swap},
     execution_params::ExecutionParams,
-    protocol::bob::BobState,
+    protocol::bob::{BobState, State4},
 };
 use anyhow::{bail, Result};
 use std::sync::Arc;
+use tracing::{error, warn};
 use uuid::Uuid;
 
 #[derive(thiserror::Error, Debug, Clone, Copy)]
@@ -43,14 +44,68 @@ pub async fn refund(
         }
     };
 
-    state4
-        .refund_btc(bitcoin_wallet.as_ref(), execution_params)
-        .await?;
+    let state = match state4.check_for_tx_punish(bitcoin_wallet.as_ref()).await? {
+        Some(tx_punish_id) => punished(swap_id, &state4, tx_punish_id),
+        None => {
+            warn_about_punish_timelock(swap_id, &state4, bitcoin_wallet.as_ref()).await?;
 
-    let state = BobState::BtcRefunded(state4);
-    let db_state = state.clone().into();
+            match state4
+                .refund_btc(bitcoin_wallet.as_ref(), execution_params)
+                .await
+            {
+                Ok(()) => BobState::BtcRefunded(state4),
+                // Publishing the refund transaction fails if Alice punished us first.
+                Err(refund_error) => {
+                    match state4.check_for_tx_punish(bitcoin_wallet.as_ref()).await? {
+                        Some(tx_punish_id) => punished(swap_id, &state4, tx_punish_id),
+                        None => return Err(refund_error),
+                    }
+                }
+            }
+        }
+    };
 
+    let db_state = state.clone().into();
     db.insert_latest_state(swap_id, Swap::Bob(db_state)).await?;
 
     Ok(Ok(state))
 }
+
+/// Logs how many blocks are left to refund the Bitcoin before Alice can
+/// punish us and take it. Returns that number.
+pub async fn warn_about_punish_timelock(
+    swap_id: Uuid,
+    state4: &State4,
+    bitcoin_wallet: &Wallet,
+) -> Result<u32> {
+    let blocks_left = state4.blocks_until_punish_timelock(bitcoin_wallet).await?;
+
+    if blocks_left == 0 {
+        warn!(
+            "The punish timelock of swap {} expired, Alice can take the Bitcoin at any time \
+             unless it is refunded first",
+            swap_id
+        );
+    } else {
+        warn!(
+            "The Bitcoin of swap {} must be refunded within {} blocks, after that Alice can take it",
+            swap_id, blocks_left
+        );
+    }
+
+    Ok(blocks_left)
+}
+
+/// The state after Alice published the punish transaction. The Bitcoin is
+/// lost.
+pub fn punished(swap_id: Uuid, state4: &State4, tx_punish_id: Txid) -> BobState {
+    error!(
+        "Alice punished swap {} with transaction {}, the Bitcoin cannot be refunded anymore",
+        swap_id, tx_punish_id
+    );
+
+    BobState::BtcPunished {
+        tx_lock_id: state4.tx_lock_id(),
+        state4: Some(state4.clone()),
+    }
+}
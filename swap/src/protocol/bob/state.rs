@@ -47,8 +47,11 @@ pub enum BobState {
     XmrRedeemed {
         tx_lock_id: bitcoin::Txid,
     },
+    /// Keeps the state to redeem the Monero if Alice hands out her key share,
+    /// `None` for swaps punished before it was kept.
     BtcPunished {
         tx_lock_id: bitcoin::Txid,
+        state4: Option<State4>,
     },
     SafelyAborted,
 }
@@ -255,6 +258,10 @@ pub struct State2 {
 }
 
 impl State2 {
+    pub fn tx_lock_id(&self) -> bitcoin::Txid {
+        self.tx_lock.txid()
+    }
+
     pub fn next_message(&self) -> Message4 {
         let tx_cancel = TxCancel::new(&self.tx_lock, self.cancel_timelock, self.A, self.b.public());
         let tx_cancel_sig = self.b.sign(tx_cancel.digest());
@@ -337,7 +344,7 @@ impl State3 {
                 self.xmr,
                 self.min_monero_confirmations,
             )
-            .await
+            .await?
         {
             return Ok(Err(e));
         }
@@ -439,6 +446,10 @@ impl State4 {
         self.b.encsign(self.S_a_bitcoin, tx_redeem.digest())
     }
 
+    pub fn tx_redeem_id(&self) -> bitcoin::Txid {
+        bitcoin::TxRedeem::new(&self.tx_lock, &self.redeem_address).txid()
+    }
+
     pub async fn check_for_tx_cancel(
         &self,
         bitcoin_wallet: &bitcoin::Wallet,
@@ -534,6 +545,18 @@ impl State4 {
         bitcoin_wallet: &bitcoin::Wallet,
         execution_params: ExecutionParams,
     ) -> Result<()> {
+        let txid = self.publish_refund_btc(bitcoin_wallet).await?;
+
+        bitcoin_wallet
+            .wait_for_transaction_finality(txid, execution_params)
+            .await?;
+
+        Ok(())
+    }
+
+    /// Publishes the refund transaction without waiting for it to be
+    /// confirmed.
+    pub async fn publish_refund_btc(&self, bitcoin_wallet: &bitcoin::Wallet) -> Result<Txid> {
         let tx_cancel =
             bitcoin::TxCancel::new(&self.tx_lock, self.cancel_timelock, self.A, self.b.public());
         let tx_refund = bitcoin::TxRefund::new(&tx_cancel, &self.refund_address);
@@ -547,18 +570,65 @@ impl State4 {
         let signed_tx_refund =
             tx_refund.add_signatures((self.A, sig_a), (self.b.public(), sig_b))?;
 
-        let txid = bitcoin_wallet.broadcast(signed_tx_refund, "refund").await?;
+        bitcoin_wallet.broadcast(signed_tx_refund, "refund").await
+    }
 
-        bitcoin_wallet
-            .wait_for_transaction_finality(txid, execution_params)
+    /// The state to redeem the Monero with the key share Alice gave us after
+    /// punishing the swap. Fails if it is not her key share.
+    pub fn redeem_with_key_share(&self, s_a: monero::Scalar) -> Result<State5> {
+        if bitcoin::PublicKey::from(s_a.to_secpfun_scalar()) != self.S_a_bitcoin {
+            bail!("The key share does not belong to Alice's public key of the swap")
+        }
+
+        Ok(State5 {
+            s_a: monero::PrivateKey::from_scalar(s_a),
+            s_b: self.s_b,
+            v: self.v,
+            tx_lock: self.tx_lock.clone(),
+            monero_wallet_restore_blockheight: self.monero_wallet_restore_blockheight,
+        })
+    }
+
+    /// The id of the transaction that spent the cancel output if it is not our
+    /// refund transaction. Only Alice's punish transaction can do that.
+    pub async fn check_for_tx_punish(
+        &self,
+        bitcoin_wallet: &bitcoin::Wallet,
+    ) -> Result<Option<Txid>> {
+        let tx_cancel =
+            bitcoin::TxCancel::new(&self.tx_lock, self.cancel_timelock, self.A, self.b.public());
+        let tx_refund = bitcoin::TxRefund::new(&tx_cancel, &self.refund_address);
+
+        let spending_txid = bitcoin_wallet
+            .spending_transaction(tx_cancel.as_outpoint())
             .await?;
 
-        Ok(())
+        Ok(spending_txid.filter(|txid| *txid != tx_refund.txid()))
+    }
+
+    /// The number of blocks left until Alice can punish us, zero once she can.
+    /// Counts from the earliest block the cancel transaction could have been
+    /// mined in, so the actual deadline is never earlier.
+    pub async fn blocks_until_punish_timelock(
+        &self,
+        bitcoin_wallet: &bitcoin::Wallet,
+    ) -> Result<u32> {
+        let current_block_height = bitcoin_wallet.get_block_height().await?;
+        let tx_lock_height = bitcoin_wallet
+            .transaction_block_height(self.tx_lock.txid())
+            .await?;
+        let punish_timelock_height = tx_lock_height + self.cancel_timelock + self.punish_timelock;
+
+        Ok(u32::from(punish_timelock_height).saturating_sub(u32::from(current_block_height)))
     }
 
     pub fn tx_lock_id(&self) -> bitcoin::Txid {
         self.tx_lock.txid()
     }
+
+    pub fn cancel_timelock(&self) -> CancelTimelock {
+        self.cancel_timelock
+    }
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
@@ -588,6 +658,28 @@ impl State5 {
 
         Ok(())
     }
+
+    /// Redeems the Monero and sweeps it to `receive_address`. Returns the sweep
+    /// transactions.
+    pub async fn sweep_xmr(
+        &self,
+        monero_wallet: &monero::Wallet,
+        receive_address: monero::Address,
+    ) -> Result<Vec<monero::TxHash>> {
+        let s_b = monero::PrivateKey { scalar: self.s_b };
+
+        let s = self.s_a + s_b;
+
+        monero_wallet
+            .sweep_output(
+                s,
+                self.v,
+                self.monero_wallet_restore_blockheight,
+                receive_address,
+            )
+            .await
+    }
+
     pub fn tx_lock_id(&self) -> bitcoin::Txid {
         self.tx_lock.txid()
     }
@@ -5,14 +5,17 @@ use crate::{
     execution_params::ExecutionParams,
     monero,
     monero::InsufficientFunds,
-    protocol::bob::{self, event_loop::EventLoopHandle, state::*, QuoteRequest},
+    protocol::{
+        bob::{self, event_loop::EventLoopHandle, state::*, QuoteRequest},
+        progress::{Reporter, Timelock},
+    },
 };
 use anyhow::{bail, Result};
 use async_recursion::async_recursion;
 use rand::rngs::OsRng;
 use std::sync::Arc;
 use tokio::select;
-use tracing::{trace, warn};
+use tracing::{info, trace, warn};
 use uuid::Uuid;
 
 pub fn is_complete(state: &BobState) -> bool {
@@ -25,11 +28,35 @@ pub fn is_complete(state: &BobState) -> bool {
     )
 }
 
+/// Redeems the Monero and sends it to the receive address given when the swap
+/// was started. Without one it is kept in a wallet of its own.
+pub async fn receive_xmr(
+    swap_id: Uuid,
+    state5: &State5,
+    monero_wallet: &monero::Wallet,
+    db: &Database,
+) -> Result<()> {
+    let receive_address = match db.get_monero_address(swap_id)? {
+        Some(receive_address) => receive_address,
+        None => return state5.claim_xmr(monero_wallet).await,
+    };
+
+    for tx_hash in state5.sweep_xmr(monero_wallet, receive_address).await? {
+        info!(
+            "Sent the Monero of swap {} to {} with transaction {}",
+            swap_id, receive_address, tx_hash.0
+        );
+    }
+
+    Ok(())
+}
+
 #[allow(clippy::too_many_arguments)]
 pub async fn run(swap: bob::Swap) -> Result<BobState> {
     run_until(swap, is_complete).await
 }
 
+#[tracing::instrument(name = "swap", skip(swap, is_target_state), fields(id = %swap.swap_id))]
 pub async fn run_until(
     swap: bob::Swap,
     is_target_state: fn(&BobState) -> bool,
@@ -41,8 +68,10 @@ pub async fn run_until(
         swap.db,
         swap.bitcoin_wallet,
         swap.monero_wallet,
+        swap.monero_daemon,
         swap.swap_id,
         swap.execution_params,
+        Reporter::new(swap.swap_id, swap.progress),
     )
     .await
 }
@@ -57,15 +86,22 @@ async fn run_until_internal(
     db: Database,
     bitcoin_wallet: Arc<bitcoin::Wallet>,
     monero_wallet: Arc<monero::Wallet>,
+    monero_daemon: Arc<monero::Daemon>,
     swap_id: Uuid,
     execution_params: ExecutionParams,
+    progress: Reporter,
 ) -> Result<BobState> {
     trace!("Current state: {}", state);
+    progress.state_entered(&state);
     if is_target_state(&state) {
         Ok(state)
     } else {
         match state {
             BobState::Started { btc_amount } => {
+                // Saved so that the swap can be aborted before the execution setup is done.
+                let db_state = BobState::Started { btc_amount }.into();
+                db.insert_latest_state(swap_id, Swap::Bob(db_state)).await?;
+
                 let bitcoin_refund_address = bitcoin_wallet.new_address().await?;
 
                 event_loop_handle.dial().await?;
@@ -88,16 +124,22 @@ async fn run_until_internal(
                     db,
                     bitcoin_wallet,
                     monero_wallet,
+                    monero_daemon,
                     swap_id,
                     execution_params,
+                    progress,
                 )
                 .await
             }
             BobState::ExecutionSetupDone(state2) => {
                 // Do not lock Bitcoin if not connected to Alice.
                 event_loop_handle.dial().await?;
+                // Once the lock transaction may be out the swap must not be
+                // aborted anymore, its state is only saved after the broadcast.
+                db.insert_btc_lock_broadcast(swap_id).await?;
                 // Alice and Bob have exchanged info
                 let state3 = state2.lock_btc(bitcoin_wallet.as_ref()).await?;
+                progress.transaction_broadcast("lock", state3.tx_lock_id());
 
                 let state = BobState::BtcLocked(state3);
                 let db_state = state.clone().into();
@@ -109,8 +151,10 @@ async fn run_until_internal(
                     db,
                     bitcoin_wallet,
                     monero_wallet,
+                    monero_daemon,
                     swap_id,
                     execution_params,
+                    progress,
                 )
                 .await
             }
@@ -157,8 +201,10 @@ async fn run_until_internal(
                     db,
                     bitcoin_wallet,
                     monero_wallet,
+                    monero_daemon,
                     swap_id,
                     execution_params,
+                    progress,
                 )
                 .await
             }
@@ -173,7 +219,7 @@ async fn run_until_internal(
                     event_loop_handle.dial().await?;
 
                     let xmr_lock_watcher = state.clone().watch_for_lock_xmr(
-                        monero_wallet.as_ref(),
+                        monero_daemon.as_ref(),
                         lock_transfer_proof,
                         monero_wallet_restore_blockheight,
                     );
@@ -186,6 +232,7 @@ async fn run_until_internal(
                                 Ok(state4) => BobState::XmrLocked(state4),
                                 Err(InsufficientFunds {..}) => {
                                      warn!("The other party has locked insufficient Monero funds! Waiting for refund...");
+                                     progress.waiting_for_timelock(Timelock::Cancel);
                                      state.wait_for_cancel_timelock_to_expire(bitcoin_wallet.as_ref()).await?;
                                      let state4 = state.cancel();
                                      BobState::CancelTimelockExpired(state4)
@@ -211,8 +258,10 @@ async fn run_until_internal(
                     db,
                     bitcoin_wallet,
                     monero_wallet,
+                    monero_daemon,
                     swap_id,
                     execution_params,
+                    progress,
                 )
                 .await
             }
@@ -227,8 +276,12 @@ async fn run_until_internal(
 
                     let state4_clone = state.clone();
 
-                    let enc_sig_sent_watcher =
-                        event_loop_handle.send_encrypted_signature(tx_redeem_encsig);
+                    let enc_sig_sent_watcher = event_loop_handle.send_encrypted_signature(
+                        swap_id,
+                        tx_redeem_encsig,
+                        state.tx_lock_id(),
+                        state.cancel_timelock(),
+                    );
                     let bitcoin_wallet = bitcoin_wallet.clone();
                     let cancel_timelock_expires =
                         state4_clone.wait_for_cancel_timelock_to_expire(bitcoin_wallet.as_ref());
@@ -253,8 +306,10 @@ async fn run_until_internal(
                     db,
                     bitcoin_wallet,
                     monero_wallet,
+                    monero_daemon,
                     swap_id,
                     execution_params,
+                    progress,
                 )
                 .await
             }
@@ -288,14 +343,16 @@ async fn run_until_internal(
                     db,
                     bitcoin_wallet.clone(),
                     monero_wallet,
+                    monero_daemon,
                     swap_id,
                     execution_params,
+                    progress,
                 )
                 .await
             }
             BobState::BtcRedeemed(state) => {
                 // Bob redeems XMR using revealed s_a
-                state.claim_xmr(monero_wallet.as_ref()).await?;
+                receive_xmr(swap_id, &state, monero_wallet.as_ref(), &db).await?;
 
                 let state = BobState::XmrRedeemed {
                     tx_lock_id: state.tx_lock_id(),
@@ -309,8 +366,10 @@ async fn run_until_internal(
                     db,
                     bitcoin_wallet,
                     monero_wallet,
+                    monero_daemon,
                     swap_id,
                     execution_params,
+                    progress,
                 )
                 .await
             }
@@ -320,7 +379,8 @@ async fn run_until_internal(
                     .await
                     .is_err()
                 {
-                    state4.submit_tx_cancel(bitcoin_wallet.as_ref()).await?;
+                    let txid = state4.submit_tx_cancel(bitcoin_wallet.as_ref()).await?;
+                    progress.transaction_broadcast("cancel", txid);
                 }
 
                 let state = BobState::BtcCancelled(state4);
@@ -334,26 +394,63 @@ async fn run_until_internal(
                     db,
                     bitcoin_wallet,
                     monero_wallet,
+                    monero_daemon,
                     swap_id,
                     execution_params,
+                    progress,
                 )
                 .await
             }
             BobState::BtcCancelled(state) => {
                 // Bob has cancelled the swap
-                let state = match state.expired_timelock(bitcoin_wallet.as_ref()).await? {
-                    ExpiredTimelocks::None => {
-                        bail!("Internal error: canceled state reached before cancel timelock was expired");
-                    }
-                    ExpiredTimelocks::Cancel => {
-                        state
-                            .refund_btc(bitcoin_wallet.as_ref(), execution_params)
-                            .await?;
-                        BobState::BtcRefunded(state)
+                if let ExpiredTimelocks::None =
+                    state.expired_timelock(bitcoin_wallet.as_ref()).await?
+                {
+                    bail!(
+                        "Internal error: canceled state reached before cancel timelock was expired"
+                    );
+                }
+
+                // Even after the punish timelock expired the Bitcoin can be refunded as long as
+                // Alice did not punish us yet.
+                let state = match state.check_for_tx_punish(bitcoin_wallet.as_ref()).await? {
+                    Some(tx_punish_id) => bob::refund::punished(swap_id, &state, tx_punish_id),
+                    None => {
+                        let blocks_left = bob::refund::warn_about_punish_timelock(
+                            swap_id,
+                            &state,
+                            bitcoin_wallet.as_ref(),
+                        )
+                        .await?;
+                        progress.punish_timelock_approaching(blocks_left);
+
+                        match state.publish_refund_btc(bitcoin_wallet.as_ref()).await {
+                            Ok(txid) => {
+                                progress.transaction_broadcast("refund", txid);
+
+                                let required = execution_params.bitcoin_finality_confirmations;
+                                bitcoin_wallet
+                                    .wait_for_transaction_finality_with_progress(
+                                        txid,
+                                        execution_params,
+                                        |confirmations| {
+                                            progress.confirmations(txid, confirmations, required)
+                                        },
+                                    )
+                                    .await?;
+                                BobState::BtcRefunded(state)
+                            }
+                            // Publishing the refund transaction fails if Alice punished us first.
+                            Err(refund_error) => {
+                                match state.check_for_tx_punish(bitcoin_wallet.as_ref()).await? {
+                                    Some(tx_punish_id) => {
+                                        bob::refund::punished(swap_id, &state, tx_punish_id)
+                                    }
+                                    None => return Err(refund_error),
+                                }
+                            }
+                        }
                     }
-                    ExpiredTimelocks::Punish => BobState::BtcPunished {
-                        tx_lock_id: state.tx_lock_id(),
-                    },
                 };
 
                 let db_state = state.clone().into();
@@ -365,13 +462,17 @@ async fn run_until_internal(
                     db,
                     bitcoin_wallet,
                     monero_wallet,
+                    monero_daemon,
                     swap_id,
                     execution_params,
+                    progress,
                 )
                 .await
             }
             BobState::BtcRefunded(state4) => Ok(BobState::BtcRefunded(state4)),
-            BobState::BtcPunished { tx_lock_id } => Ok(BobState::BtcPunished { tx_lock_id }),
+            BobState::BtcPunished { tx_lock_id, state4 } => {
+                Ok(BobState::BtcPunished { tx_lock_id, state4 })
+            }
             BobState::SafelyAborted => Ok(BobState::SafelyAborted),
             BobState::XmrRedeemed { tx_lock_id } => Ok(BobState::XmrRedeemed { tx_lock_id }),
         }
@@ -1,5 +1,5 @@
 use crate::{
-    network::request_response::{CborCodec, TransferProofProtocol, TIMEOUT},
+    network::request_response::{CborCodec, TransferProofProtocol},
     protocol::alice::TransferProof,
 };
 use anyhow::{anyhow, Error, Result};
@@ -40,9 +40,9 @@ impl Behaviour {
     }
 }
 
-impl Default for Behaviour {
-    fn default() -> Self {
-        let timeout = Duration::from_secs(TIMEOUT);
+impl Behaviour {
+    /// How long we may take to acknowledge a transfer proof.
+    pub fn new(timeout: Duration) -> Self {
         let mut config = RequestResponseConfig::default();
         config.set_request_timeout(timeout);
 
@@ -0,0 +1,161 @@
+//! Progress of running swaps for embedders that want to show or react to each
+//! step without polling the database.
+//!
+//! Both state machines publish [`ProgressEvent`]s on a
+//! [`tokio::sync::broadcast`] channel. Subscribers that fall behind by more
+//! than [`CHANNEL_CAPACITY`] events miss the oldest ones.
+
+use crate::bitcoin::Txid;
+use serde::Serialize;
+use std::fmt;
+use tokio::sync::broadcast;
+use uuid::Uuid;
+
+pub const CHANNEL_CAPACITY: usize = 100;
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct ProgressEvent {
+    pub swap_id: Uuid,
+    #[serde(flatten)]
+    pub progress: Progress,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Progress {
+    /// The swap entered the state described by `state`.
+    StateEntered { state: String },
+    /// We published a Bitcoin transaction, `kind` is e.g. `lock` or `refund`.
+    TransactionBroadcast { kind: String, txid: Txid },
+    /// A Bitcoin transaction has `confirmations` of the `required` ones.
+    Confirmations {
+        txid: Txid,
+        confirmations: u32,
+        required: u32,
+    },
+    /// The swap cannot continue before `timelock` expires.
+    WaitingForTimelock { timelock: Timelock },
+    /// The Bitcoin has to be refunded within `blocks_left` blocks, after that
+    /// Alice can punish the swap and take it.
+    PunishTimelockApproaching { blocks_left: u32 },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Timelock {
+    Cancel,
+    Punish,
+}
+
+impl fmt::Display for Progress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Progress::StateEntered { state } => write!(f, "Swap is in state: {}", state),
+            Progress::TransactionBroadcast { kind, txid } => {
+                write!(f, "Published {} transaction {}", kind, txid)
+            }
+            Progress::Confirmations {
+                txid,
+                confirmations,
+                required,
+            } => write!(
+                f,
+                "Transaction {} has {}/{} confirmations",
+                txid, confirmations, required
+            ),
+            Progress::WaitingForTimelock {
+                timelock: Timelock::Cancel,
+            } => write!(f, "Waiting for the cancel timelock to expire"),
+            Progress::WaitingForTimelock {
+                timelock: Timelock::Punish,
+            } => write!(f, "Waiting for the punish timelock to expire"),
+            Progress::PunishTimelockApproaching { blocks_left } => write!(
+                f,
+                "The Bitcoin must be refunded within {} blocks before Alice can punish the swap",
+                blocks_left
+            ),
+        }
+    }
+}
+
+pub fn channel() -> broadcast::Sender<ProgressEvent> {
+    let (sender, _) = broadcast::channel(CHANNEL_CAPACITY);
+    sender
+}
+
+/// Publishes the progress of the swap with `swap_id`.
+#[derive(Clone, Debug)]
+pub struct Reporter {
+    swap_id: Uuid,
+    sender: broadcast::Sender<ProgressEvent>,
+}
+
+impl Reporter {
+    pub fn new(swap_id: Uuid, sender: broadcast::Sender<ProgressEvent>) -> Self {
+        Self { swap_id, sender }
+    }
+
+    pub fn report(&self, progress: Progress) {
+        // Sending only fails if nobody is subscribed.
+        let _ = self.sender.send(ProgressEvent {
+            swap_id: self.swap_id,
+            progress,
+        });
+    }
+
+    pub fn state_entered(&self, state: impl ToString) {
+        self.report(Progress::StateEntered {
+            state: state.to_string(),
+        })
+    }
+
+    pub fn transaction_broadcast(&self, kind: &str, txid: Txid) {
+        self.report(Progress::TransactionBroadcast {
+            kind: kind.to_string(),
+            txid,
+        })
+    }
+
+    pub fn confirmations(&self, txid: Txid, confirmations: u32, required: u32) {
+        self.report(Progress::Confirmations {
+            txid,
+            confirmations,
+            required,
+        })
+    }
+
+    pub fn waiting_for_timelock(&self, timelock: Timelock) {
+        self.report(Progress::WaitingForTimelock { timelock })
+    }
+
+    pub fn punish_timelock_approaching(&self, blocks_left: u32) {
+        self.report(Progress::PunishTimelockApproaching { blocks_left })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn subscribers_receive_the_events_of_all_swaps() {
+        let sender = channel();
+        let mut receiver = sender.subscribe();
+        let swap_id = Uuid::new_v4();
+
+        Reporter::new(swap_id, sender).waiting_for_timelock(Timelock::Cancel);
+
+        let event = receiver.recv().await.unwrap();
+        assert_eq!(event, ProgressEvent {
+            swap_id,
+            progress: Progress::WaitingForTimelock {
+                timelock: Timelock::Cancel
+            }
+        });
+        assert_eq!(
+            serde_json::to_value(&event).unwrap(),
+            json!({ "swap_id": swap_id, "event": "waiting_for_timelock", "timelock": "cancel" })
+        );
+    }
+}
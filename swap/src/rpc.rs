@@ -0,0 +1,248 @@
+//! JSON-RPC 2.0 over HTTP POST, shared by the control API of the ASB and the
+//! daemon mode of the CLI.
+//!
+//! Every request has to carry a token as `Authorization: Bearer <token>`. The
+//! token is stored in the data directory so that only local users with access
+//! to it can drive the API.
+
+use crate::fs::ensure_directory_exists;
+use anyhow::{Context, Result};
+use hyper::{
+    header::{HeaderValue, AUTHORIZATION, CONTENT_TYPE},
+    service::{make_service_fn, service_fn},
+    Body, Method, Server, StatusCode,
+};
+use rand::{rngs::OsRng, RngCore};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+use std::{convert::Infallible, fs, future::Future, io::Write, net::SocketAddr, path::Path};
+use tracing::info;
+
+/// Name of the file in the data directory that holds the token clients have
+/// to send as `Authorization: Bearer <token>`.
+pub const TOKEN_FILE_NAME: &str = "rpc_token";
+
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const SERVER_ERROR: i64 = -32000;
+
+#[derive(Debug, Deserialize)]
+struct JsonRpcRequest {
+    jsonrpc: String,
+    #[serde(default)]
+    id: Value,
+    method: String,
+    #[serde(default)]
+    params: Value,
+}
+
+/// Reads the token from the data directory, a new one is generated on first
+/// use.
+pub fn load_or_generate_token(data_dir: &Path) -> Result<String> {
+    let path = data_dir.join(TOKEN_FILE_NAME);
+
+    if path.exists() {
+        let token = fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read RPC token from {}", path.display()))?;
+        return Ok(token.trim().to_string());
+    }
+
+    let mut bytes = [0u8; 32];
+    OsRng.fill_bytes(&mut bytes);
+    let token = hex::encode(bytes);
+
+    ensure_directory_exists(&path)?;
+    // Whoever can read the token can drive the API.
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    options
+        .open(&path)
+        .and_then(|mut file| file.write_all(token.as_bytes()))
+        .with_context(|| format!("Failed to write RPC token to {}", path.display()))?;
+    info!("Generated RPC token at {}", path.display());
+
+    Ok(token)
+}
+
+/// Serves the API on `address`. The `method` and `params` of each request are
+/// deserialized into an `R` and passed to `handler`, whose result is sent back
+/// to the client.
+pub async fn serve<R, T, F, Fut>(address: SocketAddr, token: String, handler: F) -> Result<()>
+where
+    R: DeserializeOwned + Send + 'static,
+    T: Serialize,
+    F: Fn(R) -> Fut + Clone + Send + Sync + 'static,
+    Fut: Future<Output = Result<T>> + Send + 'static,
+{
+    let make_service = make_service_fn(move |_| {
+        let token = token.clone();
+        let handler = handler.clone();
+
+        async move {
+            Ok::<_, Infallible>(service_fn(move |request| {
+                handle(request, token.clone(), handler.clone())
+            }))
+        }
+    });
+
+    let server = Server::try_bind(&address)
+        .with_context(|| format!("Failed to bind RPC server to {}", address))?
+        .serve(make_service);
+
+    info!("Serving the RPC API on http://{}", address);
+    server.await.context("RPC server failed")?;
+
+    Ok(())
+}
+
+async fn handle<R, T, F, Fut>(
+    request: hyper::Request<Body>,
+    token: String,
+    handler: F,
+) -> Result<hyper::Response<Body>, Infallible>
+where
+    R: DeserializeOwned,
+    T: Serialize,
+    F: Fn(R) -> Fut,
+    Fut: Future<Output = Result<T>>,
+{
+    if request.method() != Method::POST {
+        return Ok(status(StatusCode::METHOD_NOT_ALLOWED));
+    }
+
+    let header = request
+        .headers()
+        .get(AUTHORIZATION)
+        .and_then(|header| header.to_str().ok());
+    if !is_authorized(header, &token) {
+        return Ok(status(StatusCode::UNAUTHORIZED));
+    }
+
+    let body = match hyper::body::to_bytes(request.into_body()).await {
+        Ok(body) => body,
+        Err(_) => return Ok(status(StatusCode::BAD_REQUEST)),
+    };
+
+    let result = respond(&body, handler).await;
+    let mut response = hyper::Response::new(Body::from(result.to_string()));
+    response
+        .headers_mut()
+        .insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+
+    Ok(response)
+}
+
+async fn respond<R, T, F, Fut>(body: &[u8], handler: F) -> Value
+where
+    R: DeserializeOwned,
+    T: Serialize,
+    F: Fn(R) -> Fut,
+    Fut: Future<Output = Result<T>>,
+{
+    let request = match serde_json::from_slice::<JsonRpcRequest>(body) {
+        Ok(request) => request,
+        Err(error) => return error_response(Value::Null, PARSE_ERROR, error),
+    };
+
+    if request.jsonrpc != "2.0" {
+        return error_response(
+            request.id,
+            INVALID_REQUEST,
+            "Only JSON-RPC 2.0 is supported",
+        );
+    }
+
+    let command = match parse_request(request.method, request.params) {
+        Ok(command) => command,
+        Err(error) => return error_response(request.id, INVALID_REQUEST, error),
+    };
+
+    let result = handler(command)
+        .await
+        .and_then(|result| serde_json::to_value(result).context("Failed to serialize result"));
+
+    match result {
+        Ok(result) => json!({ "jsonrpc": "2.0", "id": request.id, "result": result }),
+        Err(error) => error_response(request.id, SERVER_ERROR, format!("{:#}", error)),
+    }
+}
+
+/// Deserializes a request that is tagged by `method` with its `params` as
+/// content.
+pub fn parse_request<R>(method: String, params: Value) -> Result<R, serde_json::Error>
+where
+    R: DeserializeOwned,
+{
+    let mut request = Map::new();
+    request.insert("method".to_string(), Value::String(method));
+    if !params.is_null() {
+        request.insert("params".to_string(), params);
+    }
+
+    serde_json::from_value(Value::Object(request))
+}
+
+fn error_response(id: Value, code: i64, message: impl ToString) -> Value {
+    json!({
+        "jsonrpc": "2.0",
+        "id": id,
+        "error": { "code": code, "message": message.to_string() }
+    })
+}
+
+/// Checks the value of an `Authorization` header against `token`. The tokens
+/// are compared in constant time.
+pub fn is_authorized(header: Option<&str>, token: &str) -> bool {
+    let provided = match header.and_then(|header| header.strip_prefix("Bearer ")) {
+        Some(provided) => provided.as_bytes(),
+        None => return false,
+    };
+
+    provided.len() == token.len()
+        && provided
+            .iter()
+            .zip(token.as_bytes())
+            .fold(0, |difference, (a, b)| difference | (a ^ b))
+            == 0
+}
+
+fn status(status: StatusCode) -> hyper::Response<Body> {
+    let mut response = hyper::Response::new(Body::empty());
+    *response.status_mut() = status;
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_the_right_token_is_authorized() {
+        let token = "0123456789abcdef";
+
+        assert!(is_authorized(Some("Bearer 0123456789abcdef"), token));
+        assert!(!is_authorized(Some("Bearer 0123456789abcdee"), token));
+        assert!(!is_authorized(Some("0123456789abcdef"), token));
+        assert!(!is_authorized(None, token));
+    }
+
+    #[test]
+    fn generated_token_is_loaded_again() {
+        let data_dir = tempfile::tempdir().unwrap();
+
+        let token = load_or_generate_token(data_dir.path()).unwrap();
+
+        assert_eq!(load_or_generate_token(data_dir.path()).unwrap(), token);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let metadata = fs::metadata(data_dir.path().join(TOKEN_FILE_NAME)).unwrap();
+            assert_eq!(metadata.permissions().mode() & 0o777, 0o600);
+        }
+    }
+}
@@ -1,29 +1,195 @@
-use anyhow::Result;
+use anyhow::{Context, Result};
 use atty::{self};
-use tracing::{info, subscriber};
+use std::path::{Path, PathBuf};
+use tracing::{info, subscriber, Subscriber};
+use tracing_appender::{
+    non_blocking::{NonBlocking, WorkerGuard},
+    rolling,
+};
 use tracing_log::LogTracer;
-use tracing_subscriber::{filter::LevelFilter, FmtSubscriber};
+use tracing_subscriber::{
+    filter::LevelFilter,
+    fmt::{self, time::ChronoLocal, MakeWriter},
+    layer::SubscriberExt,
+    registry::LookupSpan,
+    EnvFilter, Layer,
+};
+use uuid::Uuid;
 
-pub fn init_tracing(level: LevelFilter) -> Result<()> {
+/// How events are written to stderr and the log file.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    Text,
+    /// One JSON object per event, including the spans it happened in, e.g.
+    /// the swap with its id.
+    Json,
+}
+
+impl Format {
+    pub fn new(json: bool) -> Self {
+        if json {
+            Format::Json
+        } else {
+            Format::Text
+        }
+    }
+}
+
+/// A file in `dir` that receives the same events as stderr.
+#[derive(Debug, Clone, PartialEq)]
+pub enum LogFile {
+    /// Starts a new file every day, for long running processes.
+    Daily { dir: PathBuf, prefix: String },
+    /// Everything that is logged about one swap.
+    Swap { dir: PathBuf, swap_id: Uuid },
+}
+
+impl LogFile {
+    fn writer(&self) -> Result<(NonBlocking, WorkerGuard)> {
+        let appender = match self {
+            LogFile::Daily { dir, prefix } => {
+                create_dir(dir)?;
+                rolling::daily(dir, prefix)
+            }
+            LogFile::Swap { dir, swap_id } => {
+                create_dir(dir)?;
+                rolling::never(dir, format!("swap-{}.log", swap_id))
+            }
+        };
+
+        Ok(tracing_appender::non_blocking(appender))
+    }
+}
+
+fn create_dir(dir: &Path) -> Result<()> {
+    std::fs::create_dir_all(dir)
+        .with_context(|| format!("Failed to create log directory {}", dir.display()))
+}
+
+/// If a `log_file` is given the returned guard has to be held until the end
+/// of `main`, the file is written on a background thread that stops once the
+/// guard is dropped.
+pub fn init_tracing(
+    level: LevelFilter,
+    format: Format,
+    log_file: Option<LogFile>,
+) -> Result<Option<WorkerGuard>> {
     if level == LevelFilter::OFF {
-        return Ok(());
+        return Ok(None);
     }
 
     // We want upstream library log messages, just only at Info level.
     LogTracer::init_with_filter(tracing_log::log::LevelFilter::Info)?;
 
     let is_terminal = atty::is(atty::Stream::Stderr);
-    let subscriber = FmtSubscriber::builder()
-        .with_env_filter(format!(
+    let stderr_text = match format {
+        Format::Text => Some(
+            fmt::layer()
+                .with_writer(std::io::stderr)
+                .with_ansi(is_terminal),
+        ),
+        Format::Json => None,
+    };
+    let (file, guard) = split(log_file.map(|log_file| log_file.writer()).transpose()?);
+
+    let subscriber = tracing_subscriber::registry()
+        .with(EnvFilter::new(format!(
             "swap={},monero_harness={},bitcoin_harness={},http=warn,warp=warn",
             level, level, level
-        ))
-        .with_writer(std::io::stderr)
-        .with_ansi(is_terminal)
-        .finish();
+        )))
+        .with(stderr_text)
+        .with(json_layer(format, std::io::stderr))
+        .with(file.clone().and_then(|file| text_layer(format, file)))
+        .with(file.and_then(|file| json_layer(format, file)));
 
     subscriber::set_global_default(subscriber)?;
     info!("Initialized tracing with level: {}", level);
 
-    Ok(())
+    Ok(guard)
+}
+
+/// Logs everything of the swap crate on `Info` level, or on `Debug` level with
+/// timestamps if `debug` is set. The returned guard has to be held like the
+/// one of [`init_tracing`].
+pub fn init_cli_tracing(
+    debug: bool,
+    format: Format,
+    log_file: Option<LogFile>,
+) -> Result<Option<WorkerGuard>> {
+    let level = if debug {
+        LevelFilter::DEBUG
+    } else {
+        LevelFilter::INFO
+    };
+
+    let is_terminal = atty::is(atty::Stream::Stderr);
+    let stderr_debug = match (format, debug) {
+        (Format::Text, true) => Some(
+            fmt::layer()
+                .with_writer(std::io::stderr)
+                .with_ansi(is_terminal)
+                .with_target(false)
+                .with_timer(ChronoLocal::with_format("%F %T".to_owned())),
+        ),
+        _ => None,
+    };
+    let stderr_info = match (format, debug) {
+        (Format::Text, false) => Some(
+            fmt::layer()
+                .with_writer(std::io::stderr)
+                .with_ansi(is_terminal)
+                .with_target(false)
+                .without_time()
+                .with_level(false),
+        ),
+        _ => None,
+    };
+    let (file, guard) = split(log_file.map(|log_file| log_file.writer()).transpose()?);
+
+    let subscriber = tracing_subscriber::registry()
+        .with(EnvFilter::new(format!("swap={}", level)))
+        .with(stderr_debug)
+        .with(stderr_info)
+        .with(json_layer(format, std::io::stderr))
+        .with(file.clone().and_then(|file| text_layer(format, file)))
+        .with(file.and_then(|file| json_layer(format, file)));
+
+    subscriber::set_global_default(subscriber)?;
+
+    Ok(guard)
+}
+
+/// Text with timestamps and without colours, as written to log files.
+fn text_layer<S, W>(format: Format, writer: W) -> Option<impl Layer<S>>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    W: MakeWriter + Send + Sync + 'static,
+{
+    match format {
+        Format::Text => Some(
+            fmt::layer()
+                .with_writer(writer)
+                .with_ansi(false)
+                .with_timer(ChronoLocal::with_format("%F %T".to_owned())),
+        ),
+        Format::Json => None,
+    }
+}
+
+fn json_layer<S, W>(format: Format, writer: W) -> Option<impl Layer<S>>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    W: MakeWriter + Send + Sync + 'static,
+{
+    match format {
+        Format::Json => Some(fmt::layer().json().with_writer(writer)),
+        Format::Text => None,
+    }
+}
+
+fn split<A, B>(pair: Option<(A, B)>) -> (Option<A>, Option<B>) {
+    match pair {
+        Some((a, b)) => (Some(a), Some(b)),
+        None => (None, None),
+    }
 }
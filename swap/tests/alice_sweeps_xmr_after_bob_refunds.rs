@@ -0,0 +1,44 @@
+pub mod testutils;
+
+use swap::{
+    cli::watch,
+    protocol::{bob, bob::BobState},
+};
+use testutils::{bob_run_until::is_xmr_locked, FastCancelConfig};
+
+#[tokio::test]
+async fn given_bob_refunds_alice_sweeps_xmr_into_her_wallet() {
+    testutils::setup_test(FastCancelConfig, |mut ctx| async move {
+        let (bob_swap, bob_join_handle) = ctx.new_swap_as_bob().await;
+
+        let bob_state = bob::run_until(bob_swap, is_xmr_locked).await.unwrap();
+
+        let state4 = match bob_state {
+            BobState::XmrLocked(state4) => state4,
+            state => panic!("Bob in unexpected state {}", state),
+        };
+
+        let (bob_swap, bob_join_handle) = ctx.stop_and_resume_bob_from_db(bob_join_handle).await;
+        bob_join_handle.abort();
+
+        state4
+            .wait_for_cancel_timelock_to_expire(bob_swap.bitcoin_wallet.as_ref())
+            .await
+            .unwrap();
+
+        // Alice may cancel the swap before Bob does
+        let bob_state = watch::cancel_and_refund(
+            bob_swap.swap_id,
+            bob_swap.state,
+            bob_swap.execution_params,
+            bob_swap.bitcoin_wallet,
+            bob_swap.db,
+        )
+        .await
+        .unwrap();
+
+        ctx.assert_bob_refunded(bob_state).await;
+        ctx.assert_alice_refunded().await;
+    })
+    .await;
+}
@@ -0,0 +1,37 @@
+pub mod testutils;
+
+use swap::protocol::{bob, bob::BobState};
+use testutils::{bob_run_until::is_execution_setup_done, SlowCancelConfig};
+
+#[tokio::test]
+async fn given_bob_aborts_before_btc_is_locked_alice_aborts() {
+    testutils::setup_test(SlowCancelConfig, |mut ctx| async move {
+        let (bob_swap, bob_join_handle) = ctx.new_swap_as_bob().await;
+        let swap_id = bob_swap.swap_id;
+        let bitcoin_wallet = bob_swap.bitcoin_wallet.clone();
+        let db = bob_swap.db.clone();
+
+        let bob_state = bob::run_until(bob_swap, is_execution_setup_done)
+            .await
+            .unwrap();
+
+        assert!(matches!(bob_state, BobState::ExecutionSetupDone(..)));
+
+        let tx_lock_id = bob::abort(swap_id, bob_state, bitcoin_wallet, db.clone())
+            .await
+            .unwrap()
+            .unwrap()
+            .expect("Alice has to be told about the lock transaction");
+        bob_join_handle
+            .abort_handle()
+            .abort(tx_lock_id)
+            .await
+            .unwrap();
+
+        let bob_state = db.get_state(swap_id).unwrap().try_into_bob().unwrap();
+        ctx.assert_bob_aborted(bob_state.into()).await;
+
+        ctx.assert_alice_aborted().await;
+    })
+    .await;
+}
@@ -0,0 +1,57 @@
+pub mod testutils;
+
+use swap::protocol::{bob, bob::BobState};
+use testutils::{bob_run_until::is_xmr_locked, FastPunishConfig};
+
+#[tokio::test]
+async fn given_alice_punished_bob_and_agrees_bob_redeems_xmr_cooperatively() {
+    testutils::setup_test(FastPunishConfig, |mut ctx| async move {
+        let (bob_swap, bob_join_handle) = ctx.new_swap_as_bob().await;
+
+        let bob_state = bob::run_until(bob_swap, is_xmr_locked).await.unwrap();
+
+        assert!(matches!(bob_state, BobState::XmrLocked { .. }));
+
+        // Bob goes offline without sending the encrypted signature
+        let (bob_swap, bob_join_handle) = ctx.stop_and_resume_bob_from_db(bob_join_handle).await;
+        bob_join_handle.abort();
+
+        let alice_state = ctx.alice_final_state().await;
+        ctx.assert_alice_punished(alice_state).await;
+
+        let bob_state = bob::refund(
+            bob_swap.swap_id,
+            bob_swap.state,
+            bob_swap.execution_params,
+            bob_swap.bitcoin_wallet,
+            bob_swap.db,
+            true,
+        )
+        .await
+        .unwrap()
+        .unwrap();
+
+        assert!(matches!(bob_state, BobState::BtcPunished {
+            state4: Some(_),
+            ..
+        }));
+
+        // Bob comes back online and asks Alice for her key share
+        let (mut bob_swap, _bob_join_handle) =
+            ctx.stop_and_resume_bob_from_db(bob_join_handle).await;
+
+        let bob_state = bob::cooperative_redeem(
+            bob_swap.swap_id,
+            bob_swap.state,
+            &mut bob_swap.event_loop_handle,
+            bob_swap.monero_wallet,
+            bob_swap.db,
+        )
+        .await
+        .unwrap()
+        .unwrap();
+
+        ctx.assert_bob_redeemed(bob_state).await;
+    })
+    .await;
+}
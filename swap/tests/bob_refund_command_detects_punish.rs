@@ -0,0 +1,38 @@
+pub mod testutils;
+
+use swap::protocol::{bob, bob::BobState};
+use testutils::{bob_run_until::is_btc_locked, FastPunishConfig};
+
+#[tokio::test]
+async fn given_alice_punished_bob_while_offline_refund_reports_punished() {
+    testutils::setup_test(FastPunishConfig, |mut ctx| async move {
+        let (bob_swap, bob_join_handle) = ctx.new_swap_as_bob().await;
+
+        let bob_state = bob::run_until(bob_swap, is_btc_locked).await.unwrap();
+
+        assert!(matches!(bob_state, BobState::BtcLocked { .. }));
+
+        // Bob goes offline until Alice punished him
+        let (bob_swap, bob_join_handle) = ctx.stop_and_resume_bob_from_db(bob_join_handle).await;
+        bob_join_handle.abort();
+
+        let alice_state = ctx.alice_final_state().await;
+        ctx.assert_alice_punished(alice_state).await;
+
+        // Bob tries to refund
+        let bob_state = bob::refund(
+            bob_swap.swap_id,
+            bob_swap.state,
+            bob_swap.execution_params,
+            bob_swap.bitcoin_wallet,
+            bob_swap.db,
+            true,
+        )
+        .await
+        .unwrap()
+        .unwrap();
+
+        ctx.assert_bob_punished(bob_state).await;
+    })
+    .await;
+}
@@ -0,0 +1,48 @@
+pub mod testutils;
+
+use swap::{
+    cli::watch,
+    protocol::{bob, bob::BobState},
+};
+use testutils::{bob_run_until::is_btc_locked, FastCancelConfig};
+
+#[tokio::test]
+async fn given_bob_stops_after_btc_locked_watch_refunds_once_cancel_timelock_expired() {
+    testutils::setup_test(FastCancelConfig, |mut ctx| async move {
+        let (bob_swap, bob_join_handle) = ctx.new_swap_as_bob().await;
+
+        let bob_state = bob::run_until(bob_swap, is_btc_locked).await.unwrap();
+
+        assert!(matches!(bob_state, BobState::BtcLocked { .. }));
+
+        let (bob_swap, bob_join_handle) = ctx.stop_and_resume_bob_from_db(bob_join_handle).await;
+        bob_join_handle.abort();
+
+        // The watch keeps running, it is stopped once the swap is refunded.
+        let mut updates = bob_swap.db.watch();
+        let swap_id = bob_swap.swap_id;
+        let refunded = async move {
+            while let Some(update) = updates.next().await {
+                let (updated_swap_id, swap) = update.unwrap();
+                let state = BobState::from(swap.try_into_bob().unwrap());
+                if updated_swap_id == swap_id && matches!(state, BobState::BtcRefunded(_)) {
+                    return state;
+                }
+            }
+            panic!("The database was closed before the swap was refunded")
+        };
+
+        let bob_state = tokio::select! {
+            _ = watch::watch(
+                bob_swap.db.clone(),
+                bob_swap.bitcoin_wallet,
+                bob_swap.monero_wallet,
+                bob_swap.execution_params,
+            ) => unreachable!("The watch keeps running"),
+            bob_state = refunded => bob_state,
+        };
+
+        ctx.assert_bob_refunded(bob_state).await;
+    })
+    .await;
+}
@@ -0,0 +1,43 @@
+pub mod testutils;
+
+use swap::{
+    bitcoin, monero,
+    network::handshake::{Capabilities, SWAP_PROTOCOL_VERSION},
+    protocol::bob,
+};
+use testutils::SlowCancelConfig;
+
+#[tokio::test]
+async fn given_bob_on_other_network_bob_refuses_to_swap() {
+    testutils::setup_test(SlowCancelConfig, |mut ctx| async move {
+        let capabilities = Capabilities::new(bitcoin::Network::Testnet, monero::Network::default());
+        let (bob_swap, bob_join_handle) = ctx.new_swap_as_bob_with_capabilities(capabilities).await;
+
+        let (swap_result, event_loop_result) =
+            tokio::join!(bob::run(bob_swap), bob_join_handle.join());
+
+        assert!(swap_result.is_err());
+        let error = format!("{:#}", event_loop_result.unwrap_err());
+        assert!(error.contains("Bitcoin network"), "{}", error);
+    })
+    .await;
+}
+
+#[tokio::test]
+async fn given_no_common_protocol_version_bob_refuses_to_swap() {
+    testutils::setup_test(SlowCancelConfig, |mut ctx| async move {
+        let capabilities = Capabilities {
+            protocol_versions: vec![SWAP_PROTOCOL_VERSION + 1],
+            ..Capabilities::new(bitcoin::Network::Regtest, monero::Network::default())
+        };
+        let (bob_swap, bob_join_handle) = ctx.new_swap_as_bob_with_capabilities(capabilities).await;
+
+        let (swap_result, event_loop_result) =
+            tokio::join!(bob::run(bob_swap), bob_join_handle.join());
+
+        assert!(swap_result.is_err());
+        let error = format!("{:#}", event_loop_result.unwrap_err());
+        assert!(error.contains("swap protocol versions"), "{}", error);
+    })
+    .await;
+}
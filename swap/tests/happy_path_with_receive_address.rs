@@ -0,0 +1,43 @@
+pub mod testutils;
+
+use std::time::Duration;
+use swap::{monero, protocol::bob};
+use testutils::SlowCancelConfig;
+
+#[tokio::test]
+async fn given_receive_address_bob_sweeps_redeemed_xmr_to_it() {
+    testutils::setup_test(SlowCancelConfig, |mut ctx| async move {
+        let (bob_swap, _) = ctx.new_swap_as_bob().await;
+
+        let monero_wallet = bob_swap.monero_wallet.clone();
+        let receive_address = monero_wallet.get_main_address().await.unwrap();
+        bob_swap
+            .db
+            .insert_monero_address(bob_swap.swap_id, receive_address)
+            .await
+            .unwrap();
+
+        let bob_state = bob::run(bob_swap).await.unwrap();
+
+        assert!(matches!(bob_state, bob::BobState::XmrRedeemed { .. }));
+        ctx.assert_alice_redeemed().await;
+
+        // The swept Monero only shows up in the balance once the sweep is mined
+        let balance = tokio::time::timeout(Duration::from_secs(60), async {
+            loop {
+                monero_wallet.refresh().await.unwrap();
+                let balance = monero_wallet.get_balance().await.unwrap();
+                if balance > monero::Amount::ZERO {
+                    return balance;
+                }
+
+                tokio::time::sleep(Duration::from_secs(1)).await;
+            }
+        })
+        .await
+        .expect("Bob did not receive the Monero");
+        // Only the fee of the sweep is lost
+        assert!(balance < ctx.xmr_amount());
+    })
+    .await;
+}
@@ -0,0 +1,43 @@
+pub mod testutils;
+
+use std::time::Duration;
+use swap::{
+    asb::fixed_rate::RATE,
+    bitcoin,
+    cli::list_sellers::{list_sellers, Status},
+    monero,
+    network::rendezvous::XMR_BTC_NAMESPACE,
+};
+use testutils::SlowCancelConfig;
+
+#[tokio::test]
+async fn list_sellers_shows_registered_asb() {
+    testutils::setup_test(SlowCancelConfig, |ctx| async move {
+        let sellers = tokio::time::timeout(Duration::from_secs(60), async {
+            loop {
+                let sellers = list_sellers(&ctx.rendezvous_point(), XMR_BTC_NAMESPACE, None)
+                    .await
+                    .unwrap();
+                if !sellers.is_empty() {
+                    return sellers;
+                }
+
+                tokio::time::sleep(Duration::from_secs(1)).await;
+            }
+        })
+        .await
+        .expect("Alice did not register at the rendezvous point");
+
+        assert_eq!(sellers.len(), 1);
+        assert_eq!(sellers[0].peer_id, ctx.alice_peer_id());
+        match sellers[0].status {
+            Status::Online(quote) => {
+                assert_eq!(quote.price, bitcoin::Amount::from_btc(RATE).unwrap());
+                assert!(quote.min_quantity > monero::Amount::ZERO);
+                assert!(quote.max_quantity > quote.min_quantity);
+            }
+            Status::Unreachable => panic!("Alice should be reachable"),
+        }
+    })
+    .await;
+}
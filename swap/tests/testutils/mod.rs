@@ -8,20 +8,16 @@ use futures::{future::RemoteHandle, Future};
 use get_port::get_port;
 use libp2p::{core::Multiaddr, PeerId};
 use monero_harness::{image, Monero};
-use std::{
-    convert::Infallible,
-    path::{Path, PathBuf},
-    sync::Arc,
-    time::Duration,
-};
+use std::{convert::Infallible, path::Path, sync::Arc, time::Duration};
 use swap::{
-    asb::{fixed_rate, fixed_rate::RATE},
+    asb::{config::Limits, fixed_rate, fixed_rate::RATE},
     bitcoin,
     bitcoin::{CancelTimelock, PunishTimelock},
     database::Database,
     execution_params,
     execution_params::{ExecutionParams, GetExecutionParams},
     monero,
+    network::{handshake::Capabilities, rendezvous},
     protocol::{alice, alice::AliceState, bob, bob::BobState},
     seed::Seed,
 };
@@ -44,22 +40,25 @@ pub struct StartingBalances {
 #[derive(Clone)]
 struct BobParams {
     seed: Seed,
-    db_path: PathBuf,
+    db: Database,
     swap_id: Uuid,
     bitcoin_wallet: Arc<bitcoin::Wallet>,
     monero_wallet: Arc<monero::Wallet>,
+    monero_daemon: Arc<monero::Daemon>,
     alice_address: Multiaddr,
     alice_peer_id: PeerId,
     execution_params: ExecutionParams,
+    capabilities: Capabilities,
 }
 
 impl BobParams {
     pub fn builder(&self, event_loop_handle: bob::EventLoopHandle) -> bob::Builder {
         bob::Builder::new(
-            Database::open(&self.db_path.clone().as_path()).unwrap(),
+            self.db.clone(),
             self.swap_id,
             self.bitcoin_wallet.clone(),
             self.monero_wallet.clone(),
+            self.monero_daemon.clone(),
             self.execution_params,
             event_loop_handle,
         )
@@ -71,15 +70,41 @@ impl BobParams {
             self.alice_peer_id,
             self.alice_address.clone(),
             self.bitcoin_wallet.clone(),
+            self.db.clone(),
+            None,
+            self.capabilities.clone(),
+            self.execution_params,
         )
     }
 }
 
-pub struct BobEventLoopJoinHandle(JoinHandle<Result<Infallible>>);
+pub struct BobEventLoopJoinHandle {
+    join_handle: JoinHandle<Result<Infallible>>,
+    abort_handle: bob::AbortHandle,
+}
 
 impl BobEventLoopJoinHandle {
+    fn spawn(event_loop: bob::EventLoop) -> Self {
+        let abort_handle = event_loop.abort_handle();
+        let join_handle = tokio::spawn(event_loop.run());
+
+        Self {
+            join_handle,
+            abort_handle,
+        }
+    }
+
     pub fn abort(&self) {
-        self.0.abort()
+        self.join_handle.abort()
+    }
+
+    /// Asks Alice to abort a swap through the event loop.
+    pub fn abort_handle(&self) -> bob::AbortHandle {
+        self.abort_handle.clone()
+    }
+
+    pub async fn join(self) -> Result<Infallible> {
+        self.join_handle.await?
     }
 }
 
@@ -98,6 +123,8 @@ pub struct TestContext {
     bob_starting_balances: StartingBalances,
     bob_bitcoin_wallet: Arc<bitcoin::Wallet>,
     bob_monero_wallet: Arc<monero::Wallet>,
+
+    rendezvous_point: Multiaddr,
 }
 
 impl TestContext {
@@ -111,9 +138,26 @@ impl TestContext {
             .build()
             .unwrap();
 
-        let join_handle = tokio::spawn(event_loop.run());
+        (swap, BobEventLoopJoinHandle::spawn(event_loop))
+    }
 
-        (swap, BobEventLoopJoinHandle(join_handle))
+    pub async fn new_swap_as_bob_with_capabilities(
+        &mut self,
+        capabilities: Capabilities,
+    ) -> (bob::Swap, BobEventLoopJoinHandle) {
+        let bob_params = BobParams {
+            capabilities,
+            ..self.bob_params.clone()
+        };
+        let (event_loop, event_loop_handle) = bob_params.new_eventloop().unwrap();
+
+        let swap = bob_params
+            .builder(event_loop_handle)
+            .with_init_params(self.btc_amount)
+            .build()
+            .unwrap();
+
+        (swap, BobEventLoopJoinHandle::spawn(event_loop))
     }
 
     pub async fn stop_and_resume_bob_from_db(
@@ -126,9 +170,25 @@ impl TestContext {
 
         let swap = self.bob_params.builder(event_loop_handle).build().unwrap();
 
-        let join_handle = tokio::spawn(event_loop.run());
+        (swap, BobEventLoopJoinHandle::spawn(event_loop))
+    }
+
+    pub fn xmr_amount(&self) -> monero::Amount {
+        self.xmr_amount
+    }
+
+    pub fn alice_peer_id(&self) -> PeerId {
+        self.bob_params.alice_peer_id
+    }
 
-        (swap, BobEventLoopJoinHandle(join_handle))
+    pub fn rendezvous_point(&self) -> Multiaddr {
+        self.rendezvous_point.clone()
+    }
+
+    /// Waits until the swap of Alice is finished.
+    pub async fn alice_final_state(&mut self) -> AliceState {
+        let swap_handle = self.alice_swap_handle.recv().await.unwrap();
+        swap_handle.await.unwrap()
     }
 
     pub async fn assert_alice_redeemed(&mut self) {
@@ -178,19 +238,47 @@ impl TestContext {
         let btc_balance_after_swap = self.alice_bitcoin_wallet.as_ref().balance().await.unwrap();
         assert_eq!(btc_balance_after_swap, self.alice_starting_balances.btc);
 
-        // Ensure that Alice's balance is refreshed as we use a newly created wallet
-        self.alice_monero_wallet.as_ref().refresh().await.unwrap();
+        // The swept Monero only shows up in the balance once the sweep is mined
+        let xmr_balance_before_refund = self.alice_starting_balances.xmr - self.xmr_amount;
+        let xmr_balance_after_swap = tokio::time::timeout(Duration::from_secs(60), async {
+            loop {
+                self.alice_monero_wallet.as_ref().refresh().await.unwrap();
+                let balance = self
+                    .alice_monero_wallet
+                    .as_ref()
+                    .get_balance()
+                    .await
+                    .unwrap();
+                if balance > xmr_balance_before_refund {
+                    return balance;
+                }
+
+                tokio::time::sleep(Duration::from_secs(1)).await;
+            }
+        })
+        .await
+        .expect("Alice did not get the Monero back");
+        // Only the fees are lost
+        assert!(xmr_balance_after_swap < self.alice_starting_balances.xmr);
+    }
+
+    pub async fn assert_alice_aborted(&mut self) {
+        let swap_handle = self.alice_swap_handle.recv().await.unwrap();
+        let state = swap_handle.await.unwrap();
+
+        assert!(matches!(state, AliceState::SafelyAborted));
+
         let xmr_balance_after_swap = self
             .alice_monero_wallet
             .as_ref()
             .get_balance()
             .await
             .unwrap();
-        assert_eq!(xmr_balance_after_swap, self.xmr_amount);
+        assert_eq!(xmr_balance_after_swap, self.alice_starting_balances.xmr);
     }
 
     pub async fn assert_alice_punished(&self, state: AliceState) {
-        assert!(matches!(state, AliceState::BtcPunished));
+        assert!(matches!(state, AliceState::BtcPunished { .. }));
 
         self.alice_bitcoin_wallet
             .sync_wallet()
@@ -283,13 +371,25 @@ impl TestContext {
         assert_eq!(xmr_balance_after_swap, self.bob_starting_balances.xmr);
     }
 
+    pub async fn assert_bob_aborted(&self, state: BobState) {
+        assert!(matches!(state, BobState::SafelyAborted));
+
+        self.bob_bitcoin_wallet
+            .sync_wallet()
+            .await
+            .expect("Could not sync wallet");
+
+        let btc_balance_after_swap = self.bob_bitcoin_wallet.as_ref().balance().await.unwrap();
+        assert_eq!(btc_balance_after_swap, self.bob_starting_balances.btc);
+    }
+
     pub async fn assert_bob_punished(&self, state: BobState) {
         self.bob_bitcoin_wallet
             .sync_wallet()
             .await
             .expect("Could not sync wallet");
 
-        let lock_tx_id = if let BobState::BtcPunished { tx_lock_id } = state {
+        let lock_tx_id = if let BobState::BtcPunished { tx_lock_id, .. } = state {
             tx_lock_id
         } else {
             panic!("Bob in not in btc punished state: {:?}", state);
@@ -386,7 +486,21 @@ where
     )
     .await;
 
-    let (alice_event_loop, alice_swap_handle) = alice::EventLoop::new(
+    let rendezvous_address: Multiaddr = format!(
+        "/ip4/127.0.0.1/tcp/{}",
+        get_port().expect("Failed to find a free port")
+    )
+    .parse()
+    .expect("failed to parse rendezvous address");
+    let rendezvous_server = rendezvous::Server::new(
+        &Seed::random().unwrap().derive_libp2p_identity(),
+        rendezvous_address.clone(),
+    )
+    .unwrap();
+    let rendezvous_peer_id = rendezvous_server.peer_id();
+    tokio::spawn(rendezvous_server.run());
+
+    let (mut alice_event_loop, alice_swap_handle) = alice::EventLoop::new(
         alice_listen_address.clone(),
         alice_seed,
         execution_params,
@@ -395,22 +509,30 @@ where
         alice_db,
         fixed_rate::RateService::default(),
         alice_starting_balances.xmr,
+        capabilities(),
+        Limits::default(),
     )
     .unwrap();
 
     let alice_peer_id = alice_event_loop.peer_id();
+    alice_event_loop
+        .register_at(rendezvous_peer_id, rendezvous_address.clone(), vec![])
+        .unwrap();
+    alice_event_loop.enable_cooperative_redeem();
 
     tokio::spawn(alice_event_loop.run());
 
     let bob_params = BobParams {
         seed: Seed::random().unwrap(),
-        db_path: tempdir().unwrap().path().to_path_buf(),
+        db: Database::open(tempdir().unwrap().path()).unwrap(),
         swap_id: Uuid::new_v4(),
         bitcoin_wallet: bob_bitcoin_wallet.clone(),
         monero_wallet: bob_monero_wallet.clone(),
+        monero_daemon: Arc::new(monero::Daemon::new_with_client(monero.monerod().client())),
         alice_address: alice_listen_address,
         alice_peer_id,
         execution_params,
+        capabilities: capabilities(),
     };
 
     let test = TestContext {
@@ -424,6 +546,9 @@ where
         bob_starting_balances,
         bob_bitcoin_wallet,
         bob_monero_wallet,
+        rendezvous_point: format!("{}/p2p/{}", rendezvous_address, rendezvous_peer_id)
+            .parse()
+            .expect("failed to parse rendezvous point"),
     };
 
     testfn(test).await;
@@ -579,6 +704,12 @@ async fn init_monero_container(
 }
 
 #[allow(clippy::too_many_arguments)]
+/// The capabilities of Alice and Bob, matching the networks of the test
+/// wallets.
+fn capabilities() -> Capabilities {
+    Capabilities::new(bitcoin::Network::Regtest, monero::Network::default())
+}
+
 async fn init_test_wallets(
     name: &str,
     bitcoind_url: Url,
@@ -594,10 +725,11 @@ async fn init_test_wallets(
         .await
         .unwrap();
 
+    let wallet_rpc = monero.wallet(name).unwrap();
     let xmr_wallet = swap::monero::Wallet::new_with_client(
-        monero.wallet(name).unwrap().client(),
+        wallet_rpc.client(),
         monero::Network::default(),
-        "irrelevant_for_tests".to_string(),
+        wallet_rpc.name().to_string(),
     );
 
     let electrum_rpc_url = {
@@ -713,6 +845,10 @@ pub mod alice_run_until {
 pub mod bob_run_until {
     use swap::protocol::bob::BobState;
 
+    pub fn is_execution_setup_done(state: &BobState) -> bool {
+        matches!(state, BobState::ExecutionSetupDone(..))
+    }
+
     pub fn is_btc_locked(state: &BobState) -> bool {
         matches!(state, BobState::BtcLocked(..))
     }